@@ -11,7 +11,6 @@ use crate::{
 };
 use anyhow::{anyhow, Result};
 use ark_starknet::{cairo_string_parser::parse_cairo_string, client::StarknetClient, CairoU256};
-use num_bigint::BigUint;
 use reqwest::Client as ReqwestClient;
 use starknet::core::types::{BlockId, BlockTag, FieldElement};
 use starknet::macros::selector;
@@ -353,21 +352,13 @@ impl<'a, T: Storage, C: StarknetClient, F: FileManager, E: ElasticsearchManager>
             }
         };
 
-        let token_id_big_number = match BigUint::from_str(token_id) {
-            Ok(token_id_big_number) => token_id_big_number,
+        let token_id_u256 = match CairoU256::from_str(token_id) {
+            Ok(token_id_u256) => token_id_u256,
             Err(_) => {
                 return Err(anyhow!("Invalid token ID"));
             }
         };
 
-        let token_id_u256 =
-            match CairoU256::from_hex_be(token_id_big_number.to_str_radix(16).as_str()) {
-                Ok(token_id_u256) => token_id_u256,
-                Err(_) => {
-                    return Err(anyhow!("Invalid token ID"));
-                }
-            };
-
         let token_uri_cairo0 = self
             .get_contract_property_string(
                 contract_address_field_element,
@@ -550,7 +541,7 @@ mod tests {
             .times(1)
             .withf(
                 move |arg_filter: &Option<(String, String)>, arg_refresh_collection: &bool| {
-                    *arg_filter == Some(filter_clone.clone()) && *arg_refresh_collection == false
+                    *arg_filter == Some(filter_clone.clone()) && !*arg_refresh_collection
                 },
             )
             .returning(|_, _| {
@@ -669,4 +660,79 @@ mod tests {
         let parsed_string = result.expect("Failed to get contract property string");
         assert_eq!(parsed_string, "http");
     }
+
+    /// Fixture shared with the Pontos and Sana tests: a block with the
+    /// mint of the token 42 of an ERC721 contract, and an ERC20 transfer.
+    /// See the `recording` module of `ark-starknet` to regenerate it.
+    const RECORDED_FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../ark-starknet/fixtures/erc721_mint_block.jsonl"
+    );
+    const RECORDED_NFT: &str = "0x0727a63f78ee3f1bd18f78009067411ab369c31dece1ae22e16f567906409905";
+
+    /// Refreshes the metadata of the token minted in the recorded block,
+    /// whose on-chain URI is only returned by `token_uri`.
+    async fn refresh_recorded_token_metadata<C: StarknetClient>(client: C) {
+        let mut mock_storage = MockStorage::default();
+        let mock_file = MockFileManager::default();
+        let mut mock_elasticsearch_manager = MockElasticsearchManager::default();
+
+        mock_storage
+            .expect_register_token_metadata()
+            .times(1)
+            .withf(|contract_address, token_id, _, metadata| {
+                contract_address == RECORDED_NFT
+                    && token_id == "42"
+                    && metadata.normalized.name.as_deref() == Some("Fixture #42")
+                    && metadata.normalized.attributes.as_ref().map(Vec::len) == Some(1)
+            })
+            .returning(|_, _, _, _| Ok(()));
+        mock_elasticsearch_manager
+            .expect_upsert_token_metadata()
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut metadata_manager = MetadataManager::new(
+            &mock_storage,
+            &client,
+            &mock_file,
+            Some(&mock_elasticsearch_manager),
+        );
+
+        metadata_manager
+            .refresh_token_metadata(
+                RECORDED_NFT,
+                "42",
+                "0x534e5f4d41494e",
+                false,
+                "https://ipfs.example.com",
+                Duration::from_secs(5),
+                "https://arkproject.dev",
+            )
+            .await
+            .unwrap();
+    }
+
+    /// Records the requests of the pipeline on a mainnet node.
+    #[tokio::test]
+    #[ignore = "requires a mainnet RPC node, see ARK_STARKNET_RECORD_RPC_URL"]
+    async fn test_record_block() {
+        let rpc_url = std::env::var("ARK_STARKNET_RECORD_RPC_URL")
+            .expect("ARK_STARKNET_RECORD_RPC_URL is the URL of a mainnet RPC node");
+        let client = ark_starknet::client::RecordingStarknetClient::wrap(
+            ark_starknet::client::StarknetClientHttp::new(&rpc_url).unwrap(),
+            std::env::temp_dir().join("erc721_mint_block.metadata.jsonl"),
+        )
+        .unwrap();
+
+        refresh_recorded_token_metadata(client).await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_recorded_token_metadata() {
+        let client =
+            ark_starknet::client::ReplayStarknetClient::from_path(RECORDED_FIXTURE).unwrap();
+
+        refresh_recorded_token_metadata(client).await;
+    }
 }
@@ -0,0 +1,192 @@
+//! Derive macros for the `ark_starknet::cairo_serde` traits.
+//!
+//! Fields are (de)serialized in declaration order, following the Cairo Serde
+//! layout. Enums are prefixed with the variant index, as Cairo does.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Generics, Index};
+
+/// Derives `ark_starknet::cairo_serde::CairoSerialize`.
+#[proc_macro_derive(CairoSerialize)]
+pub fn derive_cairo_serialize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let name = &input.ident;
+    let generics = add_bound(
+        input.generics.clone(),
+        quote!(::ark_starknet::cairo_serde::CairoSerialize),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let fields = field_accessors(&data.fields);
+            quote! {
+                #( ::ark_starknet::cairo_serde::CairoSerialize::cairo_serialize(&self.#fields, out); )*
+            }
+        }
+        Data::Enum(data) => {
+            let arms = data.variants.iter().enumerate().map(|(index, variant)| {
+                let variant_name = &variant.ident;
+                let bindings: Vec<_> = (0..variant.fields.len())
+                    .map(|i| format_ident!("field_{}", i))
+                    .collect();
+
+                let pattern = match &variant.fields {
+                    Fields::Named(fields) => {
+                        let names = fields.named.iter().map(|f| f.ident.as_ref().unwrap());
+                        quote!(Self::#variant_name { #( #names: #bindings ),* })
+                    }
+                    Fields::Unnamed(_) => quote!(Self::#variant_name( #( #bindings ),* )),
+                    Fields::Unit => quote!(Self::#variant_name),
+                };
+
+                quote! {
+                    #pattern => {
+                        ::ark_starknet::cairo_serde::CairoSerialize::cairo_serialize(&#index, out);
+                        #( ::ark_starknet::cairo_serde::CairoSerialize::cairo_serialize(#bindings, out); )*
+                    }
+                }
+            });
+
+            quote! {
+                match self {
+                    #( #arms )*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return syn::Error::new_spanned(&input, "CairoSerialize can't be derived for unions")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    quote! {
+        impl #impl_generics ::ark_starknet::cairo_serde::CairoSerialize for #name #ty_generics #where_clause {
+            fn cairo_serialize(
+                &self,
+                out: &mut ::std::vec::Vec<::ark_starknet::cairo_serde::FieldElement>,
+            ) {
+                #body
+            }
+        }
+    }
+    .into()
+}
+
+/// Derives `ark_starknet::cairo_serde::CairoDeserialize`.
+///
+/// Errors are reported with the path of the field being deserialized.
+#[proc_macro_derive(CairoDeserialize)]
+pub fn derive_cairo_deserialize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let name = &input.ident;
+    let generics = add_bound(
+        input.generics.clone(),
+        quote!(::ark_starknet::cairo_serde::CairoDeserialize),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let construct = construct(quote!(Self), &data.fields, None);
+            quote!(Ok(#construct))
+        }
+        Data::Enum(data) => {
+            let arms = data.variants.iter().enumerate().map(|(index, variant)| {
+                let variant_name = &variant.ident;
+                let construct = construct(
+                    quote!(Self::#variant_name),
+                    &variant.fields,
+                    Some(variant_name.to_string()),
+                );
+                quote!(#index => Ok(#construct),)
+            });
+            let expected = format!("{} variant", name);
+
+            quote! {
+                let position = reader.position();
+                let index: usize = reader.read_field("variant")?;
+
+                match index {
+                    #( #arms )*
+                    _ => Err(::ark_starknet::cairo_serde::CairoSerdeError::invalid_value(
+                        position,
+                        #expected,
+                        ::ark_starknet::cairo_serde::FieldElement::from(index as u64),
+                    )),
+                }
+            }
+        }
+        Data::Union(_) => {
+            return syn::Error::new_spanned(&input, "CairoDeserialize can't be derived for unions")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    quote! {
+        impl #impl_generics ::ark_starknet::cairo_serde::CairoDeserialize for #name #ty_generics #where_clause {
+            fn cairo_deserialize(
+                reader: &mut ::ark_starknet::cairo_serde::FeltReader<'_>,
+            ) -> ::std::result::Result<Self, ::ark_starknet::cairo_serde::CairoSerdeError> {
+                #body
+            }
+        }
+    }
+    .into()
+}
+
+/// Adds the given trait bound to every type parameter.
+fn add_bound(mut generics: Generics, bound: TokenStream2) -> Generics {
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+/// Returns the expressions to access the fields of a struct from `self`.
+fn field_accessors(fields: &Fields) -> Vec<TokenStream2> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| match &f.ident {
+            Some(ident) => quote!(#ident),
+            None => {
+                let index = Index::from(i);
+                quote!(#index)
+            }
+        })
+        .collect()
+}
+
+/// Returns the expression building `path` with the fields read from `reader`.
+/// Fields are named after their variant in enums.
+fn construct(path: TokenStream2, fields: &Fields, variant: Option<String>) -> TokenStream2 {
+    let field_name = |name: String| match &variant {
+        Some(v) => format!("{}.{}", v, name),
+        None => name,
+    };
+
+    match fields {
+        Fields::Named(named) => {
+            let fields = named.named.iter().map(|f| {
+                let ident = f.ident.as_ref().unwrap();
+                let name = field_name(ident.to_string());
+                quote!(#ident: reader.read_field(#name)?)
+            });
+            quote!(#path { #( #fields ),* })
+        }
+        Fields::Unnamed(unnamed) => {
+            let fields = (0..unnamed.unnamed.len()).map(|i| {
+                let name = field_name(i.to_string());
+                quote!(reader.read_field(#name)?)
+            });
+            quote!(#path( #( #fields ),* ))
+        }
+        Fields::Unit => quote!(#path),
+    }
+}
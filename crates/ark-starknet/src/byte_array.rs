@@ -74,41 +74,36 @@ impl ByteArray {
     /// Converts `ByteArray` instance into a UTF-8 encoded string on success.
     /// Returns error if the `ByteArray` contains an invalid UTF-8 string.
     pub fn to_string(&self) -> Result<String, FromUtf8Error> {
-        let mut s = String::new();
+        // Bytes are decoded at once, as a character can span two words.
+        let mut bytes = Vec::new();
 
         for d in &self.data {
             // Chunks are always 31 bytes long (MAX_WORD_LEN).
-            s.push_str(&felt_to_utf8(d, MAX_WORD_LEN)?);
+            push_felt_bytes(&mut bytes, d, MAX_WORD_LEN);
         }
 
         if self.pending_word_len > 0 {
-            s.push_str(&felt_to_utf8(&self.pending_word, self.pending_word_len)?);
+            push_felt_bytes(&mut bytes, &self.pending_word, self.pending_word_len);
         }
 
-        Ok(s)
+        String::from_utf8(bytes)
     }
 }
 
-/// Converts a felt into a UTF-8 string.
-/// Returns an error if the felt contains an invalid UTF-8 string.
+/// Appends the `len` last bytes of a felt to `buffer`.
 ///
 /// # Arguments
 ///
+/// * `buffer` - The bytes of the string being decoded.
 /// * `felt` - The `FieldElement` to convert. In the context of `ByteArray` this
 ///            felt always contains at most 31 bytes.
 /// * `len` - The number of bytes in the felt, at most 31. In the context
 ///           of `ByteArray`, we don't need to check `len` as the `MAX_WORD_LEN`
 ///           already protect against that.
-fn felt_to_utf8(felt: &FieldElement, len: usize) -> Result<String, FromUtf8Error> {
-    let mut buffer = Vec::new();
-
+fn push_felt_bytes(buffer: &mut Vec<u8>, felt: &FieldElement, len: usize) {
     // ByteArray always enforce to have the first byte equal to 0.
     // That's why we start to 1.
-    for byte in felt.to_bytes_be()[1 + MAX_WORD_LEN - len..].iter() {
-        buffer.push(*byte)
-    }
-
-    String::from_utf8(buffer)
+    buffer.extend_from_slice(&felt.to_bytes_be()[1 + MAX_WORD_LEN - len..]);
 }
 
 impl From<String> for ByteArray {
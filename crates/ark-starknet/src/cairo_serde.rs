@@ -0,0 +1,630 @@
+//! Cairo Serde codec.
+//!
+//! Cairo serializes every value as a sequence of felts: calldata, call results
+//! and events keys and data all follow this layout. Instead of indexing the felts
+//! by hand, a layout can be declared as a struct deriving [`CairoDeserialize`]
+//! and [`CairoSerialize`]:
+//!
+//! ```
+//! use ark_starknet::cairo_serde::{CairoDeserialize, CairoSerialize, FieldElement};
+//! use ark_starknet::CairoU256;
+//!
+//! #[derive(Debug, CairoDeserialize, CairoSerialize)]
+//! struct Transfer {
+//!     from: FieldElement,
+//!     to: FieldElement,
+//!     token_id: CairoU256,
+//! }
+//!
+//! let felts = vec![FieldElement::ONE, FieldElement::TWO, FieldElement::ONE, FieldElement::ZERO];
+//! let transfer = Transfer::from_felts(&felts).unwrap();
+//! assert_eq!(transfer.token_id.low, 1);
+//! assert_eq!(transfer.to_felts(), felts);
+//! ```
+//!
+//! Deserialization errors report the position of the faulty felt,
+//! and the path of the field being deserialized.
+use crate::byte_array::ByteArray;
+use crate::CairoU256;
+use std::fmt;
+
+pub use ark_starknet_macros::{CairoDeserialize, CairoSerialize};
+pub use starknet::core::types::FieldElement;
+
+/// Maximum length of the pending word of a `ByteArray`.
+const MAX_PENDING_WORD_LEN: usize = 30;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CairoSerdeErrorKind {
+    /// Felts are missing to deserialize the expected value.
+    UnexpectedEnd { expected: &'static str },
+    /// The felt can't be converted into the expected value.
+    InvalidValue {
+        expected: &'static str,
+        value: FieldElement,
+    },
+    /// A `ByteArray` doesn't contain a valid UTF-8 string.
+    InvalidUtf8,
+    /// Felts are remaining once the value is deserialized.
+    TrailingData { remaining: usize },
+}
+
+impl fmt::Display for CairoSerdeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CairoSerdeErrorKind::UnexpectedEnd { expected } => {
+                write!(f, "unexpected end of felts, expected {}", expected)
+            }
+            CairoSerdeErrorKind::InvalidValue { expected, value } => {
+                write!(f, "invalid value {:#x}, expected {}", value, expected)
+            }
+            CairoSerdeErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8 string"),
+            CairoSerdeErrorKind::TrailingData { remaining } => {
+                write!(f, "{} felts remaining after the value", remaining)
+            }
+        }
+    }
+}
+
+/// Error returned while deserializing felts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CairoSerdeError {
+    /// Index of the faulty felt.
+    pub position: usize,
+    /// Path of the field being deserialized, like `token_id.low`.
+    pub field: Option<String>,
+    pub kind: CairoSerdeErrorKind,
+}
+
+impl CairoSerdeError {
+    pub fn new(position: usize, kind: CairoSerdeErrorKind) -> Self {
+        Self {
+            position,
+            field: None,
+            kind,
+        }
+    }
+
+    pub fn invalid_value(position: usize, expected: &'static str, value: FieldElement) -> Self {
+        Self::new(
+            position,
+            CairoSerdeErrorKind::InvalidValue { expected, value },
+        )
+    }
+
+    /// Prefixes the field path of the error with the given field name.
+    pub fn in_field(mut self, name: &str) -> Self {
+        self.field = Some(match self.field.take() {
+            Some(inner) if inner.starts_with('[') => format!("{}{}", name, inner),
+            Some(inner) => format!("{}.{}", name, inner),
+            None => name.to_string(),
+        });
+        self
+    }
+}
+
+impl fmt::Display for CairoSerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(
+                f,
+                "Cairo deserialization error at felt #{} (field `{}`): {}",
+                self.position, field, self.kind
+            ),
+            None => write!(
+                f,
+                "Cairo deserialization error at felt #{}: {}",
+                self.position, self.kind
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CairoSerdeError {}
+
+/// Cursor over the felts being deserialized.
+#[derive(Debug, Clone)]
+pub struct FeltReader<'a> {
+    felts: &'a [FieldElement],
+    position: usize,
+}
+
+impl<'a> FeltReader<'a> {
+    pub fn new(felts: &'a [FieldElement]) -> Self {
+        Self { felts, position: 0 }
+    }
+
+    /// Index of the next felt to be read.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Number of felts not read yet.
+    pub fn remaining(&self) -> usize {
+        self.felts.len() - self.position
+    }
+
+    /// Reads the next felt, `expected` describing the value
+    /// being deserialized in case of error.
+    pub fn read_felt(&mut self, expected: &'static str) -> Result<FieldElement, CairoSerdeError> {
+        let felt = self.felts.get(self.position).copied().ok_or_else(|| {
+            CairoSerdeError::new(
+                self.position,
+                CairoSerdeErrorKind::UnexpectedEnd { expected },
+            )
+        })?;
+
+        self.position += 1;
+        Ok(felt)
+    }
+
+    /// Reads the next value.
+    pub fn read<T: CairoDeserialize>(&mut self) -> Result<T, CairoSerdeError> {
+        T::cairo_deserialize(self)
+    }
+
+    /// Reads the next value, reporting errors in the given field.
+    pub fn read_field<T: CairoDeserialize>(&mut self, name: &str) -> Result<T, CairoSerdeError> {
+        T::cairo_deserialize(self).map_err(|e| e.in_field(name))
+    }
+
+    /// Fails if some felts were not read.
+    pub fn finish(&self) -> Result<(), CairoSerdeError> {
+        match self.remaining() {
+            0 => Ok(()),
+            remaining => Err(CairoSerdeError::new(
+                self.position,
+                CairoSerdeErrorKind::TrailingData { remaining },
+            )),
+        }
+    }
+}
+
+/// Serialization into felts, following the Cairo Serde layout.
+pub trait CairoSerialize {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>);
+
+    fn to_felts(&self) -> Vec<FieldElement> {
+        let mut out = vec![];
+        self.cairo_serialize(&mut out);
+        out
+    }
+}
+
+/// Deserialization from felts, following the Cairo Serde layout.
+pub trait CairoDeserialize: Sized {
+    fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError>;
+
+    /// Deserializes a value from all the given felts,
+    /// failing if some felts are remaining.
+    ///
+    /// Use [`FeltReader`] to deserialize only the first felts,
+    /// like events data that may have more fields in newer versions.
+    fn from_felts(felts: &[FieldElement]) -> Result<Self, CairoSerdeError> {
+        let mut reader = FeltReader::new(felts);
+        let value = reader.read()?;
+        reader.finish()?;
+        Ok(value)
+    }
+}
+
+impl<T: CairoSerialize + ?Sized> CairoSerialize for &T {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        (**self).cairo_serialize(out)
+    }
+}
+
+impl CairoSerialize for FieldElement {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        out.push(*self);
+    }
+}
+
+impl CairoDeserialize for FieldElement {
+    fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError> {
+        reader.read_felt("felt252")
+    }
+}
+
+impl CairoSerialize for bool {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        out.push(if *self {
+            FieldElement::ONE
+        } else {
+            FieldElement::ZERO
+        });
+    }
+}
+
+impl CairoDeserialize for bool {
+    fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError> {
+        let position = reader.position();
+        let felt = reader.read_felt("bool")?;
+
+        if felt == FieldElement::ZERO {
+            Ok(false)
+        } else if felt == FieldElement::ONE {
+            Ok(true)
+        } else {
+            Err(CairoSerdeError::invalid_value(position, "bool", felt))
+        }
+    }
+}
+
+macro_rules! impl_unsigned {
+    ($($ty:ty),*) => {
+        $(
+            impl CairoSerialize for $ty {
+                fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+                    out.push(FieldElement::from(*self));
+                }
+            }
+
+            impl CairoDeserialize for $ty {
+                fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError> {
+                    const SIZE: usize = std::mem::size_of::<$ty>();
+
+                    let position = reader.position();
+                    let felt = reader.read_felt(stringify!($ty))?;
+                    let bytes = felt.to_bytes_be();
+
+                    if bytes[..32 - SIZE].iter().any(|b| *b != 0) {
+                        return Err(CairoSerdeError::invalid_value(
+                            position,
+                            stringify!($ty),
+                            felt,
+                        ));
+                    }
+
+                    let mut value = [0_u8; SIZE];
+                    value.copy_from_slice(&bytes[32 - SIZE..]);
+                    Ok(<$ty>::from_be_bytes(value))
+                }
+            }
+        )*
+    };
+}
+
+impl_unsigned!(u8, u16, u32, u64, u128, usize);
+
+impl CairoSerialize for CairoU256 {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        self.low.cairo_serialize(out);
+        self.high.cairo_serialize(out);
+    }
+}
+
+impl CairoDeserialize for CairoU256 {
+    fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError> {
+        Ok(CairoU256 {
+            low: reader.read_field("low")?,
+            high: reader.read_field("high")?,
+        })
+    }
+}
+
+impl CairoSerialize for ByteArray {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        self.data.cairo_serialize(out);
+        self.pending_word.cairo_serialize(out);
+        self.pending_word_len.cairo_serialize(out);
+    }
+}
+
+impl CairoDeserialize for ByteArray {
+    fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError> {
+        let data = reader.read_field("data")?;
+        let pending_word = reader.read_field("pending_word")?;
+
+        let position = reader.position();
+        let pending_word_len: usize = reader.read_field("pending_word_len")?;
+        if pending_word_len > MAX_PENDING_WORD_LEN {
+            return Err(CairoSerdeError::invalid_value(
+                position,
+                "pending word length lower than 31",
+                FieldElement::from(pending_word_len as u64),
+            )
+            .in_field("pending_word_len"));
+        }
+
+        Ok(ByteArray {
+            data,
+            pending_word,
+            pending_word_len,
+        })
+    }
+}
+
+impl CairoSerialize for String {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        ByteArray::from_string(self).cairo_serialize(out)
+    }
+}
+
+impl CairoSerialize for str {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        ByteArray::from_string(self).cairo_serialize(out)
+    }
+}
+
+impl CairoDeserialize for String {
+    fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError> {
+        let position = reader.position();
+        let byte_array: ByteArray = reader.read()?;
+
+        byte_array
+            .to_string()
+            .map_err(|_| CairoSerdeError::new(position, CairoSerdeErrorKind::InvalidUtf8))
+    }
+}
+
+/// Cairo `Option` layout: variant `Some` is 0, `None` is 1.
+impl<T: CairoSerialize> CairoSerialize for Option<T> {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        match self {
+            Some(v) => {
+                out.push(FieldElement::ZERO);
+                v.cairo_serialize(out);
+            }
+            None => out.push(FieldElement::ONE),
+        }
+    }
+}
+
+impl<T: CairoDeserialize> CairoDeserialize for Option<T> {
+    fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError> {
+        let position = reader.position();
+        let variant = reader.read_felt("Option variant")?;
+
+        if variant == FieldElement::ZERO {
+            Ok(Some(reader.read()?))
+        } else if variant == FieldElement::ONE {
+            Ok(None)
+        } else {
+            Err(CairoSerdeError::invalid_value(
+                position,
+                "Option variant",
+                variant,
+            ))
+        }
+    }
+}
+
+/// Cairo `Array` and `Span` layout: the length followed by the items.
+impl<T: CairoSerialize> CairoSerialize for [T] {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        self.len().cairo_serialize(out);
+        for item in self {
+            item.cairo_serialize(out);
+        }
+    }
+}
+
+impl<T: CairoSerialize> CairoSerialize for Vec<T> {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        self.as_slice().cairo_serialize(out)
+    }
+}
+
+impl<T: CairoDeserialize> CairoDeserialize for Vec<T> {
+    fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError> {
+        let position = reader.position();
+        let len: usize = reader.read_field("len")?;
+
+        // Every item is at least one felt long, checking the length
+        // first avoids allocating for an invalid input.
+        if len > reader.remaining() {
+            return Err(CairoSerdeError::new(
+                position,
+                CairoSerdeErrorKind::UnexpectedEnd {
+                    expected: "array items",
+                },
+            ));
+        }
+
+        (0..len)
+            .map(|i| reader.read_field(&format!("[{}]", i)))
+            .collect()
+    }
+}
+
+/// A contract address, serialized as a felt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ContractAddress(pub FieldElement);
+
+impl From<FieldElement> for ContractAddress {
+    fn from(felt: FieldElement) -> Self {
+        ContractAddress(felt)
+    }
+}
+
+impl From<ContractAddress> for FieldElement {
+    fn from(address: ContractAddress) -> Self {
+        address.0
+    }
+}
+
+impl CairoSerialize for ContractAddress {
+    fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+        out.push(self.0);
+    }
+}
+
+impl CairoDeserialize for ContractAddress {
+    fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError> {
+        Ok(ContractAddress(reader.read_felt("ContractAddress")?))
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident: $index:tt),+) => {
+        impl<$($name: CairoSerialize),+> CairoSerialize for ($($name,)+) {
+            fn cairo_serialize(&self, out: &mut Vec<FieldElement>) {
+                $( self.$index.cairo_serialize(out); )+
+            }
+        }
+
+        impl<$($name: CairoDeserialize),+> CairoDeserialize for ($($name,)+) {
+            fn cairo_deserialize(reader: &mut FeltReader<'_>) -> Result<Self, CairoSerdeError> {
+                Ok(($( reader.read_field::<$name>(stringify!($index))?, )+))
+            }
+        }
+    };
+}
+
+impl_tuple!(A: 0);
+impl_tuple!(A: 0, B: 1);
+impl_tuple!(A: 0, B: 1, C: 2);
+impl_tuple!(A: 0, B: 1, C: 2, D: 3);
+impl_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, CairoSerialize, CairoDeserialize)]
+    struct Listing {
+        seller: ContractAddress,
+        token_id: CairoU256,
+        price: u128,
+        fees: Vec<(ContractAddress, FieldElement)>,
+        end_date: Option<u64>,
+        name: String,
+        active: bool,
+    }
+
+    #[derive(Debug, PartialEq, CairoSerialize, CairoDeserialize)]
+    enum Status {
+        Active,
+        Sold(ContractAddress),
+        Cancelled { reason: FieldElement },
+    }
+
+    fn felt(v: u64) -> FieldElement {
+        FieldElement::from(v)
+    }
+
+    fn listing() -> Listing {
+        Listing {
+            seller: ContractAddress(felt(0x123)),
+            token_id: CairoU256 { low: 5, high: 1 },
+            price: 1000,
+            fees: vec![(ContractAddress(felt(1)), felt(10))],
+            end_date: Some(42),
+            name: "Everai".to_string(),
+            active: true,
+        }
+    }
+
+    #[test]
+    fn test_struct_roundtrip() {
+        let felts = listing().to_felts();
+
+        assert_eq!(felts[0], felt(0x123));
+        // u256 low then high.
+        assert_eq!(&felts[1..3], &[felt(5), felt(1)]);
+        // Array length.
+        assert_eq!(felts[4], felt(1));
+
+        assert_eq!(Listing::from_felts(&felts).unwrap(), listing());
+    }
+
+    #[test]
+    fn test_enum_roundtrip() {
+        for status in [
+            Status::Active,
+            Status::Sold(ContractAddress(felt(7))),
+            Status::Cancelled { reason: felt(3) },
+        ] {
+            assert_eq!(Status::from_felts(&status.to_felts()).unwrap(), status);
+        }
+
+        assert_eq!(
+            Status::Sold(ContractAddress(felt(7))).to_felts()[0],
+            felt(1)
+        );
+
+        let e = Status::from_felts(&[felt(3)]).unwrap_err();
+        assert_eq!(e.position, 0);
+    }
+
+    #[test]
+    fn test_option_layout() {
+        assert_eq!(Some(felt(9)).to_felts(), vec![felt(0), felt(9)]);
+        assert_eq!(None::<FieldElement>.to_felts(), vec![felt(1)]);
+        assert!(Option::<FieldElement>::from_felts(&[felt(2)]).is_err());
+    }
+
+    #[test]
+    fn test_error_position_and_field() {
+        let mut felts = listing().to_felts();
+        // token_id.high doesn't fit in u128.
+        felts[2] = FieldElement::MAX;
+
+        let e = Listing::from_felts(&felts).unwrap_err();
+        assert_eq!(e.position, 2);
+        assert_eq!(e.field.as_deref(), Some("token_id.high"));
+        assert!(matches!(
+            e.kind,
+            CairoSerdeErrorKind::InvalidValue {
+                expected: "u128",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_error_in_array_item() {
+        let mut felts = listing().to_felts();
+        felts.truncate(6);
+
+        let e = Listing::from_felts(&felts).unwrap_err();
+        assert_eq!(e.position, 6);
+        assert_eq!(e.field.as_deref(), Some("fees[0].1"));
+        assert!(matches!(e.kind, CairoSerdeErrorKind::UnexpectedEnd { .. }));
+    }
+
+    #[test]
+    fn test_array_length_too_long() {
+        let e = Vec::<FieldElement>::from_felts(&[felt(1000), felt(1)]).unwrap_err();
+        assert_eq!(e.position, 0);
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let e = u64::from_felts(&[felt(1), felt(2)]).unwrap_err();
+        assert_eq!(e.kind, CairoSerdeErrorKind::TrailingData { remaining: 1 });
+
+        let felts = [felt(1), felt(2)];
+        let mut reader = FeltReader::new(&felts);
+        assert_eq!(reader.read::<u64>().unwrap(), 1);
+        assert_eq!(reader.remaining(), 1);
+    }
+
+    #[test]
+    fn test_bool() {
+        assert!(bool::from_felts(&[felt(1)]).unwrap());
+        assert!(!bool::from_felts(&[felt(0)]).unwrap());
+        assert!(bool::from_felts(&[felt(2)]).is_err());
+    }
+
+    #[test]
+    fn test_byte_array() {
+        let s = "A string longer than thirty one bytes, packed in several words.";
+        let felts = s.to_string().to_felts();
+
+        assert_eq!(String::from_felts(&felts).unwrap(), s);
+        assert_eq!(
+            ByteArray::from_felts(&felts).unwrap(),
+            ByteArray::from_string(s)
+        );
+    }
+
+    #[test]
+    fn test_byte_array_invalid_pending_word_len() {
+        let e = ByteArray::from_felts(&[felt(0), felt(0x41), felt(31)]).unwrap_err();
+        assert_eq!(e.position, 2);
+        assert_eq!(e.field.as_deref(), Some("pending_word_len"));
+    }
+}
@@ -1,82 +1,217 @@
-use anyhow::Result;
+//! Parsing of the strings returned by contracts, like `name()` or `token_uri()`.
+//!
+//! Depending on the Cairo version and the contract, a string is returned as:
+//! * a single felt252 short string (up to 31 ASCII characters),
+//! * a Cairo 0 array of short strings, prefixed by its length,
+//! * a Cairo 1 `ByteArray`: the count of full words, the 31 bytes words,
+//!   the pending word and the pending word length.
+//!
+//! The layout is detected from the felts, and malformed values
+//! are reported as errors without panicking.
 use starknet::core::{types::FieldElement, utils::parse_cairo_short_string};
-use std::ops::Add;
+use std::fmt;
+use thiserror::Error;
 
 use crate::byte_array::ByteArray;
+use crate::cairo_serde::CairoDeserialize;
 
-#[derive(Debug)]
+/// Maximum number of bytes in a `ByteArray` word.
+const MAX_WORD_LEN: usize = 31;
+
+/// Layouts a Cairo string can be returned with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringLayout {
+    /// A single felt252 short string.
+    ShortString,
+    /// A Cairo 0 array of short strings, prefixed by its length.
+    FeltArray,
+    /// A Cairo 1 `ByteArray`.
+    ByteArray,
+}
+
+impl fmt::Display for StringLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StringLayout::ShortString => write!(f, "short string"),
+            StringLayout::FeltArray => write!(f, "felt array"),
+            StringLayout::ByteArray => write!(f, "ByteArray"),
+        }
+    }
+}
+
+/// Reason why the felts don't match the detected layout.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LayoutError {
+    #[error("felt {0:#x} is not a valid short string")]
+    InvalidShortString(FieldElement),
+    #[error("word {0:#x} doesn't fit in 31 bytes")]
+    WordTooLong(FieldElement),
+    #[error("pending word length {0:#x} is greater than 30")]
+    InvalidPendingWordLen(FieldElement),
+    #[error("pending word {word:#x} doesn't fit in {len} bytes")]
+    PendingWordTooLong { word: FieldElement, len: usize },
+    #[error("invalid UTF-8 string")]
+    InvalidUtf8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
 pub enum ParseError {
+    #[error("No value found")]
     NoValueFound,
-    ShortStringError,
-    ByteArrayError,
+    #[error("{len} felts don't match any string layout")]
+    UnknownLayout { len: usize },
+    #[error("Invalid {layout} at felt #{position}: {reason}")]
+    InvalidLayout {
+        layout: StringLayout,
+        /// Index of the faulty felt.
+        position: usize,
+        reason: LayoutError,
+    },
+}
+
+impl ParseError {
+    fn invalid(layout: StringLayout, position: usize, reason: LayoutError) -> Self {
+        ParseError::InvalidLayout {
+            layout,
+            position,
+            reason,
+        }
+    }
+}
+
+/// Detects the layout of a Cairo string from its felts.
+///
+/// A single felt is a short string. Otherwise, the first felt is a length:
+/// the count of the following felts for a felt array, or the count of full
+/// words for a `ByteArray`, which is followed by 2 more felts.
+pub fn detect_layout(field_elements: &[FieldElement]) -> Result<StringLayout, ParseError> {
+    let (first, rest) = field_elements
+        .split_first()
+        .ok_or(ParseError::NoValueFound)?;
+
+    if rest.is_empty() {
+        return Ok(StringLayout::ShortString);
+    }
+
+    let unknown = ParseError::UnknownLayout {
+        len: field_elements.len(),
+    };
+
+    match usize::from_felts(std::slice::from_ref(first)) {
+        Ok(len) if len == rest.len() => Ok(StringLayout::FeltArray),
+        Ok(len) if len.checked_add(2) == Some(rest.len()) => Ok(StringLayout::ByteArray),
+        _ => Err(unknown),
+    }
 }
 
-/// Parse a Cairo "long string" represented as a Vec of FieldElements into a Rust String.
+/// Parse a Cairo string represented as a Vec of FieldElements into a Rust String.
 ///
 /// # Arguments
-/// * `field_elements`: A vector of FieldElements representing the Cairo long string.
+/// * `field_elements`: A vector of FieldElements representing the Cairo string,
+///   in any of the supported layouts.
 ///
 /// # Returns
-/// * A `Result` which is either the parsed Rust string or an error.
+/// * A `Result` which is either the parsed Rust string or an error
+///   describing which layout failed and why.
 pub fn parse_cairo_string(field_elements: Vec<FieldElement>) -> Result<String, ParseError> {
-    match field_elements.len() {
-        0 => Err(ParseError::NoValueFound),
-        // If the long_string contains only one FieldElement, try to parse it using the short string parser.
-        1 => match field_elements.first() {
-            Some(first_string_field_element) => {
-                match parse_cairo_short_string(first_string_field_element) {
-                    Ok(value) => Ok(value),
-                    Err(_) => Err(ParseError::ShortStringError),
-                }
-            }
-            None => Err(ParseError::NoValueFound),
-        },
-        // If the long_string has more than one FieldElement, parse each FieldElement sequentially
-        // and concatenate their results.
-        len => {
-            let first_element = field_elements.first().unwrap();
-
-            let a_size = first_element
-                .add(FieldElement::ONE)
-                .to_string()
-                .parse::<usize>()
-                .unwrap();
-
-            if len == a_size {
-                let results: Result<Vec<_>, _> = field_elements[1..]
-                    .iter()
-                    .map(parse_cairo_short_string)
-                    .collect();
-
-                results
-                    .map(|strings| strings.concat())
-                    .map_err(|_| ParseError::ShortStringError)
-            } else {
-                let data = field_elements[1..field_elements.len() - 2].to_vec();
-                let pending_word = field_elements[field_elements.len() - 2];
-                let pending_word_len = field_elements[field_elements.len() - 1];
-                let pending_word_len = pending_word_len.to_string().parse::<usize>().unwrap();
-
-                let byte_array = ByteArray {
-                    data,
-                    pending_word,
-                    pending_word_len,
-                };
-
-                byte_array
-                    .to_string()
-                    .map_err(|_| ParseError::ByteArrayError)
-            }
+    match detect_layout(&field_elements)? {
+        StringLayout::ShortString => parse_short_strings(&field_elements, 0),
+        StringLayout::FeltArray => parse_short_strings(&field_elements[1..], 1),
+        StringLayout::ByteArray => parse_byte_array(&field_elements),
+    }
+}
+
+/// Concatenates short strings, `offset` being the position of the first one
+/// in the parsed felts.
+fn parse_short_strings(felts: &[FieldElement], offset: usize) -> Result<String, ParseError> {
+    let layout = if offset == 0 {
+        StringLayout::ShortString
+    } else {
+        StringLayout::FeltArray
+    };
+
+    felts
+        .iter()
+        .enumerate()
+        .map(|(i, felt)| {
+            parse_cairo_short_string(felt).map_err(|_| {
+                ParseError::invalid(layout, offset + i, LayoutError::InvalidShortString(*felt))
+            })
+        })
+        .collect()
+}
+
+/// Parses a `ByteArray`, whose data length was checked by `detect_layout`.
+fn parse_byte_array(felts: &[FieldElement]) -> Result<String, ParseError> {
+    let layout = StringLayout::ByteArray;
+    let pending_word_len_position = felts.len() - 1;
+    let pending_word_position = felts.len() - 2;
+
+    let data = felts[1..pending_word_position].to_vec();
+    if let Some((i, word)) = data
+        .iter()
+        .enumerate()
+        .find(|(_, word)| !fits_in_bytes(word, MAX_WORD_LEN))
+    {
+        return Err(ParseError::invalid(
+            layout,
+            i + 1,
+            LayoutError::WordTooLong(*word),
+        ));
+    }
+
+    let pending_word_len_felt = felts[pending_word_len_position];
+    let pending_word_len = match usize::from_felts(&[pending_word_len_felt]) {
+        Ok(len) if len < MAX_WORD_LEN => len,
+        _ => {
+            return Err(ParseError::invalid(
+                layout,
+                pending_word_len_position,
+                LayoutError::InvalidPendingWordLen(pending_word_len_felt),
+            ))
         }
+    };
+
+    let pending_word = felts[pending_word_position];
+    if !fits_in_bytes(&pending_word, pending_word_len) {
+        return Err(ParseError::invalid(
+            layout,
+            pending_word_position,
+            LayoutError::PendingWordTooLong {
+                word: pending_word,
+                len: pending_word_len,
+            },
+        ));
     }
+
+    let byte_array = ByteArray {
+        data,
+        pending_word,
+        pending_word_len,
+    };
+
+    byte_array
+        .to_string()
+        .map_err(|_| ParseError::invalid(layout, 0, LayoutError::InvalidUtf8))
+}
+
+/// Returns true if the felt value is encoded on at most `len` bytes.
+fn fits_in_bytes(felt: &FieldElement, len: usize) -> bool {
+    felt.to_bytes_be()[..32 - len].iter().all(|b| *b == 0)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::cairo_string_parser::ParseError;
+    use crate::cairo_serde::CairoSerialize;
+    use crate::cairo_string_parser::{LayoutError, ParseError, StringLayout};
 
-    use super::parse_cairo_string;
-    use starknet::core::types::FieldElement;
+    use super::{detect_layout, parse_cairo_string};
+    use proptest::prelude::*;
+    use starknet::core::{types::FieldElement, utils::cairo_short_string_to_felt};
+
+    fn felt(v: u64) -> FieldElement {
+        FieldElement::from(v)
+    }
 
     #[test]
     fn should_handle_single_field_element() {
@@ -259,4 +394,150 @@ mod tests {
         let value = result.unwrap();
         assert!(value == "ipfs://bafybeieocsz5txpxgp7zrx7fexrdneyj4kzq2v4x5g3asx45m65cx7rgxu/0");
     }
+
+    #[test]
+    fn should_detect_layouts() {
+        assert_eq!(detect_layout(&[felt(0x68)]), Ok(StringLayout::ShortString));
+        assert_eq!(
+            detect_layout(&[felt(2), felt(0x68), felt(0x69)]),
+            Ok(StringLayout::FeltArray)
+        );
+        assert_eq!(
+            detect_layout(&[felt(0), felt(0x68), felt(1)]),
+            Ok(StringLayout::ByteArray)
+        );
+        assert_eq!(detect_layout(&[]), Err(ParseError::NoValueFound));
+        assert_eq!(
+            detect_layout(&[felt(7), felt(0x68)]),
+            Err(ParseError::UnknownLayout { len: 2 })
+        );
+        assert_eq!(
+            detect_layout(&[FieldElement::MAX, felt(0x68)]),
+            Err(ParseError::UnknownLayout { len: 2 })
+        );
+    }
+
+    #[test]
+    fn should_parse_byte_array() {
+        let s = "https://api.example.com/metadata/collection/42.json";
+        let felts = s.to_felts();
+
+        assert_eq!(parse_cairo_string(felts).unwrap(), s);
+        assert_eq!(
+            parse_cairo_string(vec![felt(0), felt(0), felt(0)]).unwrap(),
+            ""
+        );
+    }
+
+    #[test]
+    fn should_return_error_for_invalid_short_string() {
+        let result = parse_cairo_string(vec![felt(2), felt(0x68), FieldElement::MAX]);
+
+        assert_eq!(
+            result,
+            Err(ParseError::InvalidLayout {
+                layout: StringLayout::FeltArray,
+                position: 2,
+                reason: LayoutError::InvalidShortString(FieldElement::MAX),
+            })
+        );
+    }
+
+    #[test]
+    fn should_return_error_for_invalid_pending_word_len() {
+        let result = parse_cairo_string(vec![felt(0), felt(0x68), felt(31)]);
+
+        assert_eq!(
+            result,
+            Err(ParseError::InvalidLayout {
+                layout: StringLayout::ByteArray,
+                position: 2,
+                reason: LayoutError::InvalidPendingWordLen(felt(31)),
+            })
+        );
+    }
+
+    #[test]
+    fn should_return_error_for_pending_word_too_long() {
+        let result = parse_cairo_string(vec![felt(0), felt(0x6869), felt(1)]);
+
+        assert_eq!(
+            result,
+            Err(ParseError::InvalidLayout {
+                layout: StringLayout::ByteArray,
+                position: 1,
+                reason: LayoutError::PendingWordTooLong {
+                    word: felt(0x6869),
+                    len: 1,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn should_return_error_for_word_too_long() {
+        let result = parse_cairo_string(vec![felt(1), FieldElement::MAX, felt(0), felt(0)]);
+
+        assert_eq!(
+            result,
+            Err(ParseError::InvalidLayout {
+                layout: StringLayout::ByteArray,
+                position: 1,
+                reason: LayoutError::WordTooLong(FieldElement::MAX),
+            })
+        );
+    }
+
+    #[test]
+    fn should_return_error_for_invalid_utf8() {
+        let result = parse_cairo_string(vec![felt(0), felt(0xff), felt(1)]);
+
+        assert!(matches!(
+            result,
+            Err(ParseError::InvalidLayout {
+                layout: StringLayout::ByteArray,
+                reason: LayoutError::InvalidUtf8,
+                ..
+            })
+        ));
+    }
+
+    fn any_felt() -> impl Strategy<Value = FieldElement> {
+        prop_oneof![
+            any::<u64>().prop_map(FieldElement::from),
+            prop::array::uniform31(any::<u8>())
+                .prop_map(|bytes| FieldElement::from_byte_slice_be(&bytes).unwrap()),
+            Just(FieldElement::MAX),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn should_never_panic(mut felts in prop::collection::vec(any_felt(), 0..40), prefix in 0..3_u8) {
+            // Makes the first felt a plausible length, to exercise every layout.
+            if !felts.is_empty() {
+                let len = felts.len() as u64;
+                match prefix {
+                    0 => felts[0] = FieldElement::from(len - 1),
+                    1 => felts[0] = FieldElement::from(len.saturating_sub(3)),
+                    _ => {}
+                }
+            }
+
+            let _ = parse_cairo_string(felts);
+        }
+
+        #[test]
+        fn should_roundtrip_byte_array(s in ".{0,200}") {
+            prop_assert_eq!(parse_cairo_string(s.to_felts()).unwrap(), s);
+        }
+
+        #[test]
+        fn should_roundtrip_short_strings(parts in prop::collection::vec("[ -~]{1,31}", 1..8)) {
+            let mut felts = vec![FieldElement::from(parts.len() as u64)];
+            felts.extend(parts.iter().map(|p| cairo_short_string_to_felt(p).unwrap()));
+
+            prop_assert_eq!(parse_cairo_string(felts).unwrap(), parts.concat());
+        }
+    }
 }
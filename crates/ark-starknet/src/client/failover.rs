@@ -0,0 +1,850 @@
+//! Starknet client routing the requests across several RPC endpoints.
+//!
+//! Full nodes may be restarted (OOM, upgrade...) or fall behind the chain
+//! head while indexing. [`StarknetClientFailover`] holds a pool of endpoints,
+//! regularly checks their head with `block_number`, and sends each request
+//! to the first healthy endpoint, routing around the failed or lagging ones.
+use super::http::{StarknetClientHttp, StarknetClientHttpConfig};
+use super::retry::RetryPolicy;
+use super::{BatchResult, BlockHeader, StarknetClient, StarknetClientError, SubscriptionStream};
+use crate::EventResult;
+use async_trait::async_trait;
+use futures::future::join_all;
+use starknet::core::types::*;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Configuration of [`StarknetClientFailover`].
+#[derive(Debug, Clone)]
+pub struct FailoverConfig {
+    /// Maximum number of blocks an endpoint can be behind
+    /// the highest head before being considered as lagging.
+    pub max_block_lag: u64,
+    /// Minimum delay between two health checks of the endpoints.
+    pub health_check_interval: Duration,
+    /// If true, the block hash of the events returned by an endpoint
+    /// is checked against the other healthy endpoints, and an error
+    /// is returned if they disagree.
+    pub verify_block_hash: bool,
+    /// Requests configuration of each endpoint, used by
+    /// [`StarknetClientFailover::new_with_config`].
+    /// Retries are kept low, as a failing request is routed
+    /// to the next endpoint.
+    pub http: StarknetClientHttpConfig,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            max_block_lag: 5,
+            health_check_interval: Duration::from_secs(10),
+            verify_block_hash: false,
+            http: StarknetClientHttpConfig {
+                retry_policy: RetryPolicy {
+                    max_attempts: 2,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct EndpointState {
+    healthy: bool,
+    block_number: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Endpoint<C> {
+    url: String,
+    client: C,
+    state: Mutex<EndpointState>,
+}
+
+impl<C> Endpoint<C> {
+    fn is_healthy(&self) -> bool {
+        self.state
+            .lock()
+            .expect("Endpoint state lock poisoned")
+            .healthy
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        self.state
+            .lock()
+            .expect("Endpoint state lock poisoned")
+            .healthy = healthy;
+    }
+}
+
+/// Health of an endpoint, as seen during the last health check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointStatus {
+    pub url: String,
+    pub healthy: bool,
+    pub block_number: Option<u64>,
+}
+
+/// Starknet client over a pool of RPC endpoints.
+///
+/// Requests are sent to the last endpoint that succeeded if it's still
+/// healthy, then to the other healthy endpoints in the configured order.
+/// Unhealthy endpoints are only tried as a last resort.
+///
+/// Only transient errors (see [`StarknetClientError::is_retryable`]) are
+/// routed to the next endpoint. Contract errors are deterministic and
+/// returned as is.
+///
+/// Continuation tokens are specific to each node. The tokens returned by
+/// this client are prefixed by the index of the endpoint that issued them,
+/// and the next pages are only requested to this endpoint. If it fails,
+/// the error is returned, and the caller must restart from the first page.
+#[derive(Debug)]
+pub struct StarknetClientFailover<C = StarknetClientHttp> {
+    endpoints: Vec<Endpoint<C>>,
+    config: FailoverConfig,
+    preferred: AtomicUsize,
+    last_health_check: Mutex<Option<Instant>>,
+}
+
+impl StarknetClientFailover<StarknetClientHttp> {
+    /// Initializes a new client with an HTTP endpoint for each given url.
+    pub fn new_with_config(
+        rpc_urls: &[&str],
+        config: FailoverConfig,
+    ) -> Result<Self, StarknetClientError> {
+        let clients = rpc_urls
+            .iter()
+            .map(|url| {
+                Ok((
+                    url.to_string(),
+                    StarknetClientHttp::new_with_config(url, config.http.clone())?,
+                ))
+            })
+            .collect::<Result<Vec<_>, StarknetClientError>>()?;
+
+        Self::from_clients(clients, config)
+    }
+}
+
+impl<C> StarknetClientFailover<C>
+where
+    C: StarknetClient + Send + Sync,
+{
+    /// Initializes a new client from already built clients,
+    /// each one identified by its url.
+    pub fn from_clients(
+        clients: Vec<(String, C)>,
+        config: FailoverConfig,
+    ) -> Result<Self, StarknetClientError> {
+        if clients.is_empty() {
+            return Err(StarknetClientError::Other(
+                "At least one RPC endpoint is required".to_string(),
+            ));
+        }
+
+        let endpoints = clients
+            .into_iter()
+            .map(|(url, client)| Endpoint {
+                url,
+                client,
+                state: Mutex::new(EndpointState {
+                    healthy: true,
+                    block_number: None,
+                }),
+            })
+            .collect();
+
+        Ok(Self {
+            endpoints,
+            config,
+            preferred: AtomicUsize::new(0),
+            last_health_check: Mutex::new(None),
+        })
+    }
+
+    /// Returns the status of every endpoint.
+    pub fn endpoints_status(&self) -> Vec<EndpointStatus> {
+        self.endpoints
+            .iter()
+            .map(|e| {
+                let state = e.state.lock().expect("Endpoint state lock poisoned");
+                EndpointStatus {
+                    url: e.url.clone(),
+                    healthy: state.healthy,
+                    block_number: state.block_number,
+                }
+            })
+            .collect()
+    }
+
+    /// Requests the head of every endpoint, and marks as unhealthy
+    /// the ones failing or lagging behind the highest head.
+    pub async fn health_check(&self) {
+        let heads = join_all(self.endpoints.iter().map(|e| e.client.block_number())).await;
+        let max_head = heads.iter().filter_map(|h| h.as_ref().ok()).max().copied();
+
+        for (endpoint, head) in self.endpoints.iter().zip(heads) {
+            let mut state = endpoint.state.lock().expect("Endpoint state lock poisoned");
+
+            match head {
+                Ok(n) => {
+                    let lag = max_head.unwrap_or(n).saturating_sub(n);
+                    state.block_number = Some(n);
+                    state.healthy = lag <= self.config.max_block_lag;
+
+                    if !state.healthy {
+                        warn!("Endpoint {} is lagging by {} blocks", endpoint.url, lag);
+                    }
+                }
+                Err(e) => {
+                    state.healthy = false;
+                    warn!("Endpoint {} failed health check: {}", endpoint.url, e);
+                }
+            }
+        }
+
+        *self
+            .last_health_check
+            .lock()
+            .expect("Health check lock poisoned") = Some(Instant::now());
+    }
+
+    async fn health_check_if_due(&self) {
+        let due = self
+            .last_health_check
+            .lock()
+            .expect("Health check lock poisoned")
+            .map_or(true, |t| t.elapsed() >= self.config.health_check_interval);
+
+        if due {
+            self.health_check().await;
+        }
+    }
+
+    /// Endpoints indices in the order they must be tried.
+    fn routing_order(&self) -> Vec<usize> {
+        let n = self.endpoints.len();
+        let preferred = self.preferred.load(Ordering::Relaxed);
+
+        let (mut healthy, unhealthy): (Vec<usize>, Vec<usize>) = (0..n)
+            .map(|k| (preferred + k) % n)
+            .partition(|i| self.endpoints[*i].is_healthy());
+
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    /// Sends the request built by `f` to the endpoints until one succeeds,
+    /// returning the index of the endpoint with the result.
+    async fn route_indexed<'a, T, F, Fut>(&'a self, f: F) -> Result<(usize, T), StarknetClientError>
+    where
+        F: Fn(&'a C) -> Fut,
+        Fut: Future<Output = Result<T, StarknetClientError>>,
+    {
+        self.health_check_if_due().await;
+
+        let mut last_error = None;
+
+        for i in self.routing_order() {
+            let endpoint = &self.endpoints[i];
+
+            match f(&endpoint.client).await {
+                Ok(r) => {
+                    if !endpoint.is_healthy() {
+                        debug!("Endpoint {} is responding again", endpoint.url);
+                        endpoint.set_healthy(true);
+                    }
+                    self.preferred.store(i, Ordering::Relaxed);
+                    return Ok((i, r));
+                }
+                Err(e) if e.is_retryable() => {
+                    warn!(
+                        "Endpoint {} failed: {}. Routing to next endpoint",
+                        endpoint.url, e
+                    );
+                    endpoint.set_healthy(false);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| StarknetClientError::Other("No RPC endpoint available".to_string())))
+    }
+
+    /// Sends the request built by `f` to the endpoint at `index` only,
+    /// for requests that can't be answered by another endpoint.
+    async fn route_pinned<'a, T, F, Fut>(
+        &'a self,
+        index: usize,
+        f: F,
+    ) -> Result<T, StarknetClientError>
+    where
+        F: Fn(&'a C) -> Fut,
+        Fut: Future<Output = Result<T, StarknetClientError>>,
+    {
+        let endpoint = &self.endpoints[index];
+
+        f(&endpoint.client).await.map_err(|e| {
+            if e.is_retryable() {
+                warn!(
+                    "Endpoint {} failed while paging: {}. Paging must be restarted",
+                    endpoint.url, e
+                );
+                endpoint.set_healthy(false);
+            }
+            e
+        })
+    }
+
+    /// Sends a paged request, routed like any other request for the
+    /// first page, and pinned to the endpoint that issued the
+    /// continuation token for the next pages.
+    /// Returns the index of the endpoint with the result.
+    async fn route_page<'a, T, F, Fut>(
+        &'a self,
+        continuation_token: Option<String>,
+        f: F,
+    ) -> Result<(usize, T), StarknetClientError>
+    where
+        F: Fn(&'a C, Option<String>) -> Fut,
+        Fut: Future<Output = Result<T, StarknetClientError>>,
+    {
+        match continuation_token {
+            None => self.route_indexed(|c| f(c, None)).await,
+            Some(token) => {
+                let (index, token) = self.unpin_token(&token)?;
+                let r = self
+                    .route_pinned(index, |c| f(c, Some(token.clone())))
+                    .await?;
+                Ok((index, r))
+            }
+        }
+    }
+
+    /// Prefixes the continuation token issued by the endpoint at `index`.
+    fn pin_token(index: usize, token: Option<String>) -> Option<String> {
+        token.map(|t| format!("{}:{}", index, t))
+    }
+
+    /// Splits a continuation token returned by [`Self::pin_token`].
+    fn unpin_token(&self, token: &str) -> Result<(usize, String), StarknetClientError> {
+        token
+            .split_once(':')
+            .and_then(|(index, t)| Some((index.parse::<usize>().ok()?, t.to_string())))
+            .filter(|(index, _)| *index < self.endpoints.len())
+            .ok_or_else(|| {
+                StarknetClientError::Other(format!("Invalid continuation token: {}", token))
+            })
+    }
+
+    async fn route<'a, T, F, Fut>(&'a self, f: F) -> Result<T, StarknetClientError>
+    where
+        F: Fn(&'a C) -> Fut,
+        Fut: Future<Output = Result<T, StarknetClientError>>,
+    {
+        self.route_indexed(f).await.map(|(_, r)| r)
+    }
+
+    /// Checks that the other healthy endpoints agree on the hash of the
+    /// blocks of the given events, returned by the endpoint at `source`.
+    ///
+    /// Endpoints that can't return the block hash (not synced up to
+    /// the block yet for instance) are ignored.
+    async fn verify_block_hashes<'e>(
+        &self,
+        source: usize,
+        events: impl Iterator<Item = &'e EmittedEvent>,
+    ) -> Result<(), StarknetClientError> {
+        if !self.config.verify_block_hash {
+            return Ok(());
+        }
+
+        let mut blocks: Vec<(u64, FieldElement)> = events
+            .filter_map(|e| Some((e.block_number?, e.block_hash?)))
+            .collect();
+        blocks.sort_unstable_by_key(|(n, _)| *n);
+        blocks.dedup();
+
+        for (block_number, block_hash) in blocks {
+            for (i, endpoint) in self.endpoints.iter().enumerate() {
+                if i == source || !endpoint.is_healthy() {
+                    continue;
+                }
+
+                match endpoint
+                    .client
+                    .block_hash(BlockId::Number(block_number))
+                    .await
+                {
+                    Ok(h) if h != block_hash => {
+                        return Err(StarknetClientError::Other(format!(
+                            "Endpoints {} and {} disagree on block {} hash: {:#x} != {:#x}",
+                            self.endpoints[source].url, endpoint.url, block_number, block_hash, h
+                        )));
+                    }
+                    Ok(_) => {}
+                    Err(e) => debug!(
+                        "Can't verify block {} hash on {}: {}",
+                        block_number, endpoint.url, e
+                    ),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<C> StarknetClient for StarknetClientFailover<C>
+where
+    C: StarknetClient + Send + Sync,
+{
+    /// Initializes a new client from a comma separated list of urls,
+    /// with the default failover configuration.
+    fn new(rpc_url: &str) -> Result<Self, StarknetClientError> {
+        let clients = rpc_url
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(|url| Ok((url.to_string(), C::new(url)?)))
+            .collect::<Result<Vec<_>, StarknetClientError>>()?;
+
+        Self::from_clients(clients, FailoverConfig::default())
+    }
+
+    async fn events_from_tx_receipt(
+        &self,
+        transaction_hash: FieldElement,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<Vec<EmittedEvent>, StarknetClientError> {
+        self.route(|c| c.events_from_tx_receipt(transaction_hash, keys.clone()))
+            .await
+    }
+
+    async fn block_txs_hashes(
+        &self,
+        block: BlockId,
+    ) -> Result<(u64, Vec<FieldElement>), StarknetClientError> {
+        self.route(|c| c.block_txs_hashes(block)).await
+    }
+
+    async fn block_id_to_u64(&self, id: &BlockId) -> Result<u64, StarknetClientError> {
+        self.route(|c| c.block_id_to_u64(id)).await
+    }
+
+    fn parse_block_range(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<(BlockId, BlockId), StarknetClientError> {
+        self.endpoints[0].client.parse_block_range(from, to)
+    }
+
+    fn parse_block_id(&self, id: &str) -> Result<BlockId, StarknetClientError> {
+        self.endpoints[0].client.parse_block_id(id)
+    }
+
+    async fn block_time(&self, block: BlockId) -> Result<u64, StarknetClientError> {
+        self.route(|c| c.block_time(block)).await
+    }
+
+    async fn block_times(&self, blocks: &[BlockId]) -> BatchResult<u64> {
+        self.route(|c| c.block_times(blocks)).await
+    }
+
+    async fn block_number(&self) -> Result<u64, StarknetClientError> {
+        self.route(|c| c.block_number()).await
+    }
+
+    async fn block_headers(&self, blocks: &[BlockId]) -> BatchResult<BlockHeader> {
+        self.route(|c| c.block_headers(blocks)).await
+    }
+
+    async fn block_hash(&self, block: BlockId) -> Result<FieldElement, StarknetClientError> {
+        self.route(|c| c.block_hash(block)).await
+    }
+
+    async fn class_hash_at(
+        &self,
+        contract_address: FieldElement,
+        block: BlockId,
+    ) -> Result<FieldElement, StarknetClientError> {
+        self.route(|c| c.class_hash_at(contract_address, block))
+            .await
+    }
+
+    async fn fetch_events_page(
+        &self,
+        filter: EventFilter,
+        continuation_token: Option<String>,
+        chunk_size: u64,
+    ) -> Result<EventsPage, StarknetClientError> {
+        let (source, mut page) = self
+            .route_page(continuation_token, |c, token| {
+                c.fetch_events_page(filter.clone(), token, chunk_size)
+            })
+            .await?;
+
+        self.verify_block_hashes(source, page.events.iter()).await?;
+
+        page.continuation_token = Self::pin_token(source, page.continuation_token);
+        Ok(page)
+    }
+
+    async fn fetch_events(
+        &self,
+        from_block: Option<BlockId>,
+        to_block: Option<BlockId>,
+        keys: Option<Vec<Vec<FieldElement>>>,
+        contract_address: Option<FieldElement>,
+        continuation_token: Option<String>,
+    ) -> Result<EventResult, StarknetClientError> {
+        let (source, mut result) = self
+            .route_page(continuation_token, |c, token| {
+                c.fetch_events(from_block, to_block, keys.clone(), contract_address, token)
+            })
+            .await?;
+
+        self.verify_block_hashes(source, result.events.values().flatten())
+            .await?;
+
+        result.continuation_token = Self::pin_token(source, result.continuation_token);
+        Ok(result)
+    }
+
+    /// All the pages are fetched from the same endpoint,
+    /// as continuation tokens are specific to each node.
+    async fn fetch_all_block_events(
+        &self,
+        block_id: BlockId,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<HashMap<u64, Vec<EmittedEvent>>, StarknetClientError> {
+        let (source, events) = self
+            .route_indexed(|c| c.fetch_all_block_events(block_id, keys.clone()))
+            .await?;
+
+        self.verify_block_hashes(source, events.values().flatten())
+            .await?;
+
+        Ok(events)
+    }
+
+    async fn fetch_all_block_events_for_pending_block(
+        &self,
+        timestamp: u64,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<HashMap<u64, Vec<EmittedEvent>>, StarknetClientError> {
+        self.route(|c| c.fetch_all_block_events_for_pending_block(timestamp, keys.clone()))
+            .await
+    }
+
+    async fn call_contract(
+        &self,
+        contract_address: FieldElement,
+        selector: FieldElement,
+        calldata: Vec<FieldElement>,
+        block: BlockId,
+    ) -> Result<Vec<FieldElement>, StarknetClientError> {
+        self.route(|c| c.call_contract(contract_address, selector, calldata.clone(), block))
+            .await
+    }
+
+    async fn call_contracts(
+        &self,
+        calls: Vec<FunctionCall>,
+        block: BlockId,
+    ) -> BatchResult<Vec<FieldElement>> {
+        self.route(|c| c.call_contracts(calls.clone(), block)).await
+    }
+
+    async fn subscribe_new_heads(
+        &self,
+    ) -> Result<SubscriptionStream<BlockHeader>, StarknetClientError> {
+        self.route(|c| c.subscribe_new_heads()).await
+    }
+
+    async fn subscribe_events(
+        &self,
+        from_address: Option<FieldElement>,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<SubscriptionStream<EmittedEvent>, StarknetClientError> {
+        self.route(|c| c.subscribe_events(from_address, keys.clone()))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::MockStarknetClient;
+    use starknet::providers::ProviderError;
+
+    fn config() -> FailoverConfig {
+        FailoverConfig {
+            max_block_lag: 5,
+            health_check_interval: Duration::from_secs(3600),
+            verify_block_hash: false,
+            http: StarknetClientHttpConfig::default(),
+        }
+    }
+
+    fn node(head: Result<u64, ()>) -> MockStarknetClient {
+        let mut client = MockStarknetClient::default();
+        client.expect_block_number().returning(move || {
+            head.map_err(|_| StarknetClientError::Provider(ProviderError::RateLimited))
+        });
+        client
+    }
+
+    fn failover(
+        nodes: Vec<MockStarknetClient>,
+        config: FailoverConfig,
+    ) -> StarknetClientFailover<MockStarknetClient> {
+        let clients = nodes
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| (format!("node{}", i), c))
+            .collect();
+
+        StarknetClientFailover::from_clients(clients, config).unwrap()
+    }
+
+    fn event(block_number: u64, block_hash: u64) -> EmittedEvent {
+        EmittedEvent {
+            from_address: FieldElement::ONE,
+            keys: vec![],
+            data: vec![],
+            block_hash: Some(FieldElement::from(block_hash)),
+            block_number: Some(block_number),
+            transaction_hash: FieldElement::TWO,
+        }
+    }
+
+    fn filter() -> EventFilter {
+        EventFilter {
+            from_block: Some(BlockId::Number(1)),
+            to_block: Some(BlockId::Number(1)),
+            address: None,
+            keys: None,
+        }
+    }
+
+    #[test]
+    fn test_new_splits_urls() {
+        let client = StarknetClientFailover::<StarknetClientHttp>::new(
+            "http://127.0.0.1:5050, http://127.0.0.1:6060,",
+        )
+        .unwrap();
+
+        let urls: Vec<String> = client
+            .endpoints_status()
+            .into_iter()
+            .map(|s| s.url)
+            .collect();
+
+        assert_eq!(urls, vec!["http://127.0.0.1:5050", "http://127.0.0.1:6060"]);
+    }
+
+    #[test]
+    fn test_new_requires_an_url() {
+        assert!(StarknetClientFailover::<StarknetClientHttp>::new(" , ").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_routes_around_failed_endpoint() {
+        let mut down = node(Err(()));
+        down.expect_block_time().times(0);
+
+        let mut up = node(Ok(100));
+        up.expect_block_time().times(1).returning(|_| Ok(1234));
+
+        let client = failover(vec![down, up], config());
+
+        assert_eq!(client.block_time(BlockId::Number(1)).await.unwrap(), 1234);
+
+        let status = client.endpoints_status();
+        assert!(!status[0].healthy);
+        assert!(status[1].healthy);
+    }
+
+    #[tokio::test]
+    async fn test_routes_around_lagging_endpoint() {
+        let mut lagging = node(Ok(90));
+        lagging.expect_block_time().times(0);
+
+        let mut synced = node(Ok(100));
+        synced.expect_block_time().times(1).returning(|_| Ok(1234));
+
+        let client = failover(vec![lagging, synced], config());
+
+        assert_eq!(client.block_time(BlockId::Number(1)).await.unwrap(), 1234);
+        assert_eq!(client.endpoints_status()[0].block_number, Some(90));
+    }
+
+    #[tokio::test]
+    async fn test_transient_error_is_routed_to_next_endpoint() {
+        let mut first = node(Ok(100));
+        first
+            .expect_block_time()
+            .times(1)
+            .returning(|_| Err(StarknetClientError::Provider(ProviderError::RateLimited)));
+
+        let mut second = node(Ok(100));
+        second.expect_block_time().times(1).returning(|_| Ok(1234));
+
+        let client = failover(vec![first, second], config());
+
+        assert_eq!(client.block_time(BlockId::Number(1)).await.unwrap(), 1234);
+        assert!(!client.endpoints_status()[0].healthy);
+    }
+
+    #[tokio::test]
+    async fn test_contract_error_is_not_routed() {
+        let mut first = node(Ok(100));
+        first
+            .expect_call_contract()
+            .times(1)
+            .returning(|_, _, _, _| Err(StarknetClientError::InputTooShort));
+
+        let mut second = node(Ok(100));
+        second.expect_call_contract().times(0);
+
+        let client = failover(vec![first, second], config());
+
+        let r = client
+            .call_contract(
+                FieldElement::ONE,
+                FieldElement::TWO,
+                vec![],
+                BlockId::Tag(BlockTag::Latest),
+            )
+            .await;
+
+        assert!(matches!(r, Err(StarknetClientError::InputTooShort)));
+    }
+
+    #[tokio::test]
+    async fn test_block_hash_disagreement() {
+        let mut first = node(Ok(100));
+        first.expect_fetch_events_page().returning(|_, _, _| {
+            Ok(EventsPage {
+                events: vec![event(1, 0xaa)],
+                continuation_token: None,
+            })
+        });
+
+        let mut second = node(Ok(100));
+        second
+            .expect_block_hash()
+            .returning(|_| Ok(FieldElement::from(0xbb_u64)));
+
+        let client = failover(
+            vec![first, second],
+            FailoverConfig {
+                verify_block_hash: true,
+                ..config()
+            },
+        );
+
+        let r = client.fetch_events_page(filter(), None, 10).await;
+        assert!(matches!(r, Err(StarknetClientError::Other(_))));
+    }
+
+    #[tokio::test]
+    async fn test_block_hash_agreement() {
+        let mut first = node(Ok(100));
+        first.expect_fetch_events_page().returning(|_, _, _| {
+            Ok(EventsPage {
+                events: vec![event(1, 0xaa), event(1, 0xaa)],
+                continuation_token: None,
+            })
+        });
+
+        let mut second = node(Ok(100));
+        second
+            .expect_block_hash()
+            .times(1)
+            .returning(|_| Ok(FieldElement::from(0xaa_u64)));
+
+        let client = failover(
+            vec![first, second],
+            FailoverConfig {
+                verify_block_hash: true,
+                ..config()
+            },
+        );
+
+        let page = client.fetch_events_page(filter(), None, 10).await.unwrap();
+        assert_eq!(page.events.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_next_pages_are_pinned_to_endpoint() {
+        let mut first = node(Ok(100));
+        first
+            .expect_fetch_events_page()
+            .times(1)
+            .returning(|_, _, _| Err(StarknetClientError::Provider(ProviderError::RateLimited)));
+
+        let mut second = node(Ok(100));
+        second
+            .expect_fetch_events_page()
+            .times(2)
+            .returning(|_, token, _| {
+                Ok(EventsPage {
+                    events: vec![event(1, 0xaa)],
+                    continuation_token: match token.as_deref() {
+                        None => Some("10".to_string()),
+                        Some("10") => None,
+                        Some(t) => panic!("Unexpected token {}", t),
+                    },
+                })
+            });
+
+        let client = failover(vec![first, second], config());
+
+        let page = client.fetch_events_page(filter(), None, 10).await.unwrap();
+        assert_eq!(page.continuation_token, Some("1:10".to_string()));
+
+        let page = client
+            .fetch_events_page(filter(), page.continuation_token, 10)
+            .await
+            .unwrap();
+        assert_eq!(page.continuation_token, None);
+    }
+
+    #[tokio::test]
+    async fn test_pinned_endpoint_failure_is_not_routed() {
+        let mut first = node(Ok(100));
+        first
+            .expect_fetch_events_page()
+            .times(1)
+            .returning(|_, _, _| Err(StarknetClientError::Provider(ProviderError::RateLimited)));
+
+        let mut second = node(Ok(100));
+        second.expect_fetch_events_page().times(0);
+
+        let client = failover(vec![first, second], config());
+
+        let r = client
+            .fetch_events_page(filter(), Some("0:10".to_string()), 10)
+            .await;
+        assert!(matches!(r, Err(StarknetClientError::Provider(_))));
+        assert!(!client.endpoints_status()[0].healthy);
+
+        let r = client
+            .fetch_events_page(filter(), Some("10".to_string()), 10)
+            .await;
+        assert!(matches!(r, Err(StarknetClientError::Other(_))));
+    }
+}
@@ -1,13 +1,27 @@
 //! Starknet Client implementation using `JsonRpcHttp` provider.
-use super::{StarknetClient, StarknetClientError};
+use super::retry::{
+    is_retryable_http_error, is_retryable_provider_error, with_retry, RateLimit, RateLimiter,
+    RetryPolicy,
+};
+use super::stream::{collect_events_by_block, events_pages, DEFAULT_CHUNK_SIZE};
+#[cfg(feature = "ws")]
+use super::ws::StarknetWsClient;
+use super::{BatchResult, BlockHeader, StarknetClient, StarknetClientError, SubscriptionStream};
 use crate::EventResult;
 use async_trait::async_trait;
+use futures::TryStreamExt;
 use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
 use starknet::{
     core::types::*,
-    providers::{jsonrpc::HttpTransport, AnyProvider, JsonRpcClient, Provider, ProviderError},
+    providers::{
+        jsonrpc::{HttpTransport, HttpTransportError, JsonRpcClientError},
+        AnyProvider, JsonRpcClient, Provider, ProviderError,
+    },
 };
 use std::collections::HashMap;
+use std::future::Future;
 use url::Url;
 
 const INPUT_TOO_SHORT: &str = "0x496e70757420746f6f2073686f727420666f7220617267756d656e7473";
@@ -15,23 +29,322 @@ const INPUT_TOO_LONG: &str = "0x496e70757420746f6f206c6f6e6720666f7220617267756d
 const FAILED_DESERIALIZE: &str = "0x4661696c656420746f20646573657269616c697a6520706172616d202331";
 const ENTRYPOINT_NOT_FOUND: &str = "not found in contract";
 
+/// JSON-RPC error code returned when a contract call reverts.
+const CONTRACT_ERROR_CODE: i64 = 40;
+
+/// Default maximum number of requests sent in one JSON-RPC batch.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+
+/// Configuration of the requests sent by [`StarknetClientHttp`].
+#[derive(Debug, Clone)]
+pub struct StarknetClientHttpConfig {
+    /// Policy used to retry the requests failing with a transient error.
+    pub retry_policy: RetryPolicy,
+    /// Maximum rate of requests, unlimited if `None`.
+    /// A JSON-RPC batch counts as one request.
+    pub rate_limit: Option<RateLimit>,
+    /// Maximum number of requests sent in one JSON-RPC batch,
+    /// larger batches are split.
+    pub max_batch_size: usize,
+    /// WebSocket endpoint used for the subscriptions, which are
+    /// unsupported if `None`. Requires the `ws` feature.
+    pub ws_url: Option<String>,
+}
+
+impl Default for StarknetClientHttpConfig {
+    fn default() -> Self {
+        Self {
+            retry_policy: RetryPolicy::default(),
+            rate_limit: None,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            ws_url: None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct StarknetClientHttp {
     /// Provider is kept public to allow custom reuse of
     /// the raw provider elsewhere.
     pub provider: AnyProvider,
+    rpc_url: Url,
+    http: reqwest::Client,
+    retry_policy: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    max_batch_size: usize,
+    #[cfg(feature = "ws")]
+    ws: Option<StarknetWsClient>,
 }
 
-#[async_trait]
-impl StarknetClient for StarknetClientHttp {
-    fn new(rpc_url: &str) -> Result<StarknetClientHttp, StarknetClientError> {
+#[derive(Debug, Deserialize)]
+struct JsonRpcResponse {
+    id: u64,
+    result: Option<Value>,
+    error: Option<JsonRpcError>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct JsonRpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl JsonRpcError {
+    /// Maps the error of a `starknet_call` request, in the same
+    /// way as [`StarknetClientHttp::call_contract`] does.
+    /// Only the errors of the contract itself are contract errors: the
+    /// others (missing response, rate limit, node internal error...)
+    /// tell nothing about the contract.
+    fn into_call_error(self) -> StarknetClientError {
+        if self.code != CONTRACT_ERROR_CODE {
+            return self.into_other_error();
+        }
+
+        match self
+            .data
+            .as_ref()
+            .and_then(|d| d.get("revert_error"))
+            .and_then(Value::as_str)
+        {
+            Some(revert_error) => contract_error(revert_error.to_string()),
+            None => StarknetClientError::Contract(self.message),
+        }
+    }
+
+    fn into_other_error(self) -> StarknetClientError {
+        StarknetClientError::Other(format!("JSON-RPC error {}: {}", self.code, self.message))
+    }
+}
+
+/// Maps the revert error of a contract call to the matching client error.
+fn contract_error(revert_error: String) -> StarknetClientError {
+    if revert_error.contains(ENTRYPOINT_NOT_FOUND) {
+        StarknetClientError::EntrypointNotFound(revert_error)
+    } else if revert_error.contains(INPUT_TOO_SHORT) || revert_error.contains(FAILED_DESERIALIZE) {
+        StarknetClientError::InputTooShort
+    } else if revert_error.contains(INPUT_TOO_LONG) {
+        StarknetClientError::InputTooLong
+    } else {
+        StarknetClientError::Contract(revert_error)
+    }
+}
+
+/// Parses a block range, see [`parse_block_id`].
+pub fn parse_block_range(from: &str, to: &str) -> Result<(BlockId, BlockId), StarknetClientError> {
+    let from_block = parse_block_id(from)?;
+    let to_block = parse_block_id(to)?;
+
+    Ok((from_block, to_block))
+}
+
+/// Parses a block id from `latest`, `pending`, a block number
+/// or an hexadecimal block hash.
+pub fn parse_block_id(id: &str) -> Result<BlockId, StarknetClientError> {
+    let regex_block_number = Regex::new("^[0-9]{1,}$").unwrap();
+
+    if id == "latest" {
+        Ok(BlockId::Tag(BlockTag::Latest))
+    } else if id == "pending" {
+        Ok(BlockId::Tag(BlockTag::Pending))
+    } else if regex_block_number.is_match(id) {
+        Ok(BlockId::Number(id.parse::<u64>().map_err(|_| {
+            StarknetClientError::Conversion("Can't convert block id to u64".to_string())
+        })?))
+    } else {
+        Ok(BlockId::Hash(FieldElement::from_hex_be(id).map_err(
+            |_| {
+                StarknetClientError::Conversion(
+                    "Can't convert block hash from given hexadecimal string".to_string(),
+                )
+            },
+        )?))
+    }
+}
+
+/// JSON-RPC representation of a block id.
+fn block_id_to_json(block: &BlockId) -> Value {
+    match block {
+        BlockId::Hash(h) => json!({ "block_hash": format!("{:#x}", h) }),
+        BlockId::Number(n) => json!({ "block_number": n }),
+        BlockId::Tag(BlockTag::Latest) => json!("latest"),
+        BlockId::Tag(BlockTag::Pending) => json!("pending"),
+    }
+}
+
+/// JSON-RPC representation of a function call.
+fn function_call_to_json(call: &FunctionCall) -> Value {
+    json!({
+        "contract_address": format!("{:#x}", call.contract_address),
+        "entry_point_selector": format!("{:#x}", call.entry_point_selector),
+        "calldata": call
+            .calldata
+            .iter()
+            .map(|f| format!("{:#x}", f))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Parses the result of a `starknet_call` request.
+fn parse_felts(value: Value) -> Result<Vec<FieldElement>, StarknetClientError> {
+    let felts: Vec<String> = serde_json::from_value(value)
+        .map_err(|e| StarknetClientError::Conversion(format!("Invalid call result: {}", e)))?;
+
+    felts
+        .iter()
+        .map(|f| {
+            FieldElement::from_hex_be(f).map_err(|_| {
+                StarknetClientError::Conversion(format!("Invalid felt in call result: {}", f))
+            })
+        })
+        .collect()
+}
+
+/// Orders the responses of a batch of `count` requests by id,
+/// as the JSON-RPC specification allows any order.
+fn order_responses(
+    responses: Vec<JsonRpcResponse>,
+    count: usize,
+) -> Vec<Result<Value, JsonRpcError>> {
+    let mut responses: HashMap<u64, JsonRpcResponse> =
+        responses.into_iter().map(|r| (r.id, r)).collect();
+
+    (0..count as u64)
+        .map(|id| match responses.remove(&id) {
+            Some(JsonRpcResponse {
+                result: Some(result),
+                ..
+            }) => Ok(result),
+            Some(JsonRpcResponse {
+                error: Some(error), ..
+            }) => Err(error),
+            _ => Err(JsonRpcError {
+                code: -32603,
+                message: format!("Missing response for request {} of the batch", id),
+                data: None,
+            }),
+        })
+        .collect()
+}
+
+impl StarknetClientHttp {
+    /// Initializes a new client with the given requests configuration.
+    pub fn new_with_config(
+        rpc_url: &str,
+        config: StarknetClientHttpConfig,
+    ) -> Result<Self, StarknetClientError> {
         let rpc_url = Url::parse(rpc_url).map_err(|_| {
             StarknetClientError::Other("Can't parse RPC url to create the provider".to_string())
         })?;
 
-        let provider = AnyProvider::JsonRpcHttp(JsonRpcClient::new(HttpTransport::new(rpc_url)));
+        let provider =
+            AnyProvider::JsonRpcHttp(JsonRpcClient::new(HttpTransport::new(rpc_url.clone())));
+
+        Ok(Self {
+            provider,
+            rpc_url,
+            http: reqwest::Client::new(),
+            retry_policy: config.retry_policy,
+            rate_limiter: config.rate_limit.map(RateLimiter::new),
+            max_batch_size: config.max_batch_size.max(1),
+            #[cfg(feature = "ws")]
+            ws: config.ws_url.as_deref().map(StarknetWsClient::new),
+        })
+    }
 
-        Ok(Self { provider })
+    #[cfg(feature = "ws")]
+    fn ws_client(&self) -> Result<&StarknetWsClient, StarknetClientError> {
+        self.ws.as_ref().ok_or_else(|| {
+            StarknetClientError::Unsupported("subscriptions require a WebSocket url".to_string())
+        })
+    }
+
+    /// Sends a request to the provider, applying the rate limit
+    /// and retrying on transient errors.
+    async fn request<T, F, Fut>(&self, f: F) -> Result<T, ProviderError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, ProviderError>>,
+    {
+        with_retry(
+            &self.retry_policy,
+            self.rate_limiter.as_ref(),
+            is_retryable_provider_error,
+            f,
+        )
+        .await
+    }
+
+    /// Sends the given `(method, params)` requests in JSON-RPC batches,
+    /// returning the result of each request in the same order.
+    async fn batch_request(
+        &self,
+        requests: Vec<(&str, Value)>,
+    ) -> Result<Vec<Result<Value, JsonRpcError>>, StarknetClientError> {
+        let mut results = Vec::with_capacity(requests.len());
+
+        for chunk in requests.chunks(self.max_batch_size) {
+            let body: Vec<Value> = chunk
+                .iter()
+                .enumerate()
+                .map(|(id, (method, params))| {
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "method": method,
+                        "params": params,
+                    })
+                })
+                .collect();
+
+            let responses = with_retry(
+                &self.retry_policy,
+                self.rate_limiter.as_ref(),
+                is_retryable_http_error,
+                || self.send_batch(&body),
+            )
+            .await
+            .map_err(batch_error)?;
+
+            results.extend(order_responses(responses, chunk.len()));
+        }
+
+        Ok(results)
+    }
+
+    async fn send_batch(&self, body: &[Value]) -> Result<Vec<JsonRpcResponse>, reqwest::Error> {
+        self.http
+            .post(self.rpc_url.clone())
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+/// Converts the error of a failed batch request. Transient failures
+/// are mapped to the provider errors returned by the JSON-RPC transport,
+/// to be retried by the callers like any other request.
+fn batch_error(e: reqwest::Error) -> StarknetClientError {
+    if e.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS) {
+        StarknetClientError::Provider(ProviderError::RateLimited)
+    } else if is_retryable_http_error(&e) {
+        StarknetClientError::Provider(ProviderError::Other(Box::new(
+            JsonRpcClientError::TransportError(HttpTransportError::Reqwest(e)),
+        )))
+    } else {
+        StarknetClientError::Other(format!("JSON-RPC batch request failed: {}", e))
+    }
+}
+
+#[async_trait]
+impl StarknetClient for StarknetClientHttp {
+    /// Initializes a new client with the default retry policy
+    /// and without rate limit.
+    fn new(rpc_url: &str) -> Result<StarknetClientHttp, StarknetClientError> {
+        Self::new_with_config(rpc_url, StarknetClientHttpConfig::default())
     }
 
     /// Transaction receipts don't have `EmittedEvent` but `Event` instead.
@@ -43,8 +356,7 @@ impl StarknetClient for StarknetClientHttp {
         keys: Option<Vec<Vec<FieldElement>>>,
     ) -> Result<Vec<EmittedEvent>, StarknetClientError> {
         let receipt = self
-            .provider
-            .get_transaction_receipt(transaction_hash)
+            .request(|| self.provider.get_transaction_receipt(transaction_hash))
             .await
             .map_err(StarknetClientError::Provider)?;
 
@@ -115,8 +427,7 @@ impl StarknetClient for StarknetClientHttp {
     async fn block_id_to_u64(&self, id: &BlockId) -> Result<u64, StarknetClientError> {
         match id {
             BlockId::Tag(BlockTag::Latest) => Ok(self
-                .provider
-                .block_number()
+                .request(|| self.provider.block_number())
                 .await
                 .map_err(StarknetClientError::Provider)?),
             BlockId::Number(n) => Ok(*n),
@@ -131,38 +442,16 @@ impl StarknetClient for StarknetClientHttp {
         from: &str,
         to: &str,
     ) -> Result<(BlockId, BlockId), StarknetClientError> {
-        let from_block = self.parse_block_id(from)?;
-        let to_block = self.parse_block_id(to)?;
-
-        Ok((from_block, to_block))
+        parse_block_range(from, to)
     }
 
     fn parse_block_id(&self, id: &str) -> Result<BlockId, StarknetClientError> {
-        let regex_block_number = Regex::new("^[0-9]{1,}$").unwrap();
-
-        if id == "latest" {
-            Ok(BlockId::Tag(BlockTag::Latest))
-        } else if id == "pending" {
-            Ok(BlockId::Tag(BlockTag::Pending))
-        } else if regex_block_number.is_match(id) {
-            Ok(BlockId::Number(id.parse::<u64>().map_err(|_| {
-                StarknetClientError::Conversion("Can't convert block id to u64".to_string())
-            })?))
-        } else {
-            Ok(BlockId::Hash(FieldElement::from_hex_be(id).map_err(
-                |_| {
-                    StarknetClientError::Conversion(
-                        "Can't convert block hash from given hexadecimal string".to_string(),
-                    )
-                },
-            )?))
-        }
+        parse_block_id(id)
     }
 
     async fn block_time(&self, block: BlockId) -> Result<u64, StarknetClientError> {
         let block = self
-            .provider
-            .get_block_with_tx_hashes(block)
+            .request(|| self.provider.get_block_with_tx_hashes(block))
             .await
             .map_err(StarknetClientError::Provider)?;
 
@@ -180,8 +469,7 @@ impl StarknetClient for StarknetClientHttp {
         block: BlockId,
     ) -> Result<(u64, Vec<FieldElement>), StarknetClientError> {
         let block = self
-            .provider
-            .get_block_with_tx_hashes(block)
+            .request(|| self.provider.get_block_with_tx_hashes(block))
             .await
             .map_err(StarknetClientError::Provider)?;
 
@@ -195,14 +483,102 @@ impl StarknetClient for StarknetClientHttp {
         Ok(timestamp)
     }
 
+    async fn block_hash(&self, block: BlockId) -> Result<FieldElement, StarknetClientError> {
+        let block = self
+            .request(|| self.provider.get_block_with_tx_hashes(block))
+            .await
+            .map_err(StarknetClientError::Provider)?;
+
+        match block {
+            MaybePendingBlockWithTxHashes::Block(block) => Ok(block.block_hash),
+            MaybePendingBlockWithTxHashes::PendingBlock(_) => Err(StarknetClientError::Other(
+                "Pending block has no hash".to_string(),
+            )),
+        }
+    }
+
+    async fn class_hash_at(
+        &self,
+        contract_address: FieldElement,
+        block: BlockId,
+    ) -> Result<FieldElement, StarknetClientError> {
+        self.request(|| self.provider.get_class_hash_at(block, contract_address))
+            .await
+            .map_err(StarknetClientError::Provider)
+    }
+
+    async fn block_times(&self, blocks: &[BlockId]) -> BatchResult<u64> {
+        let requests = blocks
+            .iter()
+            .map(|b| {
+                (
+                    "starknet_getBlockWithTxHashes",
+                    json!({ "block_id": block_id_to_json(b) }),
+                )
+            })
+            .collect();
+
+        Ok(self
+            .batch_request(requests)
+            .await?
+            .into_iter()
+            .map(|r| match r {
+                Ok(block) => block
+                    .get("timestamp")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| {
+                        StarknetClientError::Conversion("Block timestamp is missing".to_string())
+                    }),
+                Err(e) => Err(e.into_other_error()),
+            })
+            .collect())
+    }
+
+    async fn block_headers(&self, blocks: &[BlockId]) -> BatchResult<BlockHeader> {
+        let requests = blocks
+            .iter()
+            .map(|b| {
+                (
+                    "starknet_getBlockWithTxHashes",
+                    json!({ "block_id": block_id_to_json(b) }),
+                )
+            })
+            .collect();
+
+        Ok(self
+            .batch_request(requests)
+            .await?
+            .into_iter()
+            .map(|r| match r {
+                Ok(block) => serde_json::from_value(block).map_err(|e| {
+                    StarknetClientError::Conversion(format!("Invalid block header: {}", e))
+                }),
+                Err(e) => Err(e.into_other_error()),
+            })
+            .collect())
+    }
+
     async fn block_number(&self) -> Result<u64, StarknetClientError> {
         Ok(self
-            .provider
-            .block_number()
+            .request(|| self.provider.block_number())
             .await
             .map_err(StarknetClientError::Provider)?)
     }
 
+    async fn fetch_events_page(
+        &self,
+        filter: EventFilter,
+        continuation_token: Option<String>,
+        chunk_size: u64,
+    ) -> Result<EventsPage, StarknetClientError> {
+        self.request(|| {
+            self.provider
+                .get_events(filter.clone(), continuation_token.clone(), chunk_size)
+        })
+        .await
+        .map_err(StarknetClientError::Provider)
+    }
+
     async fn fetch_events(
         &self,
         from_block: Option<BlockId>,
@@ -220,20 +596,13 @@ impl StarknetClient for StarknetClientHttp {
             keys,
         };
 
-        let chunk_size = 1000;
-
         let event_page = self
-            .provider
-            .get_events(filter.clone(), continuation_token, chunk_size)
-            .await
-            .map_err(StarknetClientError::Provider)?;
+            .fetch_events_page(filter, continuation_token, DEFAULT_CHUNK_SIZE)
+            .await?;
 
-        event_page.events.iter().for_each(|e| {
+        event_page.events.into_iter().for_each(|e| {
             if let Some(block_number) = e.block_number {
-                events
-                    .entry(block_number)
-                    .and_modify(|v| v.push(e.clone()))
-                    .or_insert(vec![e.clone()]);
+                events.entry(block_number).or_default().push(e);
             }
         });
 
@@ -248,8 +617,6 @@ impl StarknetClient for StarknetClientHttp {
         block_id: BlockId,
         keys: Option<Vec<Vec<FieldElement>>>,
     ) -> Result<HashMap<u64, Vec<EmittedEvent>>, StarknetClientError> {
-        let mut events: HashMap<u64, Vec<EmittedEvent>> = HashMap::new();
-
         let filter = EventFilter {
             from_block: Some(block_id),
             to_block: Some(block_id),
@@ -257,33 +624,7 @@ impl StarknetClient for StarknetClientHttp {
             keys,
         };
 
-        let chunk_size = 1000;
-        let mut continuation_token: Option<String> = None;
-
-        loop {
-            let event_page = self
-                .provider
-                .get_events(filter.clone(), continuation_token, chunk_size)
-                .await
-                .map_err(StarknetClientError::Provider)?;
-
-            event_page.events.iter().for_each(|e| {
-                if let Some(block_number) = e.block_number {
-                    events
-                        .entry(block_number)
-                        .and_modify(|v| v.push(e.clone()))
-                        .or_insert_with(|| vec![e.clone()]);
-                }
-            });
-
-            continuation_token = event_page.continuation_token;
-
-            if continuation_token.is_none() {
-                break;
-            }
-        }
-
-        Ok(events)
+        collect_events_by_block(events_pages(self, filter, DEFAULT_CHUNK_SIZE)).await
     }
 
     async fn fetch_all_block_events_for_pending_block(
@@ -291,7 +632,6 @@ impl StarknetClient for StarknetClientHttp {
         timestamp: u64,
         keys: Option<Vec<Vec<FieldElement>>>,
     ) -> Result<HashMap<u64, Vec<EmittedEvent>>, StarknetClientError> {
-        let mut events: HashMap<u64, Vec<EmittedEvent>> = HashMap::new();
         let block_id = BlockId::Tag(BlockTag::Pending);
 
         let filter = EventFilter {
@@ -301,31 +641,16 @@ impl StarknetClient for StarknetClientHttp {
             keys,
         };
 
-        let chunk_size = 1000;
-        let mut continuation_token: Option<String> = None;
-
-        loop {
-            let event_page = self
-                .provider
-                .get_events(filter.clone(), continuation_token, chunk_size)
-                .await
-                .map_err(StarknetClientError::Provider)?;
-
-            event_page.events.iter().for_each(|e| {
-                events
-                    .entry(timestamp)
-                    .and_modify(|v| v.push(e.clone()))
-                    .or_insert_with(|| vec![e.clone()]);
-            });
-
-            continuation_token = event_page.continuation_token;
-
-            if continuation_token.is_none() {
-                break;
-            }
-        }
-
-        Ok(events)
+        // Pending events don't have a block number, they are
+        // mapped to the pending block timestamp instead.
+        events_pages(self, filter, DEFAULT_CHUNK_SIZE)
+            .try_fold(HashMap::<_, Vec<_>>::new(), |mut events, page| async move {
+                if !page.events.is_empty() {
+                    events.entry(timestamp).or_default().extend(page.events);
+                }
+                Ok(events)
+            })
+            .await
     }
 
     async fn call_contract(
@@ -336,37 +661,91 @@ impl StarknetClient for StarknetClientHttp {
         block: BlockId,
     ) -> Result<Vec<FieldElement>, StarknetClientError> {
         let r = self
-            .provider
-            .call(
-                FunctionCall {
-                    contract_address,
-                    entry_point_selector: selector,
-                    calldata,
-                },
-                block,
-            )
+            .request(|| {
+                self.provider.call(
+                    FunctionCall {
+                        contract_address,
+                        entry_point_selector: selector,
+                        calldata: calldata.clone(),
+                    },
+                    block,
+                )
+            })
             .await;
 
         match r {
             Ok(felts) => Ok(felts),
             Err(e) => {
                 if let ProviderError::StarknetError(StarknetError::ContractError(ref data)) = e {
-                    let s = data.revert_error.clone();
-                    if s.contains(ENTRYPOINT_NOT_FOUND) {
-                        Err(StarknetClientError::EntrypointNotFound(s))
-                    } else if s.contains(INPUT_TOO_SHORT) || s.contains(FAILED_DESERIALIZE) {
-                        Err(StarknetClientError::InputTooShort)
-                    } else if s.contains(INPUT_TOO_LONG) {
-                        Err(StarknetClientError::InputTooLong)
-                    } else {
-                        Err(StarknetClientError::Contract(s))
-                    }
+                    Err(contract_error(data.revert_error.clone()))
                 } else {
-                    Err(StarknetClientError::Contract(e.to_string()))
+                    Err(StarknetClientError::Provider(e))
                 }
             }
         }
     }
+
+    async fn call_contracts(
+        &self,
+        calls: Vec<FunctionCall>,
+        block: BlockId,
+    ) -> BatchResult<Vec<FieldElement>> {
+        let block_id = block_id_to_json(&block);
+
+        let requests = calls
+            .iter()
+            .map(|c| {
+                (
+                    "starknet_call",
+                    json!({ "request": function_call_to_json(c), "block_id": block_id }),
+                )
+            })
+            .collect();
+
+        Ok(self
+            .batch_request(requests)
+            .await?
+            .into_iter()
+            .map(|r| match r {
+                Ok(felts) => parse_felts(felts),
+                Err(e) => Err(e.into_call_error()),
+            })
+            .collect())
+    }
+
+    async fn subscribe_new_heads(
+        &self,
+    ) -> Result<SubscriptionStream<BlockHeader>, StarknetClientError> {
+        #[cfg(feature = "ws")]
+        {
+            self.ws_client()?.subscribe_new_heads().await
+        }
+        #[cfg(not(feature = "ws"))]
+        {
+            Err(ws_feature_disabled())
+        }
+    }
+
+    async fn subscribe_events(
+        &self,
+        from_address: Option<FieldElement>,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<SubscriptionStream<EmittedEvent>, StarknetClientError> {
+        #[cfg(feature = "ws")]
+        {
+            self.ws_client()?.subscribe_events(from_address, keys).await
+        }
+        #[cfg(not(feature = "ws"))]
+        {
+            let _ = (from_address, keys);
+            Err(ws_feature_disabled())
+        }
+    }
+}
+
+#[cfg(not(feature = "ws"))]
+fn ws_feature_disabled() -> StarknetClientError {
+    StarknetClientError::Unsupported("subscriptions require the `ws` feature".to_string())
 }
 
 #[cfg(test)]
@@ -376,7 +755,140 @@ mod tests {
     use std::sync::Arc;
     use tokio;
 
+    #[test]
+    fn test_block_id_to_json() {
+        assert_eq!(
+            block_id_to_json(&BlockId::Number(12)),
+            json!({ "block_number": 12 })
+        );
+        assert_eq!(
+            block_id_to_json(&BlockId::Hash(FieldElement::from(255_u64))),
+            json!({ "block_hash": "0xff" })
+        );
+        assert_eq!(
+            block_id_to_json(&BlockId::Tag(BlockTag::Pending)),
+            json!("pending")
+        );
+    }
+
+    #[test]
+    fn test_function_call_to_json() {
+        let call = FunctionCall {
+            contract_address: FieldElement::from(16_u64),
+            entry_point_selector: FieldElement::ONE,
+            calldata: vec![FieldElement::ZERO, FieldElement::TWO],
+        };
+
+        assert_eq!(
+            function_call_to_json(&call),
+            json!({
+                "contract_address": "0x10",
+                "entry_point_selector": "0x1",
+                "calldata": ["0x0", "0x2"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_order_responses() {
+        let responses: Vec<JsonRpcResponse> = serde_json::from_value(json!([
+            { "jsonrpc": "2.0", "id": 1, "error": { "code": 24, "message": "Block not found" } },
+            { "jsonrpc": "2.0", "id": 0, "result": ["0x1"] },
+        ]))
+        .unwrap();
+
+        let ordered = order_responses(responses, 3);
+
+        assert_eq!(ordered[0].as_ref().unwrap(), &json!(["0x1"]));
+        assert_eq!(ordered[1].as_ref().unwrap_err().code, 24);
+        assert!(ordered[2].is_err());
+    }
+
+    #[test]
+    fn test_json_rpc_call_error() {
+        let error = JsonRpcError {
+            code: CONTRACT_ERROR_CODE,
+            message: "Contract error".to_string(),
+            data: Some(json!({ "revert_error": format!("Error: {}", INPUT_TOO_LONG) })),
+        };
+        assert!(matches!(
+            error.into_call_error(),
+            StarknetClientError::InputTooLong
+        ));
+
+        let error = JsonRpcError {
+            code: CONTRACT_ERROR_CODE,
+            message: "Contract error".to_string(),
+            data: None,
+        };
+        assert!(matches!(
+            error.into_call_error(),
+            StarknetClientError::Contract(s) if s == "Contract error"
+        ));
+
+        let error = JsonRpcError {
+            code: 20,
+            message: "Contract not found".to_string(),
+            data: None,
+        };
+        assert!(matches!(
+            error.into_call_error(),
+            StarknetClientError::Other(_)
+        ));
+    }
+
+    #[test]
+    fn test_missing_batch_response_is_not_contract_error() {
+        let responses: Vec<JsonRpcResponse> = serde_json::from_value(json!([
+            { "jsonrpc": "2.0", "id": 0, "result": ["0x1"] },
+        ]))
+        .unwrap();
+
+        let error = order_responses(responses, 2)
+            .remove(1)
+            .unwrap_err()
+            .into_call_error();
+
+        assert!(matches!(
+            error,
+            StarknetClientError::Other(s) if s.contains("Missing response for request 1")
+        ));
+    }
+
     #[tokio::test]
+    async fn test_batch_transport_error_is_retryable() {
+        // Nothing listens on the port once the listener is dropped.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let client = StarknetClientHttp::new_with_config(
+            &url,
+            StarknetClientHttpConfig {
+                retry_policy: RetryPolicy {
+                    max_attempts: 1,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let r = client.block_times(&[BlockId::Number(1)]).await;
+        assert!(matches!(r, Err(ref e) if e.is_retryable()), "{:?}", r);
+    }
+
+    #[test]
+    fn test_parse_felts() {
+        assert_eq!(
+            parse_felts(json!(["0x1", "0x2"])).unwrap(),
+            vec![FieldElement::ONE, FieldElement::TWO]
+        );
+        assert!(parse_felts(json!({ "not": "felts" })).is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires network access to the public blastapi.io RPC nodes"]
     async fn test_contract_error_entrypoint_not_found() {
         let client = Arc::new(
             StarknetClientHttp::new("https://starknet-sepolia.public.blastapi.io").unwrap(),
@@ -422,6 +934,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires network access to the public blastapi.io RPC nodes"]
     async fn test_contract_error_input_too_short() {
         let client = Arc::new(
             StarknetClientHttp::new("https://starknet-mainnet.public.blastapi.io").unwrap(),
@@ -446,14 +959,15 @@ mod tests {
             )
             .await;
 
-        if let Err(StarknetClientError::InputTooShort) = r {
-            ()
-        } else {
-            panic!("Expected StarknetClientError::InputTooShort, got {:?}", r);
-        }
+        assert!(
+            matches!(r, Err(StarknetClientError::InputTooShort)),
+            "Expected StarknetClientError::InputTooShort, got {:?}",
+            r
+        );
     }
 
     #[tokio::test]
+    #[ignore = "requires network access to the public blastapi.io RPC nodes"]
     async fn test_contract_error_input_too_long() {
         let client = Arc::new(
             StarknetClientHttp::new("https://starknet-mainnet.public.blastapi.io").unwrap(),
@@ -485,10 +999,10 @@ mod tests {
             )
             .await;
 
-        if let Err(StarknetClientError::InputTooLong) = r {
-            ()
-        } else {
-            panic!("Expected StarknetClientError::InputTooLong, got {:?}", r);
-        }
+        assert!(
+            matches!(r, Err(StarknetClientError::InputTooLong)),
+            "Expected StarknetClientError::InputTooLong, got {:?}",
+            r
+        );
     }
 }
@@ -1,9 +1,19 @@
+pub mod failover;
 pub mod http;
+pub mod recording;
+pub mod retry;
+pub mod stream;
+#[cfg(feature = "ws")]
+pub mod ws;
 use crate::EventResult;
 use async_trait::async_trait;
-pub use http::StarknetClientHttp;
+pub use failover::{FailoverConfig, StarknetClientFailover};
+use futures::stream::BoxStream;
+pub use http::{StarknetClientHttp, StarknetClientHttpConfig};
 #[cfg(any(test, feature = "mock"))]
 use mockall::automock;
+pub use recording::{RecordingStarknetClient, ReplayStarknetClient};
+use serde::{Deserialize, Serialize};
 use starknet::core::{types::FieldElement, types::*};
 use starknet::providers::ProviderError;
 use std::collections::HashMap;
@@ -24,10 +34,30 @@ pub enum StarknetClientError {
     Conversion(String),
     #[error("Starknet-rs provider error: {0}")]
     Provider(ProviderError),
+    #[error("Operation not supported by the client: {0}")]
+    Unsupported(String),
     #[error("Other error: {0}")]
     Other(String),
 }
 
+/// Header of a block added to the chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub block_number: u64,
+    pub block_hash: FieldElement,
+    pub parent_hash: FieldElement,
+    pub timestamp: u64,
+}
+
+/// Stream of the items pushed by a subscription.
+/// The stream ends when the subscription is closed.
+pub type SubscriptionStream<T> = BoxStream<'static, Result<T, StarknetClientError>>;
+
+/// Result of a batch of requests.
+/// The outer error is returned when the whole batch failed,
+/// and the inner errors for individual requests.
+pub type BatchResult<T> = Result<Vec<Result<T, StarknetClientError>>, StarknetClientError>;
+
 /// Starknet client interface with required methods
 /// for arkproject capabilities only.
 #[cfg_attr(any(test, feature = "mock"), automock)]
@@ -60,17 +90,47 @@ pub trait StarknetClient {
 
     async fn block_time(&self, block: BlockId) -> Result<u64, StarknetClientError>;
 
+    /// Returns the timestamp of each given block, in the same order.
+    ///
+    /// All the blocks are requested at once, in a single JSON-RPC batch
+    /// when supported by the client.
+    async fn block_times(&self, blocks: &[BlockId]) -> BatchResult<u64>;
+
     async fn block_number(&self) -> Result<u64, StarknetClientError>;
 
-    /// On Starknet, a chunk size limits the maximum number of events
-    /// that can be retrieved with one call.
-    /// To ensure all events are fetched, we must ensure all events pages
-    /// are correctly fechted.
+    /// Returns the header of each given block, in the same order.
+    /// Pending block has no hash yet, and returns an error.
     ///
-    /// TODO: for now this version is ok, but it can be RAM consuming
-    /// as the events are accumulated before this function returns.
-    /// We can think of an other version that returns each page, and let
-    /// the caller process the pages.
+    /// All the blocks are requested at once, in a single JSON-RPC batch
+    /// when supported by the client.
+    async fn block_headers(&self, blocks: &[BlockId]) -> BatchResult<BlockHeader>;
+
+    /// Returns the hash of the given block.
+    /// Pending block has no hash yet, and returns an error.
+    async fn block_hash(&self, block: BlockId) -> Result<FieldElement, StarknetClientError>;
+
+    /// Returns the class hash of the contract at the given block.
+    async fn class_hash_at(
+        &self,
+        contract_address: FieldElement,
+        block: BlockId,
+    ) -> Result<FieldElement, StarknetClientError>;
+
+    /// Fetches one page of events matching the given filter.
+    ///
+    /// This is the building block of the streams in the [`stream`] module,
+    /// which lazily follow the continuation token to process events
+    /// page by page with a bounded memory usage.
+    async fn fetch_events_page(
+        &self,
+        filter: EventFilter,
+        continuation_token: Option<String>,
+        chunk_size: u64,
+    ) -> Result<EventsPage, StarknetClientError>;
+
+    /// Fetches one page of events, mapped by block number.
+    /// The returned continuation token must be given back to
+    /// fetch the next page.
     async fn fetch_events(
         &self,
         from_block: Option<BlockId>,
@@ -80,6 +140,13 @@ pub trait StarknetClient {
         continuation_token: Option<String>,
     ) -> Result<EventResult, StarknetClientError>;
 
+    /// On Starknet, a chunk size limits the maximum number of events
+    /// that can be retrieved with one call.
+    /// To ensure all events are fetched, all the events pages
+    /// are fetched and accumulated before this function returns.
+    ///
+    /// For busy blocks, prefer [`stream::events_pages`] which lets
+    /// the caller process the pages as they arrive.
     async fn fetch_all_block_events(
         &self,
         block_id: BlockId,
@@ -101,4 +168,35 @@ pub trait StarknetClient {
         calldata: Vec<FieldElement>,
         block: BlockId,
     ) -> Result<Vec<FieldElement>, StarknetClientError>;
+
+    /// Calls several contracts at once on the same block, returning
+    /// the result of each call in the same order.
+    ///
+    /// The calls are sent in a single JSON-RPC batch when supported
+    /// by the client, which is useful to probe several entrypoints.
+    async fn call_contracts(
+        &self,
+        calls: Vec<FunctionCall>,
+        block: BlockId,
+    ) -> BatchResult<Vec<FieldElement>>;
+
+    /// Subscribes to the blocks added to the chain, pushed as soon as
+    /// they are produced.
+    ///
+    /// Only supported by clients with a WebSocket endpoint, returns
+    /// [`StarknetClientError::Unsupported`] otherwise.
+    async fn subscribe_new_heads(
+        &self,
+    ) -> Result<SubscriptionStream<BlockHeader>, StarknetClientError>;
+
+    /// Subscribes to the events emitted by `from_address` (any contract if `None`)
+    /// and matching the given keys.
+    ///
+    /// Only supported by clients with a WebSocket endpoint, returns
+    /// [`StarknetClientError::Unsupported`] otherwise.
+    async fn subscribe_events(
+        &self,
+        from_address: Option<FieldElement>,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<SubscriptionStream<EmittedEvent>, StarknetClientError>;
 }
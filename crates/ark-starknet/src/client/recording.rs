@@ -0,0 +1,997 @@
+//! Record and replay of Starknet client requests.
+//!
+//! [`RecordingStarknetClient`] wraps any client and appends every request
+//! with its response to a fixture file, one JSON entry per line.
+//! [`ReplayStarknetClient`] serves the responses of a fixture file back,
+//! without network, to run the indexers on real data in deterministic tests.
+//!
+//! The fixtures of the `fixtures` directory of this crate are replayed
+//! by the tests of the Pontos, Sana and metadata pipelines.
+//!
+//! # Regenerating the fixtures
+//!
+//! Each pipeline has an ignored `test_record_block` test, running it on a
+//! mainnet node with a [`RecordingStarknetClient`] to record its requests in
+//! `erc721_mint_block.<pipeline>.jsonl` of the temporary directory. The
+//! fixture is the concatenation of the three recordings:
+//!
+//! ```sh
+//! export ARK_STARKNET_RECORD_RPC_URL=<mainnet RPC url>
+//! cargo test -p pontos --all-features test_record_block -- --ignored
+//! cargo test -p sana test_record_block -- --ignored
+//! cargo test -p ark-metadata test_record_block -- --ignored
+//! cat ${TMPDIR:-/tmp}/erc721_mint_block.{pontos,sana,metadata}.jsonl \
+//!     > crates/ark-starknet/fixtures/erc721_mint_block.jsonl
+//! ```
+//!
+//! The block, contract, token and owner expected by the tests are the
+//! `RECORDED_*` constants of each pipeline, to update with the recorded block.
+use super::http::{parse_block_id, parse_block_range};
+use super::{BatchResult, BlockHeader, StarknetClient, StarknetClientError, SubscriptionStream};
+use crate::EventResult;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use starknet::core::types::*;
+use starknet::providers::ProviderError;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::error;
+
+/// Environment variable giving the fixture path to the clients
+/// initialized with [`StarknetClient::new`].
+pub const FIXTURE_PATH_ENV: &str = "ARK_STARKNET_FIXTURE";
+
+/// Client error, in a serializable form.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "message")]
+enum RecordedError {
+    Contract(String),
+    EntrypointNotFound(String),
+    InputTooLong,
+    InputTooShort,
+    Conversion(String),
+    RateLimited,
+    Provider(String),
+    Unsupported(String),
+    Other(String),
+}
+
+impl From<&StarknetClientError> for RecordedError {
+    fn from(e: &StarknetClientError) -> Self {
+        match e {
+            StarknetClientError::Contract(s) => RecordedError::Contract(s.clone()),
+            StarknetClientError::EntrypointNotFound(s) => {
+                RecordedError::EntrypointNotFound(s.clone())
+            }
+            StarknetClientError::InputTooLong => RecordedError::InputTooLong,
+            StarknetClientError::InputTooShort => RecordedError::InputTooShort,
+            StarknetClientError::Conversion(s) => RecordedError::Conversion(s.clone()),
+            StarknetClientError::Provider(ProviderError::RateLimited) => RecordedError::RateLimited,
+            StarknetClientError::Provider(e) => RecordedError::Provider(e.to_string()),
+            StarknetClientError::Unsupported(s) => RecordedError::Unsupported(s.clone()),
+            StarknetClientError::Other(s) => RecordedError::Other(s.clone()),
+        }
+    }
+}
+
+impl From<RecordedError> for StarknetClientError {
+    fn from(e: RecordedError) -> Self {
+        match e {
+            RecordedError::Contract(s) => StarknetClientError::Contract(s),
+            RecordedError::EntrypointNotFound(s) => StarknetClientError::EntrypointNotFound(s),
+            RecordedError::InputTooLong => StarknetClientError::InputTooLong,
+            RecordedError::InputTooShort => StarknetClientError::InputTooShort,
+            RecordedError::Conversion(s) => StarknetClientError::Conversion(s),
+            RecordedError::RateLimited => StarknetClientError::Provider(ProviderError::RateLimited),
+            // Provider errors can't be rebuilt from their message.
+            RecordedError::Provider(s) => {
+                StarknetClientError::Other(format!("Recorded provider error: {}", s))
+            }
+            RecordedError::Unsupported(s) => StarknetClientError::Unsupported(s),
+            RecordedError::Other(s) => StarknetClientError::Other(s),
+        }
+    }
+}
+
+/// A request with its response, as stored in the fixture file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FixtureEntry {
+    method: String,
+    /// Arguments of the request, formatted with `Debug`.
+    request: String,
+    response: Result<Value, RecordedError>,
+}
+
+/// Returns the method under which the items pushed by a subscription are recorded.
+fn subscription_item_method(method: &str) -> String {
+    format!("{}_item", method)
+}
+
+fn to_recorded<T: Serialize>(
+    response: &Result<T, StarknetClientError>,
+) -> serde_json::Result<Result<Value, RecordedError>> {
+    match response {
+        Ok(r) => serde_json::to_value(r).map(Ok),
+        Err(e) => Ok(Err(RecordedError::from(e))),
+    }
+}
+
+fn write_entry(file: &Mutex<File>, entry: FixtureEntry) {
+    let line = match serde_json::to_string(&entry) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("Can't serialize fixture entry {}: {}", entry.method, e);
+            return;
+        }
+    };
+
+    let mut file = file.lock().expect("Fixture file lock poisoned");
+    if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+        error!("Can't write fixture entry {}: {}", entry.method, e);
+    }
+}
+
+fn fixture_path_from_env() -> Result<PathBuf, StarknetClientError> {
+    std::env::var(FIXTURE_PATH_ENV)
+        .map(PathBuf::from)
+        .map_err(|_| {
+            StarknetClientError::Other(format!(
+                "{} must be set to the fixture file path",
+                FIXTURE_PATH_ENV
+            ))
+        })
+}
+
+/// Client recording every request sent to the wrapped client,
+/// with its response, into a fixture file.
+///
+/// Entries are appended as soon as the response is received,
+/// the fixture is then usable even if the process is interrupted.
+/// Items pushed by subscriptions are recorded as they are received.
+#[derive(Debug)]
+pub struct RecordingStarknetClient<C> {
+    inner: C,
+    file: Arc<Mutex<File>>,
+}
+
+impl<C> RecordingStarknetClient<C>
+where
+    C: StarknetClient + Send + Sync,
+{
+    /// Wraps the given client, recording into the fixture file at `path`.
+    /// An existing fixture file is overwritten.
+    pub fn wrap(inner: C, path: impl AsRef<Path>) -> Result<Self, StarknetClientError> {
+        let file = File::create(path.as_ref()).map_err(|e| {
+            StarknetClientError::Other(format!(
+                "Can't create fixture file {}: {}",
+                path.as_ref().display(),
+                e
+            ))
+        })?;
+
+        Ok(Self {
+            inner,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Returns the wrapped client.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    async fn record<T, Fut>(
+        &self,
+        method: &str,
+        request: String,
+        f: Fut,
+    ) -> Result<T, StarknetClientError>
+    where
+        T: Serialize,
+        Fut: Future<Output = Result<T, StarknetClientError>>,
+    {
+        let response = f.await;
+
+        match to_recorded(&response) {
+            Ok(recorded) => write_entry(
+                &self.file,
+                FixtureEntry {
+                    method: method.to_string(),
+                    request,
+                    response: recorded,
+                },
+            ),
+            Err(e) => error!("Can't serialize {} response: {}", method, e),
+        }
+
+        response
+    }
+
+    async fn record_batch<T, Fut>(&self, method: &str, request: String, f: Fut) -> BatchResult<T>
+    where
+        T: Serialize,
+        Fut: Future<Output = BatchResult<T>>,
+    {
+        let response = f.await;
+
+        let recorded = match &response {
+            Ok(results) => {
+                let results: Vec<Result<&T, RecordedError>> = results
+                    .iter()
+                    .map(|r| r.as_ref().map_err(RecordedError::from))
+                    .collect();
+                serde_json::to_value(results).map(Ok)
+            }
+            Err(e) => Ok(Err(RecordedError::from(e))),
+        };
+
+        match recorded {
+            Ok(recorded) => write_entry(
+                &self.file,
+                FixtureEntry {
+                    method: method.to_string(),
+                    request,
+                    response: recorded,
+                },
+            ),
+            Err(e) => error!("Can't serialize {} response: {}", method, e),
+        }
+
+        response
+    }
+
+    /// Records the subscription, then each item pushed by the returned stream.
+    async fn record_subscription<T, Fut>(
+        &self,
+        method: &str,
+        request: String,
+        f: Fut,
+    ) -> Result<SubscriptionStream<T>, StarknetClientError>
+    where
+        T: Serialize + Send + 'static,
+        Fut: Future<Output = Result<SubscriptionStream<T>, StarknetClientError>>,
+    {
+        // The subscription itself is recorded without value,
+        // to replay its error if any.
+        let response = f.await;
+        let recorded = match &response {
+            Ok(_) => Ok(Value::Null),
+            Err(e) => Err(RecordedError::from(e)),
+        };
+        write_entry(
+            &self.file,
+            FixtureEntry {
+                method: method.to_string(),
+                request: request.clone(),
+                response: recorded,
+            },
+        );
+        let stream = response?;
+
+        let file = Arc::clone(&self.file);
+        let method = subscription_item_method(method);
+
+        Ok(stream
+            .inspect(move |item| match to_recorded(item) {
+                Ok(recorded) => write_entry(
+                    &file,
+                    FixtureEntry {
+                        method: method.clone(),
+                        request: request.clone(),
+                        response: recorded,
+                    },
+                ),
+                Err(e) => error!("Can't serialize {} item: {}", method, e),
+            })
+            .boxed())
+    }
+}
+
+#[async_trait]
+impl<C> StarknetClient for RecordingStarknetClient<C>
+where
+    C: StarknetClient + Send + Sync,
+{
+    /// Initializes the wrapped client with the given url, recording
+    /// into the fixture file given by the `ARK_STARKNET_FIXTURE` variable.
+    fn new(rpc_url: &str) -> Result<Self, StarknetClientError> {
+        Self::wrap(C::new(rpc_url)?, fixture_path_from_env()?)
+    }
+
+    async fn events_from_tx_receipt(
+        &self,
+        transaction_hash: FieldElement,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<Vec<EmittedEvent>, StarknetClientError> {
+        let request = format!("{:?}", (&transaction_hash, &keys));
+        self.record(
+            "events_from_tx_receipt",
+            request,
+            self.inner.events_from_tx_receipt(transaction_hash, keys),
+        )
+        .await
+    }
+
+    async fn block_txs_hashes(
+        &self,
+        block: BlockId,
+    ) -> Result<(u64, Vec<FieldElement>), StarknetClientError> {
+        let request = format!("{:?}", block);
+        self.record(
+            "block_txs_hashes",
+            request,
+            self.inner.block_txs_hashes(block),
+        )
+        .await
+    }
+
+    async fn block_id_to_u64(&self, id: &BlockId) -> Result<u64, StarknetClientError> {
+        let request = format!("{:?}", id);
+        self.record("block_id_to_u64", request, self.inner.block_id_to_u64(id))
+            .await
+    }
+
+    fn parse_block_range(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<(BlockId, BlockId), StarknetClientError> {
+        self.inner.parse_block_range(from, to)
+    }
+
+    fn parse_block_id(&self, id: &str) -> Result<BlockId, StarknetClientError> {
+        self.inner.parse_block_id(id)
+    }
+
+    async fn block_time(&self, block: BlockId) -> Result<u64, StarknetClientError> {
+        let request = format!("{:?}", block);
+        self.record("block_time", request, self.inner.block_time(block))
+            .await
+    }
+
+    async fn block_times(&self, blocks: &[BlockId]) -> BatchResult<u64> {
+        let request = format!("{:?}", blocks);
+        self.record_batch("block_times", request, self.inner.block_times(blocks))
+            .await
+    }
+
+    async fn block_headers(&self, blocks: &[BlockId]) -> BatchResult<BlockHeader> {
+        let request = format!("{:?}", blocks);
+        self.record_batch("block_headers", request, self.inner.block_headers(blocks))
+            .await
+    }
+
+    async fn block_number(&self) -> Result<u64, StarknetClientError> {
+        self.record("block_number", String::new(), self.inner.block_number())
+            .await
+    }
+
+    async fn block_hash(&self, block: BlockId) -> Result<FieldElement, StarknetClientError> {
+        let request = format!("{:?}", block);
+        self.record("block_hash", request, self.inner.block_hash(block))
+            .await
+    }
+
+    async fn class_hash_at(
+        &self,
+        contract_address: FieldElement,
+        block: BlockId,
+    ) -> Result<FieldElement, StarknetClientError> {
+        let request = format!("{:?}", (&contract_address, &block));
+        self.record(
+            "class_hash_at",
+            request,
+            self.inner.class_hash_at(contract_address, block),
+        )
+        .await
+    }
+
+    async fn fetch_events_page(
+        &self,
+        filter: EventFilter,
+        continuation_token: Option<String>,
+        chunk_size: u64,
+    ) -> Result<EventsPage, StarknetClientError> {
+        let request = format!("{:?}", (&filter, &continuation_token, chunk_size));
+        self.record(
+            "fetch_events_page",
+            request,
+            self.inner
+                .fetch_events_page(filter, continuation_token, chunk_size),
+        )
+        .await
+    }
+
+    async fn fetch_events(
+        &self,
+        from_block: Option<BlockId>,
+        to_block: Option<BlockId>,
+        keys: Option<Vec<Vec<FieldElement>>>,
+        contract_address: Option<FieldElement>,
+        continuation_token: Option<String>,
+    ) -> Result<EventResult, StarknetClientError> {
+        let request = format!(
+            "{:?}",
+            (
+                &from_block,
+                &to_block,
+                &keys,
+                &contract_address,
+                &continuation_token
+            )
+        );
+        self.record(
+            "fetch_events",
+            request,
+            self.inner.fetch_events(
+                from_block,
+                to_block,
+                keys,
+                contract_address,
+                continuation_token,
+            ),
+        )
+        .await
+    }
+
+    async fn fetch_all_block_events(
+        &self,
+        block_id: BlockId,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<HashMap<u64, Vec<EmittedEvent>>, StarknetClientError> {
+        let request = format!("{:?}", (&block_id, &keys));
+        self.record(
+            "fetch_all_block_events",
+            request,
+            self.inner.fetch_all_block_events(block_id, keys),
+        )
+        .await
+    }
+
+    async fn fetch_all_block_events_for_pending_block(
+        &self,
+        timestamp: u64,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<HashMap<u64, Vec<EmittedEvent>>, StarknetClientError> {
+        let request = format!("{:?}", (timestamp, &keys));
+        self.record(
+            "fetch_all_block_events_for_pending_block",
+            request,
+            self.inner
+                .fetch_all_block_events_for_pending_block(timestamp, keys),
+        )
+        .await
+    }
+
+    async fn call_contract(
+        &self,
+        contract_address: FieldElement,
+        selector: FieldElement,
+        calldata: Vec<FieldElement>,
+        block: BlockId,
+    ) -> Result<Vec<FieldElement>, StarknetClientError> {
+        let request = format!("{:?}", (&contract_address, &selector, &calldata, &block));
+        self.record(
+            "call_contract",
+            request,
+            self.inner
+                .call_contract(contract_address, selector, calldata, block),
+        )
+        .await
+    }
+
+    async fn call_contracts(
+        &self,
+        calls: Vec<FunctionCall>,
+        block: BlockId,
+    ) -> BatchResult<Vec<FieldElement>> {
+        let request = format!("{:?}", (&calls, &block));
+        self.record_batch(
+            "call_contracts",
+            request,
+            self.inner.call_contracts(calls, block),
+        )
+        .await
+    }
+
+    async fn subscribe_new_heads(
+        &self,
+    ) -> Result<SubscriptionStream<BlockHeader>, StarknetClientError> {
+        self.record_subscription(
+            "subscribe_new_heads",
+            String::new(),
+            self.inner.subscribe_new_heads(),
+        )
+        .await
+    }
+
+    async fn subscribe_events(
+        &self,
+        from_address: Option<FieldElement>,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<SubscriptionStream<EmittedEvent>, StarknetClientError> {
+        let request = format!("{:?}", (&from_address, &keys));
+        self.record_subscription(
+            "subscribe_events",
+            request,
+            self.inner.subscribe_events(from_address, keys),
+        )
+        .await
+    }
+}
+
+/// Recorded responses, in the recorded order, by method and request.
+type RecordedResponses = HashMap<(String, String), VecDeque<Result<Value, RecordedError>>>;
+
+/// Client serving the responses of a fixture file
+/// written by [`RecordingStarknetClient`].
+///
+/// When the same request was recorded several times (like `block_number`
+/// while following the chain head), the responses are served in the
+/// recorded order, the last one being repeated once all were served.
+#[derive(Debug)]
+pub struct ReplayStarknetClient {
+    responses: Mutex<RecordedResponses>,
+}
+
+impl ReplayStarknetClient {
+    /// Loads the fixture file at `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, StarknetClientError> {
+        let content = fs::read_to_string(path.as_ref()).map_err(|e| {
+            StarknetClientError::Other(format!(
+                "Can't read fixture file {}: {}",
+                path.as_ref().display(),
+                e
+            ))
+        })?;
+
+        Self::from_fixture(&content)
+    }
+
+    /// Loads the fixture from its content, one JSON entry per line.
+    pub fn from_fixture(content: &str) -> Result<Self, StarknetClientError> {
+        let mut responses: HashMap<_, VecDeque<_>> = HashMap::new();
+
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry: FixtureEntry = serde_json::from_str(line).map_err(|e| {
+                StarknetClientError::Other(format!("Invalid fixture entry line {}: {}", i + 1, e))
+            })?;
+
+            responses
+                .entry((entry.method, entry.request))
+                .or_default()
+                .push_back(entry.response);
+        }
+
+        Ok(Self {
+            responses: Mutex::new(responses),
+        })
+    }
+
+    fn next_response(
+        &self,
+        method: &str,
+        request: String,
+    ) -> Result<Result<Value, RecordedError>, StarknetClientError> {
+        let mut responses = self.responses.lock().expect("Fixture lock poisoned");
+
+        let queue = responses
+            .get_mut(&(method.to_string(), request))
+            .filter(|q| !q.is_empty())
+            .ok_or_else(|| {
+                StarknetClientError::Other(format!("No recorded response for {}", method))
+            })?;
+
+        if queue.len() > 1 {
+            Ok(queue.pop_front().expect("Queue is not empty"))
+        } else {
+            Ok(queue[0].clone())
+        }
+    }
+
+    fn replay<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        request: String,
+    ) -> Result<T, StarknetClientError> {
+        let value = self.next_response(method, request)??;
+
+        serde_json::from_value(value).map_err(|e| {
+            StarknetClientError::Conversion(format!("Invalid recorded {} response: {}", method, e))
+        })
+    }
+
+    /// Replays the subscription, pushing all the recorded items at once.
+    fn replay_subscription<T: DeserializeOwned + Send + 'static>(
+        &self,
+        method: &str,
+        request: String,
+    ) -> Result<SubscriptionStream<T>, StarknetClientError> {
+        self.next_response(method, request.clone())??;
+
+        let items = self
+            .responses
+            .lock()
+            .expect("Fixture lock poisoned")
+            .remove(&(subscription_item_method(method), request))
+            .unwrap_or_default();
+
+        let method = method.to_string();
+        Ok(futures::stream::iter(items.into_iter().map(
+            move |item| -> Result<T, StarknetClientError> {
+                serde_json::from_value(item?).map_err(|e| {
+                    StarknetClientError::Conversion(format!(
+                        "Invalid recorded {} item: {}",
+                        method, e
+                    ))
+                })
+            },
+        ))
+        .boxed())
+    }
+
+    fn replay_batch<T: DeserializeOwned>(&self, method: &str, request: String) -> BatchResult<T> {
+        let results: Vec<Result<T, RecordedError>> = self.replay(method, request)?;
+
+        Ok(results
+            .into_iter()
+            .map(|r| r.map_err(StarknetClientError::from))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl StarknetClient for ReplayStarknetClient {
+    /// The given url is the path of the fixture file.
+    fn new(rpc_url: &str) -> Result<Self, StarknetClientError> {
+        Self::from_path(rpc_url)
+    }
+
+    async fn events_from_tx_receipt(
+        &self,
+        transaction_hash: FieldElement,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<Vec<EmittedEvent>, StarknetClientError> {
+        self.replay(
+            "events_from_tx_receipt",
+            format!("{:?}", (&transaction_hash, &keys)),
+        )
+    }
+
+    async fn block_txs_hashes(
+        &self,
+        block: BlockId,
+    ) -> Result<(u64, Vec<FieldElement>), StarknetClientError> {
+        self.replay("block_txs_hashes", format!("{:?}", block))
+    }
+
+    async fn block_id_to_u64(&self, id: &BlockId) -> Result<u64, StarknetClientError> {
+        self.replay("block_id_to_u64", format!("{:?}", id))
+    }
+
+    fn parse_block_range(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<(BlockId, BlockId), StarknetClientError> {
+        parse_block_range(from, to)
+    }
+
+    fn parse_block_id(&self, id: &str) -> Result<BlockId, StarknetClientError> {
+        parse_block_id(id)
+    }
+
+    async fn block_time(&self, block: BlockId) -> Result<u64, StarknetClientError> {
+        self.replay("block_time", format!("{:?}", block))
+    }
+
+    async fn block_times(&self, blocks: &[BlockId]) -> BatchResult<u64> {
+        self.replay_batch("block_times", format!("{:?}", blocks))
+    }
+
+    async fn block_headers(&self, blocks: &[BlockId]) -> BatchResult<BlockHeader> {
+        self.replay_batch("block_headers", format!("{:?}", blocks))
+    }
+
+    async fn block_number(&self) -> Result<u64, StarknetClientError> {
+        self.replay("block_number", String::new())
+    }
+
+    async fn block_hash(&self, block: BlockId) -> Result<FieldElement, StarknetClientError> {
+        self.replay("block_hash", format!("{:?}", block))
+    }
+
+    async fn class_hash_at(
+        &self,
+        contract_address: FieldElement,
+        block: BlockId,
+    ) -> Result<FieldElement, StarknetClientError> {
+        self.replay(
+            "class_hash_at",
+            format!("{:?}", (&contract_address, &block)),
+        )
+    }
+
+    async fn fetch_events_page(
+        &self,
+        filter: EventFilter,
+        continuation_token: Option<String>,
+        chunk_size: u64,
+    ) -> Result<EventsPage, StarknetClientError> {
+        self.replay(
+            "fetch_events_page",
+            format!("{:?}", (&filter, &continuation_token, chunk_size)),
+        )
+    }
+
+    async fn fetch_events(
+        &self,
+        from_block: Option<BlockId>,
+        to_block: Option<BlockId>,
+        keys: Option<Vec<Vec<FieldElement>>>,
+        contract_address: Option<FieldElement>,
+        continuation_token: Option<String>,
+    ) -> Result<EventResult, StarknetClientError> {
+        self.replay(
+            "fetch_events",
+            format!(
+                "{:?}",
+                (
+                    &from_block,
+                    &to_block,
+                    &keys,
+                    &contract_address,
+                    &continuation_token
+                )
+            ),
+        )
+    }
+
+    async fn fetch_all_block_events(
+        &self,
+        block_id: BlockId,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<HashMap<u64, Vec<EmittedEvent>>, StarknetClientError> {
+        self.replay(
+            "fetch_all_block_events",
+            format!("{:?}", (&block_id, &keys)),
+        )
+    }
+
+    async fn fetch_all_block_events_for_pending_block(
+        &self,
+        timestamp: u64,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<HashMap<u64, Vec<EmittedEvent>>, StarknetClientError> {
+        self.replay(
+            "fetch_all_block_events_for_pending_block",
+            format!("{:?}", (timestamp, &keys)),
+        )
+    }
+
+    async fn call_contract(
+        &self,
+        contract_address: FieldElement,
+        selector: FieldElement,
+        calldata: Vec<FieldElement>,
+        block: BlockId,
+    ) -> Result<Vec<FieldElement>, StarknetClientError> {
+        self.replay(
+            "call_contract",
+            format!("{:?}", (&contract_address, &selector, &calldata, &block)),
+        )
+    }
+
+    async fn call_contracts(
+        &self,
+        calls: Vec<FunctionCall>,
+        block: BlockId,
+    ) -> BatchResult<Vec<FieldElement>> {
+        self.replay_batch("call_contracts", format!("{:?}", (&calls, &block)))
+    }
+
+    async fn subscribe_new_heads(
+        &self,
+    ) -> Result<SubscriptionStream<BlockHeader>, StarknetClientError> {
+        self.replay_subscription("subscribe_new_heads", String::new())
+    }
+
+    async fn subscribe_events(
+        &self,
+        from_address: Option<FieldElement>,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<SubscriptionStream<EmittedEvent>, StarknetClientError> {
+        self.replay_subscription("subscribe_events", format!("{:?}", (&from_address, &keys)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::MockStarknetClient;
+
+    fn fixture_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "ark_starknet_{}_{}.jsonl",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn event() -> EmittedEvent {
+        EmittedEvent {
+            from_address: FieldElement::ONE,
+            keys: vec![FieldElement::TWO],
+            data: vec![FieldElement::from(3_u64)],
+            block_hash: Some(FieldElement::from(0xaa_u64)),
+            block_number: Some(1),
+            transaction_hash: FieldElement::from(0xbb_u64),
+        }
+    }
+
+    fn filter() -> EventFilter {
+        EventFilter {
+            from_block: Some(BlockId::Number(1)),
+            to_block: Some(BlockId::Number(1)),
+            address: None,
+            keys: None,
+        }
+    }
+
+    fn mock() -> MockStarknetClient {
+        let mut client = MockStarknetClient::default();
+
+        let mut head = 10;
+        client.expect_block_number().returning(move || {
+            head += 1;
+            Ok(head)
+        });
+        client.expect_fetch_events_page().returning(|_, _, _| {
+            Ok(EventsPage {
+                events: vec![event()],
+                continuation_token: None,
+            })
+        });
+        client
+            .expect_call_contract()
+            .returning(|_, _, _, _| Err(StarknetClientError::EntrypointNotFound("ownerOf".into())));
+        client.expect_block_times().returning(|blocks| {
+            Ok(blocks
+                .iter()
+                .map(|b| match b {
+                    BlockId::Number(n) => Ok(n * 10),
+                    _ => Err(StarknetClientError::Other("Block not found".to_string())),
+                })
+                .collect())
+        });
+
+        client
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let path = fixture_path("record_and_replay");
+
+        let recording = RecordingStarknetClient::wrap(mock(), &path).unwrap();
+        assert_eq!(recording.block_number().await.unwrap(), 11);
+        assert_eq!(recording.block_number().await.unwrap(), 12);
+        let page = recording
+            .fetch_events_page(filter(), None, 10)
+            .await
+            .unwrap();
+        assert!(recording
+            .call_contract(
+                FieldElement::ONE,
+                FieldElement::TWO,
+                vec![],
+                BlockId::Tag(BlockTag::Pending)
+            )
+            .await
+            .is_err());
+        recording
+            .block_times(&[BlockId::Number(1), BlockId::Tag(BlockTag::Latest)])
+            .await
+            .unwrap();
+
+        let replay = ReplayStarknetClient::from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Recorded order, then last response repeated.
+        assert_eq!(replay.block_number().await.unwrap(), 11);
+        assert_eq!(replay.block_number().await.unwrap(), 12);
+        assert_eq!(replay.block_number().await.unwrap(), 12);
+
+        let replayed_page = replay.fetch_events_page(filter(), None, 10).await.unwrap();
+        assert_eq!(replayed_page.events, page.events);
+        assert_eq!(replayed_page.continuation_token, None);
+
+        assert!(matches!(
+            replay
+                .call_contract(
+                    FieldElement::ONE,
+                    FieldElement::TWO,
+                    vec![],
+                    BlockId::Tag(BlockTag::Pending)
+                )
+                .await,
+            Err(StarknetClientError::EntrypointNotFound(s)) if s == "ownerOf"
+        ));
+
+        let times = replay
+            .block_times(&[BlockId::Number(1), BlockId::Tag(BlockTag::Latest)])
+            .await
+            .unwrap();
+        assert_eq!(times[0].as_ref().unwrap(), &10);
+        assert!(times[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay_subscription() {
+        let path = fixture_path("record_and_replay_subscription");
+
+        let header = |n: u64| BlockHeader {
+            block_number: n,
+            block_hash: FieldElement::from(n + 100),
+            parent_hash: FieldElement::from(n + 99),
+            timestamp: n * 10,
+        };
+
+        let mut client = MockStarknetClient::default();
+        client.expect_subscribe_new_heads().returning(move || {
+            Ok(futures::stream::iter(vec![Ok(header(1)), Ok(header(2))]).boxed())
+        });
+        client
+            .expect_subscribe_events()
+            .returning(|_, _| Err(StarknetClientError::Unsupported("events".to_string())));
+
+        let recording = RecordingStarknetClient::wrap(client, &path).unwrap();
+        let heads: Vec<_> = recording
+            .subscribe_new_heads()
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(heads.len(), 2);
+        assert!(recording.subscribe_events(None, None).await.is_err());
+
+        let replay = ReplayStarknetClient::from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let heads: Vec<BlockHeader> = replay
+            .subscribe_new_heads()
+            .await
+            .unwrap()
+            .map(|h| h.unwrap())
+            .collect()
+            .await;
+        assert_eq!(heads, vec![header(1), header(2)]);
+
+        assert!(matches!(
+            replay.subscribe_events(None, None).await,
+            Err(StarknetClientError::Unsupported(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_replay_unknown_request() {
+        let replay = ReplayStarknetClient::from_fixture("").unwrap();
+
+        assert!(replay.block_time(BlockId::Number(1)).await.is_err());
+    }
+
+    #[test]
+    fn test_replay_invalid_fixture() {
+        assert!(ReplayStarknetClient::from_fixture("{ not json").is_err());
+    }
+}
@@ -0,0 +1,334 @@
+//! Retry and rate limiting layer for Starknet clients.
+//!
+//! Public RPC providers are throttling the requests, and full nodes
+//! may be temporarily unavailable (for instance Juno being OOM and restarted
+//! by the OS when some contracts are causing too much recursion for the Cairo VM).
+//! Instead of having every caller looping on errors, the client is configured
+//! once with a [`RetryPolicy`] and an optional [`RateLimit`].
+use super::StarknetClientError;
+use rand::Rng;
+use starknet::providers::ProviderError;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::warn;
+
+/// Exponential backoff policy, with jitter.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    /// A value of 1 disables the retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between two attempts.
+    pub max_backoff: Duration,
+    /// Factor applied to the delay after each failed attempt.
+    pub multiplier: f64,
+    /// Ratio of the delay randomly added or removed, between 0.0 and 1.0.
+    /// Jitter avoids several clients retrying at the exact same time.
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the delay to wait after the given failed attempt,
+    /// starting at 1 for the first attempt.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = self.initial_backoff.as_secs_f64() * self.multiplier.max(1.0).powi(exponent);
+        let capped = base.min(self.max_backoff.as_secs_f64());
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let delay = if jitter > 0.0 {
+            capped * rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter)
+        } else {
+            capped
+        };
+
+        Duration::from_secs_f64(delay.max(0.0))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+/// Maximum rate of requests sent to the RPC provider.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// Sustained number of requests per second.
+    pub requests_per_second: f64,
+    /// Number of requests that can be sent at once
+    /// after an idle period.
+    pub burst: u32,
+}
+
+/// Token bucket enforcing a [`RateLimit`].
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    bucket: AsyncMutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            bucket: AsyncMutex::new(Bucket {
+                tokens: limit.burst.max(1) as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a request can be sent, and consumes one token.
+    pub async fn acquire(&self) {
+        let capacity = self.limit.burst.max(1) as f64;
+        let rate = self.limit.requests_per_second;
+
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+                bucket.last_refill = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+
+                if rate <= 0.0 {
+                    // Misconfigured limit, never block forever.
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - bucket.tokens) / rate)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Returns true if the provider error is transient, and the
+/// request may succeed if sent again.
+///
+/// Errors returned by Starknet itself (contract errors, block not found...)
+/// are deterministic and never retried.
+pub fn is_retryable_provider_error(e: &ProviderError) -> bool {
+    match e {
+        ProviderError::RateLimited => true,
+        // Transport errors (connection reset, timeout, node restarting...).
+        ProviderError::Other(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns true if the HTTP error is transient, and the
+/// request may succeed if sent again.
+pub fn is_retryable_http_error(e: &reqwest::Error) -> bool {
+    e.is_timeout()
+        || e.is_connect()
+        || e.status().map_or(false, |s| {
+            s == reqwest::StatusCode::TOO_MANY_REQUESTS || s.is_server_error()
+        })
+}
+
+impl StarknetClientError {
+    /// Returns true if the error is transient, and the
+    /// request may succeed if sent again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            StarknetClientError::Provider(e) => is_retryable_provider_error(e),
+            _ => false,
+        }
+    }
+}
+
+/// Runs the request built by `f`, waiting for the rate limiter before
+/// each attempt, and retrying with the policy backoff as long as
+/// `is_retryable` returns true for the returned error.
+pub async fn with_retry<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    rate_limiter: Option<&RateLimiter>,
+    is_retryable: impl Fn(&E) -> bool,
+    f: F,
+) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+
+    loop {
+        if let Some(limiter) = rate_limiter {
+            limiter.acquire().await;
+        }
+
+        match f().await {
+            Ok(r) => return Ok(r),
+            Err(e) if attempt < policy.max_attempts && is_retryable(&e) => {
+                let delay = policy.backoff(attempt);
+                warn!(
+                    "Attempt #{} failed: {}. Retrying in {:?}",
+                    attempt, e, delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn no_jitter() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            multiplier: 2.0,
+            jitter: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let policy = no_jitter();
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(1));
+        assert_eq!(policy.backoff(2), Duration::from_millis(2));
+        assert_eq!(policy.backoff(3), Duration::from_millis(4));
+        assert_eq!(policy.backoff(10), Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_backoff_jitter_bounds() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            jitter: 0.5,
+            ..Default::default()
+        };
+
+        for _ in 0..100 {
+            let delay = policy.backoff(1);
+            assert!(delay >= Duration::from_millis(50));
+            assert!(delay <= Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn test_error_classification() {
+        assert!(StarknetClientError::Provider(ProviderError::RateLimited).is_retryable());
+        assert!(!StarknetClientError::Provider(ProviderError::ArrayLengthMismatch).is_retryable());
+        assert!(!StarknetClientError::Contract("reverted".to_string()).is_retryable());
+        assert!(!StarknetClientError::EntrypointNotFound("ownerOf".to_string()).is_retryable());
+        assert!(!StarknetClientError::InputTooLong.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_with_retry_retries_transient_errors() {
+        let calls = AtomicU32::new(0);
+
+        let r: Result<u32, StarknetClientError> = with_retry(
+            &no_jitter(),
+            None,
+            StarknetClientError::is_retryable,
+            || async {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(StarknetClientError::Provider(ProviderError::RateLimited))
+                } else {
+                    Ok(7)
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(r.unwrap(), 7);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_with_retry_gives_up_after_max_attempts() {
+        let calls = AtomicU32::new(0);
+
+        let r: Result<u32, StarknetClientError> = with_retry(
+            &no_jitter(),
+            None,
+            StarknetClientError::is_retryable,
+            || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(StarknetClientError::Provider(ProviderError::RateLimited))
+            },
+        )
+        .await;
+
+        assert!(r.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_with_retry_does_not_retry_contract_errors() {
+        let calls = AtomicU32::new(0);
+
+        let r: Result<u32, StarknetClientError> = with_retry(
+            &no_jitter(),
+            None,
+            StarknetClientError::is_retryable,
+            || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(StarknetClientError::InputTooShort)
+            },
+        )
+        .await;
+
+        assert!(r.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_waits_once_burst_is_consumed() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_second: 20.0,
+            burst: 2,
+        });
+
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.acquire().await;
+        }
+
+        // 2 requests are immediate, the 2 others wait 50ms each.
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+}
@@ -0,0 +1,291 @@
+//! Lazy streaming of events pages.
+//!
+//! On Starknet, `starknet_getEvents` is paginated using a continuation token.
+//! Instead of accumulating every page in memory before returning, the streams
+//! of this module only request the next page once the previous one has been
+//! consumed by the caller, keeping the memory usage bounded to one page.
+use super::{StarknetClient, StarknetClientError};
+use futures::stream::{self, Stream, TryStreamExt};
+use starknet::core::types::{EmittedEvent, EventFilter, EventsPage};
+use std::collections::HashMap;
+use std::future::Future;
+
+/// Default number of events requested for each page.
+pub const DEFAULT_CHUNK_SIZE: u64 = 1000;
+
+/// Position of the stream in the pages sequence.
+enum PageCursor {
+    Start,
+    Next(String),
+    Done,
+}
+
+/// Returns a stream of pages following a continuation token.
+///
+/// `fetch` is called with the continuation token of the previous
+/// page (`None` for the first page), and returns the page with
+/// its own continuation token. A page is only requested when the
+/// stream is polled. The stream ends after the page without
+/// continuation token has been yielded, and stops at the first error.
+///
+/// This helper does not depend on the Starknet types, to be shared
+/// by the crates using their own provider.
+pub fn paginate<T, E, F, Fut>(mut fetch: F) -> impl Stream<Item = Result<T, E>>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<(T, Option<String>), E>>,
+{
+    stream::try_unfold(PageCursor::Start, move |cursor| {
+        let fetched = match cursor {
+            PageCursor::Start => Some(fetch(None)),
+            PageCursor::Next(token) => Some(fetch(Some(token))),
+            PageCursor::Done => None,
+        };
+
+        async move {
+            let Some(fetched) = fetched else {
+                return Ok(None);
+            };
+
+            let (page, continuation_token) = fetched.await?;
+
+            let next = match continuation_token {
+                Some(token) => PageCursor::Next(token),
+                None => PageCursor::Done,
+            };
+
+            Ok(Some((page, next)))
+        }
+    })
+}
+
+/// Returns a stream of events pages matching the given filter.
+///
+/// The continuation token is followed lazily, see [`paginate`].
+///
+/// # Arguments
+///
+/// * `client` - The client used to fetch the pages.
+/// * `filter` - The events filter, applied to every page.
+/// * `chunk_size` - Maximum number of events for each page.
+///
+/// The stream is `Send` as long as the client is `Sync`.
+pub fn events_pages<C>(
+    client: &C,
+    filter: EventFilter,
+    chunk_size: u64,
+) -> impl Stream<Item = Result<EventsPage, StarknetClientError>> + '_
+where
+    C: StarknetClient + ?Sized,
+{
+    paginate(move |continuation_token| {
+        let filter = filter.clone();
+
+        async move {
+            let page = client
+                .fetch_events_page(filter, continuation_token, chunk_size)
+                .await?;
+            let next = page.continuation_token.clone();
+
+            Ok((page, next))
+        }
+    })
+}
+
+/// Returns a stream of events matching the given filter,
+/// flattening the pages returned by [`events_pages`].
+pub fn events<C>(
+    client: &C,
+    filter: EventFilter,
+    chunk_size: u64,
+) -> impl Stream<Item = Result<EmittedEvent, StarknetClientError>> + '_
+where
+    C: StarknetClient + ?Sized,
+{
+    events_pages(client, filter, chunk_size)
+        .map_ok(|page| stream::iter(page.events.into_iter().map(Ok)))
+        .try_flatten()
+}
+
+/// Splits a list of events into consecutive groups of events
+/// emitted in the same block, preserving the original order.
+///
+/// Events without block number (pending block) are grouped
+/// under the `u64::MAX` block number.
+pub fn chunk_by_block(events: Vec<EmittedEvent>) -> Vec<(u64, Vec<EmittedEvent>)> {
+    let mut chunks: Vec<(u64, Vec<EmittedEvent>)> = vec![];
+
+    for e in events {
+        let block_number = e.block_number.unwrap_or(u64::MAX);
+
+        match chunks.last_mut() {
+            Some((n, chunk)) if *n == block_number => chunk.push(e),
+            _ => chunks.push((block_number, vec![e])),
+        }
+    }
+
+    chunks
+}
+
+/// Consumes a stream of pages, accumulating all the events
+/// mapped by block number.
+/// Events without block number are ignored.
+pub async fn collect_events_by_block<S>(
+    pages: S,
+) -> Result<HashMap<u64, Vec<EmittedEvent>>, StarknetClientError>
+where
+    S: Stream<Item = Result<EventsPage, StarknetClientError>>,
+{
+    pages
+        .try_fold(HashMap::<_, Vec<_>>::new(), |mut events, page| async move {
+            for e in page.events {
+                if let Some(block_number) = e.block_number {
+                    events.entry(block_number).or_default().push(e);
+                }
+            }
+
+            Ok(events)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::MockStarknetClient;
+    use futures::StreamExt;
+    use starknet::core::types::{BlockId, FieldElement};
+
+    fn event(block_number: u64, tx: u64) -> EmittedEvent {
+        EmittedEvent {
+            from_address: FieldElement::ONE,
+            keys: vec![],
+            data: vec![],
+            block_hash: Some(FieldElement::from(block_number)),
+            block_number: Some(block_number),
+            transaction_hash: FieldElement::from(tx),
+        }
+    }
+
+    fn filter() -> EventFilter {
+        EventFilter {
+            from_block: Some(BlockId::Number(1)),
+            to_block: Some(BlockId::Number(2)),
+            address: None,
+            keys: None,
+        }
+    }
+
+    fn mock_two_pages() -> MockStarknetClient {
+        let mut client = MockStarknetClient::default();
+
+        client
+            .expect_fetch_events_page()
+            .times(2)
+            .returning(|_, token, _| match token {
+                None => Ok(EventsPage {
+                    events: vec![event(1, 10), event(1, 11)],
+                    continuation_token: Some("1".to_string()),
+                }),
+                Some(_) => Ok(EventsPage {
+                    events: vec![event(2, 12)],
+                    continuation_token: None,
+                }),
+            });
+
+        client
+    }
+
+    #[tokio::test]
+    async fn test_events_pages_follows_continuation_token() {
+        let client = mock_two_pages();
+
+        let pages: Vec<_> = events_pages(&client, filter(), DEFAULT_CHUNK_SIZE)
+            .collect()
+            .await;
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].as_ref().unwrap().events.len(), 2);
+        assert_eq!(pages[1].as_ref().unwrap().events.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_events_pages_is_lazy() {
+        let mut client = MockStarknetClient::default();
+
+        client
+            .expect_fetch_events_page()
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(EventsPage {
+                    events: vec![event(1, 10)],
+                    continuation_token: Some("1".to_string()),
+                })
+            });
+
+        let mut pages = Box::pin(events_pages(&client, filter(), DEFAULT_CHUNK_SIZE));
+
+        // Only the first page must be requested.
+        assert!(pages.next().await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_events_pages_stops_on_error() {
+        let mut client = MockStarknetClient::default();
+
+        client
+            .expect_fetch_events_page()
+            .times(1)
+            .returning(|_, _, _| Err(StarknetClientError::Other("boom".to_string())));
+
+        let pages: Vec<_> = events_pages(&client, filter(), DEFAULT_CHUNK_SIZE)
+            .collect()
+            .await;
+
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_events_flattened() {
+        let client = mock_two_pages();
+
+        let events: Vec<EmittedEvent> = events(&client, filter(), DEFAULT_CHUNK_SIZE)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let hashes: Vec<FieldElement> = events.iter().map(|e| e.transaction_hash).collect();
+        assert_eq!(
+            hashes,
+            vec![
+                FieldElement::from(10_u64),
+                FieldElement::from(11_u64),
+                FieldElement::from(12_u64)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_events_by_block() {
+        let client = mock_two_pages();
+
+        let events = collect_events_by_block(events_pages(&client, filter(), DEFAULT_CHUNK_SIZE))
+            .await
+            .unwrap();
+
+        assert_eq!(events.get(&1).unwrap().len(), 2);
+        assert_eq!(events.get(&2).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_chunk_by_block() {
+        let chunks = chunk_by_block(vec![event(1, 10), event(1, 11), event(2, 12), event(1, 13)]);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].0, 1);
+        assert_eq!(chunks[0].1.len(), 2);
+        assert_eq!(chunks[1].0, 2);
+        assert_eq!(chunks[2].0, 1);
+    }
+}
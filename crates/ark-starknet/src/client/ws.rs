@@ -0,0 +1,307 @@
+//! Subscriptions over the Starknet WebSocket JSON-RPC API.
+//!
+//! Each subscription opens its own connection, which is closed
+//! when the returned stream is dropped.
+use super::{BlockHeader, StarknetClientError, SubscriptionStream};
+use futures::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+use starknet::core::types::{EmittedEvent, FieldElement};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tracing::{debug, warn};
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Id of the subscription request, the only one sent on a connection.
+const SUBSCRIBE_REQUEST_ID: u64 = 1;
+
+const REORG_NOTIFICATION: &str = "starknet_subscriptionReorg";
+
+/// Starknet client for the WebSocket subscriptions.
+#[derive(Debug, Clone)]
+pub struct StarknetWsClient {
+    ws_url: String,
+}
+
+impl StarknetWsClient {
+    pub fn new(ws_url: &str) -> Self {
+        Self {
+            ws_url: ws_url.to_string(),
+        }
+    }
+
+    /// Subscribes to the blocks added to the chain.
+    pub async fn subscribe_new_heads(
+        &self,
+    ) -> Result<SubscriptionStream<BlockHeader>, StarknetClientError> {
+        self.subscribe(
+            "starknet_subscribeNewHeads",
+            "starknet_subscriptionNewHeads",
+            json!({}),
+        )
+        .await
+    }
+
+    /// Subscribes to the events emitted by `from_address`
+    /// and matching the given keys.
+    pub async fn subscribe_events(
+        &self,
+        from_address: Option<FieldElement>,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> Result<SubscriptionStream<EmittedEvent>, StarknetClientError> {
+        let mut params = Map::new();
+        if let Some(from_address) = from_address {
+            params.insert("from_address".to_string(), json!(from_address));
+        }
+        if let Some(keys) = keys {
+            params.insert("keys".to_string(), json!(keys));
+        }
+
+        self.subscribe(
+            "starknet_subscribeEvents",
+            "starknet_subscriptionEvents",
+            Value::Object(params),
+        )
+        .await
+    }
+
+    /// Sends the subscription request, and returns the stream of the
+    /// `notification` results pushed for this subscription.
+    async fn subscribe<T>(
+        &self,
+        method: &str,
+        notification: &'static str,
+        params: Value,
+    ) -> Result<SubscriptionStream<T>, StarknetClientError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let (mut socket, _) = connect_async(self.ws_url.as_str())
+            .await
+            .map_err(ws_error)?;
+
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": SUBSCRIBE_REQUEST_ID,
+            "method": method,
+            "params": params,
+        });
+
+        socket
+            .send(Message::Text(request.to_string()))
+            .await
+            .map_err(ws_error)?;
+
+        let subscription_id = loop {
+            let message = next_json(&mut socket).await?.ok_or_else(|| {
+                StarknetClientError::Other(format!("WebSocket closed before {} response", method))
+            })?;
+
+            if message.get("id").and_then(Value::as_u64) != Some(SUBSCRIBE_REQUEST_ID) {
+                continue;
+            }
+
+            if let Some(error) = message.get("error") {
+                return Err(StarknetClientError::Other(format!(
+                    "{} failed: {}",
+                    method, error
+                )));
+            }
+
+            match message.get("result") {
+                Some(id) => break id.clone(),
+                None => {
+                    return Err(StarknetClientError::Other(format!(
+                        "Missing subscription id in {} response",
+                        method
+                    )))
+                }
+            }
+        };
+
+        debug!("Subscribed with {} (id {})", method, subscription_id);
+
+        let stream = futures::stream::unfold(Some(socket), move |socket| {
+            let subscription_id = subscription_id.clone();
+
+            async move {
+                let mut socket = socket?;
+
+                loop {
+                    let message = match next_json(&mut socket).await {
+                        Ok(Some(message)) => message,
+                        Ok(None) => return None,
+                        // The connection can't be used anymore.
+                        Err(e) => return Some((Err(e), None)),
+                    };
+
+                    let result = match notification_result(&message, &subscription_id) {
+                        Some((method, result)) if method == notification => result,
+                        Some((method, result)) if method == REORG_NOTIFICATION => {
+                            warn!("Chain reorganization notified: {}", result);
+                            continue;
+                        }
+                        _ => continue,
+                    };
+
+                    let item = serde_json::from_value(result).map_err(|e| {
+                        StarknetClientError::Conversion(format!(
+                            "Invalid {} notification: {}",
+                            notification, e
+                        ))
+                    });
+
+                    return Some((item, Some(socket)));
+                }
+            }
+        });
+
+        Ok(stream.boxed())
+    }
+}
+
+fn ws_error(e: tokio_tungstenite::tungstenite::Error) -> StarknetClientError {
+    StarknetClientError::Other(format!("WebSocket error: {}", e))
+}
+
+/// Returns the next JSON message received, or `None` once the connection is closed.
+async fn next_json(socket: &mut WsStream) -> Result<Option<Value>, StarknetClientError> {
+    while let Some(message) = socket.next().await {
+        let text = match message.map_err(ws_error)? {
+            Message::Text(text) => text,
+            Message::Binary(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Message::Close(_) => return Ok(None),
+            // Pings are answered by tungstenite.
+            _ => continue,
+        };
+
+        return serde_json::from_str(&text).map(Some).map_err(|e| {
+            StarknetClientError::Conversion(format!("Invalid WebSocket message: {}", e))
+        });
+    }
+
+    Ok(None)
+}
+
+/// Returns the method and the result of the notification,
+/// if it was pushed for the given subscription.
+fn notification_result(message: &Value, subscription_id: &Value) -> Option<(String, Value)> {
+    let method = message.get("method")?.as_str()?;
+    let params = message.get("params")?;
+
+    if params.get("subscription_id")? != subscription_id {
+        return None;
+    }
+
+    Some((method.to_string(), params.get("result")?.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    fn header_json(n: u64) -> Value {
+        json!({
+            "block_hash": format!("{:#x}", n + 100),
+            "parent_hash": format!("{:#x}", n + 99),
+            "block_number": n,
+            "new_root": "0x1",
+            "timestamp": n * 10,
+            "sequencer_address": "0x2",
+        })
+    }
+
+    fn notification(method: &str, subscription_id: u64, result: Value) -> Message {
+        Message::Text(
+            json!({
+                "jsonrpc": "2.0",
+                "method": method,
+                "params": { "subscription_id": subscription_id, "result": result },
+            })
+            .to_string(),
+        )
+    }
+
+    /// Starts a stand-in node accepting one connection, which answers
+    /// the subscription request with `response` then pushes `messages`.
+    async fn stand_in_node(response: Value, messages: Vec<Message>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(stream).await.unwrap();
+
+            let request = socket.next().await.unwrap().unwrap();
+            let request: Value = serde_json::from_str(request.to_text().unwrap()).unwrap();
+            assert_eq!(request["method"], "starknet_subscribeNewHeads");
+
+            let mut response = response;
+            response["id"] = request["id"].clone();
+            socket
+                .send(Message::Text(response.to_string()))
+                .await
+                .unwrap();
+
+            for message in messages {
+                socket.send(message).await.unwrap();
+            }
+
+            socket.close(None).await.unwrap();
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_new_heads() {
+        let url = stand_in_node(
+            json!({ "jsonrpc": "2.0", "result": 7 }),
+            vec![
+                notification("starknet_subscriptionNewHeads", 7, header_json(1)),
+                // Other subscription, ignored.
+                notification("starknet_subscriptionNewHeads", 8, header_json(5)),
+                notification(
+                    REORG_NOTIFICATION,
+                    7,
+                    json!({ "starting_block_number": 1, "ending_block_number": 1 }),
+                ),
+                notification("starknet_subscriptionNewHeads", 7, header_json(2)),
+            ],
+        )
+        .await;
+
+        let heads: Vec<BlockHeader> = StarknetWsClient::new(&url)
+            .subscribe_new_heads()
+            .await
+            .unwrap()
+            .map(|h| h.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(heads.len(), 2);
+        assert_eq!(heads[0].block_number, 1);
+        assert_eq!(heads[0].block_hash, FieldElement::from(101_u64));
+        assert_eq!(heads[0].parent_hash, FieldElement::from(100_u64));
+        assert_eq!(heads[0].timestamp, 10);
+        assert_eq!(heads[1].block_number, 2);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_error() {
+        let url = stand_in_node(
+            json!({
+                "jsonrpc": "2.0",
+                "error": { "code": 68, "message": "Cannot go back more than 1024 blocks" },
+            }),
+            vec![],
+        )
+        .await;
+
+        let r = StarknetWsClient::new(&url).subscribe_new_heads().await;
+        assert!(matches!(r, Err(StarknetClientError::Other(_))));
+    }
+}
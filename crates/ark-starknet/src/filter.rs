@@ -0,0 +1,229 @@
+//! Selection of the contracts indexed.
+//!
+//! A [`ContractFilter`] restricts the indexation to some contract
+//! addresses or contract types, the types being the ones of each indexer.
+//! Addresses are checked before the contract is identified, to avoid the
+//! calls to the chain for the contracts that are not indexed. Types are
+//! checked once identified.
+use starknet::core::types::FieldElement;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+
+/// Allow-lists and deny-lists of contract addresses and types.
+/// Deny-lists have precedence over allow-lists.
+#[derive(Debug, Clone)]
+pub struct ContractFilter<T> {
+    /// Only these addresses are indexed, all if `None`.
+    pub allowed_addresses: Option<HashSet<FieldElement>>,
+    pub denied_addresses: HashSet<FieldElement>,
+    /// Only these types are indexed, all if `None`.
+    pub allowed_types: Option<HashSet<T>>,
+    pub denied_types: HashSet<T>,
+}
+
+impl<T> Default for ContractFilter<T> {
+    fn default() -> Self {
+        Self {
+            allowed_addresses: None,
+            denied_addresses: HashSet::new(),
+            allowed_types: None,
+            denied_types: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Eq + Hash> PartialEq for ContractFilter<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.allowed_addresses == other.allowed_addresses
+            && self.denied_addresses == other.denied_addresses
+            && self.allowed_types == other.allowed_types
+            && self.denied_types == other.denied_types
+    }
+}
+
+impl<T: Eq + Hash> ContractFilter<T> {
+    /// Initializes a filter indexing all the contracts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow_address(mut self, address: FieldElement) -> Self {
+        self.allowed_addresses
+            .get_or_insert_with(HashSet::new)
+            .insert(address);
+        self
+    }
+
+    pub fn deny_address(mut self, address: FieldElement) -> Self {
+        self.denied_addresses.insert(address);
+        self
+    }
+
+    pub fn allow_type(mut self, contract_type: T) -> Self {
+        self.allowed_types
+            .get_or_insert_with(HashSet::new)
+            .insert(contract_type);
+        self
+    }
+
+    pub fn deny_type(mut self, contract_type: T) -> Self {
+        self.denied_types.insert(contract_type);
+        self
+    }
+
+    pub fn is_address_allowed(&self, address: &FieldElement) -> bool {
+        !self.denied_addresses.contains(address)
+            && self
+                .allowed_addresses
+                .as_ref()
+                .map_or(true, |allowed| allowed.contains(address))
+    }
+
+    pub fn is_type_allowed(&self, contract_type: &T) -> bool {
+        !self.denied_types.contains(contract_type)
+            && self
+                .allowed_types
+                .as_ref()
+                .map_or(true, |allowed| allowed.contains(contract_type))
+    }
+
+    /// Returns the address to set in the `EventFilter` of the blocks,
+    /// if the events of a single contract are indexed.
+    ///
+    /// The RPC only filters on one address. The events of the other
+    /// contracts still required, like the marketplaces, must be given
+    /// to not be filtered out.
+    pub fn event_filter_address(&self, required: &[FieldElement]) -> Option<FieldElement> {
+        match self.allowed_addresses.as_ref()?.iter().collect::<Vec<_>>()[..] {
+            [address] if required.iter().all(|r| r == address) => {
+                self.is_address_allowed(address).then_some(*address)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Shared handle on the [`ContractFilter`] of an indexer, to change it
+/// while indexing. Changes apply to the next events processed.
+#[derive(Debug)]
+pub struct ContractFilterHandle<T> {
+    inner: Arc<RwLock<ContractFilter<T>>>,
+}
+
+impl<T> Clone for ContractFilterHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> Default for ContractFilterHandle<T> {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(ContractFilter::default())),
+        }
+    }
+}
+
+impl<T: Clone + Eq + Hash> ContractFilterHandle<T> {
+    pub fn new(filter: ContractFilter<T>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(filter)),
+        }
+    }
+
+    /// Returns a copy of the current filter.
+    pub fn get(&self) -> ContractFilter<T> {
+        self.inner.read().unwrap().clone()
+    }
+
+    /// Replaces the current filter.
+    pub fn set(&self, filter: ContractFilter<T>) {
+        *self.inner.write().unwrap() = filter;
+    }
+
+    /// Updates the current filter in place.
+    pub fn update(&self, f: impl FnOnce(&mut ContractFilter<T>)) {
+        f(&mut self.inner.write().unwrap());
+    }
+
+    pub fn is_address_allowed(&self, address: &FieldElement) -> bool {
+        self.inner.read().unwrap().is_address_allowed(address)
+    }
+
+    pub fn is_type_allowed(&self, contract_type: &T) -> bool {
+        self.inner.read().unwrap().is_type_allowed(contract_type)
+    }
+
+    pub fn event_filter_address(&self, required: &[FieldElement]) -> Option<FieldElement> {
+        self.inner.read().unwrap().event_filter_address(required)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum ContractType {
+        Other,
+        ERC721,
+        ERC1155,
+    }
+
+    #[test]
+    fn test_address_lists() {
+        let filter = ContractFilter::<ContractType>::new();
+        assert!(filter.is_address_allowed(&FieldElement::ONE));
+
+        let filter = ContractFilter::<ContractType>::new()
+            .allow_address(FieldElement::ONE)
+            .allow_address(FieldElement::TWO)
+            .deny_address(FieldElement::TWO);
+        assert!(filter.is_address_allowed(&FieldElement::ONE));
+        assert!(!filter.is_address_allowed(&FieldElement::TWO));
+        assert!(!filter.is_address_allowed(&FieldElement::THREE));
+    }
+
+    #[test]
+    fn test_type_lists() {
+        let filter = ContractFilter::new().deny_type(ContractType::Other);
+        assert!(filter.is_type_allowed(&ContractType::ERC721));
+        assert!(!filter.is_type_allowed(&ContractType::Other));
+
+        let filter = ContractFilter::new().allow_type(ContractType::ERC1155);
+        assert!(filter.is_type_allowed(&ContractType::ERC1155));
+        assert!(!filter.is_type_allowed(&ContractType::ERC721));
+    }
+
+    #[test]
+    fn test_event_filter_address() {
+        assert_eq!(
+            ContractFilter::<ContractType>::new().event_filter_address(&[]),
+            None
+        );
+
+        let filter = ContractFilter::<ContractType>::new().allow_address(FieldElement::ONE);
+        assert_eq!(filter.event_filter_address(&[]), Some(FieldElement::ONE));
+        assert_eq!(filter.event_filter_address(&[FieldElement::TWO]), None);
+
+        let filter = filter.allow_address(FieldElement::TWO);
+        assert_eq!(filter.event_filter_address(&[]), None);
+    }
+
+    #[test]
+    fn test_handle_update() {
+        let handle = ContractFilterHandle::<ContractType>::new(ContractFilter::new());
+        let other = handle.clone();
+
+        other.update(|f| {
+            f.denied_addresses.insert(FieldElement::ONE);
+        });
+        assert!(!handle.is_address_allowed(&FieldElement::ONE));
+
+        handle.set(ContractFilter::new());
+        assert!(other.is_address_allowed(&FieldElement::ONE));
+    }
+}
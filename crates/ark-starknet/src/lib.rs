@@ -1,27 +1,64 @@
 pub mod byte_array;
+pub mod cairo_serde;
 pub mod cairo_string_parser;
 pub mod client;
+pub mod filter;
 pub mod format;
+
+// Allows the derive macros to refer to `::ark_starknet` inside this crate.
+extern crate self as ark_starknet;
+
 use anyhow::Result;
 use format::to_hex_str;
 use num_bigint::BigUint;
 use num_traits::Num;
-use starknet::core::types::EmittedEvent;
-use std::collections::HashMap;
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use starknet::core::types::{EmittedEvent, FieldElement};
+use std::{cmp::Ordering, collections::HashMap, fmt, str::FromStr};
+use thiserror::Error;
 
-#[derive(Debug, Clone)]
+/// Cairo `u256`, made of two 128 bits limbs.
+///
+/// Ordering and arithmetic follow the numeric value. Serialized as an hex
+/// string, and deserialized from either an hex (`0x` prefixed) or a decimal string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub struct CairoU256 {
     pub low: u128,
     pub high: u128,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EventResult {
     pub events: HashMap<u64, Vec<EmittedEvent>>,
     pub continuation_token: Option<String>,
 }
 
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CairoU256Error {
+    #[error("Invalid u256 string: {0}")]
+    InvalidString(String),
+    #[error("Value doesn't fit in a u256")]
+    Overflow,
+}
+
 impl CairoU256 {
+    pub const ZERO: Self = Self { low: 0, high: 0 };
+    pub const MAX: Self = Self {
+        low: u128::MAX,
+        high: u128::MAX,
+    };
+
+    pub fn new(low: u128, high: u128) -> Self {
+        Self { low, high }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.low == 0 && self.high == 0
+    }
+
     pub fn to_biguint(&self) -> BigUint {
         let low_bytes = self.low.to_be_bytes();
         let high_bytes = self.high.to_be_bytes();
@@ -59,18 +96,169 @@ impl CairoU256 {
             Err(_) => return Err(anyhow::anyhow!("Invalid hexadecimal string")),
         };
 
-        // Convert the BigUint to a 32-byte buffer
-        let mut bytes = biguint.to_bytes_be();
-        let padding = vec![0; 32 - bytes.len()];
-        bytes.splice(0..0, padding);
+        Ok(Self::try_from(biguint)?)
+    }
+
+    pub fn checked_add(&self, rhs: &Self) -> Option<Self> {
+        let (low, carry) = self.low.overflowing_add(rhs.low);
+        let high = self
+            .high
+            .checked_add(rhs.high)?
+            .checked_add(carry as u128)?;
+        Some(Self { low, high })
+    }
+
+    pub fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+        let (low, borrow) = self.low.overflowing_sub(rhs.low);
+        let high = self
+            .high
+            .checked_sub(rhs.high)?
+            .checked_sub(borrow as u128)?;
+        Some(Self { low, high })
+    }
+
+    pub fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        Self::try_from(self.to_biguint() * rhs.to_biguint()).ok()
+    }
+
+    /// Returns `None` if `rhs` is zero.
+    pub fn checked_div(&self, rhs: &Self) -> Option<Self> {
+        if rhs.is_zero() {
+            return None;
+        }
+        Self::try_from(self.to_biguint() / rhs.to_biguint()).ok()
+    }
+
+    /// Returns `None` if `rhs` is zero.
+    pub fn checked_rem(&self, rhs: &Self) -> Option<Self> {
+        if rhs.is_zero() {
+            return None;
+        }
+        Self::try_from(self.to_biguint() % rhs.to_biguint()).ok()
+    }
+}
+
+impl Ord for CairoU256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.high
+            .cmp(&other.high)
+            .then_with(|| self.low.cmp(&other.low))
+    }
+}
+
+impl PartialOrd for CairoU256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<u128> for CairoU256 {
+    fn from(value: u128) -> Self {
+        Self {
+            low: value,
+            high: 0,
+        }
+    }
+}
+
+impl From<u64> for CairoU256 {
+    fn from(value: u64) -> Self {
+        Self::from(value as u128)
+    }
+}
 
-        // Split the input array into two parts
+impl From<FieldElement> for CairoU256 {
+    fn from(value: FieldElement) -> Self {
+        let bytes = value.to_bytes_be();
         let (high, low) = bytes.split_at(16);
 
-        let low = u128::from_be_bytes(low.try_into()?);
-        let high = u128::from_be_bytes(high.try_into()?);
+        Self {
+            low: u128::from_be_bytes(low.try_into().expect("16 bytes")),
+            high: u128::from_be_bytes(high.try_into().expect("16 bytes")),
+        }
+    }
+}
+
+impl TryFrom<BigUint> for CairoU256 {
+    type Error = CairoU256Error;
+
+    fn try_from(value: BigUint) -> std::result::Result<Self, Self::Error> {
+        let bytes = value.to_bytes_be();
+        if bytes.len() > 32 {
+            return Err(CairoU256Error::Overflow);
+        }
+
+        let mut padded = [0u8; 32];
+        padded[32 - bytes.len()..].copy_from_slice(&bytes);
+        let (high, low) = padded.split_at(16);
+
+        Ok(Self {
+            low: u128::from_be_bytes(low.try_into().expect("16 bytes")),
+            high: u128::from_be_bytes(high.try_into().expect("16 bytes")),
+        })
+    }
+}
+
+impl From<CairoU256> for BigUint {
+    fn from(value: CairoU256) -> Self {
+        value.to_biguint()
+    }
+}
+
+impl FromStr for CairoU256 {
+    type Err = CairoU256Error;
+
+    /// Parses an hex string if prefixed by `0x`, a decimal string otherwise.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (digits, radix) = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => (hex, 16),
+            None => (s, 10),
+        };
+
+        let biguint = BigUint::from_str_radix(digits, radix)
+            .map_err(|_| CairoU256Error::InvalidString(s.to_string()))?;
+
+        Self::try_from(biguint)
+    }
+}
 
-        Ok(Self { low, high })
+impl fmt::Display for CairoU256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_decimal(false))
+    }
+}
+
+impl Serialize for CairoU256 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for CairoU256 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct CairoU256Visitor;
+
+        impl<'de> Visitor<'de> for CairoU256Visitor {
+            type Value = CairoU256;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an hex or decimal string, or an unsigned integer")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<CairoU256, E> {
+                CairoU256::from_str(v).map_err(E::custom)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<CairoU256, E> {
+                Ok(CairoU256::from(v))
+            }
+
+            fn visit_u128<E: de::Error>(self, v: u128) -> std::result::Result<CairoU256, E> {
+                Ok(CairoU256::from(v))
+            }
+        }
+
+        deserializer.deserialize_any(CairoU256Visitor)
     }
 }
 
@@ -135,4 +323,105 @@ mod tests {
             u128::from_str_radix("05f7cd1fd465baff2ba9d2d1501ad0a2", 16).unwrap()
         );
     }
+
+    #[test]
+    fn test_from_hex_be_overflow() {
+        let hex_string = format!("0x1{}", "0".repeat(64));
+        assert!(CairoU256::from_hex_be(&hex_string).is_err());
+    }
+
+    #[test]
+    fn test_ordering() {
+        let small = CairoU256::new(u128::MAX, 0);
+        let big = CairoU256::new(0, 1);
+
+        assert!(small < big);
+        assert!(big > CairoU256::from(42_u128));
+        assert_eq!(big.max(small), big);
+        assert_eq!(CairoU256::from(7_u64), CairoU256::new(7, 0));
+    }
+
+    #[test]
+    fn test_checked_add_sub() {
+        let a = CairoU256::new(u128::MAX, 0);
+        let one = CairoU256::from(1_u128);
+
+        assert_eq!(a.checked_add(&one), Some(CairoU256::new(0, 1)));
+        assert_eq!(CairoU256::new(0, 1).checked_sub(&one), Some(a));
+        assert_eq!(CairoU256::MAX.checked_add(&one), None);
+        assert_eq!(CairoU256::ZERO.checked_sub(&one), None);
+    }
+
+    #[test]
+    fn test_checked_mul_div() {
+        let a = CairoU256::new(u128::MAX, 0);
+        let two = CairoU256::from(2_u128);
+
+        let doubled = a.checked_mul(&two).unwrap();
+        assert_eq!(doubled, CairoU256::new(u128::MAX - 1, 1));
+        assert_eq!(doubled.checked_div(&two), Some(a));
+        assert_eq!(
+            CairoU256::from(7_u128).checked_rem(&two),
+            Some(CairoU256::from(1_u128))
+        );
+
+        assert_eq!(CairoU256::MAX.checked_mul(&two), None);
+        assert_eq!(a.checked_div(&CairoU256::ZERO), None);
+    }
+
+    #[test]
+    fn test_biguint_conversions() {
+        let value = CairoU256::new(15, 3);
+        let biguint: BigUint = value.into();
+
+        assert_eq!(CairoU256::try_from(biguint), Ok(value));
+        assert_eq!(
+            CairoU256::try_from(BigUint::from(1_u8) << 256),
+            Err(CairoU256Error::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_from_field_element() {
+        let felt = FieldElement::from_hex_be("0x1000000000000000000000000000000ff").unwrap();
+        assert_eq!(CairoU256::from(felt), CairoU256::new(0xff, 1));
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            CairoU256::from_str("340282366920938463463374607431768211456"),
+            Ok(CairoU256::new(0, 1))
+        );
+        assert_eq!(CairoU256::from_str("0xff"), Ok(CairoU256::from(255_u128)));
+        assert_eq!(CairoU256::from_str("0"), Ok(CairoU256::ZERO));
+        assert!(matches!(
+            CairoU256::from_str("12a"),
+            Err(CairoU256Error::InvalidString(_))
+        ));
+        assert!(CairoU256::from_str("").is_err());
+        assert_eq!(
+            CairoU256::from_str(&format!("0x1{}", "0".repeat(64))),
+            Err(CairoU256Error::Overflow)
+        );
+        assert_eq!(
+            CairoU256::new(0, 1).to_string(),
+            "340282366920938463463374607431768211456"
+        );
+    }
+
+    #[test]
+    fn test_serde() {
+        let value = CairoU256::from(15_u128);
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(
+            json,
+            "\"0x000000000000000000000000000000000000000000000000000000000000000f\""
+        );
+        assert_eq!(serde_json::from_str::<CairoU256>(&json).unwrap(), value);
+
+        assert_eq!(serde_json::from_str::<CairoU256>("\"15\"").unwrap(), value);
+        assert_eq!(serde_json::from_str::<CairoU256>("15").unwrap(), value);
+        assert!(serde_json::from_str::<CairoU256>("\"0xzz\"").is_err());
+    }
 }
@@ -6,8 +6,10 @@ use event_handler::EventHandler;
 
 mod orderbook;
 
+use ark_starknet::client::stream::paginate;
+use futures::stream::{Stream, StreamExt, TryStreamExt};
 use starknet::core::types::{
-    BlockId, EmittedEvent, EventFilter, FieldElement, MaybePendingBlockWithTxHashes,
+    BlockId, EmittedEvent, EventFilter, EventsPage, FieldElement, MaybePendingBlockWithTxHashes,
 };
 use starknet::macros::selector;
 use starknet::providers::{AnyProvider, Provider, ProviderError};
@@ -62,79 +64,174 @@ impl<S: Storage, E: EventHandler> Diri<S, E> {
         from_block: BlockId,
         to_block: BlockId,
     ) -> IndexerResult<()> {
-        let blocks_events = self
-            .fetch_events(
-                from_block,
-                to_block,
-                Some(vec![vec![
-                    selector!("OrderPlaced"),
-                    selector!("OrderFulfilled"),
-                    selector!("OrderCancelled"),
-                    selector!("OrderExecuted"),
-                    selector!("RollbackStatus"),
-                ]]),
-            )
-            .await?;
-
-        for (block_number, events) in blocks_events {
-            let block_timestamp = self.block_time(BlockId::Number(block_number)).await?;
-
-            for any_event in events {
-                let orderbook_event: Event = match any_event.try_into() {
-                    Ok(ev) => ev,
-                    Err(e) => {
-                        trace!("Event can't be deserialized: {e}");
-                        continue;
-                    }
-                };
+        let filter = EventFilter {
+            from_block: Some(from_block),
+            to_block: Some(to_block),
+            address: None,
+            keys: Some(vec![vec![
+                selector!("OrderPlaced"),
+                selector!("OrderFulfilled"),
+                selector!("OrderCancelled"),
+                selector!("OrderExecuted"),
+                selector!("RollbackStatus"),
+            ]]),
+        };
 
-                match orderbook_event {
-                    Event::OrderPlaced(ev) => {
-                        trace!("OrderPlaced found: {:?}", ev);
-                        self.storage
-                            .register_placed(block_number, block_timestamp, &ev.into())
-                            .await?;
-                    }
-                    Event::OrderCancelled(ev) => {
-                        trace!("OrderCancelled found: {:?}", ev);
-                        self.storage
-                            .register_cancelled(block_number, block_timestamp, &ev.into())
-                            .await?;
-                    }
-                    Event::OrderFulfilled(ev) => {
-                        trace!("OrderFulfilled found: {:?}", ev);
-                        self.storage
-                            .register_fulfilled(block_number, block_timestamp, &ev.into())
-                            .await?;
-                    }
-                    Event::OrderExecuted(ev) => {
-                        trace!("OrderExecuted found: {:?}", ev);
-                        self.storage
-                            .register_executed(block_number, block_timestamp, &ev.into())
-                            .await?;
-                    }
-                    Event::RollbackStatus(ev) => {
-                        trace!("RollbackStatus found: {:?}", ev);
-                        self.storage
-                            .status_back_to_open(block_number, block_timestamp, &ev.into())
-                            .await?;
+        let mut pages = std::pin::pin!(self.events_pages(filter));
+
+        // Pages are processed as they arrive. Events are ordered by block,
+        // a block is then considered as processed once an event of
+        // the next block is received, or once the last page is processed.
+        let mut current_block: Option<(u64, u64)> = None;
+
+        while let Some(page) = pages.try_next().await? {
+            for any_event in page.events {
+                let block_number = any_event.block_number;
+
+                let block_timestamp = match current_block {
+                    Some((number, ts)) if number == block_number => ts,
+                    _ => {
+                        if let Some((number, _)) = current_block {
+                            self.event_handler.on_block_processed(number).await;
+                        }
+
+                        let ts = self.block_time(BlockId::Number(block_number)).await?;
+                        current_block = Some((block_number, ts));
+                        ts
                     }
-                    _ => warn!("Orderbook event not handled: {:?}", orderbook_event),
                 };
+
+                self.process_event(any_event, block_number, block_timestamp)
+                    .await?;
             }
+        }
 
-            self.event_handler.on_block_processed(block_number).await;
+        if let Some((number, _)) = current_block {
+            self.event_handler.on_block_processed(number).await;
         }
 
         Ok(())
     }
 
+    /// Indexes the blocks as soon as their number is pushed by `heads`,
+    /// instead of polling for new blocks.
+    ///
+    /// Diri can't depend on `ark-starknet` yet, the new heads subscription
+    /// is then opened by the caller, with `StarknetClient::subscribe_new_heads`
+    /// for instance. Blocks missed between two heads are indexed too.
+    ///
+    /// # Arguments
+    ///
+    /// * `heads` - The numbers of the blocks added to the chain.
+    pub async fn index_new_heads<H>(&self, heads: H) -> IndexerResult<()>
+    where
+        H: Stream<Item = u64>,
+    {
+        let mut heads = std::pin::pin!(heads);
+        let mut next_block: Option<u64> = None;
+
+        while let Some(head) = heads.next().await {
+            let from = next_block.filter(|n| *n <= head).unwrap_or(head);
+
+            self.index_block_range(BlockId::Number(from), BlockId::Number(head))
+                .await?;
+
+            next_block = Some(head + 1);
+        }
+
+        Ok(())
+    }
+
+    /// Decodes and registers one orderbook event.
+    async fn process_event(
+        &self,
+        any_event: EmittedEvent,
+        block_number: u64,
+        block_timestamp: u64,
+    ) -> IndexerResult<()> {
+        let orderbook_event: Event = match any_event.try_into() {
+            Ok(ev) => ev,
+            Err(e) => {
+                trace!("Event can't be deserialized: {e}");
+                return Ok(());
+            }
+        };
+
+        match orderbook_event {
+            Event::OrderPlaced(ev) => {
+                trace!("OrderPlaced found: {:?}", ev);
+                self.storage
+                    .register_placed(block_number, block_timestamp, &ev.into())
+                    .await?;
+            }
+            Event::OrderCancelled(ev) => {
+                trace!("OrderCancelled found: {:?}", ev);
+                self.storage
+                    .register_cancelled(block_number, block_timestamp, &ev.into())
+                    .await?;
+            }
+            Event::OrderFulfilled(ev) => {
+                trace!("OrderFulfilled found: {:?}", ev);
+                self.storage
+                    .register_fulfilled(block_number, block_timestamp, &ev.into())
+                    .await?;
+            }
+            Event::OrderExecuted(ev) => {
+                trace!("OrderExecuted found: {:?}", ev);
+                self.storage
+                    .register_executed(block_number, block_timestamp, &ev.into())
+                    .await?;
+            }
+            Event::RollbackStatus(ev) => {
+                trace!("RollbackStatus found: {:?}", ev);
+                self.storage
+                    .status_back_to_open(block_number, block_timestamp, &ev.into())
+                    .await?;
+            }
+            _ => warn!("Orderbook event not handled: {:?}", orderbook_event),
+        };
+
+        Ok(())
+    }
+
+    /// Returns a stream of the events pages matching the given filter.
+    /// The continuation token returned by the provider is followed
+    /// lazily, a page being only requested once the previous one
+    /// has been consumed.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The events filter, applied to every page.
+    pub fn events_pages(
+        &self,
+        filter: EventFilter,
+    ) -> impl Stream<Item = Result<EventsPage, IndexerError>> + '_ {
+        let chunk_size = 1000;
+
+        paginate(move |continuation_token| {
+            let filter = filter.clone();
+
+            async move {
+                let page = self
+                    .provider
+                    .get_events(filter, continuation_token, chunk_size)
+                    .await?;
+                let next = page.continuation_token.clone();
+
+                Ok::<_, IndexerError>((page, next))
+            }
+        })
+    }
+
     /// Fetches the events with the given keys filter.
     /// This function fetches all the events by auto-following
     /// the continuation token returned by the provider.
     /// This ensures that all the events are returned for the
     /// given block range.
     ///
+    /// All the events are accumulated in memory, prefer
+    /// [`Diri::events_pages`] for large block ranges.
+    ///
     /// # Arguments
     ///
     /// * `provider` - The Starknet provider to get events from.
@@ -147,10 +244,6 @@ impl<S: Storage, E: EventHandler> Diri<S, E> {
         to_block: BlockId,
         keys: Option<Vec<Vec<FieldElement>>>,
     ) -> Result<HashMap<u64, Vec<EmittedEvent>>, IndexerError> {
-        // TODO: setup key filtering here.
-
-        let mut events: HashMap<u64, Vec<EmittedEvent>> = HashMap::new();
-
         let filter = EventFilter {
             from_block: Some(from_block),
             to_block: Some(to_block),
@@ -158,30 +251,18 @@ impl<S: Storage, E: EventHandler> Diri<S, E> {
             keys,
         };
 
-        let chunk_size = 1000;
-        let mut continuation_token: Option<String> = None;
-
-        loop {
-            let event_page = self
-                .provider
-                .get_events(filter.clone(), continuation_token, chunk_size)
-                .await?;
-
-            event_page.events.iter().for_each(|e| {
-                events
-                    .entry(e.block_number)
-                    .and_modify(|v| v.push(e.clone()))
-                    .or_insert(vec![e.clone()]);
-            });
-
-            continuation_token = event_page.continuation_token;
-
-            if continuation_token.is_none() {
-                break;
-            }
-        }
+        self.events_pages(filter)
+            .try_fold(HashMap::new(), |mut events, page| async move {
+                for e in page.events {
+                    events
+                        .entry(e.block_number)
+                        .or_insert_with(Vec::new)
+                        .push(e);
+                }
 
-        Ok(events)
+                Ok(events)
+            })
+            .await
     }
 
     /// Retrieves the timestamp of the given block.
@@ -29,4 +29,9 @@ pub trait EventHandler {
 
     // A new latest block has been detected.
     async fn on_new_latest_block(&self, block_number: u64) {}
+
+    /// The indexed blocks from `from_block` to `to_block` (included) were
+    /// orphaned by a chain reorganization. Their events are rolled back,
+    /// and the blocks of the canonical chain are indexed again.
+    async fn on_reorg(&self, from_block: u64, to_block: u64) {}
 }
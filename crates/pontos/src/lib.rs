@@ -1,14 +1,28 @@
 pub mod event_handler;
+pub mod filter;
 pub mod managers;
+pub mod marketplace;
+pub mod price_oracle;
 pub mod storage;
 
 use crate::storage::types::BlockIndexingStatus;
 use anyhow::Result;
-use ark_starknet::client::{StarknetClient, StarknetClientError};
+use ark_starknet::client::retry::RetryPolicy;
+use ark_starknet::client::stream::{chunk_by_block, events_pages, DEFAULT_CHUNK_SIZE};
+use ark_starknet::client::{BlockHeader, StarknetClient, StarknetClientError};
 use ark_starknet::format::to_hex_str;
 use event_handler::EventHandler;
-use managers::{BlockManager, ContractManager, EventManager, PendingBlockData, TokenManager};
+use filter::{ContractFilter, ContractFilterHandle};
+use futures::{StreamExt, TryStreamExt};
+use managers::{
+    BlockManager, ContractCacheConfig, ContractManager, CurrencyManager, EventManager,
+    OwnershipMode, OwnershipReport, PendingBlockData, TokenManager, TokenOwners,
+    TransferOccurrences,
+};
+use marketplace::{MarketplaceDecoder, MarketplaceRegistry};
+use price_oracle::PriceOracle;
 use starknet::core::types::*;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::sync::Arc;
 use storage::types::{ContractType, StorageError};
@@ -18,14 +32,16 @@ use tracing::{debug, error, info, trace, warn};
 
 pub type IndexerResult<T> = Result<T, IndexerError>;
 
-const ELEMENT_MARKETPLACE_EVENT_HEX: &str =
-    "0x351e5a57ea6ca22e3e3cd212680ef7f3b57404609bda942a5e75ba4724b55e0";
+/// Number of block headers requested in one batch
+/// while walking a block range.
+const BLOCK_HEADERS_BATCH_SIZE: u64 = 100;
 
-const VENTORY_MARKETPLACE_EVENT_HEX: &str =
-    "0x1b43f40d55364e989b3a8674460f61ba8f327542298ee6240a54ee2bf7b55bb"; // EventListingBought
+/// Delay between two polls of the head of the chain, in seconds.
+const HEAD_POLLING_SECS: u64 = 2;
 
-const VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX: &str =
-    "0xe214ba50bf9d17a50de9ab9f433295bd671144999d5258dbc261cbf1e1c2cc"; // EventOfferAccepted
+/// Maximum number of blocks walked back to find
+/// the fork of a chain reorganization.
+const MAX_REORG_DEPTH: u64 = 100;
 
 /// Generic errors for Pontos.
 #[derive(Debug)]
@@ -47,9 +63,18 @@ impl From<StarknetClientError> for IndexerError {
     }
 }
 
+/// Storage and Starknet errors returned by the managers keep their kind.
 impl From<anyhow::Error> for IndexerError {
     fn from(e: anyhow::Error) -> Self {
-        IndexerError::Anyhow(e.to_string())
+        let e = match e.downcast::<StorageError>() {
+            Ok(e) => return IndexerError::StorageError(e),
+            Err(e) => e,
+        };
+
+        match e.downcast::<StarknetClientError>() {
+            Ok(e) => IndexerError::Starknet(e),
+            Err(e) => IndexerError::Anyhow(e.to_string()),
+        }
     }
 }
 
@@ -65,9 +90,24 @@ impl fmt::Display for IndexerError {
 
 impl std::error::Error for IndexerError {}
 
+/// Configuration of [`Pontos`]. The default indexes the transfers of
+/// all the contracts and the sales of the built-in marketplaces.
+#[derive(Default)]
 pub struct PontosConfig {
     pub indexer_version: String,
     pub indexer_identifier: String,
+    /// Decoders of the marketplaces whose sales are indexed.
+    pub marketplaces: Arc<MarketplaceRegistry>,
+    /// Oracle converting the sale prices to its reference currency, if any.
+    pub price_oracle: Option<Arc<dyn PriceOracle>>,
+    pub ownership_mode: OwnershipMode,
+    /// Contracts indexed, see [`Pontos::contract_filter`]
+    /// to change them while indexing.
+    pub contract_filter: ContractFilter,
+    pub contract_cache: ContractCacheConfig,
+    /// Backoff between the attempts to index the blocks failing
+    /// in [`Pontos::run_forever`], giving up after `max_attempts`.
+    pub retry_policy: RetryPolicy,
 }
 
 pub struct Pontos<S: Storage, C: StarknetClient, E: EventHandler> {
@@ -77,8 +117,10 @@ pub struct Pontos<S: Storage, C: StarknetClient, E: EventHandler> {
     block_manager: Arc<BlockManager<S>>,
     event_manager: Arc<EventManager<S>>,
     token_manager: Arc<TokenManager<S, C>>,
-    contract_manager: Arc<AsyncRwLock<ContractManager<S, C>>>,
+    contract_manager: Arc<ContractManager<S, C>>,
+    currency_manager: Arc<CurrencyManager<C>>,
     pending_cache: Arc<AsyncRwLock<PendingBlockData>>,
+    contract_filter: ContractFilterHandle,
 }
 
 impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C, E> {
@@ -89,23 +131,54 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         config: PontosConfig,
     ) -> Self {
         Pontos {
-            config,
             client: Arc::clone(&client),
             event_handler: Arc::clone(&event_handler),
             block_manager: Arc::new(BlockManager::new(Arc::clone(&storage))),
-            event_manager: Arc::new(EventManager::new(Arc::clone(&storage))),
-            token_manager: Arc::new(TokenManager::new(Arc::clone(&storage), Arc::clone(&client))),
-            // Contract manager has internal cache, so some functions are using `&mut self`.
-            // For this reason, we must protect the write operations in order to share
-            // the cache with any possible thread using `index_block_range` of this instance.
-            contract_manager: Arc::new(AsyncRwLock::new(ContractManager::new(
+            event_manager: Arc::new(EventManager::new(
                 Arc::clone(&storage),
+                Arc::clone(&config.marketplaces),
+            )),
+            token_manager: Arc::new(TokenManager::new(
+                Arc::clone(&storage),
+                Arc::clone(&client),
+                config.ownership_mode,
+            )),
+            // Contract manager cache is locked internally, and can be shared
+            // with any possible thread using `index_block_range` of this instance.
+            contract_manager: Arc::new(ContractManager::new(
+                Arc::clone(&storage),
+                Arc::clone(&client),
+                config.contract_cache.clone(),
+            )),
+            currency_manager: Arc::new(CurrencyManager::new(
                 Arc::clone(&client),
-            ))),
+                config.price_oracle.clone(),
+            )),
             pending_cache: Arc::new(AsyncRwLock::new(PendingBlockData::new())),
+            contract_filter: ContractFilterHandle::new(config.contract_filter.clone()),
+            // Last, as the marketplaces and the price oracle are shared with the managers.
+            config,
         }
     }
 
+    /// Returns a handle to change the contracts indexed while indexing.
+    pub fn contract_filter(&self) -> ContractFilterHandle {
+        self.contract_filter.clone()
+    }
+
+    /// Fills the contract cache with the contracts already identified in
+    /// the storage, to avoid a storage request for each of them at boot.
+    pub async fn warm_contract_cache(&self, chain_id: &str) -> IndexerResult<usize> {
+        Ok(self.contract_manager.warm_start(chain_id).await?)
+    }
+
+    /// Returns the owners checked on chain so far, and the last mismatches
+    /// with the owners derived from the transfers.
+    /// Always empty if the ownership is not event-sourced.
+    pub fn ownership_report(&self) -> OwnershipReport {
+        self.token_manager.ownership_report()
+    }
+
     /// Starts a loop to only index the pending block.
     pub async fn index_pending(&self) -> IndexerResult<()> {
         loop {
@@ -166,6 +239,157 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         }
     }
 
+    /// Backfills the blocks from `from_block`, then follows the head of
+    /// the chain, indexing each new latest block as soon as it is produced.
+    ///
+    /// If `with_pending` is true, the transactions of the pending block are
+    /// indexed as they arrive, with provisional events. Those events are
+    /// cleaned once the block is sealed, the sealed block being then
+    /// indexed like any latest block.
+    ///
+    /// Blocks failing to be indexed are retried with the
+    /// [`PontosConfig::retry_policy`], the error being returned
+    /// once all the attempts failed.
+    pub async fn run_forever(
+        &self,
+        from_block: BlockId,
+        with_pending: bool,
+        chain_id: &str,
+    ) -> IndexerResult<()> {
+        let mut next_block = self.client.block_id_to_u64(&from_block).await?;
+        let mut failed_attempts = 0;
+
+        loop {
+            let latest = match self.client.block_number().await {
+                Ok(n) => n,
+                Err(e) => {
+                    error!("Error while fetching latest block number: {:?}", e);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            if latest >= next_block {
+                self.event_handler.on_new_latest_block(latest).await;
+
+                let result = async {
+                    // The pending block may now be sealed, its provisional events
+                    // are replaced by the ones of the latest blocks.
+                    self.clean_pending_block(&mut *self.pending_cache.write().await)
+                        .await?;
+
+                    self.index_block_range(
+                        BlockId::Number(next_block),
+                        BlockId::Number(latest),
+                        false,
+                        chain_id,
+                    )
+                    .await
+                }
+                .await;
+
+                match result {
+                    Ok(()) => {
+                        failed_attempts = 0;
+                        next_block = latest + 1;
+                    }
+                    Err(e) => {
+                        failed_attempts += 1;
+                        if failed_attempts >= self.config.retry_policy.max_attempts {
+                            return Err(e);
+                        }
+
+                        // The blocks already indexed are skipped by the next attempt.
+                        let delay = self.config.retry_policy.backoff(failed_attempts);
+                        error!(
+                            "Error while indexing blocks {} to {}, retrying in {:?}: {:?}",
+                            next_block, latest, delay, e
+                        );
+                        tokio::time::sleep(delay).await;
+                        continue;
+                    }
+                }
+            }
+
+            if with_pending {
+                if let Err(e) = self.index_pending_txs(chain_id).await {
+                    error!("Error while indexing pending block: {:?}", e);
+                }
+            }
+
+            tokio::time::sleep(tokio::time::Duration::from_secs(HEAD_POLLING_SECS)).await;
+        }
+    }
+
+    /// Indexes the transactions of the pending block not processed yet.
+    /// Their events are registered with the pending block timestamp.
+    async fn index_pending_txs(&self, chain_id: &str) -> IndexerResult<()> {
+        let (pending_ts, txs) = self
+            .client
+            .block_txs_hashes(BlockId::Tag(BlockTag::Pending))
+            .await?;
+
+        let mut cache = self.pending_cache.write().await;
+
+        if cache.get_timestamp() != pending_ts {
+            // The previous pending block was sealed or replaced.
+            self.clean_pending_block(&mut cache).await?;
+            cache.set_timestamp(pending_ts);
+
+            self.event_handler
+                .on_block_processing(pending_ts, None)
+                .await;
+        }
+
+        for tx_hash in txs {
+            if cache.is_tx_processed(&tx_hash) {
+                continue;
+            }
+
+            let events = self
+                .client
+                .events_from_tx_receipt(tx_hash, self.event_manager.keys_selector())
+                .await?;
+
+            debug!(
+                "Pending tx 0x{:064x}: processing {} events",
+                tx_hash,
+                events.len()
+            );
+
+            self.process_events(
+                events,
+                pending_ts,
+                chain_id,
+                &mut EventsContext {
+                    provisional: true,
+                    ..Default::default()
+                },
+            )
+            .await?;
+            cache.add_tx_as_processed(&tx_hash);
+        }
+
+        Ok(())
+    }
+
+    /// Cleans the provisional events of the pending block, if any.
+    async fn clean_pending_block(&self, cache: &mut PendingBlockData) -> IndexerResult<()> {
+        let pending_ts = cache.get_timestamp();
+        if pending_ts != 0 {
+            debug!(
+                "Cleaning provisional events of pending block {}",
+                pending_ts
+            );
+            self.block_manager.clean_block(pending_ts, None).await?;
+        }
+
+        cache.set_timestamp(0);
+        cache.clear_tx_hashes();
+
+        Ok(())
+    }
+
     pub async fn index_contract_events(
         &self,
         from_block: Option<BlockId>,
@@ -173,48 +397,350 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         contract_address: FieldElement,
         chain_id: &str,
     ) -> IndexerResult<()> {
-        let mut continuation_token: Option<String> = None;
+        let filter = EventFilter {
+            from_block,
+            to_block,
+            address: Some(contract_address),
+            keys: self.event_manager.keys_selector(),
+        };
 
-        loop {
-            let result = self
-                .client
-                .fetch_events(
-                    from_block,
-                    to_block,
-                    self.event_manager.keys_selector(),
-                    Some(contract_address),
-                    continuation_token,
+        let mut pages = std::pin::pin!(events_pages(
+            self.client.as_ref(),
+            filter,
+            DEFAULT_CHUNK_SIZE
+        ));
+
+        // Pages are processed as they arrive. The timestamps of the blocks
+        // of a page are fetched in a single batch, keeping the ones already
+        // known to avoid fetching them twice when a block spans multiple pages.
+        let mut block_times: HashMap<u64, u64> = HashMap::new();
+
+        while let Some(page) = pages.try_next().await? {
+            let chunks = chunk_by_block(page.events);
+
+            let missing: Vec<u64> = chunks
+                .iter()
+                .map(|(block_number, _)| *block_number)
+                .filter(|block_number| !block_times.contains_key(block_number))
+                .collect();
+
+            if !missing.is_empty() {
+                block_times.retain(|n, _| chunks.iter().any(|(block_number, _)| block_number == n));
+                block_times.extend(self.fetch_block_times(missing).await);
+            }
+
+            for (block_number, events) in chunks {
+                let block_timestamp = match block_times.get(&block_number) {
+                    Some(ts) => *ts,
+                    None => {
+                        error!(
+                            "Skipping events of block {} as timestamp is not available",
+                            block_number
+                        );
+                        continue;
+                    }
+                };
+
+                self.process_events(
+                    events,
+                    block_timestamp,
+                    chain_id,
+                    &mut EventsContext::default(),
                 )
                 .await?;
+            }
+        }
 
-            let mut current_block_number: u64 = 0;
-            let mut current_block_timestamp: u64 = 0;
+        Ok(())
+    }
 
-            for (block_number, events) in result.events {
-                if current_block_number != block_number {
-                    current_block_number = block_number;
+    /// Fetches the timestamps of the given blocks in a single batch.
+    /// Blocks with unavailable timestamp are missing from the returned map.
+    async fn fetch_block_times(
+        &self,
+        block_numbers: impl IntoIterator<Item = u64>,
+    ) -> HashMap<u64, u64> {
+        let block_numbers: Vec<u64> = block_numbers.into_iter().collect();
+        let block_ids: Vec<BlockId> = block_numbers.iter().map(|n| BlockId::Number(*n)).collect();
+
+        match self.client.block_times(&block_ids).await {
+            Ok(times) => block_numbers
+                .into_iter()
+                .zip(times)
+                .filter_map(|(block_number, ts)| match ts {
+                    Ok(ts) => Some((block_number, ts)),
+                    Err(e) => {
+                        error!("Couldn't get timestamp for block {}: {:?}", block_number, e);
+                        None
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                error!("Error while fetching block timestamps: {:?}", e);
+                HashMap::new()
+            }
+        }
+    }
 
-                    match self.client.block_time(BlockId::Number(block_number)).await {
-                        Ok(ts) => {
-                            current_block_timestamp = ts;
-                            self.process_events(events, current_block_timestamp, chain_id)
-                                .await?;
-                        }
-                        Err(e) => {
-                            error!("Error while fetching block timestamp: {:?}", e);
-                        }
-                    };
-                } else {
-                    self.process_events(events, current_block_timestamp, chain_id)
-                        .await?;
-                }
+    /// Fetches the headers of the given blocks in a single batch.
+    /// Blocks with unavailable header are missing from the returned map.
+    async fn fetch_block_headers(
+        &self,
+        block_numbers: impl IntoIterator<Item = u64>,
+    ) -> HashMap<u64, BlockHeader> {
+        let block_numbers: Vec<u64> = block_numbers.into_iter().collect();
+        let block_ids: Vec<BlockId> = block_numbers.iter().map(|n| BlockId::Number(*n)).collect();
+
+        match self.client.block_headers(&block_ids).await {
+            Ok(headers) => block_numbers
+                .into_iter()
+                .zip(headers)
+                .filter_map(|(block_number, header)| match header {
+                    Ok(header) => Some((block_number, header)),
+                    Err(e) => {
+                        error!("Couldn't get header for block {}: {:?}", block_number, e);
+                        None
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                error!("Error while fetching block headers: {:?}", e);
+                HashMap::new()
             }
+        }
+    }
 
-            if result.continuation_token.is_none() {
-                break;
+    /// Resumes the indexation after a crash or a restart.
+    ///
+    /// The blocks left processing are cleaned and indexed again,
+    /// then the blocks are indexed from the checkpoint of the indexer
+    /// up to the latest block. A block left processing which still fails
+    /// once retried with the [`PontosConfig::retry_policy`] is returned
+    /// as an error.
+    pub async fn resume(&self, chain_id: &str) -> IndexerResult<()> {
+        let indexer_identifier = &self.config.indexer_identifier;
+
+        let checkpoint = self
+            .block_manager
+            .get_checkpoint(indexer_identifier)
+            .await?
+            .ok_or_else(|| {
+                IndexerError::Anyhow(format!(
+                    "No checkpoint found to resume indexer {}",
+                    indexer_identifier
+                ))
+            })?;
+
+        let mut stuck_blocks = vec![];
+        for info in self
+            .block_manager
+            .get_processing_blocks(indexer_identifier)
+            .await?
+        {
+            warn!("Block {} was left processing", info.block_number);
+
+            if info.block_number <= checkpoint {
+                stuck_blocks.push(info.block_number);
             } else {
-                continuation_token = result.continuation_token;
-                continue;
+                // Blocks after the checkpoint are indexed again with the range,
+                // once cleaned to not be skipped.
+                self.block_manager
+                    .clean_block(info.block_timestamp, Some(info.block_number))
+                    .await?;
+            }
+        }
+
+        let headers = self.fetch_block_headers(stuck_blocks.clone()).await;
+        for block_number in stuck_blocks {
+            let header = headers.get(&block_number).ok_or_else(|| {
+                IndexerError::Anyhow(format!("Header of block {} not available", block_number))
+            })?;
+
+            // Forcing the indexation cleans the block first.
+            self.index_block_with_retries(header, true, chain_id)
+                .await?;
+        }
+
+        info!(
+            "Resuming indexer {} from block {}",
+            indexer_identifier,
+            checkpoint + 1
+        );
+
+        self.index_block_range(
+            BlockId::Number(checkpoint + 1),
+            BlockId::Tag(BlockTag::Latest),
+            false,
+            chain_id,
+        )
+        .await
+    }
+
+    /// Indexes the blocks as soon as they are pushed by the client
+    /// new heads subscription, instead of polling like [`Self::index_pending`].
+    ///
+    /// Blocks missed between two heads (while the node was reconnecting
+    /// for instance) are indexed first. Blocks failing with a Starknet error
+    /// are retried with the [`PontosConfig::retry_policy`]. Returns once the
+    /// subscription is closed, or an error if the client doesn't support
+    /// subscriptions, letting the caller subscribe again or fall back to polling.
+    pub async fn index_new_heads(&self, chain_id: &str) -> IndexerResult<()> {
+        let mut heads = self.client.subscribe_new_heads().await?;
+        let mut last_indexed: Option<u64> = None;
+
+        while let Some(head) = heads.try_next().await? {
+            debug!("New head #{}", head.block_number);
+            self.event_handler
+                .on_new_latest_block(head.block_number)
+                .await;
+
+            // Blocks to index before the head: the missed ones,
+            // and the ones orphaned by a reorganization.
+            let mut from_block = match last_indexed {
+                Some(last) if head.block_number > last + 1 => last + 1,
+                _ => head.block_number,
+            };
+            if let Some(fork) = self.rollback_orphaned_blocks(&head).await? {
+                from_block = from_block.min(fork);
+            }
+
+            if from_block < head.block_number {
+                self.index_block_range(
+                    BlockId::Number(from_block),
+                    BlockId::Number(head.block_number - 1),
+                    false,
+                    chain_id,
+                )
+                .await?;
+            }
+
+            self.index_block_with_retries(&head, false, chain_id)
+                .await?;
+
+            self.event_handler
+                .on_block_processed(head.block_number, 100.0)
+                .await;
+
+            last_indexed = Some(head.block_number);
+        }
+
+        info!("New heads subscription closed");
+        Ok(())
+    }
+
+    /// Indexes the blocks from the events pushed by the client events
+    /// subscription, the events of each block being pushed once instead
+    /// of being requested like [`Self::index_new_heads`] does.
+    ///
+    /// The events of a block are complete once an event of a following
+    /// block is pushed: the block is then committed, with the blocks in
+    /// between which have no event indexed. The blocks before the first
+    /// event pushed are indexed beforehand with [`Self::resume`].
+    ///
+    /// The subscription filters the events with the contract filter when
+    /// it is subscribed. Returns once the subscription is closed, the block
+    /// of the last events pushed being indexed by the next resume, or an error
+    /// if the client doesn't support subscriptions.
+    pub async fn index_new_events(&self, chain_id: &str) -> IndexerResult<()> {
+        let marketplace_contracts = self.config.marketplaces.contract_addresses(chain_id);
+        let mut pushed = self
+            .client
+            .subscribe_events(
+                self.contract_filter
+                    .event_filter_address(&marketplace_contracts),
+                self.event_manager.keys_selector(),
+            )
+            .await?;
+
+        // Block whose events are being pushed, with its events so far.
+        let mut current: Option<(u64, Vec<EmittedEvent>)> = None;
+
+        while let Some(event) = pushed.try_next().await? {
+            let block_number = match event.block_number {
+                Some(block_number) => block_number,
+                None => {
+                    trace!("Skipping event of the pending block");
+                    continue;
+                }
+            };
+
+            match current.as_mut() {
+                Some((n, events)) if *n == block_number => {
+                    events.push(event);
+                    continue;
+                }
+                Some((n, _)) if *n > block_number => {
+                    // Blocks are pushed again after a reorganization,
+                    // the block being pushed was orphaned.
+                    warn!(
+                        "Events of block {} pushed after block {}, dropping block {}",
+                        block_number, n, n
+                    );
+                }
+                _ => {}
+            }
+
+            if let Some((n, events)) = current.take().filter(|(n, _)| *n < block_number) {
+                self.event_handler.on_new_latest_block(block_number).await;
+                self.commit_pushed_blocks(n, events, block_number - 1, chain_id)
+                    .await?;
+            }
+
+            current = Some((block_number, vec![event]));
+        }
+
+        info!("Events subscription closed");
+        Ok(())
+    }
+
+    /// Commits the block `block_number` with the `events` pushed by the
+    /// subscription, then the following blocks up to `to_block`, which
+    /// have no event indexed.
+    async fn commit_pushed_blocks(
+        &self,
+        block_number: u64,
+        events: Vec<EmittedEvent>,
+        to_block: u64,
+        chain_id: &str,
+    ) -> IndexerResult<()> {
+        let mut events = Some(events);
+
+        for window_start in (block_number..=to_block).step_by(BLOCK_HEADERS_BATCH_SIZE as usize) {
+            let window = window_start..=to_block.min(window_start + BLOCK_HEADERS_BATCH_SIZE - 1);
+            let mut headers = self.fetch_block_headers(window.clone()).await;
+
+            for n in window {
+                // The block is indexed again by the next resume.
+                let header = match headers.remove(&n) {
+                    Some(header) => header,
+                    None => {
+                        warn!("Skipping block {} as header is not available", n);
+                        continue;
+                    }
+                };
+
+                if let Some(fork) = self.rollback_orphaned_blocks(&header).await? {
+                    if fork < n {
+                        self.index_block_range(
+                            BlockId::Number(fork),
+                            BlockId::Number(n - 1),
+                            false,
+                            chain_id,
+                        )
+                        .await?;
+                    }
+                }
+
+                let events = if n == block_number {
+                    events.take().unwrap_or_default()
+                } else {
+                    vec![]
+                };
+                let block = self.prepare_events(header, events, chain_id).await;
+
+                if self.commit_block(block, false, chain_id).await? {
+                    self.event_handler.on_block_processed(n, 100.0).await;
+                }
             }
         }
 
@@ -227,6 +753,9 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
     /// If you use this on latest, be sure to don't have any
     /// other pontos instance running `index_pending` as you may
     /// deal with overlaps or at least check db registers first.
+    ///
+    /// If a chain reorganization is detected, the orphaned blocks
+    /// are rolled back and the range is indexed again from the fork.
     pub async fn index_block_range(
         &self,
         from_block: BlockId,
@@ -238,15 +767,8 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         let to_u64 = self.client.block_id_to_u64(&to_block).await?;
         let from_u64 = current_u64;
 
-        // Some contracts are causing too much recursion for the Cairo VM.
-        // This is restarting the full node (Juno) as it is OOM and is shutdown by the OS.
-        // To mitigate this problem before scaling the full node up,
-        // we setup a `max_attempt` to reach the full node before skipping
-        // the entire block.
-        // Currently, we observed that the node almost always reponds after the
-        // second attempt.
-        let max_attempt = 5;
-        let mut attempt = 0;
+        // Headers are prefetched in batches of blocks.
+        let mut block_headers: HashMap<u64, BlockHeader> = HashMap::new();
 
         loop {
             trace!("Indexing block range: {} {}", current_u64, to_u64);
@@ -256,108 +778,57 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                 break;
             }
 
-            let block_ts = match self.client.block_time(BlockId::Number(current_u64)).await {
-                Ok(ts) => ts,
-                Err(e) => {
-                    error!(
-                        "Attempt #{} - Couldn't get timestamp for block {}: {:?}",
-                        attempt + 1,
-                        current_u64,
-                        e
-                    );
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                    attempt += 1;
-
-                    if attempt > max_attempt {
-                        warn!(
-                            "Skipping block {} as timestamp is not available",
-                            current_u64
-                        );
-                        current_u64 += 1;
-                    }
+            if !block_headers.contains_key(&current_u64) {
+                let window_end = to_u64.min(current_u64 + BLOCK_HEADERS_BATCH_SIZE - 1);
+                block_headers = self.fetch_block_headers(current_u64..=window_end).await;
+            }
 
+            // Transient errors (full node restarting, rate limit...) are already
+            // retried by the client with its retry policy. If the header is still
+            // not available, the entire block is skipped.
+            let header = match block_headers.get(&current_u64) {
+                Some(header) => header.clone(),
+                None => {
+                    warn!("Skipping block {} as header is not available", current_u64);
+                    current_u64 += 1;
                     continue;
                 }
             };
 
-            if self
-                .block_manager
-                .should_skip_indexing(
-                    current_u64,
-                    block_ts,
-                    self.config.indexer_version.clone(),
-                    do_force,
-                )
-                .await?
-            {
-                info!("Skipping block {}", current_u64);
-                current_u64 += 1;
-                continue;
+            if let Some(fork) = self.rollback_orphaned_blocks(&header).await? {
+                if fork < current_u64 {
+                    // Parents of the block were orphaned, the canonical chain
+                    // is indexed again from the fork.
+                    current_u64 = fork;
+                    block_headers.clear();
+                    continue;
+                }
             }
 
-            self.event_handler
-                .on_block_processing(block_ts, Some(current_u64))
-                .await;
-
-            // Set block as processing.
-            self.block_manager
-                .set_block_info(
-                    current_u64,
-                    block_ts,
-                    self.config.indexer_version.clone(),
-                    self.config.indexer_identifier.clone(),
-                    BlockIndexingStatus::Processing,
-                )
-                .await?;
-
-            let blocks_events = match self
-                .client
-                .fetch_all_block_events(
-                    BlockId::Number(current_u64),
-                    self.event_manager.keys_selector(),
-                )
+            match self
+                .index_block_with_retries(&header, do_force, chain_id)
                 .await
             {
-                Ok(events) => events,
-                Err(e) => {
-                    error!("Error while fetching events: {:?}", e);
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                Ok(true) => {}
+                Ok(false) => {
+                    current_u64 += 1;
                     continue;
                 }
-            };
-
-            let total_events_count: usize = blocks_events.values().map(|events| events.len()).sum();
-            info!(
-                "✨ Processing block {}. Total Events Count: {}.",
-                current_u64, total_events_count
-            );
-
-            for (_, events) in blocks_events {
-                self.process_events(events, block_ts, chain_id).await?;
-            }
-
-            self.block_manager
-                .set_block_info(
-                    current_u64,
-                    block_ts,
-                    self.config.indexer_version.clone(),
-                    self.config.indexer_identifier.clone(),
-                    BlockIndexingStatus::Terminated,
-                )
-                .await?;
-
-            let progress = if to_u64 == from_u64 {
-                if current_u64 == to_u64 {
-                    100.0
-                } else {
-                    0.0
+                Err(IndexerError::Starknet(e)) => {
+                    // The block is rolled back and not terminated, the checkpoint
+                    // not advancing over it: it is indexed again on resume.
+                    error!(
+                        "Skipping block {} after failed attempts: {:?}",
+                        current_u64, e
+                    );
+                    current_u64 += 1;
+                    continue;
                 }
-            } else {
-                ((current_u64 - from_u64) as f64 / (to_u64 - from_u64) as f64) * 100.0
-            };
+                Err(e) => return Err(e),
+            }
 
             self.event_handler
-                .on_block_processed(current_u64, progress)
+                .on_block_processed(current_u64, range_progress(current_u64, from_u64, to_u64))
                 .await;
 
             current_u64 += 1;
@@ -368,70 +839,581 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         Ok(())
     }
 
-    async fn process_element_sale(
-        &self,
-        event: EmittedEvent,
-        block_timestamp: u64,
-        chain_id: &str,
-    ) -> Result<()> {
-        let mut token_sale_event = self
-            .event_manager
-            .format_element_sale_event(&event, block_timestamp)
-            .await?;
+    /// Detects if the indexed blocks diverge from the canonical chain
+    /// ending with `header`, walking back through the block parents.
+    ///
+    /// The orphaned blocks are rolled back, and the first of them is
+    /// returned to be indexed again. `None` if no block is orphaned.
+    async fn rollback_orphaned_blocks(&self, header: &BlockHeader) -> IndexerResult<Option<u64>> {
+        let block_number = header.block_number;
+
+        // The block itself may already be indexed with another content.
+        let mut to_block = None;
+        if self
+            .block_manager
+            .is_orphaned(block_number, &header.block_hash)
+            .await?
+        {
+            to_block = Some(block_number);
+        }
 
-        let contract_addr = FieldElement::from_hex_be(
-            token_sale_event.nft_contract_address.as_str(),
-        )
-        .map_err(|e| {
-            error!("Invalid NFT contract address format: {:?}", e);
-            e
-        })?;
+        let mut fork = block_number;
+        let mut canonical_hash = header.parent_hash;
 
-        let contract_type = match self
-            .contract_manager
-            .write()
+        while fork > 0 && block_number - fork < MAX_REORG_DEPTH {
+            if !self
+                .block_manager
+                .is_orphaned(fork - 1, &canonical_hash)
+                .await?
+            {
+                break;
+            }
+
+            fork -= 1;
+            to_block.get_or_insert(fork);
+
+            if fork > 0 {
+                canonical_hash = self.client.block_hash(BlockId::Number(fork - 1)).await?;
+            }
+        }
+
+        let to_block = match to_block {
+            Some(to_block) => to_block,
+            None => return Ok(None),
+        };
+
+        if block_number - fork >= MAX_REORG_DEPTH {
+            warn!(
+                "Reorganization deeper than {} blocks, rolling back from block {}",
+                MAX_REORG_DEPTH, fork
+            );
+        }
+
+        // `fork` is the first orphaned block, unless only
+        // the given block itself was orphaned.
+        let from_block = fork.min(to_block);
+
+        warn!(
+            "Chain reorganization detected, rolling back blocks {} to {}",
+            from_block, to_block
+        );
+
+        self.block_manager
+            .rollback_blocks(from_block, to_block)
+            .await?;
+        if from_block > 0 {
+            self.block_manager
+                .rewind_checkpoint(&self.config.indexer_identifier, from_block - 1)
+                .await?;
+        }
+        self.event_handler.on_reorg(from_block, to_block).await;
+
+        Ok(Some(from_block))
+    }
+
+    /// Indexes the given range like [`Self::index_block_range`],
+    /// with up to `workers` blocks fetched concurrently.
+    ///
+    /// The events, the contracts and the token owners of the blocks are fetched
+    /// by the workers, sharing the contract manager cache. The blocks are then
+    /// committed to the storage one at a time in the block order, as the
+    /// ownership of the tokens depends on the order of the transfers.
+    pub async fn index_block_range_parallel(
+        &self,
+        from_block: BlockId,
+        to_block: BlockId,
+        do_force: bool,
+        chain_id: &str,
+        workers: usize,
+    ) -> IndexerResult<()> {
+        let from_u64 = self.client.block_id_to_u64(&from_block).await?;
+        let to_u64 = self.client.block_id_to_u64(&to_block).await?;
+
+        let mut current_u64 = from_u64;
+        while current_u64 <= to_u64 {
+            match self
+                .commit_prepared_blocks(current_u64, from_u64, to_u64, do_force, chain_id, workers)
+                .await?
+            {
+                // Parents of a block were orphaned, the canonical chain
+                // is indexed again from the fork.
+                Some(fork) => current_u64 = fork,
+                None => break,
+            }
+        }
+
+        info!("End of indexing block range");
+        self.event_handler.on_indexation_range_completed().await;
+
+        Ok(())
+    }
+
+    /// Prepares the blocks from `start_block` to `to_block` with `workers` concurrent
+    /// workers, and commits them in order as soon as they are prepared.
+    ///
+    /// Returns the block to restart from if a chain reorganization is detected,
+    /// the blocks already prepared after the fork being discarded.
+    async fn commit_prepared_blocks(
+        &self,
+        start_block: u64,
+        from_block: u64,
+        to_block: u64,
+        do_force: bool,
+        chain_id: &str,
+        workers: usize,
+    ) -> IndexerResult<Option<u64>> {
+        let windows = (start_block..=to_block)
+            .step_by(BLOCK_HEADERS_BATCH_SIZE as usize)
+            .map(|window_start| {
+                window_start..=to_block.min(window_start + BLOCK_HEADERS_BATCH_SIZE - 1)
+            });
+
+        let headers = futures::stream::iter(windows)
+            .then(|window| async move {
+                let mut headers = self.fetch_block_headers(window.clone()).await;
+                window
+                    .filter_map(|block_number| {
+                        let header = headers.remove(&block_number);
+                        if header.is_none() {
+                            warn!("Skipping block {} as header is not available", block_number);
+                        }
+                        header
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .flat_map(futures::stream::iter);
+
+        // `buffered` runs up to `workers` preparations concurrently,
+        // but yields the prepared blocks in the order of the headers.
+        let mut blocks = std::pin::pin!(headers
+            .map(|header| self.prepare_block(header, chain_id))
+            .buffered(workers.max(1)));
+
+        while let Some(block) = blocks.next().await {
+            let block = block?;
+            let block_number = block.header.block_number;
+
+            if let Some(fork) = self.rollback_orphaned_blocks(&block.header).await? {
+                if fork < block_number {
+                    return Ok(Some(fork));
+                }
+            }
+
+            if !self.commit_block(block, do_force, chain_id).await? {
+                continue;
+            }
+
+            self.event_handler
+                .on_block_processed(
+                    block_number,
+                    range_progress(block_number, from_block, to_block),
+                )
+                .await;
+        }
+
+        Ok(None)
+    }
+
+    /// Fetches the events of the block with the attempts of the
+    /// [`PontosConfig::retry_policy`], and prepares them.
+    async fn prepare_block(
+        &self,
+        header: BlockHeader,
+        chain_id: &str,
+    ) -> IndexerResult<PreparedBlock> {
+        let mut attempt = 0;
+        let events = loop {
+            let pages = events_pages(
+                self.client.as_ref(),
+                self.block_events_filter(header.block_number, chain_id),
+                DEFAULT_CHUNK_SIZE,
+            );
+
+            match pages.try_collect::<Vec<_>>().await {
+                Ok(pages) => break pages.into_iter().flat_map(|p| p.events).collect::<Vec<_>>(),
+                Err(e) => {
+                    attempt += 1;
+                    if attempt >= self.config.retry_policy.max_attempts {
+                        return Err(e.into());
+                    }
+
+                    error!(
+                        "Error while fetching events of block {}: {:?}",
+                        header.block_number, e
+                    );
+                    tokio::time::sleep(self.config.retry_policy.backoff(attempt)).await;
+                }
+            }
+        };
+
+        Ok(self.prepare_events(header, events, chain_id).await)
+    }
+
+    /// Identifies the contracts emitting the events of the block to fill
+    /// the contract manager cache, and fetches the owners of the ERC721
+    /// tokens transferred.
+    async fn prepare_events(
+        &self,
+        header: BlockHeader,
+        events: Vec<EmittedEvent>,
+        chain_id: &str,
+    ) -> PreparedBlock {
+        // Deployments are recorded first, for the deployers to be
+        // known when the contracts of the block are identified.
+        let contracts: HashSet<FieldElement> = events
+            .iter()
+            .filter(|e| !self.contract_manager.register_deployment(e))
+            .map(|e| e.from_address)
+            .filter(|address| {
+                !self
+                    .config
+                    .marketplaces
+                    .is_marketplace_contract(chain_id, address)
+                    && self.contract_filter.is_address_allowed(address)
+            })
+            .collect();
+
+        let mut erc721_contracts = HashSet::new();
+        for address in contracts {
+            // Failures are logged again when the event is committed.
+            match self
+                .contract_manager
+                .identify_contract(address, header.timestamp, chain_id)
+                .await
+            {
+                Ok(ContractType::ERC721) => {
+                    erc721_contracts.insert(address);
+                }
+                Ok(_) => {}
+                Err(e) => debug!(
+                    "Couldn't identify contract {} while preparing block {}: {:?}",
+                    to_hex_str(&address),
+                    header.block_number,
+                    e
+                ),
+            }
+        }
+
+        let tokens = events
+            .iter()
+            .filter(|e| erc721_contracts.contains(&e.from_address))
+            .filter_map(|e| {
+                EventManager::<S>::decode_transfer(e)
+                    .map(|(_, _, token_id)| (e.from_address, token_id))
+            })
+            .collect();
+        let owners = self.token_manager.fetch_owners(tokens).await;
+
+        PreparedBlock {
+            header,
+            events,
+            owners,
+        }
+    }
+
+    /// Commits the events of a prepared block, returning false
+    /// if the block is skipped as already indexed.
+    async fn commit_block(
+        &self,
+        block: PreparedBlock,
+        do_force: bool,
+        chain_id: &str,
+    ) -> IndexerResult<bool> {
+        let PreparedBlock {
+            header,
+            events,
+            owners,
+        } = block;
+
+        storage::in_block(header.block_number, async {
+            if !self.start_block(&header, do_force).await? {
+                return Ok(false);
+            }
+
+            let total_events_count = events.len();
+            let result = self
+                .process_events(
+                    events,
+                    header.timestamp,
+                    chain_id,
+                    &mut EventsContext {
+                        owners,
+                        ..Default::default()
+                    },
+                )
+                .await;
+
+            if result.is_ok() {
+                info!(
+                    "Block {} processed. Total Events Count: {}.",
+                    header.block_number, total_events_count
+                );
+            }
+
+            self.end_block(&header, result).await?;
+
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Indexes one block, returning false if the block is skipped
+    /// as already indexed.
+    ///
+    /// On error, the writes of the block that may already be done are
+    /// rolled back, for the block to be fully indexed again on the next attempt.
+    /// If `skip_failing_events`, the events failing with a Starknet error
+    /// are skipped instead, see [`EventsContext::skip_failing_events`].
+    async fn index_block(
+        &self,
+        header: &BlockHeader,
+        do_force: bool,
+        skip_failing_events: bool,
+        chain_id: &str,
+    ) -> IndexerResult<bool> {
+        let block_number = header.block_number;
+        let block_ts = header.timestamp;
+
+        storage::in_block(block_number, async {
+            if !self.start_block(header, do_force).await? {
+                return Ok(false);
+            }
+
+            let result = self
+                .process_block_events(block_number, block_ts, skip_failing_events, chain_id)
+                .await
+                .map(|total_events_count| {
+                    info!(
+                        "Block {} processed. Total Events Count: {}.",
+                        block_number, total_events_count
+                    );
+                });
+
+            self.end_block(header, result).await?;
+
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Indexes one block, retrying on Starknet errors with the
+    /// [`PontosConfig::retry_policy`]. On the last attempt, the failing events
+    /// are skipped. The error is returned if the block still fails.
+    async fn index_block_with_retries(
+        &self,
+        header: &BlockHeader,
+        do_force: bool,
+        chain_id: &str,
+    ) -> IndexerResult<bool> {
+        let retry_policy = &self.config.retry_policy;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            let last_attempt = attempt >= retry_policy.max_attempts;
+
+            match self
+                .index_block(header, do_force, last_attempt, chain_id)
+                .await
+            {
+                Err(IndexerError::Starknet(e)) if !last_attempt => {
+                    let delay = retry_policy.backoff(attempt);
+                    error!(
+                        "Error while indexing block {}, retrying in {:?}: {:?}",
+                        header.block_number, delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Begins the storage unit of work of the block, and sets the block
+    /// as processing, returning false if the block is skipped as already indexed.
+    /// Must be called in the scope of the block, see [`storage::in_block`].
+    async fn start_block(&self, header: &BlockHeader, do_force: bool) -> IndexerResult<bool> {
+        // A block indexed again is cleaned in its unit of work,
+        // the previous indexation being kept if the block fails.
+        self.block_manager.begin_block(header.block_number).await?;
+
+        match self
+            .block_manager
+            .should_skip_indexing(
+                header.block_number,
+                header.timestamp,
+                self.config.indexer_version.clone(),
+                do_force,
+            )
             .await
-            .identify_contract(contract_addr, block_timestamp, chain_id)
+        {
+            Ok(false) => {}
+            Ok(true) => {
+                info!("Skipping block {}", header.block_number);
+                self.rollback_block(header.block_number).await;
+                return Ok(false);
+            }
+            Err(e) => {
+                self.rollback_block(header.block_number).await;
+                return Err(e.into());
+            }
+        }
+
+        self.event_handler
+            .on_block_processing(header.timestamp, Some(header.block_number))
+            .await;
+
+        if let Err(e) = self
+            .block_manager
+            .set_block_info(
+                header,
+                self.config.indexer_version.clone(),
+                self.config.indexer_identifier.clone(),
+                BlockIndexingStatus::Processing,
+            )
             .await
         {
-            Ok(info) => info,
+            self.rollback_block(header.block_number).await;
+            return Err(e.into());
+        }
+
+        info!("✨ Processing block {}.", header.block_number);
+
+        Ok(true)
+    }
+
+    /// Sets the block as indexed and commits its writes if the block
+    /// was processed successfully, rolls back its writes otherwise.
+    async fn end_block(
+        &self,
+        header: &BlockHeader,
+        result: IndexerResult<()>,
+    ) -> IndexerResult<()> {
+        let result = match result {
+            Ok(()) => self.terminate_block(header).await,
+            Err(e) => Err(e),
+        };
+
+        let result = match result {
+            Ok(()) => self
+                .block_manager
+                .commit_block(header.block_number)
+                .await
+                .map_err(IndexerError::from),
             Err(e) => {
-                error!(
-                    "Error while identifying contract {}: {:?}",
-                    token_sale_event.nft_contract_address, e
-                );
-                return Ok(());
+                error!("Block {} rolled back: {}", header.block_number, e);
+                self.rollback_block(header.block_number).await;
+                Err(e)
             }
         };
 
-        if contract_type == ContractType::Other {
-            debug!(
-                "Contract identified as OTHER: {}",
-                token_sale_event.nft_contract_address
-            );
-            return Ok(());
+        // The transaction of the block is ended.
+        self.contract_manager.register_identified_contracts().await;
+        self.token_manager
+            .reconcile_block_owners(result.is_ok())
+            .await;
+
+        result
+    }
+
+    /// Rolls back the writes of the block. Errors are only logged,
+    /// as the error causing the rollback is the one returned.
+    async fn rollback_block(&self, block_number: u64) {
+        if let Err(e) = self.block_manager.rollback_block(block_number).await {
+            error!("Error while rolling back block {}: {:?}", block_number, e);
         }
+    }
 
-        token_sale_event.nft_type = Some(contract_type.to_string());
-        self.event_manager
-            .register_sale_event(&token_sale_event, block_timestamp)
+    /// Sets the block as indexed.
+    async fn terminate_block(&self, header: &BlockHeader) -> IndexerResult<()> {
+        self.block_manager
+            .set_block_info(
+                header,
+                self.config.indexer_version.clone(),
+                self.config.indexer_identifier.clone(),
+                BlockIndexingStatus::Terminated,
+            )
+            .await?;
+
+        self.block_manager
+            .advance_checkpoint(&self.config.indexer_identifier, header.block_number)
             .await?;
 
         Ok(())
     }
 
-    async fn process_ventory_sale_or_accepted_offer_event(
+    /// Filter of the events of a block. Only the events of the allowed
+    /// contract are requested if a single one is allowed, and there is
+    /// no marketplace on the chain.
+    fn block_events_filter(&self, block_number: u64, chain_id: &str) -> EventFilter {
+        let marketplace_contracts = self.config.marketplaces.contract_addresses(chain_id);
+
+        EventFilter {
+            from_block: Some(BlockId::Number(block_number)),
+            to_block: Some(BlockId::Number(block_number)),
+            address: self
+                .contract_filter
+                .event_filter_address(&marketplace_contracts),
+            keys: self.event_manager.keys_selector(),
+        }
+    }
+
+    /// Streams the events of the given block page by page,
+    /// processing each page as soon as it is received.
+    /// Returns the number of events processed.
+    async fn process_block_events(
         &self,
+        block_number: u64,
+        block_timestamp: u64,
+        skip_failing_events: bool,
+        chain_id: &str,
+    ) -> IndexerResult<usize> {
+        let mut pages = std::pin::pin!(events_pages(
+            self.client.as_ref(),
+            self.block_events_filter(block_number, chain_id),
+            DEFAULT_CHUNK_SIZE
+        ));
+
+        let mut total_events_count = 0;
+        let mut context = EventsContext {
+            skip_failing_events,
+            ..Default::default()
+        };
+
+        while let Some(page) = pages.try_next().await? {
+            trace!(
+                "Block {}: processing page of {} events",
+                block_number,
+                page.events.len()
+            );
+
+            total_events_count += page.events.len();
+            self.process_events(page.events, block_timestamp, chain_id, &mut context)
+                .await?;
+        }
+
+        Ok(total_events_count)
+    }
+
+    async fn process_marketplace_event(
+        &self,
+        decoder: &dyn MarketplaceDecoder,
         event: EmittedEvent,
         block_timestamp: u64,
         chain_id: &str,
     ) -> Result<()> {
-        info!("Processing Ventory Sale or Accepted Offer event...");
+        match event.keys.first() {
+            Some(selector) if decoder.selectors().contains(selector) => {
+                info!(
+                    "Processing {} marketplace event: {:?}",
+                    decoder.name(),
+                    selector
+                );
+            }
+            _ => return Ok(()),
+        }
 
-        let mut token_sale_event = self
-            .event_manager
-            .format_ventory_sale_or_accepted_offer_event(&event, block_timestamp)
-            .await?;
+        let mut token_sale_event = decoder.decode(&event, block_timestamp)?;
 
         let contract_addr = FieldElement::from_hex_be(
             token_sale_event.nft_contract_address.as_str(),
@@ -441,10 +1423,16 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
             e
         })?;
 
+        if !self.contract_filter.is_address_allowed(&contract_addr) {
+            trace!(
+                "Contract filtered out: {}",
+                token_sale_event.nft_contract_address
+            );
+            return Ok(());
+        }
+
         let contract_type = match self
             .contract_manager
-            .write()
-            .await
             .identify_contract(contract_addr, block_timestamp, chain_id)
             .await
         {
@@ -454,7 +1442,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                     "Error while identifying contract {}: {:?}",
                     token_sale_event.nft_contract_address, e
                 );
-                return Ok(());
+                return Err(e);
             }
         };
 
@@ -466,7 +1454,27 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
             return Ok(());
         }
 
+        if !self.contract_filter.is_type_allowed(&contract_type) {
+            trace!(
+                "Contract type filtered out: {}",
+                token_sale_event.nft_contract_address
+            );
+            return Ok(());
+        }
+
         token_sale_event.nft_type = Some(contract_type.to_string());
+
+        if let Err(e) = self
+            .currency_manager
+            .price_sale(&mut token_sale_event)
+            .await
+        {
+            error!(
+                "Error while pricing sale {}: {:?}",
+                token_sale_event.event_id, e
+            );
+        }
+
         self.event_manager
             .register_sale_event(&token_sale_event, block_timestamp)
             .await?;
@@ -474,54 +1482,23 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         Ok(())
     }
 
-    async fn process_marketplace_event(
-        &self,
-        event: EmittedEvent,
-        block_timestamp: u64,
-        chain_id: &str,
-    ) -> Result<()> {
-        let element_sale_event_name = FieldElement::from_hex_be(ELEMENT_MARKETPLACE_EVENT_HEX)?;
-        let ventory_sale_event_name = FieldElement::from_hex_be(VENTORY_MARKETPLACE_EVENT_HEX)?;
-        let ventory_offer_accepted_event_name =
-            FieldElement::from_hex_be(VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX)?;
-
-        if let Some(event_name) = event.keys.first() {
-            info!("Processing marketplace event: {:?}", event_name);
-
-            match event_name {
-                name if name == &element_sale_event_name => {
-                    self.process_element_sale(event, block_timestamp, chain_id)
-                        .await?
-                }
-                name if name == &ventory_sale_event_name
-                    || name == &ventory_offer_accepted_event_name =>
-                {
-                    self.process_ventory_sale_or_accepted_offer_event(
-                        event,
-                        block_timestamp,
-                        chain_id,
-                    )
-                    .await?
-                }
-                _ => (),
-            }
-        }
-
-        Ok(())
-    }
-
     async fn process_nft_transfers(
         &self,
         event: EmittedEvent,
         block_timestamp: u64,
         contract_address: FieldElement,
         chain_id: &str,
+        context: &mut EventsContext,
     ) -> Result<()> {
         let contract_address_hex = to_hex_str(&contract_address);
+
+        if !self.contract_filter.is_address_allowed(&contract_address) {
+            trace!("Contract filtered out: {}", contract_address_hex);
+            return Ok(());
+        }
+
         let contract_type = self
             .contract_manager
-            .write()
-            .await
             .identify_contract(contract_address, block_timestamp, chain_id)
             .await
             .map_err(|e| {
@@ -537,68 +1514,1056 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
             return Ok(());
         }
 
+        if !self.contract_filter.is_type_allowed(&contract_type) {
+            trace!("Contract type filtered out: {}", contract_address_hex);
+            return Ok(());
+        }
+
         info!(
             "Processing event... Block Id: {:?}, Tx Hash: 0x{:064x}, contract_type: {:?}",
             event.block_number, event.transaction_hash, contract_type
         );
 
-        let (token_id, token_event) = self
+        let transfers = self
             .event_manager
-            .format_and_register_event(&event, contract_type, block_timestamp)
+            .format_and_register_transfers(
+                &event,
+                contract_type,
+                block_timestamp,
+                &mut context.occurrences,
+            )
             .await
             .map_err(|err| {
                 error!("Error while registering event {:?}\n{:?}", err, event);
                 err
             })?;
 
-        self.token_manager
-            .format_and_register_token(&token_id, &token_event, block_timestamp, event.block_number)
-            .await
-            .map_err(|err| {
-                error!("Can't format token {:?}\ntevent: {:?}", err, token_event);
-                err
-            })?;
+        if context.provisional {
+            return Ok(());
+        }
+
+        for (token_id, token_event) in transfers {
+            self.token_manager
+                .format_and_register_token(
+                    &token_id,
+                    &token_event,
+                    block_timestamp,
+                    event.block_number,
+                    &context.owners,
+                )
+                .await
+                .map_err(|err| {
+                    error!("Can't format token {:?}\ntevent: {:?}", err, token_event);
+                    err
+                })?;
+        }
 
         Ok(())
     }
 
     /// Inner function to process events.
+    ///
+    /// Events that can't be decoded, or emitted by contracts that can't be
+    /// identified, are skipped. Storage and Starknet errors are returned,
+    /// for the block to be rolled back and indexed again, unless the
+    /// failing events are skipped by the `context`.
+    ///
+    /// The `context` must be kept for all the events of a block,
+    /// processed in their order.
     async fn process_events(
         &self,
         events: Vec<EmittedEvent>,
         block_timestamp: u64,
         chain_id: &str,
+        context: &mut EventsContext,
     ) -> IndexerResult<()> {
-        let marketplace_contracts = [
-            FieldElement::from_hex_be(
-                "0x04d8bb956e6bd7a50fcb8b49d8e9fd8269cfadbeb73f457fd6d3fc1dff4b879e", // Element Marketplace
-            )
-            .unwrap(),
-            FieldElement::from_hex_be(
-                "0x008755a98ccf7d25e69aa90ef3b73b07c470ba4ec6391b0b0c7c598f992c3fee", // Ventory Marketplace
-            )
-            .unwrap(),
-        ];
-
         for e in events {
             let contract_address = e.from_address;
-            let is_marketplace_event = marketplace_contracts.contains(&contract_address);
+            let transaction_hash = e.transaction_hash;
 
-            if is_marketplace_event {
-                if let Err(e) = self
-                    .process_marketplace_event(e, block_timestamp, chain_id)
-                    .await
-                {
-                    error!("Error while processing marketplace event: {:?}", e);
-                }
-            } else if let Err(e) = self
-                .process_nft_transfers(e, block_timestamp, contract_address, chain_id)
-                .await
+            if self.contract_manager.register_deployment(&e) {
+                continue;
+            }
+
+            let result = if let Some(decoder) = self
+                .config
+                .marketplaces
+                .decoder(chain_id, &contract_address)
             {
-                error!("Error while processing NFT transfers: {:?}", e);
+                self.process_marketplace_event(decoder, e, block_timestamp, chain_id)
+                    .await
+            } else {
+                self.process_nft_transfers(e, block_timestamp, contract_address, chain_id, context)
+                    .await
+            };
+
+            match result.map_err(IndexerError::from) {
+                Ok(()) => {}
+                Err(IndexerError::Anyhow(e)) => warn!(
+                    "Skipping event of tx 0x{:064x} emitted by {}: {}",
+                    transaction_hash,
+                    to_hex_str(&contract_address),
+                    e
+                ),
+                Err(IndexerError::Starknet(e)) if context.skip_failing_events => warn!(
+                    "Skipping failing event of tx 0x{:064x} emitted by {}: {:?}",
+                    transaction_hash,
+                    to_hex_str(&contract_address),
+                    e
+                ),
+                Err(e) => return Err(e),
             }
         }
 
         Ok(())
     }
 }
+
+/// Block fetched by a worker of [`Pontos::index_block_range_parallel`],
+/// or pushed to [`Pontos::index_new_events`], waiting to be committed.
+struct PreparedBlock {
+    header: BlockHeader,
+    events: Vec<EmittedEvent>,
+    owners: TokenOwners,
+}
+
+/// State of the processing of the events of a block,
+/// see [`Pontos::process_events`].
+#[derive(Debug, Default)]
+struct EventsContext {
+    occurrences: TransferOccurrences,
+    /// The provisional events of the pending block are only registered,
+    /// to be cleaned once the block is sealed. The tokens they transfer
+    /// are registered when the sealed block is indexed.
+    provisional: bool,
+    /// Owners fetched ahead by the workers of
+    /// [`Pontos::index_block_range_parallel`].
+    owners: TokenOwners,
+    /// Events failing with a Starknet error, like a contract which can't be
+    /// called by the full node, are skipped on the last attempt to index
+    /// the block, for a single event to not block the indexation.
+    skip_failing_events: bool,
+}
+
+/// Progress of the indexation of a block range, in percent.
+fn range_progress(current_block: u64, from_block: u64, to_block: u64) -> f64 {
+    if to_block == from_block {
+        if current_block == to_block {
+            100.0
+        } else {
+            0.0
+        }
+    } else {
+        (current_block.saturating_sub(from_block) as f64 / (to_block - from_block) as f64) * 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::types::BlockInfo;
+    use crate::storage::MockStorage;
+    use ark_starknet::client::MockStarknetClient;
+    use mockall::Sequence;
+    use starknet::macros::selector;
+
+    struct NoopEventHandler;
+
+    impl EventHandler for NoopEventHandler {}
+
+    fn config() -> PontosConfig {
+        PontosConfig {
+            indexer_version: String::from("v0.0.1"),
+            indexer_identifier: String::from("TASK#123"),
+            marketplaces: Arc::new(MarketplaceRegistry::new()),
+            ..Default::default()
+        }
+    }
+
+    fn header(block_number: u64) -> BlockHeader {
+        BlockHeader {
+            block_number,
+            block_hash: FieldElement::from(block_number),
+            parent_hash: FieldElement::from(block_number - 1),
+            timestamp: block_number * 10,
+        }
+    }
+
+    /// ERC721 transfer of the token 1 from 0x0 to 0x2.
+    fn transfer_event(block_number: u64) -> EmittedEvent {
+        EmittedEvent {
+            from_address: FieldElement::from(0x1234_u64),
+            keys: vec![selector!("Transfer")],
+            data: vec![
+                FieldElement::ZERO,
+                FieldElement::TWO,
+                FieldElement::ONE,
+                FieldElement::ZERO,
+            ],
+            block_hash: Some(FieldElement::from(block_number)),
+            block_number: Some(block_number),
+            transaction_hash: FieldElement::from(0x5678_u64),
+        }
+    }
+
+    fn client_with_events(events: Vec<EmittedEvent>) -> MockStarknetClient {
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_fetch_events_page()
+            .times(1)
+            .returning(move |_, _, _| {
+                Ok(EventsPage {
+                    events: events.clone(),
+                    continuation_token: None,
+                })
+            });
+        client
+    }
+
+    fn erc721_storage() -> MockStorage {
+        let mut storage = MockStorage::default();
+        storage
+            .expect_get_contract_type()
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(ContractType::ERC721))));
+        storage
+    }
+
+    #[tokio::test]
+    async fn test_process_events_propagates_storage_errors() {
+        let mut storage = erc721_storage();
+        storage
+            .expect_register_transfer_event()
+            .times(1)
+            .returning(|_, _| {
+                Box::pin(futures::future::ready(Err(StorageError::DatabaseError(
+                    "connection lost".to_string(),
+                ))))
+            });
+
+        let pontos = Pontos::new(
+            Arc::new(MockStarknetClient::default()),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            config(),
+        );
+
+        let result = pontos
+            .process_events(
+                vec![transfer_event(1)],
+                10,
+                "0x1",
+                &mut EventsContext::default(),
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(IndexerError::StorageError(StorageError::DatabaseError(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_process_events_skips_undecodable_events() {
+        let mut storage = erc721_storage();
+        storage.expect_register_transfer_event().times(0);
+
+        let pontos = Pontos::new(
+            Arc::new(MockStarknetClient::default()),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            config(),
+        );
+
+        let mut event = transfer_event(1);
+        event.data.clear();
+
+        assert!(pontos
+            .process_events(vec![event], 10, "0x1", &mut EventsContext::default())
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_index_block_forced_cleans_in_block_and_rolls_back() {
+        let mut storage = erc721_storage();
+        let mut seq = Sequence::new();
+
+        // The block is cleaned in its unit of work, and restored on error.
+        storage
+            .expect_begin_block()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_clean_block()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                // Cleaned in the scope of the block, hence in its transaction.
+                assert_eq!(storage::current_block(), Some(7));
+                Box::pin(futures::future::ready(Ok(())))
+            });
+        storage
+            .expect_set_block_info()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_register_transfer_event()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                Box::pin(futures::future::ready(Err(StorageError::DatabaseError(
+                    "connection lost".to_string(),
+                ))))
+            });
+        storage
+            .expect_rollback_block()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage.expect_commit_block().times(0);
+
+        let pontos = Pontos::new(
+            Arc::new(client_with_events(vec![transfer_event(7)])),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            config(),
+        );
+
+        let result = pontos.index_block(&header(7), true, false, "0x1").await;
+
+        assert!(matches!(result, Err(IndexerError::StorageError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_index_block_skipped_is_rolled_back() {
+        let mut storage = MockStorage::default();
+        let mut seq = Sequence::new();
+
+        storage
+            .expect_begin_block()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_get_block_info()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|block_number| {
+                Box::pin(futures::future::ready(Ok(BlockInfo {
+                    status: BlockIndexingStatus::Terminated,
+                    indexer_version: String::from("v0.0.1"),
+                    indexer_identifier: String::from("TASK#123"),
+                    block_number,
+                    block_timestamp: block_number * 10,
+                    block_hash: None,
+                    parent_hash: None,
+                })))
+            });
+        storage
+            .expect_rollback_block()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage.expect_clean_block().times(0);
+        storage.expect_commit_block().times(0);
+
+        let pontos = Pontos::new(
+            Arc::new(MockStarknetClient::default()),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            config(),
+        );
+
+        assert!(!pontos
+            .index_block(&header(7), false, false, "0x1")
+            .await
+            .unwrap());
+    }
+
+    /// Client of the blocks `from..=to`, whose events are given by `events`.
+    fn range_client(
+        from: u64,
+        to: u64,
+        events: fn(u64) -> Vec<EmittedEvent>,
+    ) -> MockStarknetClient {
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_block_id_to_u64()
+            .returning(move |id| match id {
+                BlockId::Number(n) => Ok(*n),
+                _ => Ok(to),
+            });
+        client.expect_block_headers().returning(move |ids| {
+            Ok(ids
+                .iter()
+                .map(|id| match id {
+                    BlockId::Number(n) if (from..=to).contains(n) => Ok(header(*n)),
+                    _ => Err(StarknetClientError::Other("unknown block".to_string())),
+                })
+                .collect())
+        });
+        client
+            .expect_fetch_events_page()
+            .returning(move |filter, _, _| match filter.from_block {
+                Some(BlockId::Number(n)) => Ok(EventsPage {
+                    events: events(n),
+                    continuation_token: None,
+                }),
+                _ => Ok(EventsPage {
+                    events: vec![],
+                    continuation_token: None,
+                }),
+            });
+        client
+    }
+
+    /// Storage of blocks never indexed, of ERC721 contracts.
+    fn range_storage() -> MockStorage {
+        let mut storage = erc721_storage();
+        storage.expect_get_block_info().returning(|n| {
+            Box::pin(futures::future::ready(Err(StorageError::NotFound(
+                n.to_string(),
+            ))))
+        });
+        storage
+            .expect_set_block_info()
+            .returning(|_, _, _| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_get_checkpoint()
+            .returning(|_| Box::pin(futures::future::ready(Ok(None))));
+        storage
+            .expect_set_checkpoint()
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+        storage
+    }
+
+    #[tokio::test]
+    async fn test_parallel_blocks_committed_in_order() {
+        let mut client = range_client(1, 3, |n| {
+            if n == 2 {
+                vec![transfer_event(2)]
+            } else {
+                vec![]
+            }
+        });
+        // The owner is fetched once by the worker.
+        client
+            .expect_call_contract()
+            .times(1)
+            .returning(|_, _, _, _| Ok(vec![FieldElement::from(0x99_u64)]));
+
+        let mut storage = range_storage();
+        let mut seq = Sequence::new();
+        for n in 1..=3 {
+            storage
+                .expect_begin_block()
+                .withf(move |block_number| *block_number == n)
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+            if n == 2 {
+                storage
+                    .expect_register_transfer_event()
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+                storage
+                    .expect_register_token()
+                    .withf(|token, _| token.owner == to_hex_str(&FieldElement::from(0x99_u64)))
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+                storage
+                    .expect_register_mint()
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .returning(|_, _, _, _| Box::pin(futures::future::ready(Ok(()))));
+            }
+            storage
+                .expect_commit_block()
+                .withf(move |block_number| *block_number == n)
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        }
+
+        let pontos = Pontos::new(
+            Arc::new(client),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            config(),
+        );
+
+        pontos
+            .index_block_range_parallel(BlockId::Number(1), BlockId::Number(3), false, "0x1", 3)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_parallel_block_rolled_back_on_error() {
+        let mut client = range_client(1, 2, |n| vec![transfer_event(n)]);
+        client
+            .expect_call_contract()
+            .returning(|_, _, _, _| Ok(vec![FieldElement::TWO]));
+
+        let mut storage = range_storage();
+        storage
+            .expect_begin_block()
+            .withf(|block_number| *block_number == 1)
+            .times(1)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_register_transfer_event()
+            .times(1)
+            .returning(|_, _| {
+                Box::pin(futures::future::ready(Err(StorageError::DatabaseError(
+                    "connection lost".to_string(),
+                ))))
+            });
+        storage
+            .expect_rollback_block()
+            .withf(|block_number| *block_number == 1)
+            .times(1)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage.expect_commit_block().times(0);
+
+        let pontos = Pontos::new(
+            Arc::new(client),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            config(),
+        );
+
+        // The following blocks are not committed.
+        let result = pontos
+            .index_block_range_parallel(BlockId::Number(1), BlockId::Number(2), false, "0x1", 2)
+            .await;
+
+        assert!(matches!(result, Err(IndexerError::StorageError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_prepare_block_bounded_retries() {
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_fetch_events_page()
+            .times(3)
+            .returning(|_, _, _| Err(StarknetClientError::Other("timeout".to_string())));
+
+        let pontos = Pontos::new(
+            Arc::new(client),
+            Arc::new(MockStorage::default()),
+            Arc::new(NoopEventHandler),
+            PontosConfig {
+                retry_policy: RetryPolicy {
+                    max_attempts: 3,
+                    initial_backoff: std::time::Duration::ZERO,
+                    ..Default::default()
+                },
+                ..config()
+            },
+        );
+
+        assert!(matches!(
+            pontos.prepare_block(header(1), "0x1").await,
+            Err(IndexerError::Starknet(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_index_new_heads_bounded_retries() {
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_subscribe_new_heads()
+            .times(1)
+            .returning(|| Ok(futures::stream::iter(vec![Ok(header(5))]).boxed()));
+        client
+            .expect_fetch_events_page()
+            .times(3)
+            .returning(|_, _, _| Err(StarknetClientError::Other("timeout".to_string())));
+
+        // Each attempt is rolled back.
+        let mut storage = range_storage();
+        storage
+            .expect_begin_block()
+            .times(3)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_rollback_block()
+            .times(3)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+
+        let pontos = Pontos::new(
+            Arc::new(client),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            PontosConfig {
+                retry_policy: RetryPolicy {
+                    max_attempts: 3,
+                    initial_backoff: std::time::Duration::ZERO,
+                    ..Default::default()
+                },
+                ..config()
+            },
+        );
+
+        assert!(matches!(
+            pontos.index_new_heads("0x1").await,
+            Err(IndexerError::Starknet(_))
+        ));
+    }
+
+    /// Storage of blocks never indexed, of contracts never identified.
+    fn unidentified_range_storage() -> MockStorage {
+        let mut storage = MockStorage::default();
+        storage.expect_get_contract_type().returning(|address, _| {
+            Box::pin(futures::future::ready(Err(StorageError::NotFound(
+                address.to_string(),
+            ))))
+        });
+        storage.expect_get_block_info().returning(|n| {
+            Box::pin(futures::future::ready(Err(StorageError::NotFound(
+                n.to_string(),
+            ))))
+        });
+        storage
+            .expect_set_block_info()
+            .returning(|_, _, _| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_get_checkpoint()
+            .returning(|_| Box::pin(futures::future::ready(Ok(None))));
+        storage
+            .expect_set_checkpoint()
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+        storage
+    }
+
+    fn two_attempts_config() -> PontosConfig {
+        PontosConfig {
+            retry_policy: RetryPolicy {
+                max_attempts: 2,
+                initial_backoff: std::time::Duration::ZERO,
+                ..Default::default()
+            },
+            ..config()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_block_range_skips_failing_events_on_last_attempt() {
+        let mut client = range_client(1, 1, |n| vec![transfer_event(n)]);
+        // The contract can't be identified, the node failing its calls.
+        client
+            .expect_call_contracts()
+            .times(2)
+            .returning(|_, _| Err(StarknetClientError::Other("out of memory".to_string())));
+
+        // The first attempt is rolled back, the last one skips the event.
+        let mut storage = unidentified_range_storage();
+        let mut seq = Sequence::new();
+        storage
+            .expect_begin_block()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_rollback_block()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_begin_block()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_commit_block()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage.expect_register_transfer_event().times(0);
+
+        let pontos = Pontos::new(
+            Arc::new(client),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            two_attempts_config(),
+        );
+
+        pontos
+            .index_block_range(BlockId::Number(1), BlockId::Number(1), false, "0x1")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_index_block_range_skips_failing_block() {
+        let mut client = MockStarknetClient::default();
+        client.expect_block_id_to_u64().returning(|id| match id {
+            BlockId::Number(n) => Ok(*n),
+            _ => Ok(2),
+        });
+        client.expect_block_headers().returning(|ids| {
+            Ok(ids
+                .iter()
+                .map(|id| match id {
+                    BlockId::Number(n) => Ok(header(*n)),
+                    _ => Err(StarknetClientError::Other("unknown block".to_string())),
+                })
+                .collect())
+        });
+        client
+            .expect_fetch_events_page()
+            .returning(|filter, _, _| match filter.from_block {
+                Some(BlockId::Number(1)) => Err(StarknetClientError::Other("timeout".to_string())),
+                _ => Ok(EventsPage {
+                    events: vec![],
+                    continuation_token: None,
+                }),
+            });
+
+        // The block 1 is rolled back on each attempt, and the range goes on.
+        let mut storage = unidentified_range_storage();
+        storage
+            .expect_begin_block()
+            .withf(|block_number| *block_number == 1)
+            .times(2)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_rollback_block()
+            .withf(|block_number| *block_number == 1)
+            .times(2)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_begin_block()
+            .withf(|block_number| *block_number == 2)
+            .times(1)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_commit_block()
+            .withf(|block_number| *block_number == 2)
+            .times(1)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+
+        let pontos = Pontos::new(
+            Arc::new(client),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            two_attempts_config(),
+        );
+
+        pontos
+            .index_block_range(BlockId::Number(1), BlockId::Number(2), false, "0x1")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_bounded_retries_of_processing_blocks() {
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_block_headers()
+            .returning(|_| Ok(vec![Ok(header(3))]));
+        client
+            .expect_fetch_events_page()
+            .times(2)
+            .returning(|_, _, _| Err(StarknetClientError::Other("timeout".to_string())));
+
+        let mut storage = MockStorage::default();
+        storage
+            .expect_get_checkpoint()
+            .returning(|_| Box::pin(futures::future::ready(Ok(Some(5)))));
+        storage
+            .expect_get_blocks_by_status()
+            .times(1)
+            .returning(|_, status| {
+                Box::pin(futures::future::ready(Ok(vec![BlockInfo {
+                    status,
+                    indexer_version: String::from("v0.0.1"),
+                    indexer_identifier: String::from("TASK#123"),
+                    block_number: 3,
+                    block_timestamp: 30,
+                    block_hash: None,
+                    parent_hash: None,
+                }])))
+            });
+        // Each attempt cleans the block and is rolled back.
+        storage
+            .expect_begin_block()
+            .times(2)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_clean_block()
+            .times(2)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_set_block_info()
+            .times(2)
+            .returning(|_, _, _| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_rollback_block()
+            .times(2)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage.expect_commit_block().times(0);
+
+        let pontos = Pontos::new(
+            Arc::new(client),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            two_attempts_config(),
+        );
+
+        assert!(matches!(
+            pontos.resume("0x1").await,
+            Err(IndexerError::Starknet(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_index_new_events_commits_complete_blocks() {
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_subscribe_events()
+            .withf(|address, _| address.is_none())
+            .times(1)
+            .returning(|_, _| {
+                Ok(
+                    futures::stream::iter(vec![Ok(transfer_event(5)), Ok(transfer_event(7))])
+                        .boxed(),
+                )
+            });
+        client.expect_block_headers().returning(|ids| {
+            Ok(ids
+                .iter()
+                .map(|id| match id {
+                    BlockId::Number(n) => Ok(header(*n)),
+                    _ => Err(StarknetClientError::Other("unknown block".to_string())),
+                })
+                .collect())
+        });
+        client
+            .expect_call_contract()
+            .times(1)
+            .returning(|_, _, _, _| Ok(vec![FieldElement::TWO]));
+        // The events pushed are not requested.
+        client.expect_fetch_events_page().times(0);
+
+        // Block 6 has no event, block 7 is not complete when
+        // the subscription is closed.
+        let mut storage = range_storage();
+        let mut seq = Sequence::new();
+        for n in 5..=6 {
+            storage
+                .expect_begin_block()
+                .withf(move |block_number| *block_number == n)
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+            if n == 5 {
+                storage
+                    .expect_register_transfer_event()
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+                storage
+                    .expect_register_token()
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+                storage
+                    .expect_register_mint()
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .returning(|_, _, _, _| Box::pin(futures::future::ready(Ok(()))));
+            }
+            storage
+                .expect_commit_block()
+                .withf(move |block_number| *block_number == n)
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        }
+
+        let pontos = Pontos::new(
+            Arc::new(client),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            config(),
+        );
+
+        pontos.index_new_events("0x1").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pending_transfers_only_register_events() {
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_block_txs_hashes()
+            .times(2)
+            .returning(|_| Ok((100, vec![FieldElement::ONE])));
+        client
+            .expect_events_from_tx_receipt()
+            .times(1)
+            .returning(|_, _| Ok(vec![transfer_event(10)]));
+
+        // Owners and mints are registered once the block is sealed.
+        let mut storage = erc721_storage();
+        storage
+            .expect_register_transfer_event()
+            .times(1)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+        storage.expect_register_token().times(0);
+        storage.expect_register_mint().times(0);
+
+        let pontos = Pontos::new(
+            Arc::new(client),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            config(),
+        );
+
+        // The transactions already processed are skipped.
+        pontos.index_pending_txs("0x1").await.unwrap();
+        pontos.index_pending_txs("0x1").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_forever_retries_failing_blocks() {
+        let mut client = MockStarknetClient::default();
+        client.expect_block_id_to_u64().returning(|_| Ok(5));
+        client.expect_block_number().returning(|| Ok(5));
+        client
+            .expect_block_headers()
+            .times(3)
+            .returning(|_| Ok(vec![Ok(header(5))]));
+
+        let mut storage = MockStorage::default();
+        storage.expect_get_block_info().times(3).returning(|_| {
+            Box::pin(futures::future::ready(Err(StorageError::DatabaseError(
+                "connection lost".to_string(),
+            ))))
+        });
+
+        let pontos = Pontos::new(
+            Arc::new(client),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            PontosConfig {
+                retry_policy: RetryPolicy {
+                    max_attempts: 3,
+                    initial_backoff: std::time::Duration::ZERO,
+                    ..Default::default()
+                },
+                ..config()
+            },
+        );
+
+        let result = pontos.run_forever(BlockId::Number(5), false, "0x1").await;
+
+        assert!(matches!(
+            result,
+            Err(IndexerError::StorageError(StorageError::DatabaseError(_)))
+        ));
+    }
+
+    /// Indexation of the block of the fixture shared with the Sana and metadata
+    /// tests, see the `recording` module of `ark-starknet` to regenerate it.
+    #[cfg(feature = "sqlxdb")]
+    mod recorded_block {
+        use super::*;
+        use crate::storage::DefaultSqlxStorage;
+        use ark_starknet::client::{
+            RecordingStarknetClient, ReplayStarknetClient, StarknetClientHttp,
+        };
+
+        /// Block with the mint of the token 42 of an ERC721 contract, and an ERC20 transfer.
+        const RECORDED_FIXTURE: &str = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../ark-starknet/fixtures/erc721_mint_block.jsonl"
+        );
+        const RECORDED_BLOCK: u64 = 640_512;
+        const RECORDED_NFT: &str =
+            "0x0727a63f78ee3f1bd18f78009067411ab369c31dece1ae22e16f567906409905";
+        const RECORDED_ERC20: &str =
+            "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
+        const RECORDED_OWNER: &str =
+            "0x05ea7b0e9e4a8b3a7f1e7cbd1ab79be0c5e0a2b8d6c2f3a1e4b9d8c7a6f5e4d3";
+
+        /// Indexes the recorded block into an in-memory SQLite database.
+        async fn index_recorded_block<C>(client: C) -> Arc<DefaultSqlxStorage>
+        where
+            C: StarknetClient + Send + Sync + 'static,
+        {
+            let storage = Arc::new(
+                DefaultSqlxStorage::migrate("sqlite::memory:")
+                    .await
+                    .unwrap(),
+            );
+            let pontos = Pontos::new(
+                Arc::new(client),
+                Arc::clone(&storage),
+                Arc::new(NoopEventHandler),
+                config(),
+            );
+
+            pontos
+                .index_block_range(
+                    BlockId::Number(RECORDED_BLOCK),
+                    BlockId::Number(RECORDED_BLOCK),
+                    false,
+                    "0x534e5f4d41494e",
+                )
+                .await
+                .unwrap();
+
+            storage
+        }
+
+        /// Records the requests of the pipeline on a mainnet node.
+        #[tokio::test]
+        #[ignore = "requires a mainnet RPC node, see ARK_STARKNET_RECORD_RPC_URL"]
+        async fn test_record_block() {
+            let rpc_url = std::env::var("ARK_STARKNET_RECORD_RPC_URL")
+                .expect("ARK_STARKNET_RECORD_RPC_URL is the URL of a mainnet RPC node");
+            let client = RecordingStarknetClient::wrap(
+                StarknetClientHttp::new(&rpc_url).unwrap(),
+                std::env::temp_dir().join("erc721_mint_block.pontos.jsonl"),
+            )
+            .unwrap();
+
+            index_recorded_block(client).await;
+        }
+
+        #[tokio::test]
+        async fn test_index_recorded_block() {
+            let client = ReplayStarknetClient::from_path(RECORDED_FIXTURE).unwrap();
+            let storage = index_recorded_block(client).await;
+
+            assert_eq!(
+                storage
+                    .get_contract_type(RECORDED_NFT, "0x534e5f4d41494e")
+                    .await
+                    .unwrap(),
+                ContractType::ERC721
+            );
+            assert_eq!(
+                storage
+                    .get_contract_type(RECORDED_ERC20, "0x534e5f4d41494e")
+                    .await
+                    .unwrap(),
+                ContractType::Other
+            );
+
+            let tokens = storage.get_tokens_by_owner(RECORDED_OWNER).await.unwrap();
+            assert_eq!(tokens.len(), 1);
+            assert_eq!(tokens[0].contract_address, RECORDED_NFT);
+            assert_eq!(tokens[0].token_id, "42");
+
+            let block = storage.get_block_info(RECORDED_BLOCK).await.unwrap();
+            assert_eq!(block.status, BlockIndexingStatus::Terminated);
+        }
+    }
+}
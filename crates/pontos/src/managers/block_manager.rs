@@ -1,5 +1,7 @@
 use crate::storage::types::{BlockIndexingStatus, BlockInfo, StorageError};
 use crate::storage::Storage;
+use ark_starknet::client::BlockHeader;
+use ark_starknet::format::to_hex_str;
 use starknet::core::types::FieldElement;
 use std::sync::Arc;
 use tracing::{debug, trace};
@@ -37,15 +39,11 @@ impl<S: Storage> BlockManager<S> {
         do_force: bool,
     ) -> Result<bool, StorageError> {
         if do_force {
-            // Force indexing by cleaning the block, and return true.
-            match self
-                .storage
+            // Force indexing by cleaning the block, and return false.
+            self.storage
                 .clean_block(block_timestamp, Some(block_number))
                 .await
-            {
-                Ok(()) => Ok(false),
-                Err(_) => Ok(true),
-            }
+                .map(|_| false)
         } else {
             match self.storage.get_block_info(block_number).await {
                 Ok(info) => {
@@ -76,26 +74,165 @@ impl<S: Storage> BlockManager<S> {
 
     pub async fn set_block_info(
         &self,
-        block_number: u64,
-        block_timestamp: u64,
+        header: &BlockHeader,
         indexer_version: String,
         indexer_identifier: String,
         status: BlockIndexingStatus,
     ) -> Result<(), StorageError> {
         self.storage
             .set_block_info(
-                block_number,
-                block_timestamp,
+                header.block_number,
+                header.timestamp,
                 BlockInfo {
                     indexer_version,
                     indexer_identifier,
                     status,
-                    block_number,
+                    block_number: header.block_number,
+                    block_timestamp: header.timestamp,
+                    block_hash: Some(to_hex_str(&header.block_hash)),
+                    parent_hash: Some(to_hex_str(&header.parent_hash)),
                 },
             )
             .await?;
         Ok(())
     }
+
+    /// Returns true if the given block was indexed with a hash different
+    /// from `canonical_hash`, the indexed block being then orphaned.
+    /// Blocks not indexed, or indexed without hash, are never orphaned.
+    pub async fn is_orphaned(
+        &self,
+        block_number: u64,
+        canonical_hash: &FieldElement,
+    ) -> Result<bool, StorageError> {
+        match self.storage.get_block_info(block_number).await {
+            Ok(BlockInfo {
+                block_hash: Some(hash),
+                ..
+            }) => Ok(hash != to_hex_str(canonical_hash)),
+            Ok(_) | Err(StorageError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the blocks left processing by the given indexer,
+    /// after a crash for instance.
+    pub async fn get_processing_blocks(
+        &self,
+        indexer_identifier: &str,
+    ) -> Result<Vec<BlockInfo>, StorageError> {
+        self.storage
+            .get_blocks_by_status(indexer_identifier, BlockIndexingStatus::Processing)
+            .await
+    }
+
+    pub async fn get_checkpoint(
+        &self,
+        indexer_identifier: &str,
+    ) -> Result<Option<u64>, StorageError> {
+        self.storage.get_checkpoint(indexer_identifier).await
+    }
+
+    /// Moves the checkpoint of the indexer forward once the given block
+    /// is indexed, over the blocks following the checkpoint that are all
+    /// terminated. A block indexed after a gap doesn't move it: the
+    /// checkpoint reaches it once the blocks of the gap are indexed.
+    /// The checkpoint is never moved backward, as older blocks
+    /// may be indexed again without the following ones.
+    pub async fn advance_checkpoint(
+        &self,
+        indexer_identifier: &str,
+        block_number: u64,
+    ) -> Result<(), StorageError> {
+        let mut next = match self.storage.get_checkpoint(indexer_identifier).await? {
+            Some(checkpoint) if checkpoint >= block_number => return Ok(()),
+            Some(checkpoint) => checkpoint + 1,
+            // The first block indexed starts the checkpoint.
+            None => block_number,
+        };
+
+        let mut last_terminated = None;
+        while self.is_terminated(indexer_identifier, next).await? {
+            last_terminated = Some(next);
+            next += 1;
+        }
+
+        match last_terminated {
+            Some(checkpoint) => {
+                self.storage
+                    .set_checkpoint(indexer_identifier, checkpoint)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Returns true if the block was indexed by the indexer.
+    async fn is_terminated(
+        &self,
+        indexer_identifier: &str,
+        block_number: u64,
+    ) -> Result<bool, StorageError> {
+        match self.storage.get_block_info(block_number).await {
+            Ok(info) => Ok(info.status == BlockIndexingStatus::Terminated
+                && info.indexer_identifier == indexer_identifier),
+            Err(StorageError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Moves the checkpoint of the indexer back to the given block,
+    /// when the following blocks were rolled back.
+    pub async fn rewind_checkpoint(
+        &self,
+        indexer_identifier: &str,
+        block_number: u64,
+    ) -> Result<(), StorageError> {
+        match self.storage.get_checkpoint(indexer_identifier).await? {
+            Some(checkpoint) if checkpoint > block_number => {
+                self.storage
+                    .set_checkpoint(indexer_identifier, block_number)
+                    .await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts the storage unit of work of the block,
+    /// see [`Storage::begin_block`].
+    pub async fn begin_block(&self, block_number: u64) -> Result<(), StorageError> {
+        self.storage.begin_block(block_number).await
+    }
+
+    pub async fn commit_block(&self, block_number: u64) -> Result<(), StorageError> {
+        self.storage.commit_block(block_number).await
+    }
+
+    pub async fn rollback_block(&self, block_number: u64) -> Result<(), StorageError> {
+        self.storage.rollback_block(block_number).await
+    }
+
+    /// Cleans the indexed blocks of the given range, rolling back their events.
+    pub async fn rollback_blocks(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<(), StorageError> {
+        for block_number in from_block..=to_block {
+            match self.storage.get_block_info(block_number).await {
+                Ok(info) => {
+                    debug!("Rolling back block {}", block_number);
+                    self.storage
+                        .clean_block(info.block_timestamp, Some(block_number))
+                        .await?
+                }
+                Err(StorageError::NotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// Data of the pending block being indexed.
@@ -178,7 +315,7 @@ mod tests {
             .await
             .unwrap();
 
-        assert!(result == false);
+        assert!(!result);
     }
 
     #[tokio::test]
@@ -200,6 +337,9 @@ mod tests {
                         indexer_version: String::from("v0.0.1"),
                         indexer_identifier: String::from("TASK#123"),
                         block_number: 123,
+                        block_timestamp: 0,
+                        block_hash: None,
+                        parent_hash: None,
                     })
                 } else {
                     Err(StorageError::NotFound("".to_string()))
@@ -220,13 +360,186 @@ mod tests {
             .should_skip_indexing(1, 0, "v0.0.2".to_string(), false)
             .await
             .unwrap();
-        assert!(result == false);
+        assert!(!result);
 
         // Force but same version, should return true for indexing.
         let result = manager
             .should_skip_indexing(2, 0, "v0.0.1".to_string(), true)
             .await
             .unwrap();
-        assert!(result == false);
+        assert!(!result);
+    }
+
+    fn block_info(block_number: u64, block_hash: Option<u64>) -> BlockInfo {
+        BlockInfo {
+            status: BlockIndexingStatus::Terminated,
+            indexer_version: String::from("v0.0.1"),
+            indexer_identifier: String::from("TASK#123"),
+            block_number,
+            block_timestamp: block_number * 10,
+            block_hash: block_hash.map(|h| to_hex_str(&FieldElement::from(h))),
+            parent_hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_is_orphaned() {
+        let mut mock_storage = MockStorage::default();
+
+        mock_storage
+            .expect_get_block_info()
+            .returning(|block_number| {
+                Box::pin(futures::future::ready(match block_number {
+                    1 => Ok(block_info(1, Some(0xaa))),
+                    2 => Ok(block_info(2, None)),
+                    _ => Err(StorageError::NotFound("".to_string())),
+                }))
+            });
+
+        let manager = BlockManager {
+            storage: Arc::new(mock_storage),
+        };
+
+        let hash = FieldElement::from(0xaa_u64);
+        let other_hash = FieldElement::from(0xbb_u64);
+
+        assert!(!manager.is_orphaned(1, &hash).await.unwrap());
+        assert!(manager.is_orphaned(1, &other_hash).await.unwrap());
+        // Unknown hash or block.
+        assert!(!manager.is_orphaned(2, &other_hash).await.unwrap());
+        assert!(!manager.is_orphaned(3, &other_hash).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_blocks() {
+        let mut mock_storage = MockStorage::default();
+
+        mock_storage
+            .expect_get_block_info()
+            .returning(|block_number| {
+                Box::pin(futures::future::ready(match block_number {
+                    5 | 7 => Ok(block_info(block_number, Some(0xaa))),
+                    _ => Err(StorageError::NotFound("".to_string())),
+                }))
+            });
+
+        mock_storage
+            .expect_clean_block()
+            .withf(|ts, n| *ts == 50 && *n == Some(5))
+            .times(1)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+        mock_storage
+            .expect_clean_block()
+            .withf(|ts, n| *ts == 70 && *n == Some(7))
+            .times(1)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+
+        let manager = BlockManager {
+            storage: Arc::new(mock_storage),
+        };
+
+        manager.rollback_blocks(5, 7).await.unwrap();
+    }
+
+    /// Storage of the checkpoint, and of the blocks terminated.
+    fn checkpoint_storage(checkpoint: Option<u64>, terminated: &'static [u64]) -> MockStorage {
+        let mut mock_storage = MockStorage::default();
+
+        mock_storage
+            .expect_get_checkpoint()
+            .returning(move |_| Box::pin(futures::future::ready(Ok(checkpoint))));
+        mock_storage.expect_get_block_info().returning(|n| {
+            let info = terminated
+                .contains(&n)
+                .then(|| BlockInfo {
+                    status: BlockIndexingStatus::Terminated,
+                    indexer_version: String::from("v0.0.1"),
+                    indexer_identifier: String::from("TASK#123"),
+                    block_number: n,
+                    block_timestamp: n * 10,
+                    block_hash: None,
+                    parent_hash: None,
+                })
+                .ok_or_else(|| StorageError::NotFound(n.to_string()));
+            Box::pin(futures::future::ready(info))
+        });
+
+        mock_storage
+    }
+
+    #[tokio::test]
+    async fn test_advance_checkpoint() {
+        let mut mock_storage = checkpoint_storage(Some(10), &[11, 12, 14]);
+
+        // The checkpoint moves over the following blocks indexed,
+        // up to the first one not indexed.
+        mock_storage
+            .expect_set_checkpoint()
+            .withf(|identifier, block_number| identifier == "TASK#123" && *block_number == 12)
+            .times(1)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+
+        let manager = BlockManager {
+            storage: Arc::new(mock_storage),
+        };
+
+        manager.advance_checkpoint("TASK#123", 9).await.unwrap();
+        manager.advance_checkpoint("TASK#123", 10).await.unwrap();
+        manager.advance_checkpoint("TASK#123", 11).await.unwrap();
+        // The blocks were indexed by another indexer.
+        manager.advance_checkpoint("TASK#456", 11).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_advance_checkpoint_over_gap() {
+        let mut mock_storage = checkpoint_storage(Some(10), &[12]);
+
+        // Block 11 is not indexed yet, block 12 doesn't move the checkpoint.
+        mock_storage.expect_set_checkpoint().times(0);
+
+        let manager = BlockManager {
+            storage: Arc::new(mock_storage),
+        };
+
+        manager.advance_checkpoint("TASK#123", 12).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_advance_first_checkpoint() {
+        let mut mock_storage = checkpoint_storage(None, &[5]);
+
+        mock_storage
+            .expect_set_checkpoint()
+            .withf(|_, block_number| *block_number == 5)
+            .times(1)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+
+        let manager = BlockManager {
+            storage: Arc::new(mock_storage),
+        };
+
+        manager.advance_checkpoint("TASK#123", 5).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rewind_checkpoint() {
+        let mut mock_storage = MockStorage::default();
+
+        mock_storage
+            .expect_get_checkpoint()
+            .returning(|_| Box::pin(futures::future::ready(Ok(Some(10)))));
+
+        mock_storage
+            .expect_set_checkpoint()
+            .withf(|_, block_number| *block_number == 7)
+            .times(1)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+
+        let manager = BlockManager {
+            storage: Arc::new(mock_storage),
+        };
+
+        manager.rewind_checkpoint("TASK#123", 12).await.unwrap();
+        manager.rewind_checkpoint("TASK#123", 7).await.unwrap();
     }
 }
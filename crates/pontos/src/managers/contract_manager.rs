@@ -1,64 +1,290 @@
 use crate::storage::{
-    types::{ContractInfo, ContractType, StorageError},
+    self,
+    types::{ContractInfo, ContractType},
     Storage,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ark_starknet::{
     cairo_string_parser::parse_cairo_string,
     client::{StarknetClient, StarknetClientError},
     format::to_hex_str,
 };
+use num_bigint::BigUint;
 use starknet::core::{
-    types::{BlockId, BlockTag, FieldElement},
+    types::{BlockId, BlockTag, EmittedEvent, FieldElement, FunctionCall},
     utils::get_selector_from_name,
 };
-use std::collections::HashMap;
-use std::sync::Arc;
+use starknet::macros::{felt, selector};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use tracing::{error, info, trace};
 
+/// SRC5 interface IDs, probed with `supports_interface`.
+pub const SRC5_INTERFACE_ID: FieldElement =
+    felt!("0x3f918d17e5ee77373b56385708f855659a07f75997f365cf87748628532a055");
+pub const ERC721_INTERFACE_ID: FieldElement =
+    felt!("0x33eb2f84c309543403fd69f0d0f363781ef06ef6faeb0131ff16ea3175bd943");
+pub const ERC721_METADATA_INTERFACE_ID: FieldElement =
+    felt!("0xabbcd595a567dce909050a1038e055daccb3c42af06f0add544fa90ee91f25");
+pub const ERC1155_INTERFACE_ID: FieldElement =
+    felt!("0x6114a8f75559e1b39fcba08ce02961a1aa082d9256a158dd3e64964e4b1b52");
+pub const ERC1155_METADATA_URI_INTERFACE_ID: FieldElement =
+    felt!("0xcabe2400d5fe509e1735ba9bad205ba5f3ca6e062da406f72f113feb889ef7");
+pub const ERC2981_INTERFACE_ID: FieldElement =
+    felt!("0x2d3414e45a8700c29f119a54b9f11dca0e29e06ddcb214018fc37340e165ed6");
+
+/// Probed interfaces, in the order of the `supports_interface` calls.
+const PROBED_INTERFACE_IDS: [FieldElement; 6] = [
+    SRC5_INTERFACE_ID,
+    ERC721_INTERFACE_ID,
+    ERC721_METADATA_INTERFACE_ID,
+    ERC1155_INTERFACE_ID,
+    ERC1155_METADATA_URI_INTERFACE_ID,
+    ERC2981_INTERFACE_ID,
+];
+
+/// Universal Deployer Contract, whose events give the deployer of the contracts.
+pub const UDC_ADDRESS: FieldElement =
+    felt!("0x041a78e741e5af2fec34b695679bc6891742439f7afb8484ecd7766661ad02bf");
+pub const CONTRACT_DEPLOYED_SELECTOR: FieldElement = selector!("ContractDeployed");
+
+/// Settings of the contract types cache of [`ContractManager`].
+#[derive(Debug, Clone)]
+pub struct ContractCacheConfig {
+    /// Maximum number of contracts cached,
+    /// the least recently used are evicted first.
+    pub capacity: usize,
+    /// Duration after which a contract identified as `Other` is
+    /// identified again on chain, as it may have been upgraded.
+    pub other_ttl: Duration,
+    /// Duration during which a contract that failed to
+    /// be identified is not requested again.
+    pub failure_ttl: Duration,
+}
+
+impl Default for ContractCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 100_000,
+            other_ttl: Duration::from_secs(3600),
+            failure_ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CachedContract {
+    Identified(ContractType),
+    /// The contract failed to be identified.
+    Failed,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    contract: CachedContract,
+    expires_at: Option<Instant>,
+    /// Tick of the cache at the last access, for the LRU eviction.
+    last_used: AtomicU64,
+}
+
+#[derive(Debug, PartialEq)]
+enum CacheLookup {
+    Hit(CachedContract),
+    /// The contract was cached, but must be identified again.
+    Expired,
+    Miss,
+}
+
+/// Bounded LRU cache of the contract types.
+///
+/// Lookups only take a read lock, the recency of the entries being
+/// atomic. The write lock is only taken to insert new contracts.
+#[derive(Debug)]
+struct ContractCache {
+    config: ContractCacheConfig,
+    entries: RwLock<HashMap<FieldElement, CacheEntry>>,
+    tick: AtomicU64,
+}
+
+impl ContractCache {
+    fn new(config: ContractCacheConfig) -> Self {
+        Self {
+            config,
+            entries: RwLock::new(HashMap::new()),
+            tick: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self, address: &FieldElement) -> CacheLookup {
+        let entries = self.entries.read().unwrap();
+
+        match entries.get(address) {
+            Some(entry) if entry.expires_at.map_or(false, |t| t <= Instant::now()) => {
+                CacheLookup::Expired
+            }
+            Some(entry) => {
+                entry
+                    .last_used
+                    .store(self.tick.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+                CacheLookup::Hit(entry.contract.clone())
+            }
+            None => CacheLookup::Miss,
+        }
+    }
+
+    fn insert(&self, address: FieldElement, contract: CachedContract) {
+        let ttl = match &contract {
+            CachedContract::Identified(ContractType::Other) => Some(self.config.other_ttl),
+            CachedContract::Identified(_) => None,
+            CachedContract::Failed => Some(self.config.failure_ttl),
+        };
+
+        let entry = CacheEntry {
+            contract,
+            expires_at: ttl.map(|ttl| Instant::now() + ttl),
+            last_used: AtomicU64::new(self.tick.fetch_add(1, Ordering::Relaxed)),
+        };
+
+        let mut entries = self.entries.write().unwrap();
+
+        if entries.len() >= self.config.capacity && !entries.contains_key(&address) {
+            Self::evict(&mut entries, self.config.capacity);
+        }
+
+        entries.insert(address, entry);
+    }
+
+    /// Removes the expired entries, and the least recently used ones
+    /// to free a tenth of the capacity, to not evict on each insert.
+    fn evict(entries: &mut HashMap<FieldElement, CacheEntry>, capacity: usize) {
+        let now = Instant::now();
+        entries.retain(|_, e| e.expires_at.map_or(true, |t| t > now));
+
+        let target = capacity - (capacity / 10).max(1).min(capacity);
+        if entries.len() <= target {
+            return;
+        }
+
+        let mut ticks: Vec<u64> = entries
+            .values()
+            .map(|e| e.last_used.load(Ordering::Relaxed))
+            .collect();
+        let evicted = entries.len() - target;
+        let (_, threshold, _) = ticks.select_nth_unstable(evicted - 1);
+        let threshold = *threshold;
+
+        entries.retain(|_, e| e.last_used.load(Ordering::Relaxed) > threshold);
+
+        trace!("{} contracts evicted from cache", evicted);
+    }
+
+    fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+}
+
+/// Deployers seen in the UDC events, kept until their contract is
+/// identified. The oldest are dropped past the capacity.
+#[derive(Debug, Default)]
+struct Deployers {
+    capacity: usize,
+    by_contract: HashMap<FieldElement, FieldElement>,
+    order: VecDeque<FieldElement>,
+}
+
+impl Deployers {
+    fn insert(&mut self, contract: FieldElement, deployer: FieldElement) {
+        self.by_contract.insert(contract, deployer);
+        self.order.push_back(contract);
+
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.by_contract.remove(&oldest);
+            }
+        }
+    }
+
+    fn take(&mut self, contract: &FieldElement) -> Option<FieldElement> {
+        self.by_contract.remove(contract)
+    }
+}
+
 pub struct ContractManager<S: Storage, C: StarknetClient> {
     storage: Arc<S>,
     client: Arc<C>,
     /// A cache with contract address mapped to its type.
-    cache: HashMap<FieldElement, ContractType>,
+    cache: ContractCache,
+    deployers: Mutex<Deployers>,
+    /// Contracts identified in the scope of a block, with the timestamp
+    /// of the block, waiting for the block to be ended to be registered.
+    unregistered: Mutex<Vec<(ContractInfo, u64)>>,
 }
 
 impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
     /// Initializes a new instance.
-    pub fn new(storage: Arc<S>, client: Arc<C>) -> Self {
+    pub fn new(storage: Arc<S>, client: Arc<C>, cache_config: ContractCacheConfig) -> Self {
         Self {
             storage,
             client,
-            cache: HashMap::new(),
+            deployers: Mutex::new(Deployers {
+                capacity: cache_config.capacity,
+                ..Default::default()
+            }),
+            cache: ContractCache::new(cache_config),
+            unregistered: Mutex::new(Vec::new()),
         }
     }
 
-    /// Gets the contract info from local cache, or fetch is from the DB.
-    async fn get_cached_or_fetch_info(
-        &mut self,
-        address: FieldElement,
-        chain_id: &str,
-    ) -> Result<ContractType, StorageError> {
-        if let Some(contract_type) = self.cache.get(&address) {
-            return Ok(contract_type.clone());
+    /// Records the deployer of a contract deployed through the UDC,
+    /// stored with the contract info once identified.
+    /// Returns false if the event is not a UDC deployment.
+    pub fn register_deployment(&self, event: &EmittedEvent) -> bool {
+        if event.from_address != UDC_ADDRESS
+            || event.keys.first() != Some(&CONTRACT_DEPLOYED_SELECTOR)
+        {
+            return false;
         }
 
-        trace!("Cache miss for contract {:#064x}", address);
+        // Data starts with the deployed address and the deployer.
+        if let [address, deployer, ..] = event.data[..] {
+            self.deployers.lock().unwrap().insert(address, deployer);
+        }
+
+        true
+    }
 
-        let contract_type = self
+    /// Fills the cache with the contracts last registered in the storage,
+    /// up to the cache capacity. Returns the number of contracts cached.
+    pub async fn warm_start(&self, chain_id: &str) -> Result<usize> {
+        let contracts = self
             .storage
-            .get_contract_type(&to_hex_str(&address), chain_id)
+            .get_contract_types(chain_id, self.cache.config.capacity as u64)
             .await?;
 
-        self.cache.insert(address, contract_type.clone()); // Adding to the cache
+        for (address, contract_type) in contracts.into_iter().rev() {
+            match FieldElement::from_hex_be(&address) {
+                Ok(address) => self
+                    .cache
+                    .insert(address, CachedContract::Identified(contract_type)),
+                Err(e) => error!("Invalid contract address {} in storage: {:?}", address, e),
+            }
+        }
 
-        Ok(contract_type)
+        let count = self.cache.len();
+        info!("Contract cache warmed with {} contracts", count);
+
+        Ok(count)
     }
 
     /// Identifies a contract from its address and caches its info.
     ///
     /// This function attempts to identify a contract by its address,
     /// fetching its type, name, and symbol, and caching these details for future use.
+    /// Contracts whose responses failed to identify them are not requested
+    /// again until the failure TTL of the cache expires, while contracts
+    /// whose batch of calls failed are requested again on their next event.
     ///
     /// # Arguments
     /// * `address` - The address of the contract as a `FieldElement`.
@@ -67,72 +293,209 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
     /// # Returns
     /// * `Result<ContractType>` - The type of the contract if identified successfully.
     pub async fn identify_contract(
-        &mut self,
+        &self,
         address: FieldElement,
         block_timestamp: u64,
         chain_id: &str,
     ) -> Result<ContractType> {
-        match self.get_cached_or_fetch_info(address, chain_id).await {
-            Ok(contract_type) => Ok(contract_type),
-            Err(_) => {
-                if let Ok(contract_type) = self.get_cached_or_fetch_info(address, chain_id).await {
+        match self.cache.get(&address) {
+            CacheLookup::Hit(CachedContract::Identified(contract_type)) => {
+                return Ok(contract_type)
+            }
+            CacheLookup::Hit(CachedContract::Failed) => {
+                return Err(anyhow!(
+                    "Contract 0x{:064x} recently failed to be identified",
+                    address
+                ))
+            }
+            CacheLookup::Expired => {
+                trace!("Cache expired for contract {:#064x}", address);
+            }
+            CacheLookup::Miss => {
+                trace!("Cache miss for contract {:#064x}", address);
+
+                if let Ok(contract_type) = self
+                    .storage
+                    .get_contract_type(&to_hex_str(&address), chain_id)
+                    .await
+                {
+                    self.cache
+                        .insert(address, CachedContract::Identified(contract_type.clone()));
                     return Ok(contract_type);
                 }
+            }
+        }
 
-                // If the contract info is not cached, identify and cache it.
-                let contract_type = self.get_contract_type(address).await?;
+        match self
+            .identify_on_chain(address, block_timestamp, chain_id)
+            .await
+        {
+            Ok(contract_type) => {
+                self.cache
+                    .insert(address, CachedContract::Identified(contract_type.clone()));
+                Ok(contract_type)
+            }
+            Err(e) => {
+                // A failing batch of calls is transient, the contract
+                // was not answered and is identified again next time.
+                if e.downcast_ref::<StarknetClientError>().is_none() {
+                    self.cache.insert(address, CachedContract::Failed);
+                }
+                Err(e)
+            }
+        }
+    }
 
-                self.cache.insert(address, contract_type.clone());
+    /// Identifies the contract on chain, collecting its metadata,
+    /// and registers them in the storage.
+    ///
+    /// Contracts supporting SRC5 are classified from their interfaces,
+    /// the others by probing `owner_of` and `balance_of`.
+    async fn identify_on_chain(
+        &self,
+        address: FieldElement,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> Result<ContractType> {
+        // Type probes and metadata are requested in a single batch.
+        let [owner_of_camel, owner_of, balance_of_camel, balance_of, name, symbol, contract_uri, contract_uri_camel, total_supply, total_supply_camel, interfaces @ ..] =
+            self.call_entrypoints(
+                address,
+                [
+                    ("ownerOf", erc721_probe_calldata()),
+                    ("owner_of", erc721_probe_calldata()),
+                    ("balanceOf", erc1155_probe_calldata()),
+                    ("balance_of", erc1155_probe_calldata()),
+                    ("name", vec![]),
+                    ("symbol", vec![]),
+                    ("contract_uri", vec![]),
+                    ("contractURI", vec![]),
+                    ("total_supply", vec![]),
+                    ("totalSupply", vec![]),
+                    ("supports_interface", vec![SRC5_INTERFACE_ID]),
+                    ("supports_interface", vec![ERC721_INTERFACE_ID]),
+                    ("supports_interface", vec![ERC721_METADATA_INTERFACE_ID]),
+                    ("supports_interface", vec![ERC1155_INTERFACE_ID]),
+                    (
+                        "supports_interface",
+                        vec![ERC1155_METADATA_URI_INTERFACE_ID],
+                    ),
+                    ("supports_interface", vec![ERC2981_INTERFACE_ID]),
+                ],
+            )
+            .await?;
 
-                let name = self
-                    .get_contract_property_string(
-                        address,
-                        "name",
-                        vec![],
-                        BlockId::Tag(BlockTag::Pending),
-                    )
-                    .await
-                    .ok();
-
-                let symbol = self
-                    .get_contract_property_string(
-                        address,
-                        "symbol",
-                        vec![],
-                        BlockId::Tag(BlockTag::Pending),
-                    )
-                    .await
-                    .ok();
-
-                info!(
-                    "Contract [0x{:064x}] details - Type: {}, Name: {:?}, Symbol: {:?}",
-                    address,
-                    contract_type.to_string(),
-                    name,
-                    symbol
-                );
+        // A probe not answered by the contract itself (missing response
+        // of the batch, rate limit...) can't classify it: the contract
+        // is identified again next time.
+        if let Some(e) = [&owner_of_camel, &owner_of, &balance_of_camel, &balance_of]
+            .into_iter()
+            .chain(&interfaces)
+            .find_map(|r| r.as_ref().err().filter(|e| !is_contract_error(e)))
+        {
+            return Err(StarknetClientError::Other(format!(
+                "Probe of contract 0x{:064x} failed: {}",
+                address, e
+            ))
+            .into());
+        }
 
-                let info = ContractInfo {
-                    contract_address: to_hex_str(&address),
-                    contract_type: contract_type.to_string(),
-                    name,
-                    symbol,
-                    image: None,
-                    chain_id: chain_id.to_string(),
-                };
+        let interfaces = supported_interfaces(&interfaces);
 
-                if let Err(e) = self
-                    .storage
-                    .register_contract_info(&info, block_timestamp, chain_id)
-                    .await
-                {
-                    error!(
-                        "Failed to store contract info for [0x{:064x}]: {:?}",
-                        address, e
-                    );
-                }
+        let contract_type = if interfaces.contains(&ERC721_INTERFACE_ID) {
+            ContractType::ERC721
+        } else if interfaces.contains(&ERC1155_INTERFACE_ID) {
+            ContractType::ERC1155
+        } else if is_erc721_from_probes(&owner_of_camel, &owner_of) {
+            ContractType::ERC721
+        } else if is_erc1155_from_probes(&balance_of_camel, &balance_of) {
+            ContractType::ERC1155
+        } else {
+            ContractType::Other
+        };
 
-                Ok(contract_type)
+        let name = name.and_then(parse_property_string).ok();
+        let symbol = symbol.and_then(parse_property_string).ok();
+
+        info!(
+            "Contract [0x{:064x}] details - Type: {}, Name: {:?}, Symbol: {:?}",
+            address,
+            contract_type.to_string(),
+            name,
+            symbol
+        );
+
+        let class_hash = match self
+            .client
+            .class_hash_at(address, BlockId::Tag(BlockTag::Pending))
+            .await
+        {
+            Ok(class_hash) => Some(to_hex_str(&class_hash)),
+            Err(e) => {
+                error!("Can't get class hash of [0x{:064x}]: {:?}", address, e);
+                None
+            }
+        };
+
+        let info = ContractInfo {
+            contract_address: to_hex_str(&address),
+            contract_type: contract_type.to_string(),
+            name,
+            symbol,
+            image: None,
+            chain_id: chain_id.to_string(),
+            contract_uri: contract_uri
+                .or(contract_uri_camel)
+                .and_then(parse_property_string)
+                .ok(),
+            total_supply: total_supply
+                .or(total_supply_camel)
+                .ok()
+                .and_then(parse_total_supply),
+            interfaces: interfaces.iter().map(to_hex_str).collect(),
+            deployer: self
+                .deployers
+                .lock()
+                .unwrap()
+                .take(&address)
+                .map(|deployer| to_hex_str(&deployer)),
+            class_hash,
+        };
+
+        // The contract stays cached if the block identifying it is rolled
+        // back, so it is registered out of the unit of work of the block,
+        // once the block is ended: the only connection of an SQLite database
+        // is used by the transaction of the block until then.
+        self.unregistered
+            .lock()
+            .unwrap()
+            .push((info, block_timestamp));
+        if storage::current_block().is_none() {
+            self.register_identified_contracts().await;
+        }
+
+        Ok(contract_type)
+    }
+
+    /// Registers the contracts identified in the scope of a block, out
+    /// of its unit of work. Must be called once the block is committed
+    /// or rolled back. A contract identified again once its cache expired
+    /// is updated.
+    pub async fn register_identified_contracts(&self) {
+        let identified = std::mem::take(&mut *self.unregistered.lock().unwrap());
+
+        for (info, block_timestamp) in identified {
+            if let Err(e) = storage::outside_block(async {
+                self.storage
+                    .register_contract_info(&info, block_timestamp, &info.chain_id)
+                    .await
+            })
+            .await
+            {
+                error!(
+                    "Failed to store contract info for [{}]: {:?}",
+                    info.contract_address, e
+                );
             }
         }
     }
@@ -140,12 +503,24 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
     /// Verifies if the contract is an ERC721, ERC1155 or an other type.
     /// `owner_of` is specific to ERC721.
     /// `balance_of` is specific to ERC1155 and different from ERC20 as 2 arguments are expected.
+    ///
+    /// All the entrypoints are probed with a single batch of calls.
     pub async fn get_contract_type(&self, contract_address: FieldElement) -> Result<ContractType> {
-        let _block = BlockId::Tag(BlockTag::Pending);
+        let [owner_of_camel, owner_of, balance_of_camel, balance_of] = self
+            .call_entrypoints(
+                contract_address,
+                [
+                    ("ownerOf", erc721_probe_calldata()),
+                    ("owner_of", erc721_probe_calldata()),
+                    ("balanceOf", erc1155_probe_calldata()),
+                    ("balance_of", erc1155_probe_calldata()),
+                ],
+            )
+            .await?;
 
-        if self.is_erc721(contract_address).await? {
+        if is_erc721_from_probes(&owner_of_camel, &owner_of) {
             Ok(ContractType::ERC721)
-        } else if self.is_erc1155(contract_address).await? {
+        } else if is_erc1155_from_probes(&balance_of_camel, &balance_of) {
             Ok(ContractType::ERC1155)
         } else {
             Ok(ContractType::Other)
@@ -154,82 +529,67 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
 
     /// Returns true if the contract is ERC721, false otherwise.
     pub async fn is_erc721(&self, contract_address: FieldElement) -> Result<bool> {
-        let block = BlockId::Tag(BlockTag::Pending);
-        let token_id = vec![FieldElement::ONE, FieldElement::ZERO]; // u256.
-
-        match self
-            .get_contract_response(contract_address, "ownerOf", token_id.clone(), block)
-            .await
-        {
-            Ok(_) => return Ok(true),
-            Err(e) => match e {
-                StarknetClientError::Contract(s) => {
-                    // Token ID may not exist, but the entrypoint was hit.
-                    if s.contains("not found in contract") {
-                        // do nothing and go to the next selector.
-                    } else {
-                        return Ok(true);
-                    }
-                }
-                StarknetClientError::EntrypointNotFound(_) => (),
-                _ => return Ok(false),
-            },
-        };
+        let [owner_of_camel, owner_of] = self
+            .call_entrypoints(
+                contract_address,
+                [
+                    ("ownerOf", erc721_probe_calldata()),
+                    ("owner_of", erc721_probe_calldata()),
+                ],
+            )
+            .await?;
 
-        match self
-            .get_contract_response(contract_address, "owner_of", token_id, block)
-            .await
-        {
-            Ok(_) => Ok(true),
-            Err(e) => match e {
-                StarknetClientError::Contract(s) => {
-                    // Token ID may not exist, but the entrypoint was hit.
-                    if s.contains("not found in contract") {
-                        Ok(false)
-                    } else {
-                        Ok(true)
-                    }
-                }
-                StarknetClientError::EntrypointNotFound(_) => Ok(false),
-                _ => Ok(false),
-            },
-        }
+        Ok(is_erc721_from_probes(&owner_of_camel, &owner_of))
     }
 
     /// Returns true if the contract is ERC1155, false otherwise.
     pub async fn is_erc1155(&self, contract_address: FieldElement) -> Result<bool> {
-        let block = BlockId::Tag(BlockTag::Pending);
-        // felt and u256 expected.
-        let address_and_token_id = vec![FieldElement::ZERO, FieldElement::ONE, FieldElement::ZERO];
-
-        match self
-            .get_contract_response(
+        let [balance_of_camel, balance_of] = self
+            .call_entrypoints(
                 contract_address,
-                "balanceOf",
-                address_and_token_id.clone(),
-                block,
+                [
+                    ("balanceOf", erc1155_probe_calldata()),
+                    ("balance_of", erc1155_probe_calldata()),
+                ],
             )
-            .await
-        {
-            Ok(_) => return Ok(true),
-            Err(e) => match e {
-                StarknetClientError::EntrypointNotFound(_) => (),
-                StarknetClientError::InputTooLong => return Ok(false), // ERC20.
-                _ => return Ok(false),
-            },
-        };
+            .await?;
 
-        match self
-            .get_contract_response(contract_address, "balance_of", address_and_token_id, block)
-            .await
-        {
-            Ok(_) => Ok(true),
-            Err(e) => match e {
-                StarknetClientError::EntrypointNotFound(_) => Ok(false),
-                StarknetClientError::InputTooLong => Ok(false), // ERC20.
-                _ => Ok(false),
-            },
-        }
+        Ok(is_erc1155_from_probes(&balance_of_camel, &balance_of))
+    }
+
+    /// Calls the given entrypoints of a contract on the pending block,
+    /// in a single batch, returning the result of each call in the same order.
+    async fn call_entrypoints<const N: usize>(
+        &self,
+        contract_address: FieldElement,
+        entrypoints: [(&str, Vec<FieldElement>); N],
+    ) -> Result<[Result<Vec<FieldElement>, StarknetClientError>; N]> {
+        let calls = entrypoints
+            .into_iter()
+            .map(|(selector_name, calldata)| {
+                Ok(FunctionCall {
+                    contract_address,
+                    entry_point_selector: get_selector_from_name(selector_name).map_err(|_| {
+                        StarknetClientError::Other(format!("Invalid selector: {}", selector_name))
+                    })?,
+                    calldata,
+                })
+            })
+            .collect::<Result<Vec<_>, StarknetClientError>>()?;
+
+        let responses = self
+            .client
+            .call_contracts(calls, BlockId::Tag(BlockTag::Pending))
+            .await?;
+
+        responses.try_into().map_err(|r: Vec<_>| {
+            anyhow!(
+                "Expected {} responses for contract 0x{:064x}, got {}",
+                N,
+                contract_address,
+                r.len()
+            )
+        })
     }
 
     pub async fn get_contract_response(
@@ -270,8 +630,450 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
             )
             .await?;
 
-        parse_cairo_string(response).map_err(|e| {
-            StarknetClientError::Other(format!("Impossible to decode response string: {:?}", e))
+        parse_property_string(response)
+    }
+}
+
+/// Calldata of the ERC721 probes: a u256 token id.
+fn erc721_probe_calldata() -> Vec<FieldElement> {
+    vec![FieldElement::ONE, FieldElement::ZERO]
+}
+
+/// Calldata of the ERC1155 probes: a felt address and a u256 token id.
+fn erc1155_probe_calldata() -> Vec<FieldElement> {
+    vec![FieldElement::ZERO, FieldElement::ONE, FieldElement::ZERO]
+}
+
+/// Returns true if the error is returned by the contract itself,
+/// and not by the node failing to answer.
+fn is_contract_error(e: &StarknetClientError) -> bool {
+    matches!(
+        e,
+        StarknetClientError::Contract(_)
+            | StarknetClientError::EntrypointNotFound(_)
+            | StarknetClientError::InputTooShort
+            | StarknetClientError::InputTooLong
+    )
+}
+
+/// Returns true if the responses of `ownerOf` and `owner_of`
+/// identify an ERC721 contract.
+fn is_erc721_from_probes(
+    owner_of_camel: &Result<Vec<FieldElement>, StarknetClientError>,
+    owner_of: &Result<Vec<FieldElement>, StarknetClientError>,
+) -> bool {
+    match owner_of_camel {
+        Ok(_) => return true,
+        // Token ID may not exist, but the entrypoint was hit.
+        Err(StarknetClientError::Contract(s)) if !s.contains("not found in contract") => {
+            return true
+        }
+        // Go to the next selector.
+        Err(StarknetClientError::Contract(_) | StarknetClientError::EntrypointNotFound(_)) => {}
+        Err(_) => return false,
+    };
+
+    match owner_of {
+        Ok(_) => true,
+        // Token ID may not exist, but the entrypoint was hit.
+        Err(StarknetClientError::Contract(s)) => !s.contains("not found in contract"),
+        Err(_) => false,
+    }
+}
+
+/// Returns true if the responses of `balanceOf` and `balance_of`
+/// identify an ERC1155 contract.
+/// ERC20 contracts fail with `InputTooLong`, as only one argument is expected.
+fn is_erc1155_from_probes(
+    balance_of_camel: &Result<Vec<FieldElement>, StarknetClientError>,
+    balance_of: &Result<Vec<FieldElement>, StarknetClientError>,
+) -> bool {
+    match balance_of_camel {
+        Ok(_) => true,
+        Err(StarknetClientError::EntrypointNotFound(_)) => balance_of.is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Returns the probed interfaces supported by the contract,
+/// from the responses of `supports_interface`.
+fn supported_interfaces(
+    responses: &[Result<Vec<FieldElement>, StarknetClientError>],
+) -> Vec<FieldElement> {
+    PROBED_INTERFACE_IDS
+        .into_iter()
+        .zip(responses)
+        .filter(|(_, r)| matches!(r, Ok(v) if v.first() == Some(&FieldElement::ONE)))
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Parses a total supply returned as a u256 or a felt, in decimal.
+fn parse_total_supply(response: Vec<FieldElement>) -> Option<String> {
+    let to_uint = |felt: &FieldElement| BigUint::from_bytes_be(&felt.to_bytes_be());
+
+    match &response[..] {
+        [low, high] => Some(((to_uint(high) << 128u32) + to_uint(low)).to_string()),
+        [supply] => Some(to_uint(supply).to_string()),
+        _ => None,
+    }
+}
+
+fn parse_property_string(response: Vec<FieldElement>) -> Result<String, StarknetClientError> {
+    parse_cairo_string(response).map_err(|e| {
+        StarknetClientError::Other(format!("Impossible to decode response string: {}", e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{types::StorageError, MockStorage};
+    use ark_starknet::client::MockStarknetClient;
+
+    fn contract_error(s: &str) -> Result<Vec<FieldElement>, StarknetClientError> {
+        Err(StarknetClientError::Contract(s.to_string()))
+    }
+
+    fn not_found() -> Result<Vec<FieldElement>, StarknetClientError> {
+        Err(StarknetClientError::EntrypointNotFound(
+            "Entry point not found in contract".to_string(),
+        ))
+    }
+
+    #[test]
+    fn test_is_erc721_from_probes() {
+        assert!(is_erc721_from_probes(&Ok(vec![]), &not_found()));
+        // Token ID doesn't exist, but the entrypoint was hit.
+        assert!(is_erc721_from_probes(
+            &contract_error("ERC721: invalid token ID"),
+            &not_found()
+        ));
+        assert!(is_erc721_from_probes(&not_found(), &Ok(vec![])));
+        assert!(!is_erc721_from_probes(&not_found(), &not_found()));
+        assert!(!is_erc721_from_probes(
+            &Err(StarknetClientError::InputTooLong),
+            &Ok(vec![])
+        ));
+    }
+
+    #[test]
+    fn test_is_erc1155_from_probes() {
+        assert!(is_erc1155_from_probes(&Ok(vec![]), &not_found()));
+        assert!(is_erc1155_from_probes(&not_found(), &Ok(vec![])));
+        assert!(!is_erc1155_from_probes(&not_found(), &not_found()));
+        // ERC20.
+        assert!(!is_erc1155_from_probes(
+            &Err(StarknetClientError::InputTooLong),
+            &Ok(vec![])
+        ));
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let cache = ContractCache::new(ContractCacheConfig {
+            capacity: 10,
+            ..Default::default()
+        });
+
+        for i in 0..10_u64 {
+            cache.insert(
+                FieldElement::from(i),
+                CachedContract::Identified(ContractType::ERC721),
+            );
+        }
+
+        // Contract 0 is the most recently used.
+        cache.get(&FieldElement::ZERO);
+        cache.insert(
+            FieldElement::from(10_u64),
+            CachedContract::Identified(ContractType::ERC721),
+        );
+
+        assert_eq!(cache.len(), 10);
+        assert_ne!(cache.get(&FieldElement::ZERO), CacheLookup::Miss);
+        assert_eq!(cache.get(&FieldElement::ONE), CacheLookup::Miss);
+        assert_ne!(cache.get(&FieldElement::from(10_u64)), CacheLookup::Miss);
+    }
+
+    #[test]
+    fn test_cache_ttl() {
+        let cache = ContractCache::new(ContractCacheConfig {
+            other_ttl: Duration::ZERO,
+            ..Default::default()
+        });
+
+        cache.insert(
+            FieldElement::ONE,
+            CachedContract::Identified(ContractType::Other),
+        );
+        cache.insert(
+            FieldElement::TWO,
+            CachedContract::Identified(ContractType::ERC1155),
+        );
+
+        assert_eq!(cache.get(&FieldElement::ONE), CacheLookup::Expired);
+        assert_eq!(
+            cache.get(&FieldElement::TWO),
+            CacheLookup::Hit(CachedContract::Identified(ContractType::ERC1155))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_identify_contract_negative_cache() {
+        let mut storage = MockStorage::default();
+        let mut client = MockStarknetClient::default();
+
+        storage
+            .expect_get_contract_type()
+            .times(1)
+            .returning(|_, _| {
+                Box::pin(futures::future::ready(Err(StorageError::NotFound(
+                    "contract".to_string(),
+                ))))
+            });
+
+        // The responses can't be interpreted, the failure is cached and
+        // the contract is only requested once.
+        client
+            .expect_call_contracts()
+            .times(1)
+            .returning(|_, _| Ok(vec![]));
+
+        let manager = ContractManager::new(
+            Arc::new(storage),
+            Arc::new(client),
+            ContractCacheConfig::default(),
+        );
+
+        assert!(manager
+            .identify_contract(FieldElement::ONE, 0, "0x1")
+            .await
+            .is_err());
+        assert!(manager
+            .identify_contract(FieldElement::ONE, 0, "0x1")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_identify_contract_transient_error_not_cached() {
+        let mut storage = MockStorage::default();
+        let mut client = MockStarknetClient::default();
+
+        storage
+            .expect_get_contract_type()
+            .times(2)
+            .returning(|_, _| {
+                Box::pin(futures::future::ready(Err(StorageError::NotFound(
+                    "contract".to_string(),
+                ))))
+            });
+
+        // The batch of calls failed, the contract is requested again.
+        client
+            .expect_call_contracts()
+            .times(2)
+            .returning(|_, _| Err(StarknetClientError::Other("timeout".to_string())));
+
+        let manager = ContractManager::new(
+            Arc::new(storage),
+            Arc::new(client),
+            ContractCacheConfig::default(),
+        );
+
+        assert!(manager
+            .identify_contract(FieldElement::ONE, 0, "0x1")
+            .await
+            .is_err());
+        assert!(manager
+            .identify_contract(FieldElement::ONE, 0, "0x1")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_missing_batch_response_not_identified() {
+        let mut storage = MockStorage::default();
+        let mut client = MockStarknetClient::default();
+
+        storage
+            .expect_get_contract_type()
+            .times(2)
+            .returning(|_, _| {
+                Box::pin(futures::future::ready(Err(StorageError::NotFound(
+                    "contract".to_string(),
+                ))))
+            });
+        storage.expect_register_contract_info().never();
+
+        // The response of `ownerOf` is missing from the batch, as mapped
+        // by the HTTP client, and the other entrypoints don't exist.
+        client
+            .expect_call_contracts()
+            .times(2)
+            .returning(|calls, _| {
+                Ok(std::iter::once(Err(StarknetClientError::Other(
+                    "JSON-RPC error -32603: Missing response for request 0 of the batch"
+                        .to_string(),
+                )))
+                .chain((1..calls.len()).map(|_| not_found()))
+                .collect())
+            });
+
+        let manager = ContractManager::new(
+            Arc::new(storage),
+            Arc::new(client),
+            ContractCacheConfig::default(),
+        );
+
+        // Neither classified as ERC721 nor cached.
+        for _ in 0..2 {
+            let error = manager
+                .identify_contract(FieldElement::ONE, 0, "0x1")
+                .await
+                .unwrap_err();
+            assert!(error.downcast_ref::<StarknetClientError>().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_identified_contract_registered_once_block_ended() {
+        let mut storage = MockStorage::default();
+        let mut client = MockStarknetClient::default();
+        let block_ended = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        storage.expect_get_contract_type().returning(|_, _| {
+            Box::pin(futures::future::ready(Err(StorageError::NotFound(
+                "contract".to_string(),
+            ))))
+        });
+        let ended = Arc::clone(&block_ended);
+        storage
+            .expect_register_contract_info()
+            .withf(|info, _, _| info.contract_type == ContractType::ERC721.to_string())
+            .times(1)
+            .returning(move |_, _, _| {
+                assert!(ended.load(Ordering::SeqCst));
+                assert_eq!(storage::current_block(), None);
+                Box::pin(futures::future::ready(Ok(())))
+            });
+
+        // Only `ownerOf` is implemented.
+        client.expect_call_contracts().returning(|calls, _| {
+            Ok(std::iter::once(Ok(vec![FieldElement::ONE]))
+                .chain((1..calls.len()).map(|_| not_found()))
+                .collect())
+        });
+        client
+            .expect_class_hash_at()
+            .returning(|_, _| Ok(FieldElement::TWO));
+
+        let manager = ContractManager::new(
+            Arc::new(storage),
+            Arc::new(client),
+            ContractCacheConfig::default(),
+        );
+
+        let contract_type = storage::in_block(5, async {
+            let contract_type = manager.identify_contract(FieldElement::ONE, 0, "0x1").await;
+            block_ended.store(true, Ordering::SeqCst);
+            manager.register_identified_contracts().await;
+            contract_type
         })
+        .await;
+
+        assert_eq!(contract_type.unwrap(), ContractType::ERC721);
+    }
+
+    #[tokio::test]
+    async fn test_warm_start() {
+        let mut storage = MockStorage::default();
+
+        storage.expect_get_contract_types().returning(|_, _| {
+            Box::pin(futures::future::ready(Ok(vec![(
+                "0x1".to_string(),
+                ContractType::ERC721,
+            )])))
+        });
+        storage.expect_get_contract_type().never();
+
+        let manager = ContractManager::new(
+            Arc::new(storage),
+            Arc::new(MockStarknetClient::default()),
+            ContractCacheConfig::default(),
+        );
+
+        assert_eq!(manager.warm_start("0x1").await.unwrap(), 1);
+        assert_eq!(
+            manager
+                .identify_contract(FieldElement::ONE, 0, "0x1")
+                .await
+                .unwrap(),
+            ContractType::ERC721
+        );
+    }
+
+    #[test]
+    fn test_supported_interfaces() {
+        let supported = || Ok(vec![FieldElement::ONE]);
+        let unsupported = || Ok(vec![FieldElement::ZERO]);
+
+        assert_eq!(
+            supported_interfaces(&[
+                supported(),
+                supported(),
+                unsupported(),
+                not_found(),
+                unsupported(),
+                supported(),
+            ]),
+            vec![SRC5_INTERFACE_ID, ERC721_INTERFACE_ID, ERC2981_INTERFACE_ID]
+        );
+        assert!(supported_interfaces(&[not_found(), not_found()]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_total_supply() {
+        assert_eq!(
+            parse_total_supply(vec![FieldElement::from(10_u8), FieldElement::ZERO]),
+            Some("10".to_string())
+        );
+        assert_eq!(
+            parse_total_supply(vec![FieldElement::ZERO, FieldElement::ONE]),
+            Some("340282366920938463463374607431768211456".to_string())
+        );
+        assert_eq!(
+            parse_total_supply(vec![FieldElement::from(7_u8)]),
+            Some("7".to_string())
+        );
+        assert_eq!(parse_total_supply(vec![]), None);
+    }
+
+    #[test]
+    fn test_register_deployment() {
+        let manager = ContractManager::new(
+            Arc::new(MockStorage::default()),
+            Arc::new(MockStarknetClient::default()),
+            ContractCacheConfig::default(),
+        );
+
+        let mut event = EmittedEvent {
+            from_address: UDC_ADDRESS,
+            keys: vec![CONTRACT_DEPLOYED_SELECTOR],
+            data: vec![FieldElement::ONE, FieldElement::TWO, FieldElement::ZERO],
+            block_hash: None,
+            block_number: None,
+            transaction_hash: FieldElement::ZERO,
+        };
+
+        assert!(manager.register_deployment(&event));
+        assert_eq!(
+            manager.deployers.lock().unwrap().take(&FieldElement::ONE),
+            Some(FieldElement::TWO)
+        );
+
+        event.from_address = FieldElement::THREE;
+        assert!(!manager.register_deployment(&event));
     }
 }
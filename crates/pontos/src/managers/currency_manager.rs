@@ -0,0 +1,293 @@
+use crate::price_oracle::{CurrencyInfo, PriceOracle};
+use crate::storage::types::TokenSaleEvent;
+use anyhow::{anyhow, Result};
+use ark_starknet::{
+    cairo_string_parser::parse_cairo_string,
+    client::{StarknetClient, StarknetClientError},
+};
+use starknet::core::types::{BlockId, BlockTag, FieldElement, FunctionCall};
+use starknet::macros::selector;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{info, trace};
+
+pub struct CurrencyManager<C: StarknetClient> {
+    client: Arc<C>,
+    oracle: Option<Arc<dyn PriceOracle>>,
+    /// A cache with currency address mapped to its metadata,
+    /// `None` for contracts without ERC20 metadata.
+    /// The lock is never held during the calls to the chain.
+    cache: RwLock<HashMap<FieldElement, Option<CurrencyInfo>>>,
+}
+
+impl<C: StarknetClient> CurrencyManager<C> {
+    /// Initializes a new instance.
+    pub fn new(client: Arc<C>, oracle: Option<Arc<dyn PriceOracle>>) -> Self {
+        Self {
+            client,
+            oracle,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Gets the currency metadata from local cache, or fetches it from the chain.
+    /// Returns `None` if the contract doesn't expose `symbol` and `decimals`.
+    ///
+    /// Only the definitive results are cached, the currencies failing
+    /// with a transient error being fetched again on the next sale.
+    pub async fn get_currency(&self, address: FieldElement) -> Result<Option<CurrencyInfo>> {
+        if let Some(currency) = self.cache.read().unwrap().get(&address) {
+            return Ok(currency.clone());
+        }
+
+        trace!("Cache miss for currency {:#064x}", address);
+
+        let calls = [selector!("symbol"), selector!("decimals")]
+            .into_iter()
+            .map(|entry_point_selector| FunctionCall {
+                contract_address: address,
+                entry_point_selector,
+                calldata: vec![],
+            })
+            .collect();
+
+        let responses = self
+            .client
+            .call_contracts(calls, BlockId::Tag(BlockTag::Pending))
+            .await?;
+
+        let currency = match &responses[..] {
+            [Ok(symbol), Ok(decimals)] => Some(CurrencyInfo {
+                address,
+                symbol: parse_cairo_string(symbol.clone()).map_err(|e| {
+                    StarknetClientError::Other(format!("Impossible to decode symbol: {}", e))
+                })?,
+                decimals: decimals
+                    .first()
+                    .and_then(|d| u8::try_from(*d).ok())
+                    .ok_or_else(|| anyhow!("Invalid decimals: {:?}", decimals))?,
+            }),
+            responses => {
+                if let Some(e) = responses
+                    .iter()
+                    .filter_map(|r| r.as_ref().err())
+                    .find(|e| !is_missing_entrypoint(e))
+                {
+                    return Err(anyhow!("Can't fetch currency {:#064x}: {}", address, e));
+                }
+
+                None
+            }
+        };
+
+        info!("Currency [{:#064x}] details: {:?}", address, currency);
+
+        self.cache
+            .write()
+            .unwrap()
+            .insert(address, currency.clone());
+
+        Ok(currency)
+    }
+
+    /// Fills the currency metadata and the normalised prices of the sale.
+    ///
+    /// Sales without currency, or paid with a contract without ERC20
+    /// metadata, only keep their raw price. The price in the reference
+    /// currency is only set if a price oracle is configured.
+    pub async fn price_sale(&self, sale: &mut TokenSaleEvent) -> Result<()> {
+        let currency_address = match &sale.currency_address {
+            Some(address) => FieldElement::from_hex_be(address)?,
+            None => return Ok(()),
+        };
+
+        let currency = match self.get_currency(currency_address).await? {
+            Some(currency) => currency,
+            None => return Ok(()),
+        };
+
+        let price_decimal =
+            FieldElement::from_dec_str(&sale.price)?.to_big_decimal(currency.decimals);
+
+        if let Some(oracle) = &self.oracle {
+            if let Some(rate) = oracle.rate(&currency, sale.timestamp).await {
+                sale.reference_price = Some((&price_decimal * rate).normalized().to_string());
+            }
+        }
+
+        sale.currency_symbol = Some(currency.symbol);
+        sale.currency_decimals = Some(currency.decimals);
+        sale.price_decimal = Some(price_decimal.to_string());
+
+        Ok(())
+    }
+}
+
+/// Returns true if the error tells that the contract doesn't implement
+/// the entrypoint, the currency having no ERC20 metadata.
+fn is_missing_entrypoint(e: &StarknetClientError) -> bool {
+    matches!(
+        e,
+        StarknetClientError::EntrypointNotFound(_)
+            | StarknetClientError::Contract(_)
+            | StarknetClientError::InputTooLong
+            | StarknetClientError::InputTooShort
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::price_oracle::StaticPriceOracle;
+    use crate::storage::types::EventType;
+    use ark_starknet::client::MockStarknetClient;
+    use bigdecimal::BigDecimal;
+    use starknet::core::utils::cairo_short_string_to_felt;
+    use std::str::FromStr;
+
+    fn sale(price: &str, currency_address: Option<FieldElement>) -> TokenSaleEvent {
+        TokenSaleEvent {
+            timestamp: 0,
+            from_address: "0x1".to_string(),
+            to_address: "0x2".to_string(),
+            nft_contract_address: "0x3".to_string(),
+            nft_type: None,
+            marketplace_contract_address: "0x4".to_string(),
+            marketplace_name: "Element".to_string(),
+            transaction_hash: "0x5".to_string(),
+            token_id: "1".to_string(),
+            token_id_hex: "0x1".to_string(),
+            event_type: EventType::Sale,
+            event_id: "0x6".to_string(),
+            block_number: None,
+            updated_at: None,
+            quantity: 1,
+            currency_address: currency_address.map(|a| format!("{:#064x}", a)),
+            price: price.to_string(),
+            currency_symbol: None,
+            currency_decimals: None,
+            price_decimal: None,
+            reference_price: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_price_sale() {
+        let mut client = MockStarknetClient::default();
+        let eth = FieldElement::from(0xe7_u64);
+
+        client.expect_call_contracts().times(1).returning(|_, _| {
+            Ok(vec![
+                Ok(vec![cairo_short_string_to_felt("ETH").unwrap()]),
+                Ok(vec![FieldElement::from(18_u8)]),
+            ])
+        });
+
+        let oracle = StaticPriceOracle::new().with_rate(eth, BigDecimal::from(2000));
+        let manager = CurrencyManager::new(Arc::new(client), Some(Arc::new(oracle)));
+
+        let mut s = sale("1500000000000000000", Some(eth));
+        manager.price_sale(&mut s).await.unwrap();
+
+        assert_eq!(s.currency_symbol, Some("ETH".to_string()));
+        assert_eq!(s.currency_decimals, Some(18));
+        assert_eq!(s.price_decimal, Some("1.500000000000000000".to_string()));
+        assert_eq!(s.reference_price, Some("3000".to_string()));
+        assert_eq!(s.price, "1500000000000000000");
+
+        // Currency is cached.
+        let mut s = sale("1000000000000000000", Some(eth));
+        manager.price_sale(&mut s).await.unwrap();
+        assert_eq!(s.reference_price, Some("2000".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_reference_price_is_exact() {
+        let mut client = MockStarknetClient::default();
+        let usdc = FieldElement::from(0x05_u64);
+
+        client.expect_call_contracts().returning(|_, _| {
+            Ok(vec![
+                Ok(vec![cairo_short_string_to_felt("USDC").unwrap()]),
+                Ok(vec![FieldElement::from(6_u8)]),
+            ])
+        });
+
+        let oracle = StaticPriceOracle::new().with_rate(usdc, BigDecimal::from_str("0.1").unwrap());
+        let manager = CurrencyManager::new(Arc::new(client), Some(Arc::new(oracle)));
+
+        let mut s = sale("300000", Some(usdc));
+        manager.price_sale(&mut s).await.unwrap();
+
+        assert_eq!(s.price_decimal, Some("0.300000".to_string()));
+        assert_eq!(s.reference_price, Some("0.03".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_get_currency_caches_definitive_results() {
+        let mut client = MockStarknetClient::default();
+        let mut seq = mockall::Sequence::new();
+        let (nft, eth) = (FieldElement::ONE, FieldElement::from(0xe7_u64));
+
+        // The contract without ERC20 metadata is only requested once.
+        client
+            .expect_call_contracts()
+            .withf(move |calls, _| calls[0].contract_address == nft)
+            .times(1)
+            .returning(|_, _| {
+                let not_found = || {
+                    Err(StarknetClientError::EntrypointNotFound(
+                        "symbol".to_string(),
+                    ))
+                };
+                Ok(vec![not_found(), not_found()])
+            });
+
+        // The currency failing with a transient error is requested again.
+        client
+            .expect_call_contracts()
+            .withf(move |calls, _| calls[0].contract_address == eth)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                Ok(vec![
+                    Err(StarknetClientError::Other("timeout".to_string())),
+                    Ok(vec![FieldElement::from(18_u8)]),
+                ])
+            });
+        client
+            .expect_call_contracts()
+            .withf(move |calls, _| calls[0].contract_address == eth)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                Ok(vec![
+                    Ok(vec![cairo_short_string_to_felt("ETH").unwrap()]),
+                    Ok(vec![FieldElement::from(18_u8)]),
+                ])
+            });
+
+        let manager = CurrencyManager::new(Arc::new(client), None);
+
+        assert_eq!(manager.get_currency(nft).await.unwrap(), None);
+        assert_eq!(manager.get_currency(nft).await.unwrap(), None);
+
+        assert!(manager.get_currency(eth).await.is_err());
+        assert_eq!(
+            manager.get_currency(eth).await.unwrap().unwrap().symbol,
+            "ETH"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_price_sale_without_currency() {
+        let client = MockStarknetClient::default();
+        let manager = CurrencyManager::new(Arc::new(client), None);
+
+        let mut s = sale("1000", None);
+        manager.price_sale(&mut s).await.unwrap();
+
+        assert_eq!(s.price_decimal, None);
+        assert_eq!(s.currency_symbol, None);
+    }
+}
@@ -1,52 +1,88 @@
-use crate::storage::types::{EventType, TokenSaleEvent, TokenTransferEvent};
+use crate::managers::contract_manager::CONTRACT_DEPLOYED_SELECTOR;
+use crate::marketplace::MarketplaceRegistry;
+use crate::storage::types::{EventType, StorageError, TokenSaleEvent, TokenTransferEvent};
 use crate::storage::Storage;
-use crate::{
-    ContractType, VENTORY_MARKETPLACE_EVENT_HEX, VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX,
-};
+use crate::ContractType;
 use anyhow::{anyhow, Result};
-use ark_starknet::{format::to_hex_str, CairoU256};
+use ark_starknet::{
+    cairo_serde::{CairoDeserialize, FeltReader},
+    format::to_hex_str,
+    CairoU256,
+};
 use starknet::core::types::{EmittedEvent, FieldElement};
 use starknet::core::utils::starknet_keccak;
 use starknet::macros::selector;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::trace;
 
 const TRANSFER_SELECTOR: FieldElement = selector!("Transfer");
-const ELEMENT_NFT_MARKETPLACE_HEX: &str =
-    "0x351e5a57ea6ca22e3e3cd212680ef7f3b57404609bda942a5e75ba4724b55e0";
+const TRANSFER_SINGLE_SELECTOR: FieldElement = selector!("TransferSingle");
+const TRANSFER_BATCH_SELECTOR: FieldElement = selector!("TransferBatch");
+
+/// Data of ERC1155 `TransferSingle` events. Keys and data are read
+/// as one sequence, as Cairo 0 contracts had no indexed keys.
+#[derive(Debug, CairoDeserialize)]
+struct TransferSingleData {
+    _operator: FieldElement,
+    from: FieldElement,
+    to: FieldElement,
+    id: CairoU256,
+    value: CairoU256,
+}
+
+/// Data of ERC1155 `TransferBatch` events, read like [`TransferSingleData`].
+#[derive(Debug, CairoDeserialize)]
+struct TransferBatchData {
+    _operator: FieldElement,
+    from: FieldElement,
+    to: FieldElement,
+    ids: Vec<CairoU256>,
+    values: Vec<CairoU256>,
+}
+
+/// Counts the identical token transfers of the events processed, for
+/// each transfer of a transaction to have its own event id, see [`event_id`].
+#[derive(Debug, Default)]
+pub struct TransferOccurrences(HashMap<FieldElement, u64>);
+
+impl TransferOccurrences {
+    /// Returns the number of transfers seen before with the given first id.
+    fn next(&mut self, first_id: FieldElement) -> u64 {
+        let count = self.0.entry(first_id).or_default();
+        *count += 1;
+        *count - 1
+    }
+}
 
 #[derive(Debug)]
 pub struct EventManager<S: Storage> {
     storage: Arc<S>,
+    marketplaces: Arc<MarketplaceRegistry>,
 }
 
 impl<S: Storage> EventManager<S> {
     /// Initializes a new instance.
-    pub fn new(storage: Arc<S>) -> Self {
+    pub fn new(storage: Arc<S>, marketplaces: Arc<MarketplaceRegistry>) -> Self {
         EventManager {
             storage: Arc::clone(&storage),
+            marketplaces,
         }
     }
 
-    /// Returns the selectors used to filter events.
+    /// Returns the selectors used to filter events: the ERC721 and
+    /// ERC1155 transfers, and the sales of the registered marketplaces.
     pub fn keys_selector(&self) -> Option<Vec<Vec<FieldElement>>> {
-        let element_nft_marketplace = FieldElement::from_hex_be(ELEMENT_NFT_MARKETPLACE_HEX)
-            .expect("Failed to parse element nft marketplace hex");
-
-        let ventory_nft_marketplace = FieldElement::from_hex_be(VENTORY_MARKETPLACE_EVENT_HEX)
-            .expect("Failed to parse ventory nft marketplace hex");
-
-        let ventory_accepted_offer_event =
-            FieldElement::from_hex_be(VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX)
-                .expect("Failed to parse ventory accepted offer selector");
-
-        Some(vec![vec![
+        let mut selectors = vec![
             TRANSFER_SELECTOR,
-            element_nft_marketplace,
-            ventory_nft_marketplace,
-            ventory_accepted_offer_event,
-        ]])
+            TRANSFER_SINGLE_SELECTOR,
+            TRANSFER_BATCH_SELECTOR,
+            CONTRACT_DEPLOYED_SELECTOR,
+        ];
+        selectors.extend(self.marketplaces.selectors());
+
+        Some(vec![selectors])
     }
 
     pub async fn register_sale_event(
@@ -60,179 +96,46 @@ impl<S: Storage> EventManager<S> {
         Ok(())
     }
 
-    pub async fn format_ventory_sale_or_accepted_offer_event(
-        &self,
-        event: &EmittedEvent,
-        block_timestamp: u64,
-    ) -> Result<TokenSaleEvent> {
-        let _listing_counter = event
-            .data
-            .first()
-            .ok_or_else(|| anyhow!("Listing counter not found"))?;
-        let token_id = event
-            .data
-            .get(1)
-            .ok_or_else(|| anyhow!("Token id not found"))?;
-        let price = event
-            .data
-            .get(2)
-            .ok_or_else(|| anyhow!("Price not found"))?;
-        let asset_contract = event
-            .data
-            .get(3)
-            .ok_or_else(|| anyhow!("Asset contract not found"))?;
-        let seller = event
-            .data
-            .get(4)
-            .ok_or_else(|| anyhow!("Seller not found"))?;
-        let buyer = event
-            .data
-            .get(5)
-            .ok_or_else(|| anyhow!("Buyer not found"))?;
-        let _status = event
-            .data
-            .get(6)
-            .ok_or_else(|| anyhow!("Status not found"))?;
-
-        let token_id = CairoU256 {
-            low: (*token_id)
-                .try_into()
-                .map_err(|_| anyhow!("Failed to parse token id"))?,
-            high: 0,
-        };
-
-        let event_id = Self::get_event_id(&token_id, seller, buyer, block_timestamp, event);
-
-        Ok(TokenSaleEvent {
-            event_id: to_hex_str(&event_id),
-            event_type: EventType::Sale,
-            block_number: event.block_number,
-            from_address: to_hex_str(seller),
-            to_address: to_hex_str(buyer),
-            nft_contract_address: to_hex_str(asset_contract),
-            nft_type: None,
-            transaction_hash: to_hex_str(&event.transaction_hash),
-            token_id_hex: token_id.to_hex(),
-            token_id: token_id.to_decimal(false),
-            timestamp: block_timestamp,
-            updated_at: Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
-            quantity: 1,
-            currency_address: None,
-            marketplace_contract_address: to_hex_str(&event.from_address),
-            marketplace_name: "Ventory".to_string(),
-            price: price.to_big_decimal(0).to_string(),
-        })
-    }
-
-    pub async fn format_element_sale_event(
+    /// Formats & register the token events of a transfer event, which
+    /// can transfer several tokens for ERC1155 `TransferBatch` events.
+    /// Returns the token_id of each token event, skipping the token
+    /// events already registered when a block is indexed again.
+    pub async fn format_and_register_transfers(
         &self,
         event: &EmittedEvent,
+        contract_type: ContractType,
         block_timestamp: u64,
-    ) -> Result<TokenSaleEvent> {
-        if event.keys.len() < 4 {
-            return Err(anyhow!("Can't find event data into this event"));
+        occurrences: &mut TransferOccurrences,
+    ) -> Result<Vec<(CairoU256, TokenTransferEvent)>> {
+        match event.keys.first() {
+            Some(selector)
+                if *selector == TRANSFER_SINGLE_SELECTOR
+                    || *selector == TRANSFER_BATCH_SELECTOR =>
+            {
+                self.format_and_register_erc1155_events(
+                    event,
+                    contract_type,
+                    block_timestamp,
+                    occurrences,
+                )
+                .await
+            }
+            _ => match self
+                .format_and_register_event(event, contract_type, block_timestamp, occurrences)
+                .await
+            {
+                Ok(token_event) => Ok(vec![token_event]),
+                Err(e)
+                    if matches!(
+                        e.downcast_ref::<StorageError>(),
+                        Some(StorageError::AlreadyExists(_))
+                    ) =>
+                {
+                    Ok(vec![])
+                }
+                Err(e) => Err(e),
+            },
         }
-
-        let maker_address = event
-            .keys
-            .get(3)
-            .ok_or_else(|| anyhow!("Maker address not found"))?;
-
-        let taker_address = event
-            .data
-            .first()
-            .ok_or_else(|| anyhow!("Taker address not found"))?;
-        let currency_address = event
-            .data
-            .get(1)
-            .ok_or_else(|| anyhow!("Currency address not found"))?;
-        let price = event
-            .data
-            .get(2)
-            .ok_or_else(|| anyhow!("Price not found"))?;
-
-        let number_of_fee_recipients = event
-            .data
-            .get(3)
-            .ok_or_else(|| anyhow!("Number of fee recipients not found"))?;
-
-        let number_of_fee_recipients_u64: u32 = (*number_of_fee_recipients)
-            .try_into()
-            .map_err(|_| anyhow!("Failed to parse number of fee recipients"))?;
-
-        let mut index = 4;
-        // for loop with number_of_fee_recipients_u64 iterations
-        for _ in 0..number_of_fee_recipients_u64 {
-            let _fee0_recipient = event.data.get(index);
-            index += 1;
-            let _fee0_value = event.data.get(index);
-            index += 1;
-        }
-
-        let nft_contract_address = event
-            .data
-            .get(index)
-            .ok_or_else(|| anyhow!("NFT contract address not found"))?;
-
-        index += 1;
-
-        let token_id_low = event
-            .data
-            .get(index)
-            .ok_or_else(|| anyhow!("Token id low not found"))?;
-
-        index += 1;
-
-        let token_id_high = event
-            .data
-            .get(index)
-            .ok_or_else(|| anyhow!("Token id high not found"))?;
-
-        index += 1;
-
-        let quantity = event
-            .data
-            .get(index)
-            .ok_or_else(|| anyhow!("Quantity not found"))?;
-
-        let token_id = CairoU256 {
-            low: (*token_id_low)
-                .try_into()
-                .map_err(|_| anyhow!("Failed to parse token id low"))?,
-            high: (*token_id_high)
-                .try_into()
-                .map_err(|_| anyhow!("Failed to parse token id high"))?,
-        };
-
-        let event_id = Self::get_event_id(
-            &token_id,
-            maker_address,
-            taker_address,
-            block_timestamp,
-            event,
-        );
-
-        Ok(TokenSaleEvent {
-            event_id: to_hex_str(&event_id),
-            event_type: EventType::Sale,
-            block_number: event.block_number,
-            from_address: to_hex_str(maker_address),
-            to_address: to_hex_str(taker_address),
-            nft_contract_address: to_hex_str(nft_contract_address),
-            nft_type: None,
-            transaction_hash: to_hex_str(&event.transaction_hash),
-            token_id_hex: token_id.to_hex(),
-            token_id: token_id.to_decimal(false),
-            timestamp: block_timestamp,
-            updated_at: Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
-            quantity: (*quantity)
-                .try_into()
-                .map_err(|_| anyhow!("Failed to parse quantity"))?,
-            currency_address: Some(to_hex_str(currency_address)),
-            marketplace_contract_address: to_hex_str(&event.from_address),
-            marketplace_name: "Element".to_string(),
-            price: price.to_big_decimal(0).to_string(),
-        })
     }
 
     /// Formats & register a token event based on the event content.
@@ -242,9 +145,8 @@ impl<S: Storage> EventManager<S> {
         event: &EmittedEvent,
         contract_type: ContractType,
         block_timestamp: u64,
+        occurrences: &mut TransferOccurrences,
     ) -> Result<(CairoU256, TokenTransferEvent)> {
-        let mut token_event = TokenTransferEvent::default();
-
         trace!(
             "Format transfer event to insert: event={:?}, contract_type={:?}, timestamp={}",
             event,
@@ -252,38 +154,18 @@ impl<S: Storage> EventManager<S> {
             block_timestamp
         );
 
-        // As cairo didn't have keys before, we first check if the data
-        // contains the info. If not, we check into the keys, skipping the first
-        // element which is the selector.
-        let event_info: (FieldElement, FieldElement, CairoU256) =
-            if let Some(d_info) = Self::get_event_info_from_felts(&event.data) {
-                d_info
-            } else if let Some(k_info) = Self::get_event_info_from_felts(&event.keys[1..]) {
-                k_info
-            } else {
-                return Err(anyhow!("Can't find event data into this event"));
-            };
-
-        let (from, to, token_id) = event_info;
-
-        let event_id = Self::get_event_id(&token_id, &from, &to, block_timestamp, event);
-
-        token_event.from_address = to_hex_str(&from);
-        token_event.to_address = to_hex_str(&to);
-        token_event.contract_address = to_hex_str(&event.from_address);
-        token_event.transaction_hash = to_hex_str(&event.transaction_hash);
-        token_event.token_id_hex = token_id.to_hex();
-        token_event.token_id = token_id.to_decimal(false);
-        token_event.timestamp = block_timestamp;
-        token_event.event_type = Self::get_event_type(from, to);
-        token_event.event_id = to_hex_str(&event_id);
-        token_event.block_number = event.block_number;
-        token_event.contract_type = contract_type.to_string();
-        token_event.updated_at = Some(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+        let (from, to, token_id) = Self::decode_transfer(event)
+            .ok_or_else(|| anyhow!("Can't find event data into this event"))?;
+
+        let token_event = Self::format_transfer(
+            event,
+            &contract_type,
+            from,
+            to,
+            &token_id,
+            &CairoU256::from(1_u64),
+            block_timestamp,
+            occurrences,
         );
 
         trace!("Registering event: {:?}", token_event);
@@ -292,7 +174,129 @@ impl<S: Storage> EventManager<S> {
             .register_transfer_event(&token_event, block_timestamp)
             .await?;
 
-        Ok((token_id, token_event.clone()))
+        Ok((token_id, token_event))
+    }
+
+    /// Formats & register the token events of an ERC1155 `TransferSingle`
+    /// or `TransferBatch` event, one for each transferred token id.
+    pub async fn format_and_register_erc1155_events(
+        &self,
+        event: &EmittedEvent,
+        contract_type: ContractType,
+        block_timestamp: u64,
+        occurrences: &mut TransferOccurrences,
+    ) -> Result<Vec<(CairoU256, TokenTransferEvent)>> {
+        let felts: Vec<FieldElement> = event
+            .keys
+            .iter()
+            .skip(1)
+            .chain(event.data.iter())
+            .copied()
+            .collect();
+        let mut reader = FeltReader::new(&felts);
+
+        let (from, to, transfers) = match event.keys.first() {
+            Some(selector) if *selector == TRANSFER_SINGLE_SELECTOR => {
+                let data: TransferSingleData = reader
+                    .read()
+                    .map_err(|e| anyhow!("Invalid TransferSingle event: {}", e))?;
+                (data.from, data.to, vec![(data.id, data.value)])
+            }
+            Some(selector) if *selector == TRANSFER_BATCH_SELECTOR => {
+                let data: TransferBatchData = reader
+                    .read()
+                    .map_err(|e| anyhow!("Invalid TransferBatch event: {}", e))?;
+
+                if data.ids.len() != data.values.len() {
+                    return Err(anyhow!(
+                        "TransferBatch event with {} ids and {} values",
+                        data.ids.len(),
+                        data.values.len()
+                    ));
+                }
+
+                (
+                    data.from,
+                    data.to,
+                    data.ids.into_iter().zip(data.values).collect(),
+                )
+            }
+            _ => return Err(anyhow!("Not an ERC1155 transfer event")),
+        };
+
+        reader
+            .finish()
+            .map_err(|e| anyhow!("Invalid ERC1155 transfer event: {}", e))?;
+
+        let mut token_events = vec![];
+        for (token_id, value) in transfers {
+            let token_event = Self::format_transfer(
+                event,
+                &contract_type,
+                from,
+                to,
+                &token_id,
+                &value,
+                block_timestamp,
+                occurrences,
+            );
+
+            trace!("Registering event: {:?}", token_event);
+
+            match self
+                .storage
+                .register_transfer_event(&token_event, block_timestamp)
+                .await
+            {
+                Ok(()) => {}
+                Err(StorageError::AlreadyExists(_)) => continue,
+                Err(e) => return Err(e.into()),
+            }
+
+            token_events.push((token_id, token_event));
+        }
+
+        Ok(token_events)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn format_transfer(
+        event: &EmittedEvent,
+        contract_type: &ContractType,
+        from: FieldElement,
+        to: FieldElement,
+        token_id: &CairoU256,
+        quantity: &CairoU256,
+        block_timestamp: u64,
+        occurrences: &mut TransferOccurrences,
+    ) -> TokenTransferEvent {
+        let first_id = Self::get_event_id(token_id, &from, &to, block_timestamp, event, 0);
+        let event_id = match occurrences.next(first_id) {
+            0 => first_id,
+            index => Self::get_event_id(token_id, &from, &to, block_timestamp, event, index),
+        };
+
+        TokenTransferEvent {
+            from_address: to_hex_str(&from),
+            to_address: to_hex_str(&to),
+            contract_address: to_hex_str(&event.from_address),
+            transaction_hash: to_hex_str(&event.transaction_hash),
+            token_id_hex: token_id.to_hex(),
+            token_id: token_id.to_decimal(false),
+            timestamp: block_timestamp,
+            event_type: Self::get_event_type(from, to),
+            event_id: to_hex_str(&event_id),
+            block_number: event.block_number,
+            contract_type: contract_type.to_string(),
+            updated_at: Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+            ),
+            quantity: quantity.to_decimal(false),
+            ..Default::default()
+        }
     }
 
     pub fn get_event_type(from: FieldElement, to: FieldElement) -> EventType {
@@ -305,26 +309,28 @@ impl<S: Storage> EventManager<S> {
         }
     }
 
-    /// Returns the event id as a field element.
-    /// We enforce everything to be a field element to have fix
-    /// bytes lengths, and ease the re-computation of this value
-    /// from else where.
+    /// Returns the event id as a field element, see [`event_id`].
     pub fn get_event_id(
         token_id: &CairoU256,
         from: &FieldElement,
         to: &FieldElement,
         timestamp: u64,
         event: &EmittedEvent,
+        index: u64,
     ) -> FieldElement {
-        let mut bytes = Vec::new();
-        bytes.extend_from_slice(&FieldElement::from(token_id.low).to_bytes_be());
-        bytes.extend_from_slice(&FieldElement::from(token_id.high).to_bytes_be());
-        bytes.extend_from_slice(&from.to_bytes_be());
-        bytes.extend_from_slice(&to.to_bytes_be());
-        bytes.extend_from_slice(&event.from_address.to_bytes_be());
-        bytes.extend_from_slice(&event.transaction_hash.to_bytes_be());
-        bytes.extend_from_slice(&FieldElement::from(timestamp).to_bytes_be());
-        starknet_keccak(&bytes)
+        event_id(token_id, from, to, timestamp, event, index)
+    }
+
+    /// Returns the (from, to, token_id) of an ERC721 transfer event.
+    ///
+    /// As cairo didn't have keys before, we first check if the data
+    /// contains the info. If not, we check into the keys, skipping the first
+    /// element which is the selector.
+    pub fn decode_transfer(
+        event: &EmittedEvent,
+    ) -> Option<(FieldElement, FieldElement, CairoU256)> {
+        Self::get_event_info_from_felts(&event.data)
+            .or_else(|| Self::get_event_info_from_felts(event.keys.get(1..)?))
     }
 
     /// Returns the event info from vector of felts.
@@ -332,23 +338,42 @@ impl<S: Storage> EventManager<S> {
     ///
     /// This methods considers that the info of the
     /// event is starting at index 0 of the input vector.
+    /// Returns `None` if the felts don't match this layout.
     fn get_event_info_from_felts(
         felts: &[FieldElement],
     ) -> Option<(FieldElement, FieldElement, CairoU256)> {
-        if felts.len() < 4 {
-            return None;
-        }
-        let from = felts[0];
-        let to = felts[1];
-
-        // Safe to unwrap, as emitted events follow cairo sequencer specification.
-        let token_id = CairoU256 {
-            low: felts[2].try_into().unwrap(),
-            high: felts[3].try_into().unwrap(),
-        };
+        FeltReader::new(felts).read().ok()
+    }
+}
 
-        Some((from, to, token_id))
+/// Returns the event id as a field element.
+/// We enforce everything to be a field element to have fix
+/// bytes lengths, and ease the re-computation of this value
+/// from else where.
+///
+/// The `index` tells apart the identical transfers of a transaction,
+/// see [`TransferOccurrences`]. It is only hashed from 1, the first
+/// transfer keeping the id it had before the index was added.
+pub fn event_id(
+    token_id: &CairoU256,
+    from: &FieldElement,
+    to: &FieldElement,
+    timestamp: u64,
+    event: &EmittedEvent,
+    index: u64,
+) -> FieldElement {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&FieldElement::from(token_id.low).to_bytes_be());
+    bytes.extend_from_slice(&FieldElement::from(token_id.high).to_bytes_be());
+    bytes.extend_from_slice(&from.to_bytes_be());
+    bytes.extend_from_slice(&to.to_bytes_be());
+    bytes.extend_from_slice(&event.from_address.to_bytes_be());
+    bytes.extend_from_slice(&event.transaction_hash.to_bytes_be());
+    bytes.extend_from_slice(&FieldElement::from(timestamp).to_bytes_be());
+    if index > 0 {
+        bytes.extend_from_slice(&FieldElement::from(index).to_bytes_be());
     }
+    starknet_keccak(&bytes)
 }
 
 #[cfg(test)]
@@ -371,7 +396,7 @@ mod tests {
         EmittedEvent {
             from_address: FieldElement::from_hex_be("0x0").unwrap(),
             block_hash: Some(block_hash),
-            transaction_hash: transaction_hash,
+            transaction_hash,
             block_number: Some(111),
             keys: vec![
                 TRANSFER_SELECTOR,
@@ -395,14 +420,20 @@ mod tests {
             .expect_register_transfer_event()
             .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
 
-        let manager = EventManager::new(Arc::new(storage));
+        let manager =
+            EventManager::new(Arc::new(storage), Arc::new(MarketplaceRegistry::default()));
 
         let sample_event = setup_sample_event();
         let contract_type = ContractType::ERC721;
         let timestamp = 1234567890;
 
         let result = manager
-            .format_and_register_event(&sample_event, contract_type, timestamp)
+            .format_and_register_event(
+                &sample_event,
+                contract_type,
+                timestamp,
+                &mut TransferOccurrences::default(),
+            )
             .await;
 
         assert!(result.is_ok());
@@ -424,7 +455,8 @@ mod tests {
             .expect_register_transfer_event()
             .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
 
-        let manager = EventManager::new(Arc::new(storage));
+        let manager =
+            EventManager::new(Arc::new(storage), Arc::new(MarketplaceRegistry::default()));
 
         // Construct an event where the event data is only present in `event.data`
         // and not in `event.keys`.
@@ -449,7 +481,12 @@ mod tests {
 
         // Call the `format_event` function
         let result = manager
-            .format_and_register_event(&sample_event, contract_type, timestamp)
+            .format_and_register_event(
+                &sample_event,
+                contract_type,
+                timestamp,
+                &mut TransferOccurrences::default(),
+            )
             .await;
 
         // Assertions
@@ -472,7 +509,7 @@ mod tests {
     #[test]
     fn test_keys_selector() {
         let storage = Arc::new(MockStorage::default());
-        let manager = EventManager::new(storage);
+        let manager = EventManager::new(storage, Arc::new(MarketplaceRegistry::default()));
 
         // Call the method
         let result = manager.keys_selector().unwrap();
@@ -480,9 +517,21 @@ mod tests {
         // Define expected result
         let expected = vec![vec![
             selector!("Transfer"),
-            FieldElement::from_hex_be(ELEMENT_NFT_MARKETPLACE_HEX).unwrap(),
-            FieldElement::from_hex_be(VENTORY_MARKETPLACE_EVENT_HEX).unwrap(),
-            FieldElement::from_hex_be(VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX).unwrap(),
+            selector!("TransferSingle"),
+            selector!("TransferBatch"),
+            selector!("ContractDeployed"),
+            FieldElement::from_hex_be(
+                "0x351e5a57ea6ca22e3e3cd212680ef7f3b57404609bda942a5e75ba4724b55e0",
+            )
+            .unwrap(),
+            FieldElement::from_hex_be(
+                "0x1b43f40d55364e989b3a8674460f61ba8f327542298ee6240a54ee2bf7b55bb",
+            )
+            .unwrap(),
+            FieldElement::from_hex_be(
+                "0xe214ba50bf9d17a50de9ab9f433295bd671144999d5258dbc261cbf1e1c2cc",
+            )
+            .unwrap(),
         ]];
 
         // Assert the output
@@ -510,7 +559,7 @@ mod tests {
         let result = EventManager::<MockStorage>::get_event_info_from_felts(&sample_data);
 
         // Assert the output
-        assert_eq!(result.is_some(), true);
+        assert!(result.is_some());
         let (from, to, token_id) = result.unwrap();
         assert_eq!(from, from_value);
         assert_eq!(to, to_value);
@@ -532,6 +581,214 @@ mod tests {
         let result = EventManager::<MockStorage>::get_event_info_from_felts(&sample_data);
 
         // Assert the output
-        assert_eq!(result.is_none(), true);
+        assert!(result.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_format_transfer_batch() {
+        let mut storage = MockStorage::default();
+
+        storage
+            .expect_register_transfer_event()
+            .times(2)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+
+        let manager =
+            EventManager::new(Arc::new(storage), Arc::new(MarketplaceRegistry::default()));
+
+        // Cairo 1 layout: operator, from and to are keys.
+        let event = EmittedEvent {
+            from_address: FieldElement::from_hex_be("0x1155").unwrap(),
+            block_hash: None,
+            transaction_hash: FieldElement::from_dec_str("5432").unwrap(),
+            block_number: Some(111),
+            keys: vec![
+                TRANSFER_BATCH_SELECTOR,
+                FieldElement::from_hex_be("0x99").unwrap(), // operator
+                FieldElement::from_hex_be("0x1234").unwrap(), // from
+                FieldElement::from_hex_be("0x5678").unwrap(), // to
+            ],
+            data: vec![
+                FieldElement::from(2_u64), // ids length
+                FieldElement::from(1_u64),
+                FieldElement::ZERO,
+                FieldElement::from(2_u64),
+                FieldElement::ZERO,
+                FieldElement::from(2_u64), // values length
+                FieldElement::from(10_u64),
+                FieldElement::ZERO,
+                FieldElement::from(20_u64),
+                FieldElement::ZERO,
+            ],
+        };
+
+        let transfers = manager
+            .format_and_register_transfers(
+                &event,
+                ContractType::ERC1155,
+                1234567890,
+                &mut TransferOccurrences::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[0].0.low, 1);
+        assert_eq!(transfers[0].1.quantity, "10");
+        assert_eq!(transfers[1].0.low, 2);
+        assert_eq!(transfers[1].1.quantity, "20");
+        assert_eq!(transfers[1].1.event_type, EventType::Transfer);
+        assert_eq!(
+            transfers[1].1.to_address,
+            to_hex_str(&FieldElement::from_hex_be("0x5678").unwrap())
+        );
+        assert_ne!(transfers[0].1.event_id, transfers[1].1.event_id);
+    }
+
+    #[tokio::test]
+    async fn test_format_transfer_single_legacy() {
+        let mut storage = MockStorage::default();
+
+        storage
+            .expect_register_transfer_event()
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+
+        let manager =
+            EventManager::new(Arc::new(storage), Arc::new(MarketplaceRegistry::default()));
+
+        // Cairo 0 layout: everything is in the data.
+        let event = EmittedEvent {
+            from_address: FieldElement::from_hex_be("0x1155").unwrap(),
+            block_hash: None,
+            transaction_hash: FieldElement::from_dec_str("5432").unwrap(),
+            block_number: Some(111),
+            keys: vec![TRANSFER_SINGLE_SELECTOR],
+            data: vec![
+                FieldElement::from_hex_be("0x99").unwrap(),
+                FieldElement::ZERO,
+                FieldElement::from_hex_be("0x5678").unwrap(),
+                FieldElement::from(7_u64),
+                FieldElement::ZERO,
+                FieldElement::from(3_u64),
+                FieldElement::ZERO,
+            ],
+        };
+
+        let transfers = manager
+            .format_and_register_transfers(
+                &event,
+                ContractType::ERC1155,
+                1234567890,
+                &mut TransferOccurrences::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].0.low, 7);
+        assert_eq!(transfers[0].1.quantity, "3");
+        assert_eq!(transfers[0].1.event_type, EventType::Mint);
+    }
+
+    /// `TransferSingle` of the token 7 from 0x1234 to 0x5678.
+    fn transfer_single(value: CairoU256) -> EmittedEvent {
+        EmittedEvent {
+            from_address: FieldElement::from_hex_be("0x1155").unwrap(),
+            block_hash: None,
+            transaction_hash: FieldElement::from_dec_str("5432").unwrap(),
+            block_number: Some(111),
+            keys: vec![
+                TRANSFER_SINGLE_SELECTOR,
+                FieldElement::from_hex_be("0x99").unwrap(),
+                FieldElement::from_hex_be("0x1234").unwrap(),
+                FieldElement::from_hex_be("0x5678").unwrap(),
+            ],
+            data: vec![
+                FieldElement::from(7_u64),
+                FieldElement::ZERO,
+                FieldElement::from(value.low),
+                FieldElement::from(value.high),
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_format_transfer_u256_quantity() {
+        let mut storage = MockStorage::default();
+
+        storage
+            .expect_register_transfer_event()
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+
+        let manager =
+            EventManager::new(Arc::new(storage), Arc::new(MarketplaceRegistry::default()));
+
+        let transfers = manager
+            .format_and_register_transfers(
+                &transfer_single(CairoU256::new(0, 1)),
+                ContractType::ERC1155,
+                1234567890,
+                &mut TransferOccurrences::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            transfers[0].1.quantity,
+            "340282366920938463463374607431768211456"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_identical_transfers_event_ids() {
+        let mut storage = MockStorage::default();
+
+        storage
+            .expect_register_transfer_event()
+            .times(3)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+
+        let manager =
+            EventManager::new(Arc::new(storage), Arc::new(MarketplaceRegistry::default()));
+
+        let event = transfer_single(CairoU256::from(3_u64));
+        let mut occurrences = TransferOccurrences::default();
+        let mut event_ids = vec![];
+        for _ in 0..2 {
+            let transfers = manager
+                .format_and_register_transfers(
+                    &event,
+                    ContractType::ERC1155,
+                    1234567890,
+                    &mut occurrences,
+                )
+                .await
+                .unwrap();
+            event_ids.push(transfers[0].1.event_id.clone());
+        }
+
+        // The first transfer keeps the id computed without index.
+        let first_id = event_id(
+            &CairoU256::from(7_u64),
+            &FieldElement::from_hex_be("0x1234").unwrap(),
+            &FieldElement::from_hex_be("0x5678").unwrap(),
+            1234567890,
+            &event,
+            0,
+        );
+        assert_eq!(event_ids[0], to_hex_str(&first_id));
+        assert_ne!(event_ids[0], event_ids[1]);
+
+        // Indexing the transaction again gives the same ids.
+        let transfers = manager
+            .format_and_register_transfers(
+                &event,
+                ContractType::ERC1155,
+                1234567890,
+                &mut TransferOccurrences::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(transfers[0].1.event_id, event_ids[0]);
     }
 }
@@ -1,11 +1,14 @@
 pub mod contract_manager;
-pub use contract_manager::ContractManager;
+pub use contract_manager::{ContractCacheConfig, ContractManager};
+
+pub mod currency_manager;
+pub use currency_manager::CurrencyManager;
 
 pub mod event_manager;
-pub use event_manager::EventManager;
+pub use event_manager::{EventManager, TransferOccurrences};
 
 pub mod token_manager;
-pub use token_manager::TokenManager;
+pub use token_manager::{OwnershipMode, OwnershipReport, TokenManager, TokenOwners};
 
 pub mod block_manager;
 pub use block_manager::{BlockManager, PendingBlockData};
@@ -1,35 +1,131 @@
-use crate::storage::types::{EventType, TokenInfo, TokenMintInfo, TokenTransferEvent};
+use crate::storage::types::{
+    ContractType, EventType, StorageError, TokenInfo, TokenMintInfo, TokenTransferEvent,
+};
 use crate::storage::Storage;
 use anyhow::{anyhow, Result};
-use ark_starknet::client::StarknetClient;
+use ark_starknet::client::{StarknetClient, StarknetClientError};
 use ark_starknet::format::to_hex_str;
 use ark_starknet::CairoU256;
+use futures::StreamExt;
 use starknet::core::types::*;
 use starknet::macros::selector;
-use std::sync::Arc;
+use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// How the owner of the ERC721 tokens is determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OwnershipMode {
+    /// The owner is requested with `owner_of` on the pending block,
+    /// for each transfer. When indexing old blocks, this is the
+    /// current owner and not the owner at the transfer block.
+    #[default]
+    Rpc,
+    /// The owner is the receiver of the last transfer, the transfers
+    /// being indexed in order. Every `reconcile_every` transfers, the
+    /// owner is checked with `owner_of` at the transfer block, and
+    /// mismatches are reported in [`OwnershipReport`].
+    EventSourced { reconcile_every: Option<u64> },
+}
+
+/// Maximum number of `owner_of` requests sent concurrently.
+const OWNER_REQUESTS_CONCURRENCY: usize = 16;
+
+/// Maximum number of mismatches kept in the [`OwnershipReport`],
+/// the oldest being dropped.
+const MAX_REPORTED_MISMATCHES: usize = 100;
+
+/// Revert errors of `owner_of` for a token which doesn't exist,
+/// with the Cairo 0 and Cairo 1 OpenZeppelin implementations.
+const NONEXISTENT_TOKEN_ERRORS: [&str; 2] = ["nonexistent token", "invalid token ID"];
+
+/// Owners of the tokens by contract address and token id,
+/// see [`TokenManager::fetch_owners`].
+pub type TokenOwners = HashMap<(FieldElement, CairoU256), String>;
+
+/// Owner derived from the transfers, different from the owner on chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnershipMismatch {
+    pub contract_address: String,
+    pub token_id: String,
+    pub block_number: u64,
+    pub derived_owner: String,
+    pub chain_owner: String,
+}
+
+/// Result of the reconciliations of the event-sourced ownership.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OwnershipReport {
+    /// Number of owners checked on chain.
+    pub checked: u64,
+    /// Number of mismatches found.
+    pub mismatches_count: u64,
+    /// Last mismatches found, the oldest being dropped.
+    pub mismatches: VecDeque<OwnershipMismatch>,
+}
 
 #[derive(Debug)]
 pub struct TokenManager<S: Storage, C: StarknetClient> {
     storage: Arc<S>,
     client: Arc<C>,
+    ownership_mode: OwnershipMode,
+    /// Number of transfers since the last reconciliation.
+    transfers_count: AtomicU64,
+    /// Reconciliations waiting for the end of their block,
+    /// by contract address and token id.
+    scheduled_reconciliations: Mutex<HashMap<(String, CairoU256), (TokenTransferEvent, u64)>>,
+    report: Mutex<OwnershipReport>,
 }
 
 impl<S: Storage, C: StarknetClient> TokenManager<S, C> {
     /// Initializes a new instance.
-    pub fn new(storage: Arc<S>, client: Arc<C>) -> Self {
+    pub fn new(storage: Arc<S>, client: Arc<C>, ownership_mode: OwnershipMode) -> Self {
         Self {
             storage: Arc::clone(&storage),
             client: Arc::clone(&client),
+            ownership_mode,
+            transfers_count: AtomicU64::new(0),
+            scheduled_reconciliations: Mutex::new(HashMap::new()),
+            report: Mutex::new(OwnershipReport::default()),
         }
     }
 
+    /// Returns the reconciliations done so far
+    /// with [`OwnershipMode::EventSourced`].
+    pub fn ownership_report(&self) -> OwnershipReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    /// Fetches the owners of the given tokens with `owner_of`, ahead of
+    /// their registration, in [`OwnershipMode::Rpc`] only. The tokens whose
+    /// owner can't be fetched are missing, and requested again when registered.
+    pub async fn fetch_owners(&self, tokens: HashSet<(FieldElement, CairoU256)>) -> TokenOwners {
+        if self.ownership_mode != OwnershipMode::Rpc {
+            return TokenOwners::new();
+        }
+
+        futures::stream::iter(tokens)
+            .map(|(contract_address, token_id)| async move {
+                self.owner_of(contract_address, &token_id)
+                    .await
+                    .map(|owner| ((contract_address, token_id), owner))
+            })
+            .buffer_unordered(OWNER_REQUESTS_CONCURRENCY)
+            .filter_map(futures::future::ready)
+            .collect()
+            .await
+    }
+
     /// Formats a token registry from the token event data.
+    /// The `owners` already fetched are not requested again.
     pub async fn format_and_register_token(
         &self,
         token_id: &CairoU256,
         event: &TokenTransferEvent,
         block_timestamp: u64,
         block_number: Option<u64>,
+        owners: &TokenOwners,
     ) -> Result<()> {
         let mut token = TokenInfo {
             contract_address: event.contract_address.clone(),
@@ -39,21 +135,43 @@ impl<S: Storage, C: StarknetClient> TokenManager<S, C> {
             ..Default::default()
         };
 
-        let token_owner_raw_result = self
-            .get_token_owner(
-                FieldElement::from_hex_be(&event.contract_address)
-                    .expect("Contract address bad format"),
-                token_id.low.into(),
-                token_id.high.into(),
-            )
-            .await;
+        // ERC1155 tokens have no single owner, see the balances.
+        if event.contract_type != ContractType::ERC1155.to_string() {
+            token.owner = match self.ownership_mode {
+                OwnershipMode::Rpc => {
+                    let contract_address = FieldElement::from_hex_be(&event.contract_address)
+                        .expect("Contract address bad format");
 
-        token.owner = token_owner_raw_result
-            .ok()
-            .and_then(|owner| owner.first().map(to_hex_str))
-            .unwrap_or_default();
+                    match owners.get(&(contract_address, *token_id)) {
+                        Some(owner) => owner.clone(),
+                        None => self
+                            .owner_of(contract_address, token_id)
+                            .await
+                            .unwrap_or_default(),
+                    }
+                }
+                OwnershipMode::EventSourced { reconcile_every } => {
+                    if let (Some(every), Some(block_number)) = (reconcile_every, block_number) {
+                        self.schedule_reconciliation(every, token_id, event, block_number);
+                    }
 
-        self.storage.register_token(&token, block_timestamp).await?;
+                    event.to_address.clone()
+                }
+            };
+        }
+
+        // Tokens are registered on their first transfer, the next
+        // transfers updating their owner.
+        match self.storage.register_token(&token, block_timestamp).await {
+            Ok(()) => {}
+            Err(StorageError::AlreadyExists(_)) if !token.owner.is_empty() => {
+                self.storage
+                    .update_token_owner(&token.contract_address, &token.token_id_hex, &token.owner)
+                    .await?;
+            }
+            Err(StorageError::AlreadyExists(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
 
         if event.event_type == EventType::Mint {
             let info = TokenMintInfo {
@@ -76,6 +194,127 @@ impl<S: Storage, C: StarknetClient> TokenManager<S, C> {
         Ok(())
     }
 
+    /// Schedules the check of the owner every `every` transfers.
+    ///
+    /// `owner_of` at the transfer block returns the owner at the end of the
+    /// block: a later transfer of a token scheduled in the same block replaces
+    /// the scheduled one, and the check is done once the block is processed,
+    /// see [`Self::reconcile_block_owners`].
+    fn schedule_reconciliation(
+        &self,
+        every: u64,
+        token_id: &CairoU256,
+        event: &TokenTransferEvent,
+        block_number: u64,
+    ) {
+        let due = self.transfers_count.fetch_add(1, Ordering::Relaxed) + 1 >= every;
+        if due {
+            self.transfers_count.store(0, Ordering::Relaxed);
+        }
+
+        let key = (event.contract_address.clone(), *token_id);
+        match self.scheduled_reconciliations.lock().unwrap().entry(key) {
+            Entry::Occupied(mut scheduled) => {
+                scheduled.insert((event.clone(), block_number));
+            }
+            Entry::Vacant(entry) if due => {
+                entry.insert((event.clone(), block_number));
+            }
+            Entry::Vacant(_) => {}
+        }
+    }
+
+    /// Checks the owners scheduled for reconciliation, once all the transfers
+    /// of their block are processed. The checks are dropped if the block
+    /// is not committed.
+    pub async fn reconcile_block_owners(&self, committed: bool) {
+        let scheduled: Vec<_> = self
+            .scheduled_reconciliations
+            .lock()
+            .unwrap()
+            .drain()
+            .collect();
+
+        if !committed {
+            return;
+        }
+
+        for ((_, token_id), (event, block_number)) in scheduled {
+            self.reconcile_owner(&token_id, &event, block_number).await;
+        }
+    }
+
+    /// Checks the owner derived from the transfer with `owner_of`
+    /// at the transfer block, reporting a mismatch if they differ.
+    async fn reconcile_owner(
+        &self,
+        token_id: &CairoU256,
+        event: &TokenTransferEvent,
+        block_number: u64,
+    ) {
+        let contract_address = match FieldElement::from_hex_be(&event.contract_address) {
+            Ok(address) => address,
+            Err(_) => return,
+        };
+
+        let chain_owner = match self
+            .call_owner_of(
+                contract_address,
+                token_id.low.into(),
+                token_id.high.into(),
+                BlockId::Number(block_number),
+            )
+            .await
+        {
+            Ok(owner) => owner.first().map(to_hex_str).unwrap_or_default(),
+            // Burnt tokens have no owner anymore.
+            Err(e) if event.event_type == EventType::Burn && is_nonexistent_token_error(&e) => {
+                event.to_address.clone()
+            }
+            Err(e) => {
+                warn!(
+                    "Can't reconcile owner of token {} {}: {:?}",
+                    event.contract_address, event.token_id, e
+                );
+                return;
+            }
+        };
+
+        let mut report = self.report.lock().unwrap();
+        report.checked += 1;
+
+        if chain_owner != event.to_address {
+            warn!(
+                "Owner mismatch for token {} {} at block {}: derived {}, on chain {}",
+                event.contract_address, event.token_id, block_number, event.to_address, chain_owner
+            );
+
+            report.mismatches_count += 1;
+            if report.mismatches.len() == MAX_REPORTED_MISMATCHES {
+                report.mismatches.pop_front();
+            }
+            report.mismatches.push_back(OwnershipMismatch {
+                contract_address: event.contract_address.clone(),
+                token_id: event.token_id.clone(),
+                block_number,
+                derived_owner: event.to_address.clone(),
+                chain_owner,
+            });
+        }
+    }
+
+    /// Current owner of the token, `None` if it can't be fetched.
+    async fn owner_of(
+        &self,
+        contract_address: FieldElement,
+        token_id: &CairoU256,
+    ) -> Option<String> {
+        self.get_token_owner(contract_address, token_id.low.into(), token_id.high.into())
+            .await
+            .ok()
+            .and_then(|owner| owner.first().map(to_hex_str))
+    }
+
     /// Retrieves the token owner for the last block.
     pub async fn get_token_owner(
         &self,
@@ -83,11 +322,41 @@ impl<S: Storage, C: StarknetClient> TokenManager<S, C> {
         token_id_low: FieldElement,
         token_id_high: FieldElement,
     ) -> Result<Vec<FieldElement>> {
-        let block = BlockId::Tag(BlockTag::Pending);
-        let selectors = vec![selector!("owner_of"), selector!("ownerOf")];
+        self.get_token_owner_at(
+            contract_address,
+            token_id_low,
+            token_id_high,
+            BlockId::Tag(BlockTag::Pending),
+        )
+        .await
+    }
+
+    /// Retrieves the token owner at the given block.
+    pub async fn get_token_owner_at(
+        &self,
+        contract_address: FieldElement,
+        token_id_low: FieldElement,
+        token_id_high: FieldElement,
+        block: BlockId,
+    ) -> Result<Vec<FieldElement>> {
+        self.call_owner_of(contract_address, token_id_low, token_id_high, block)
+            .await
+            .map_err(|e| anyhow!("Failed to get token owner from chain: {}", e))
+    }
+
+    /// Requests the owner with `owner_of`, then `ownerOf`. If both fail, the
+    /// error of the implemented entrypoint is returned.
+    async fn call_owner_of(
+        &self,
+        contract_address: FieldElement,
+        token_id_low: FieldElement,
+        token_id_high: FieldElement,
+        block: BlockId,
+    ) -> Result<Vec<FieldElement>, StarknetClientError> {
+        let mut error = None;
 
-        for selector in selectors {
-            if let Ok(res) = self
+        for selector in [selector!("owner_of"), selector!("ownerOf")] {
+            match self
                 .client
                 .call_contract(
                     contract_address,
@@ -97,14 +366,24 @@ impl<S: Storage, C: StarknetClient> TokenManager<S, C> {
                 )
                 .await
             {
-                return Ok(res);
+                Ok(res) => return Ok(res),
+                Err(e @ StarknetClientError::EntrypointNotFound(_)) => {
+                    error.get_or_insert(e);
+                }
+                Err(e) => error = Some(e),
             }
         }
 
-        Err(anyhow!("Failed to get token owner from chain"))
+        Err(error.expect("Owner requested with at least one selector"))
     }
 }
 
+/// Returns true if `owner_of` failed as the token doesn't exist.
+fn is_nonexistent_token_error(e: &StarknetClientError) -> bool {
+    matches!(e, StarknetClientError::Contract(s)
+        if NONEXISTENT_TOKEN_ERRORS.iter().any(|error| s.contains(error)))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::storage::MockStorage;
@@ -125,7 +404,11 @@ mod tests {
             .expect_call_contract()
             .returning(|_, _, _, _| Ok(vec![FieldElement::from_dec_str("1").unwrap()]));
 
-        let token_manager = TokenManager::new(Arc::new(mock_storage), Arc::new(mock_client));
+        let token_manager = TokenManager::new(
+            Arc::new(mock_storage),
+            Arc::new(mock_client),
+            OwnershipMode::Rpc,
+        );
 
         let result = token_manager
             .get_token_owner(contract_address, token_id_low, token_id_high)
@@ -137,4 +420,291 @@ mod tests {
         assert_eq!(owners.len(), 1);
         assert_eq!(owners[0], FieldElement::from_dec_str("1").unwrap());
     }
+
+    #[tokio::test]
+    async fn test_event_sourced_ownership() {
+        let mut mock_storage = MockStorage::default();
+        let mut mock_client = MockStarknetClient::default();
+
+        mock_storage
+            .expect_register_token()
+            .withf(|token, _| token.owner == "0xto")
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+
+        // Only the second transfer is reconciled, at its block.
+        mock_client
+            .expect_call_contract()
+            .withf(|_, _, _, block| *block == BlockId::Number(12))
+            .times(1)
+            .returning(|_, _, _, _| Ok(vec![FieldElement::from_hex_be("0x99").unwrap()]));
+
+        let token_manager = TokenManager::new(
+            Arc::new(mock_storage),
+            Arc::new(mock_client),
+            OwnershipMode::EventSourced {
+                reconcile_every: Some(2),
+            },
+        );
+
+        let event = TokenTransferEvent {
+            contract_address: "0x1234".to_string(),
+            from_address: "0xfrom".to_string(),
+            to_address: "0xto".to_string(),
+            contract_type: ContractType::ERC721.to_string(),
+            event_type: EventType::Transfer,
+            ..Default::default()
+        };
+        let token_id = CairoU256 { low: 1, high: 0 };
+
+        for block_number in [11, 12] {
+            token_manager
+                .format_and_register_token(
+                    &token_id,
+                    &event,
+                    0,
+                    Some(block_number),
+                    &TokenOwners::new(),
+                )
+                .await
+                .unwrap();
+            token_manager.reconcile_block_owners(true).await;
+        }
+
+        let report = token_manager.ownership_report();
+        assert_eq!(report.checked, 1);
+        assert_eq!(report.mismatches_count, 1);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].block_number, 12);
+        assert_eq!(report.mismatches[0].derived_owner, "0xto");
+        assert_eq!(
+            report.mismatches[0].chain_owner,
+            to_hex_str(&FieldElement::from_hex_be("0x99").unwrap())
+        );
+    }
+
+    fn event_sourced_storage() -> MockStorage {
+        let mut mock_storage = MockStorage::default();
+        mock_storage
+            .expect_register_token()
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+        mock_storage
+    }
+
+    fn reconciled_transfer(event_type: EventType, to_address: u64) -> TokenTransferEvent {
+        TokenTransferEvent {
+            contract_address: "0x1234".to_string(),
+            to_address: to_hex_str(&FieldElement::from(to_address)),
+            contract_type: ContractType::ERC721.to_string(),
+            event_type,
+            ..Default::default()
+        }
+    }
+
+    async fn register_reconciled_transfers(
+        token_manager: &TokenManager<MockStorage, MockStarknetClient>,
+        events: &[TokenTransferEvent],
+        block_number: u64,
+    ) {
+        for event in events {
+            token_manager
+                .format_and_register_token(
+                    &CairoU256 { low: 1, high: 0 },
+                    event,
+                    0,
+                    Some(block_number),
+                    &TokenOwners::new(),
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_last_transfer_of_block() {
+        let mut mock_client = MockStarknetClient::default();
+
+        // The owner at the end of the block is the receiver of the last transfer.
+        mock_client
+            .expect_call_contract()
+            .withf(|_, _, _, block| *block == BlockId::Number(12))
+            .times(1)
+            .returning(|_, _, _, _| Ok(vec![FieldElement::from(0xc_u64)]));
+
+        let token_manager = TokenManager::new(
+            Arc::new(event_sourced_storage()),
+            Arc::new(mock_client),
+            OwnershipMode::EventSourced {
+                reconcile_every: Some(1),
+            },
+        );
+
+        // Not checked as the block is rolled back.
+        register_reconciled_transfers(
+            &token_manager,
+            &[reconciled_transfer(EventType::Transfer, 0xa)],
+            11,
+        )
+        .await;
+        token_manager.reconcile_block_owners(false).await;
+
+        register_reconciled_transfers(
+            &token_manager,
+            &[
+                reconciled_transfer(EventType::Transfer, 0xb),
+                reconciled_transfer(EventType::Transfer, 0xc),
+            ],
+            12,
+        )
+        .await;
+        token_manager.reconcile_block_owners(true).await;
+
+        let report = token_manager.ownership_report();
+        assert_eq!(report.checked, 1);
+        assert!(report.mismatches.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_burnt_token() {
+        let mut mock_client = MockStarknetClient::default();
+        let mut seq = mockall::Sequence::new();
+
+        // The first burn is checked, the token not existing anymore.
+        mock_client
+            .expect_call_contract()
+            .withf(|_, selector, _, _| *selector == selector!("owner_of"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| {
+                Err(StarknetClientError::Contract(
+                    "ERC721: invalid token ID".to_string(),
+                ))
+            });
+        mock_client
+            .expect_call_contract()
+            .withf(|_, selector, _, _| *selector == selector!("ownerOf"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| {
+                Err(StarknetClientError::EntrypointNotFound(
+                    "Entry point not found in contract".to_string(),
+                ))
+            });
+        // The second one can't be checked, the node failing.
+        mock_client
+            .expect_call_contract()
+            .times(2)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| Err(StarknetClientError::Other("rate limited".to_string())));
+
+        let token_manager = TokenManager::new(
+            Arc::new(event_sourced_storage()),
+            Arc::new(mock_client),
+            OwnershipMode::EventSourced {
+                reconcile_every: Some(1),
+            },
+        );
+
+        for block_number in [11, 12] {
+            register_reconciled_transfers(
+                &token_manager,
+                &[reconciled_transfer(EventType::Burn, 0x0)],
+                block_number,
+            )
+            .await;
+            token_manager.reconcile_block_owners(true).await;
+        }
+
+        let report = token_manager.ownership_report();
+        assert_eq!(report.checked, 1);
+        assert!(report.mismatches.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reported_mismatches_capped() {
+        let mut mock_client = MockStarknetClient::default();
+        mock_client
+            .expect_call_contract()
+            .returning(|_, _, _, _| Ok(vec![FieldElement::from(0x99_u64)]));
+
+        let token_manager = TokenManager::new(
+            Arc::new(event_sourced_storage()),
+            Arc::new(mock_client),
+            OwnershipMode::EventSourced {
+                reconcile_every: Some(1),
+            },
+        );
+
+        let blocks = MAX_REPORTED_MISMATCHES as u64 + 1;
+        for block_number in 1..=blocks {
+            register_reconciled_transfers(
+                &token_manager,
+                &[reconciled_transfer(EventType::Transfer, 0xb)],
+                block_number,
+            )
+            .await;
+            token_manager.reconcile_block_owners(true).await;
+        }
+
+        // Only the last mismatches are kept.
+        let report = token_manager.ownership_report();
+        assert_eq!(report.mismatches_count, blocks);
+        assert_eq!(report.mismatches.len(), MAX_REPORTED_MISMATCHES);
+        assert_eq!(report.mismatches[0].block_number, 2);
+    }
+
+    #[tokio::test]
+    async fn test_event_sourced_owner_updated() {
+        let mut mock_storage = MockStorage::default();
+        let mut seq = mockall::Sequence::new();
+
+        mock_storage
+            .expect_register_token()
+            .withf(|token, _| token.owner == "0xb")
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+        mock_storage
+            .expect_register_token()
+            .withf(|token, _| token.owner == "0xc")
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|token, _| {
+                let e = StorageError::AlreadyExists(token.token_id_hex.clone());
+                Box::pin(futures::future::ready(Err(e)))
+            });
+        mock_storage
+            .expect_update_token_owner()
+            .withf(|contract_address, token_id_hex, owner| {
+                contract_address == "0x1234" && token_id_hex == "0x1" && owner == "0xc"
+            })
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| Box::pin(futures::future::ready(Ok(()))));
+
+        let token_manager = TokenManager::new(
+            Arc::new(mock_storage),
+            Arc::new(MockStarknetClient::default()),
+            OwnershipMode::EventSourced {
+                reconcile_every: None,
+            },
+        );
+
+        let token_id = CairoU256 { low: 1, high: 0 };
+        for (from, to) in [("0xa", "0xb"), ("0xb", "0xc")] {
+            let event = TokenTransferEvent {
+                contract_address: "0x1234".to_string(),
+                token_id_hex: "0x1".to_string(),
+                from_address: from.to_string(),
+                to_address: to.to_string(),
+                contract_type: ContractType::ERC721.to_string(),
+                event_type: EventType::Transfer,
+                ..Default::default()
+            };
+
+            token_manager
+                .format_and_register_token(&token_id, &event, 0, None, &TokenOwners::new())
+                .await
+                .unwrap();
+        }
+    }
 }
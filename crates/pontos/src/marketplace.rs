@@ -0,0 +1,436 @@
+//! Decoding of the marketplaces sale events.
+//!
+//! Each marketplace is supported by a [`MarketplaceDecoder`], registered
+//! in the [`MarketplaceRegistry`] given to Pontos with its configuration.
+//! Element and Ventory decoders are provided, and registered by default.
+use crate::managers::event_manager::event_id;
+use crate::storage::types::{EventType, TokenSaleEvent};
+use anyhow::{anyhow, Result};
+use ark_starknet::{
+    cairo_serde::{CairoDeserialize, FeltReader},
+    format::to_hex_str,
+    CairoU256,
+};
+use starknet::core::types::{EmittedEvent, FieldElement};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Chain id of Starknet mainnet.
+pub const MAINNET_CHAIN_ID: &str = "0x534e5f4d41494e";
+
+const ELEMENT_SALE_EVENT_HEX: &str =
+    "0x351e5a57ea6ca22e3e3cd212680ef7f3b57404609bda942a5e75ba4724b55e0";
+
+const ELEMENT_MAINNET_CONTRACT_HEX: &str =
+    "0x04d8bb956e6bd7a50fcb8b49d8e9fd8269cfadbeb73f457fd6d3fc1dff4b879e";
+
+const VENTORY_SALE_EVENT_HEX: &str =
+    "0x1b43f40d55364e989b3a8674460f61ba8f327542298ee6240a54ee2bf7b55bb"; // EventListingBought
+
+const VENTORY_OFFER_ACCEPTED_EVENT_HEX: &str =
+    "0xe214ba50bf9d17a50de9ab9f433295bd671144999d5258dbc261cbf1e1c2cc"; // EventOfferAccepted
+
+const VENTORY_MAINNET_CONTRACT_HEX: &str =
+    "0x008755a98ccf7d25e69aa90ef3b73b07c470ba4ec6391b0b0c7c598f992c3fee";
+
+const ETH_CONTRACT_HEX: &str = "0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7";
+
+/// Decodes the sale events emitted by the contracts of a marketplace.
+pub trait MarketplaceDecoder: Send + Sync {
+    /// Name of the marketplace, registered with the sales.
+    fn name(&self) -> &str;
+
+    /// Selectors of the decoded events, used to filter
+    /// the events fetched from the chain.
+    fn selectors(&self) -> Vec<FieldElement>;
+
+    /// Addresses of the marketplace contracts on the given chain.
+    fn contract_addresses(&self, chain_id: &str) -> Vec<FieldElement>;
+
+    /// Decodes a sale event emitted by one of the marketplace contracts,
+    /// with one of the decoder selectors.
+    /// The NFT type is left empty, as identified by Pontos.
+    fn decode(&self, event: &EmittedEvent, block_timestamp: u64) -> Result<TokenSaleEvent>;
+}
+
+/// Addresses of the contracts of a marketplace, by chain id.
+#[derive(Debug, Clone, Default)]
+pub struct MarketplaceContracts {
+    contracts: HashMap<String, Vec<FieldElement>>,
+}
+
+impl MarketplaceContracts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a contract of the marketplace on the given chain.
+    pub fn with_contract(mut self, chain_id: &str, address: FieldElement) -> Self {
+        self.contracts
+            .entry(chain_id.to_string())
+            .or_default()
+            .push(address);
+        self
+    }
+
+    pub fn get(&self, chain_id: &str) -> Vec<FieldElement> {
+        self.contracts.get(chain_id).cloned().unwrap_or_default()
+    }
+}
+
+/// Registry of the marketplace decoders used by Pontos.
+#[derive(Clone)]
+pub struct MarketplaceRegistry {
+    decoders: Vec<Arc<dyn MarketplaceDecoder>>,
+}
+
+impl MarketplaceRegistry {
+    /// Initializes a registry without any marketplace.
+    pub fn new() -> Self {
+        Self { decoders: vec![] }
+    }
+
+    pub fn register(&mut self, decoder: impl MarketplaceDecoder + 'static) -> &mut Self {
+        self.decoders.push(Arc::new(decoder));
+        self
+    }
+
+    /// Returns the selectors of all the registered decoders.
+    pub fn selectors(&self) -> Vec<FieldElement> {
+        let mut selectors: Vec<FieldElement> = vec![];
+        for selector in self.decoders.iter().flat_map(|d| d.selectors()) {
+            if !selectors.contains(&selector) {
+                selectors.push(selector);
+            }
+        }
+        selectors
+    }
+
+    /// Returns the decoder of the marketplace owning
+    /// the given contract on the given chain.
+    pub fn decoder(
+        &self,
+        chain_id: &str,
+        address: &FieldElement,
+    ) -> Option<&dyn MarketplaceDecoder> {
+        self.decoders
+            .iter()
+            .find(|d| d.contract_addresses(chain_id).contains(address))
+            .map(|d| d.as_ref())
+    }
+
+    /// Returns the contracts of all the marketplaces on the given chain.
+    pub fn contract_addresses(&self, chain_id: &str) -> Vec<FieldElement> {
+        self.decoders
+            .iter()
+            .flat_map(|d| d.contract_addresses(chain_id))
+            .collect()
+    }
+
+    pub fn is_marketplace_contract(&self, chain_id: &str, address: &FieldElement) -> bool {
+        self.decoder(chain_id, address).is_some()
+    }
+}
+
+/// Registers the Element and Ventory marketplaces on mainnet.
+impl Default for MarketplaceRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register(ElementDecoder::default())
+            .register(VentoryDecoder::default());
+        registry
+    }
+}
+
+impl std::fmt::Debug for MarketplaceRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.decoders.iter().map(|d| d.name()))
+            .finish()
+    }
+}
+
+/// Data of Element sale events, the maker address is in the keys.
+#[derive(Debug, CairoDeserialize)]
+struct ElementSaleData {
+    taker: FieldElement,
+    currency: FieldElement,
+    price: FieldElement,
+    _fees: Vec<(FieldElement, FieldElement)>,
+    nft_contract: FieldElement,
+    token_id: CairoU256,
+    quantity: u64,
+}
+
+/// Decoder of the Element marketplace sales.
+#[derive(Debug, Clone)]
+pub struct ElementDecoder {
+    contracts: MarketplaceContracts,
+}
+
+impl ElementDecoder {
+    pub fn new(contracts: MarketplaceContracts) -> Self {
+        Self { contracts }
+    }
+}
+
+impl Default for ElementDecoder {
+    fn default() -> Self {
+        Self::new(MarketplaceContracts::new().with_contract(
+            MAINNET_CHAIN_ID,
+            FieldElement::from_hex_be(ELEMENT_MAINNET_CONTRACT_HEX).unwrap(),
+        ))
+    }
+}
+
+impl MarketplaceDecoder for ElementDecoder {
+    fn name(&self) -> &str {
+        "Element"
+    }
+
+    fn selectors(&self) -> Vec<FieldElement> {
+        vec![FieldElement::from_hex_be(ELEMENT_SALE_EVENT_HEX).unwrap()]
+    }
+
+    fn contract_addresses(&self, chain_id: &str) -> Vec<FieldElement> {
+        self.contracts.get(chain_id)
+    }
+
+    fn decode(&self, event: &EmittedEvent, block_timestamp: u64) -> Result<TokenSaleEvent> {
+        if event.keys.len() < 4 {
+            return Err(anyhow!("Can't find event data into this event"));
+        }
+
+        let maker_address = event
+            .keys
+            .get(3)
+            .ok_or_else(|| anyhow!("Maker address not found"))?;
+
+        let data: ElementSaleData = FeltReader::new(&event.data)
+            .read()
+            .map_err(|e| anyhow!("Invalid Element sale event: {}", e))?;
+
+        let token_id = data.token_id;
+        let (taker_address, currency_address, nft_contract_address, price) =
+            (&data.taker, &data.currency, &data.nft_contract, &data.price);
+
+        let event_id = event_id(
+            &token_id,
+            maker_address,
+            taker_address,
+            block_timestamp,
+            event,
+            0,
+        );
+
+        Ok(TokenSaleEvent {
+            event_id: to_hex_str(&event_id),
+            event_type: EventType::Sale,
+            block_number: event.block_number,
+            from_address: to_hex_str(maker_address),
+            to_address: to_hex_str(taker_address),
+            nft_contract_address: to_hex_str(nft_contract_address),
+            nft_type: None,
+            transaction_hash: to_hex_str(&event.transaction_hash),
+            token_id_hex: token_id.to_hex(),
+            token_id: token_id.to_decimal(false),
+            timestamp: block_timestamp,
+            updated_at: Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
+            quantity: data.quantity,
+            currency_address: Some(to_hex_str(currency_address)),
+            marketplace_contract_address: to_hex_str(&event.from_address),
+            marketplace_name: self.name().to_string(),
+            price: price.to_big_decimal(0).to_string(),
+            currency_symbol: None,
+            currency_decimals: None,
+            price_decimal: None,
+            reference_price: None,
+        })
+    }
+}
+
+/// Data of Ventory sale and accepted offer events.
+#[derive(Debug, CairoDeserialize)]
+struct VentorySaleData {
+    _listing_counter: FieldElement,
+    token_id: u128,
+    price: FieldElement,
+    asset_contract: FieldElement,
+    seller: FieldElement,
+    buyer: FieldElement,
+    _status: FieldElement,
+}
+
+/// Decoder of the Ventory marketplace sales and accepted offers.
+///
+/// The currency is not part of Ventory events, all the sales
+/// are considered paid with the configured currency.
+#[derive(Debug, Clone)]
+pub struct VentoryDecoder {
+    contracts: MarketplaceContracts,
+    currency: Option<FieldElement>,
+}
+
+impl VentoryDecoder {
+    pub fn new(contracts: MarketplaceContracts, currency: Option<FieldElement>) -> Self {
+        Self {
+            contracts,
+            currency,
+        }
+    }
+}
+
+/// Ventory on mainnet, with sales paid in ETH.
+impl Default for VentoryDecoder {
+    fn default() -> Self {
+        Self::new(
+            MarketplaceContracts::new().with_contract(
+                MAINNET_CHAIN_ID,
+                FieldElement::from_hex_be(VENTORY_MAINNET_CONTRACT_HEX).unwrap(),
+            ),
+            Some(FieldElement::from_hex_be(ETH_CONTRACT_HEX).unwrap()),
+        )
+    }
+}
+
+impl MarketplaceDecoder for VentoryDecoder {
+    fn name(&self) -> &str {
+        "Ventory"
+    }
+
+    fn selectors(&self) -> Vec<FieldElement> {
+        vec![
+            FieldElement::from_hex_be(VENTORY_SALE_EVENT_HEX).unwrap(),
+            FieldElement::from_hex_be(VENTORY_OFFER_ACCEPTED_EVENT_HEX).unwrap(),
+        ]
+    }
+
+    fn contract_addresses(&self, chain_id: &str) -> Vec<FieldElement> {
+        self.contracts.get(chain_id)
+    }
+
+    fn decode(&self, event: &EmittedEvent, block_timestamp: u64) -> Result<TokenSaleEvent> {
+        let data: VentorySaleData = FeltReader::new(&event.data)
+            .read()
+            .map_err(|e| anyhow!("Invalid Ventory sale event: {}", e))?;
+
+        let token_id = CairoU256 {
+            low: data.token_id,
+            high: 0,
+        };
+        let (seller, buyer, asset_contract, price) =
+            (&data.seller, &data.buyer, &data.asset_contract, &data.price);
+
+        let event_id = event_id(&token_id, seller, buyer, block_timestamp, event, 0);
+
+        Ok(TokenSaleEvent {
+            event_id: to_hex_str(&event_id),
+            event_type: EventType::Sale,
+            block_number: event.block_number,
+            from_address: to_hex_str(seller),
+            to_address: to_hex_str(buyer),
+            nft_contract_address: to_hex_str(asset_contract),
+            nft_type: None,
+            transaction_hash: to_hex_str(&event.transaction_hash),
+            token_id_hex: token_id.to_hex(),
+            token_id: token_id.to_decimal(false),
+            timestamp: block_timestamp,
+            updated_at: Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
+            quantity: 1,
+            currency_address: self.currency.as_ref().map(to_hex_str),
+            marketplace_contract_address: to_hex_str(&event.from_address),
+            marketplace_name: self.name().to_string(),
+            price: price.to_big_decimal(0).to_string(),
+            currency_symbol: None,
+            currency_decimals: None,
+            price_decimal: None,
+            reference_price: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_registry() {
+        let registry = MarketplaceRegistry::default();
+
+        assert_eq!(
+            registry.selectors(),
+            vec![
+                FieldElement::from_hex_be(ELEMENT_SALE_EVENT_HEX).unwrap(),
+                FieldElement::from_hex_be(VENTORY_SALE_EVENT_HEX).unwrap(),
+                FieldElement::from_hex_be(VENTORY_OFFER_ACCEPTED_EVENT_HEX).unwrap(),
+            ]
+        );
+
+        let ventory = FieldElement::from_hex_be(VENTORY_MAINNET_CONTRACT_HEX).unwrap();
+        assert_eq!(
+            registry.decoder(MAINNET_CHAIN_ID, &ventory).unwrap().name(),
+            "Ventory"
+        );
+
+        // Contracts are only registered on mainnet.
+        assert!(!registry.is_marketplace_contract("0x534e5f5345504f4c4941", &ventory));
+    }
+
+    #[test]
+    fn test_register_per_chain() {
+        let sepolia = "0x534e5f5345504f4c4941";
+        let contract = FieldElement::from(0x1234_u64);
+
+        let mut registry = MarketplaceRegistry::new();
+        registry.register(ElementDecoder::new(
+            MarketplaceContracts::new().with_contract(sepolia, contract),
+        ));
+
+        assert_eq!(
+            registry.decoder(sepolia, &contract).unwrap().name(),
+            "Element"
+        );
+        assert!(registry.decoder(MAINNET_CHAIN_ID, &contract).is_none());
+    }
+
+    #[test]
+    fn test_decode_ventory_sale() {
+        let event = EmittedEvent {
+            from_address: FieldElement::from_hex_be(VENTORY_MAINNET_CONTRACT_HEX).unwrap(),
+            block_hash: None,
+            transaction_hash: FieldElement::from(0x99_u64),
+            block_number: Some(10),
+            keys: vec![FieldElement::from_hex_be(VENTORY_SALE_EVENT_HEX).unwrap()],
+            data: vec![
+                FieldElement::from(1_u64),    // listing counter
+                FieldElement::from(7_u64),    // token id
+                FieldElement::from(1000_u64), // price
+                FieldElement::from(0xaa_u64), // asset contract
+                FieldElement::from(0xbb_u64), // seller
+                FieldElement::from(0xcc_u64), // buyer
+                FieldElement::from(1_u64),    // status
+            ],
+        };
+
+        let sale = VentoryDecoder::default().decode(&event, 100).unwrap();
+
+        assert_eq!(sale.token_id, "7");
+        assert_eq!(sale.price, "1000");
+        assert_eq!(
+            sale.nft_contract_address,
+            to_hex_str(&FieldElement::from(0xaa_u64))
+        );
+        assert_eq!(sale.from_address, to_hex_str(&FieldElement::from(0xbb_u64)));
+        assert_eq!(sale.to_address, to_hex_str(&FieldElement::from(0xcc_u64)));
+        assert_eq!(sale.marketplace_name, "Ventory");
+        assert_eq!(sale.quantity, 1);
+        assert_eq!(
+            sale.currency_address,
+            Some(to_hex_str(
+                &FieldElement::from_hex_be(ETH_CONTRACT_HEX).unwrap()
+            ))
+        );
+    }
+}
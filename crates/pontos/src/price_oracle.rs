@@ -0,0 +1,50 @@
+//! Conversion of the sale prices to a reference currency.
+//!
+//! Pontos normalises the sale prices with the decimals of their currency,
+//! and, if a [`PriceOracle`] is configured, converts them to the reference
+//! currency of the oracle to have comparable prices across currencies.
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use starknet::core::types::FieldElement;
+use std::collections::HashMap;
+
+/// Metadata of an ERC20 currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurrencyInfo {
+    pub address: FieldElement,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// Provides the exchange rates of the currencies to a reference currency.
+#[async_trait]
+pub trait PriceOracle: Send + Sync {
+    /// Returns the value of one unit of `currency` in the reference
+    /// currency, at the given timestamp. `None` if the rate is unknown.
+    async fn rate(&self, currency: &CurrencyInfo, timestamp: u64) -> Option<BigDecimal>;
+}
+
+/// Oracle with a fixed rate for each currency, whatever the timestamp.
+#[derive(Debug, Clone, Default)]
+pub struct StaticPriceOracle {
+    rates: HashMap<FieldElement, BigDecimal>,
+}
+
+impl StaticPriceOracle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the rate of the currency at the given address.
+    pub fn with_rate(mut self, currency_address: FieldElement, rate: BigDecimal) -> Self {
+        self.rates.insert(currency_address, rate);
+        self
+    }
+}
+
+#[async_trait]
+impl PriceOracle for StaticPriceOracle {
+    async fn rate(&self, currency: &CurrencyInfo, _timestamp: u64) -> Option<BigDecimal> {
+        self.rates.get(&currency.address).cloned()
+    }
+}
@@ -4,14 +4,41 @@ pub mod types;
 pub mod utils;
 use self::types::TokenSaleEvent;
 use crate::storage::types::{
-    BlockInfo, ContractInfo, ContractType, StorageError, TokenInfo, TokenMintInfo,
+    BlockIndexingStatus, BlockInfo, CollectionStats, ContractInfo, ContractType, EventCursor,
+    EventPage, StorageError, TokenBalance, TokenEvent, TokenInfo, TokenMintInfo,
     TokenTransferEvent,
 };
 use async_trait::async_trait;
 #[cfg(test)]
 use mockall::automock;
 #[cfg(feature = "sqlxdb")]
-pub use sqlx::DefaultSqlxStorage;
+pub use sqlx::{DefaultSqlxStorage, PostgresStorage, SqliteStorage};
+use std::future::Future;
+
+tokio::task_local! {
+    static CURRENT_BLOCK: Option<u64>;
+}
+
+/// Runs `f` in the scope of the block `block_number`. Once the block
+/// is begun, the storage calls of `f` are part of its unit of work,
+/// see [`Storage::begin_block`].
+pub async fn in_block<F: Future>(block_number: u64, f: F) -> F::Output {
+    CURRENT_BLOCK.scope(Some(block_number), f).await
+}
+
+/// Runs `f` out of the scope of any block, the storage calls of `f`
+/// being kept even if the block being indexed is rolled back.
+pub async fn outside_block<F: Future>(f: F) -> F::Output {
+    CURRENT_BLOCK.scope(None, f).await
+}
+
+/// Block of the current scope, see [`in_block`].
+pub fn current_block() -> Option<u64> {
+    CURRENT_BLOCK
+        .try_with(|block_number| *block_number)
+        .ok()
+        .flatten()
+}
 
 #[async_trait]
 #[cfg_attr(test, automock)]
@@ -30,12 +57,32 @@ pub trait Storage {
         block_timestamp: u64,
     ) -> Result<(), StorageError>;
 
+    /// Sets the owner of a token registered by a previous transfer.
+    async fn update_token_owner(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        owner: &str,
+    ) -> Result<(), StorageError>;
+
+    /// Balance of the given token owned by `owner`, a u256 as a
+    /// decimal string, "0" if the owner never had the token.
+    async fn get_token_balance(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        owner: &str,
+    ) -> Result<String, StorageError>;
+
     async fn register_sale_event(
         &self,
         event: &TokenSaleEvent,
         block_timestamp: u64,
     ) -> Result<(), StorageError>;
 
+    /// Registers the transfer, and moves its quantity from the balance
+    /// of the sender to the one of the receiver. An event already
+    /// registered is not applied again.
     async fn register_transfer_event(
         &self,
         event: &TokenTransferEvent,
@@ -48,6 +95,16 @@ pub trait Storage {
         chain_id: &str,
     ) -> Result<ContractType, StorageError>;
 
+    /// Types of the contracts of the chain, the last
+    /// registered first, up to `limit` contracts.
+    async fn get_contract_types(
+        &self,
+        chain_id: &str,
+        limit: u64,
+    ) -> Result<Vec<(String, ContractType)>, StorageError>;
+
+    /// Registers the info of a contract, updating the info of a contract
+    /// identified again, its first block timestamp being kept.
     async fn register_contract_info(
         &self,
         info: &ContractInfo,
@@ -55,6 +112,23 @@ pub trait Storage {
         chain_id: &str,
     ) -> Result<(), StorageError>;
 
+    /// Starts the unit of work of a block: all the writes done in the
+    /// scope of the block until `commit_block` are applied at once, or
+    /// not at all if the block is rolled back. Several blocks can be
+    /// written at a time, each in its own scope, see [`in_block`].
+    ///
+    /// Must be called in the scope of the block. The calls done out
+    /// of the scope of a begun block are applied immediately.
+    async fn begin_block(&self, block_number: u64) -> Result<(), StorageError>;
+
+    /// Applies all the writes since `begin_block`.
+    /// Must be called in the scope of the block.
+    async fn commit_block(&self, block_number: u64) -> Result<(), StorageError>;
+
+    /// Discards all the writes since `begin_block`.
+    /// Must be called in the scope of the block.
+    async fn rollback_block(&self, block_number: u64) -> Result<(), StorageError>;
+
     /// A block info is only set if the block has a number and a timestamp.
     async fn set_block_info(
         &self,
@@ -65,11 +139,58 @@ pub trait Storage {
 
     async fn get_block_info(&self, block_number: u64) -> Result<BlockInfo, StorageError>;
 
+    /// Blocks of the given indexer currently having the given status.
+    async fn get_blocks_by_status(
+        &self,
+        indexer_identifier: &str,
+        status: BlockIndexingStatus,
+    ) -> Result<Vec<BlockInfo>, StorageError>;
+
+    /// Last block indexed by the given indexer, from which
+    /// the indexation is resumed. `None` if nothing was indexed.
+    async fn get_checkpoint(&self, indexer_identifier: &str) -> Result<Option<u64>, StorageError>;
+
+    async fn set_checkpoint(
+        &self,
+        indexer_identifier: &str,
+        block_number: u64,
+    ) -> Result<(), StorageError>;
+
     /// The block timestamps is always present. But the number can be missing
     /// for the pending block support.
+    ///
+    /// The balance changes of the transfers cleaned are reverted, the
+    /// tokens transferred getting back their owner before the block.
     async fn clean_block(
         &self,
         block_timestamp: u64,
         block_number: Option<u64>,
     ) -> Result<(), StorageError>;
+
+    /// Tokens owned by `owner`, with a balance greater than 0.
+    async fn get_tokens_by_owner(&self, owner: &str) -> Result<Vec<TokenBalance>, StorageError>;
+
+    /// Transfers and sales of a token, from the oldest. Up to `limit`
+    /// events following the `after` cursor are returned, from the
+    /// first event if `None`.
+    async fn get_token_history(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        after: Option<EventCursor>,
+        limit: u64,
+    ) -> Result<EventPage, StorageError>;
+
+    async fn get_collection_stats(
+        &self,
+        contract_address: &str,
+    ) -> Result<CollectionStats, StorageError>;
+
+    /// Transfers and sales of the blocks `from_block` to `to_block`
+    /// included, ordered by block then by event id.
+    async fn get_events_by_block_range(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<TokenEvent>, StorageError>;
 }
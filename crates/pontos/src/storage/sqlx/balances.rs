@@ -0,0 +1,200 @@
+//! Balances of the tokens by owner, derived from the transfer events.
+//!
+//! The balance changes of a transfer event are applied when the event
+//! is inserted, and reverted when it is cleaned with its block, the
+//! balances staying the sum of the transfers indexed.
+use ark_starknet::CairoU256;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::storage::types::{EventType, StorageError, TokenTransferEvent};
+
+/// Contract address, token id hex and owner of a balance.
+pub type BalanceKey = (String, String, String);
+
+/// Quantities received and sent by an owner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceChange {
+    pub token_id: String,
+    pub chain_id: String,
+    pub received: CairoU256,
+    pub sent: CairoU256,
+}
+
+/// Balance changes of transfers, ordered by balance for the rows to
+/// be always updated in the same order by concurrent blocks.
+#[derive(Debug, Default)]
+pub struct BalanceChanges(BTreeMap<BalanceKey, BalanceChange>);
+
+impl BalanceChanges {
+    /// Adds the changes of the transfer, reverted if `revert` is true.
+    /// Mints have no sender, and burns no receiver.
+    pub fn add(&mut self, event: &TokenTransferEvent, revert: bool) -> Result<(), StorageError> {
+        let quantity = CairoU256::from_str(&event.quantity).map_err(|_| {
+            StorageError::DatabaseError(format!(
+                "invalid quantity {} of event {}",
+                event.quantity, event.event_id
+            ))
+        })?;
+
+        if event.event_type != EventType::Mint {
+            self.change(event, &event.from_address, quantity, !revert)?;
+        }
+
+        if event.event_type != EventType::Burn {
+            self.change(event, &event.to_address, quantity, revert)?;
+        }
+
+        Ok(())
+    }
+
+    fn change(
+        &mut self,
+        event: &TokenTransferEvent,
+        owner: &str,
+        quantity: CairoU256,
+        sent: bool,
+    ) -> Result<(), StorageError> {
+        let change = self
+            .0
+            .entry((
+                event.contract_address.clone(),
+                event.token_id_hex.clone(),
+                owner.to_string(),
+            ))
+            .or_insert_with(|| BalanceChange {
+                token_id: event.token_id.clone(),
+                chain_id: event.chain_id.clone(),
+                received: CairoU256::ZERO,
+                sent: CairoU256::ZERO,
+            });
+
+        let total = if sent {
+            &mut change.sent
+        } else {
+            &mut change.received
+        };
+
+        *total = total
+            .checked_add(&quantity)
+            .ok_or_else(|| StorageError::DatabaseError(format!("balance overflow of {owner}")))?;
+
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&BalanceKey, &BalanceChange)> {
+        self.0.iter()
+    }
+}
+
+impl BalanceChange {
+    /// Applies the change to the balance, which can't go below zero
+    /// when the transfers received by the owner were not indexed.
+    /// Returns the new balance, and false if it was floored to zero.
+    pub fn apply(&self, balance: &CairoU256) -> (CairoU256, bool) {
+        let received = balance
+            .checked_add(&self.received)
+            .unwrap_or(CairoU256::MAX);
+
+        match received.checked_sub(&self.sent) {
+            Some(balance) => (balance, true),
+            None => (CairoU256::ZERO, false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(event_type: EventType, from: &str, to: &str, quantity: &str) -> TokenTransferEvent {
+        TokenTransferEvent {
+            contract_address: "0x1".to_string(),
+            token_id: "1".to_string(),
+            token_id_hex: "0x1".to_string(),
+            from_address: from.to_string(),
+            to_address: to.to_string(),
+            event_type,
+            quantity: quantity.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn key(owner: &str) -> BalanceKey {
+        ("0x1".to_string(), "0x1".to_string(), owner.to_string())
+    }
+
+    #[test]
+    fn test_add() {
+        let mut changes = BalanceChanges::default();
+        changes
+            .add(&transfer(EventType::Mint, "0x0", "0xa", "10"), false)
+            .unwrap();
+        changes
+            .add(&transfer(EventType::Transfer, "0xa", "0xb", "3"), false)
+            .unwrap();
+        changes
+            .add(&transfer(EventType::Burn, "0xb", "0x0", "1"), false)
+            .unwrap();
+
+        let changes: Vec<_> = changes
+            .iter()
+            .map(|(key, change)| (key.clone(), change.received, change.sent))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (key("0xa"), CairoU256::from(10_u64), CairoU256::from(3_u64)),
+                (key("0xb"), CairoU256::from(3_u64), CairoU256::from(1_u64)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_reverted() {
+        let mut changes = BalanceChanges::default();
+        changes
+            .add(&transfer(EventType::Transfer, "0xa", "0xb", "3"), true)
+            .unwrap();
+
+        let (_, change) = changes.iter().next().unwrap();
+        assert_eq!(change.received, CairoU256::from(3_u64));
+
+        let (_, change) = changes.iter().nth(1).unwrap();
+        assert_eq!(change.sent, CairoU256::from(3_u64));
+    }
+
+    #[test]
+    fn test_add_u256_quantity() {
+        let mut changes = BalanceChanges::default();
+        let max = CairoU256::MAX.to_string();
+        changes
+            .add(&transfer(EventType::Mint, "0x0", "0xa", &max), false)
+            .unwrap();
+
+        assert!(matches!(
+            changes.add(&transfer(EventType::Mint, "0x0", "0xa", "1"), false),
+            Err(StorageError::DatabaseError(_))
+        ));
+        assert!(matches!(
+            changes.add(&transfer(EventType::Mint, "0x0", "0xa", "one"), false),
+            Err(StorageError::DatabaseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_apply() {
+        let change = BalanceChange {
+            token_id: "1".to_string(),
+            chain_id: String::new(),
+            received: CairoU256::from(2_u64),
+            sent: CairoU256::from(5_u64),
+        };
+
+        assert_eq!(
+            change.apply(&CairoU256::from(4_u64)),
+            (CairoU256::from(1_u64), true)
+        );
+        assert_eq!(change.apply(&CairoU256::ZERO), (CairoU256::ZERO, false));
+    }
+}
@@ -0,0 +1,100 @@
+//! Buffering of the writes of [`SqlxStorage`], for backfills.
+//!
+//! The tokens, mints, owners and transfer events are accumulated, and written
+//! with multi-row `INSERT` statements once the flush size is reached,
+//! and at each block boundary.
+//!
+//! [`SqlxStorage`]: super::SqlxStorage
+use crate::storage::types::{TokenInfo, TokenMintInfo, TokenTransferEvent};
+
+/// Maximum number of parameters bound to one statement,
+/// the lowest limit of the supported databases (SQLite).
+pub const MAX_BINDS_PER_QUERY: usize = 999;
+
+/// Mint of a token, waiting to be written.
+#[derive(Debug, Clone)]
+pub struct BufferedMint {
+    pub contract_address: String,
+    pub token_id: String,
+    pub info: TokenMintInfo,
+}
+
+/// Owner of a token, waiting to be written.
+#[derive(Debug, Clone)]
+pub struct BufferedOwner {
+    pub contract_address: String,
+    pub token_id_hex: String,
+    pub owner: String,
+}
+
+/// Rows waiting to be written. Tokens are written before the mints
+/// and owners updating them, the owners in their order of transfer.
+#[derive(Debug, Default)]
+pub struct WriteBuffer {
+    pub tokens: Vec<(TokenInfo, u64)>,
+    pub mints: Vec<BufferedMint>,
+    pub owners: Vec<BufferedOwner>,
+    pub events: Vec<TokenTransferEvent>,
+}
+
+impl WriteBuffer {
+    pub fn len(&self) -> usize {
+        self.tokens.len() + self.mints.len() + self.owners.len() + self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Empties the buffer, returning the rows to write.
+    pub fn take(&mut self) -> WriteBuffer {
+        std::mem::take(self)
+    }
+}
+
+/// Returns the `VALUES` placeholders of a multi-row insert, each column
+/// being followed by its cast, an empty string if none.
+pub fn values_placeholders(rows: usize, casts: &[&str]) -> String {
+    (0..rows)
+        .map(|row| {
+            let values = casts
+                .iter()
+                .enumerate()
+                .map(|(column, cast)| format!("${}{}", row * casts.len() + column + 1, cast))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("({})", values)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Number of rows inserted by statement, for the given number of columns.
+pub fn rows_per_query(columns: usize) -> usize {
+    (MAX_BINDS_PER_QUERY / columns).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_placeholders() {
+        assert_eq!(
+            values_placeholders(2, &["", "::bigint", ""]),
+            "($1, $2::bigint, $3), ($4, $5::bigint, $6)"
+        );
+        assert_eq!(values_placeholders(0, &[""]), "");
+    }
+
+    #[test]
+    fn test_take() {
+        let mut buffer = WriteBuffer::default();
+        buffer.events.push(TokenTransferEvent::default());
+        buffer.tokens.push((TokenInfo::default(), 1));
+
+        let rows = buffer.take();
+        assert_eq!(rows.len(), 2);
+        assert!(buffer.is_empty());
+    }
+}
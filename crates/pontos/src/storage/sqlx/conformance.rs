@@ -0,0 +1,868 @@
+//! Conformance tests of the sqlx storages, checking that the
+//! backends implement the [`Storage`] trait the same way.
+//!
+//! The SQLite backend is tested on an in-memory database. The
+//! Postgres backend is tested on the database of
+//! `PONTOS_TEST_POSTGRES_URL`, its tables being emptied before the
+//! tests, with `cargo test -- --ignored`.
+use sqlx::{Executor, SqlitePool};
+
+use super::schema::SQLITE_MIGRATOR;
+use super::{DefaultSqlxStorage, PostgresStorage, SqliteStorage};
+use crate::storage::in_block;
+use crate::storage::types::*;
+use crate::Storage;
+
+const CHAIN_ID: &str = "0x534e5f4d41494e";
+
+/// Largest u256, to check that the token ids are not truncated.
+const MAX_TOKEN_ID: &str =
+    "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+
+fn token(contract_address: &str, token_id: &str, owner: &str) -> TokenInfo {
+    TokenInfo {
+        contract_address: contract_address.to_string(),
+        token_id: token_id.to_string(),
+        chain_id: CHAIN_ID.to_string(),
+        token_id_hex: format!("0x{token_id}"),
+        owner: owner.to_string(),
+    }
+}
+
+fn balance(token: &TokenInfo, owner: &str, balance: &str) -> TokenBalance {
+    TokenBalance {
+        contract_address: token.contract_address.clone(),
+        token_id: token.token_id.clone(),
+        token_id_hex: token.token_id_hex.clone(),
+        chain_id: token.chain_id.clone(),
+        owner: owner.to_string(),
+        balance: balance.to_string(),
+    }
+}
+
+fn transfer(
+    token: &TokenInfo,
+    event_id: &str,
+    block_number: u64,
+    event_type: EventType,
+) -> TokenTransferEvent {
+    TokenTransferEvent {
+        timestamp: block_number * 10,
+        from_address: "0xa".to_string(),
+        to_address: "0xb".to_string(),
+        contract_address: token.contract_address.clone(),
+        chain_id: CHAIN_ID.to_string(),
+        contract_type: ContractType::ERC721.to_string(),
+        transaction_hash: format!("0x{block_number}"),
+        token_id: token.token_id.clone(),
+        token_id_hex: token.token_id_hex.clone(),
+        event_type,
+        event_id: event_id.to_string(),
+        block_number: Some(block_number),
+        updated_at: None,
+        quantity: "1".to_string(),
+    }
+}
+
+/// ERC1155 transfer of `quantity` tokens, a mint if sent by 0x0.
+fn transfer_of(
+    token: &TokenInfo,
+    event_id: &str,
+    block_number: u64,
+    from: &str,
+    to: &str,
+    quantity: &str,
+) -> TokenTransferEvent {
+    let event_type = if from == "0x0" {
+        EventType::Mint
+    } else {
+        EventType::Transfer
+    };
+
+    TokenTransferEvent {
+        from_address: from.to_string(),
+        to_address: to.to_string(),
+        contract_type: ContractType::ERC1155.to_string(),
+        quantity: quantity.to_string(),
+        ..transfer(token, event_id, block_number, event_type)
+    }
+}
+
+async fn get_balance<S: Storage + Sync>(storage: &S, token: &TokenInfo, owner: &str) -> String {
+    storage
+        .get_token_balance(&token.contract_address, &token.token_id_hex, owner)
+        .await
+        .unwrap()
+}
+
+fn sale(token: &TokenInfo, event_id: &str, block_number: u64) -> TokenSaleEvent {
+    TokenSaleEvent {
+        timestamp: block_number * 10,
+        from_address: "0xb".to_string(),
+        to_address: "0xa".to_string(),
+        nft_contract_address: token.contract_address.clone(),
+        nft_type: Some(ContractType::ERC721.to_string()),
+        marketplace_contract_address: "0xm".to_string(),
+        marketplace_name: "Element".to_string(),
+        transaction_hash: format!("0x{block_number}"),
+        token_id: token.token_id.clone(),
+        token_id_hex: token.token_id_hex.clone(),
+        event_type: EventType::Sale,
+        event_id: event_id.to_string(),
+        block_number: Some(block_number),
+        updated_at: None,
+        quantity: 1,
+        currency_address: Some("0xe7".to_string()),
+        price: "1500000000000000000".to_string(),
+        currency_symbol: Some("ETH".to_string()),
+        currency_decimals: Some(18),
+        price_decimal: Some("1.500000000000000000".to_string()),
+        reference_price: Some("3000".to_string()),
+    }
+}
+
+fn block(status: BlockIndexingStatus) -> BlockInfo {
+    BlockInfo {
+        indexer_version: "0.1.0".to_string(),
+        indexer_identifier: "indexer".to_string(),
+        status,
+        block_number: 5,
+        block_timestamp: 50,
+        block_hash: Some("0x5".to_string()),
+        parent_hash: Some("0x4".to_string()),
+    }
+}
+
+async fn check_tokens<S: Storage + Sync>(storage: &S) {
+    let t = token("0x1", MAX_TOKEN_ID, "0xa");
+
+    storage.register_token(&t, 10).await.unwrap();
+    assert!(matches!(
+        storage.register_token(&t, 10).await,
+        Err(StorageError::AlreadyExists(_))
+    ));
+
+    let mint = TokenMintInfo {
+        address: "0xa".to_string(),
+        timestamp: 10,
+        transaction_hash: "0x1".to_string(),
+        block_number: Some(1),
+    };
+    storage
+        .register_mint(&t.contract_address, &t.token_id_hex, &t.token_id, &mint)
+        .await
+        .unwrap();
+}
+
+/// Checks the balances derived from the transfers, with quantities above u64.
+async fn check_balances<S: Storage + Sync>(storage: &S) {
+    let t = token("0x7", "1", "");
+    let (hundred, seventy, thirty) = (
+        "100000000000000000000",
+        "70000000000000000000",
+        "30000000000000000000",
+    );
+
+    assert_eq!(get_balance(storage, &t, "0xa").await, "0");
+
+    let mint = transfer_of(&t, "0x71", 15, "0x0", "0xa", hundred);
+    let first = transfer_of(&t, "0x72", 15, "0xa", "0xb", thirty);
+    for event in [&mint, &first] {
+        storage
+            .register_transfer_event(event, event.timestamp)
+            .await
+            .unwrap();
+    }
+
+    // An event registered again is not applied again.
+    assert!(matches!(
+        storage.register_transfer_event(&mint, mint.timestamp).await,
+        Err(StorageError::AlreadyExists(_))
+    ));
+
+    assert_eq!(get_balance(storage, &t, "0xa").await, seventy);
+    assert_eq!(get_balance(storage, &t, "0xb").await, thirty);
+    assert_eq!(
+        storage.get_tokens_by_owner("0xb").await.unwrap(),
+        vec![balance(&t, "0xb", thirty)]
+    );
+
+    let second = transfer_of(&t, "0x73", 16, "0xb", "0xa", thirty);
+    storage
+        .register_transfer_event(&second, second.timestamp)
+        .await
+        .unwrap();
+    assert_eq!(get_balance(storage, &t, "0xa").await, hundred);
+    assert!(storage.get_tokens_by_owner("0xb").await.unwrap().is_empty());
+
+    // The transfers of a block cleaned are reverted.
+    storage.clean_block(160, Some(16)).await.unwrap();
+    assert_eq!(get_balance(storage, &t, "0xa").await, seventy);
+    assert_eq!(get_balance(storage, &t, "0xb").await, thirty);
+
+    storage
+        .register_transfer_event(&second, second.timestamp)
+        .await
+        .unwrap();
+    assert_eq!(get_balance(storage, &t, "0xa").await, hundred);
+}
+
+/// Checks that the tokens transferred in a block cleaned get back
+/// their owner and mint, the tokens minted in it being removed.
+async fn check_rollback(storage: &DefaultSqlxStorage) {
+    let t = token("0x9", "1", "0xa");
+    let get_token = || storage.get_token_by_id(&t.contract_address, &t.token_id_hex, &t.token_id);
+
+    let mut mint = transfer(&t, "0x91", 20, EventType::Mint);
+    mint.from_address = "0x0".to_string();
+    mint.to_address = "0xa".to_string();
+    let second = transfer(&t, "0x92", 21, EventType::Transfer);
+
+    storage.register_token(&t, mint.timestamp).await.unwrap();
+    storage
+        .register_mint(
+            &t.contract_address,
+            &t.token_id_hex,
+            &t.token_id,
+            &TokenMintInfo {
+                address: "0xa".to_string(),
+                timestamp: mint.timestamp,
+                transaction_hash: mint.transaction_hash.clone(),
+                block_number: mint.block_number,
+            },
+        )
+        .await
+        .unwrap();
+    for event in [&mint, &second] {
+        storage
+            .register_transfer_event(event, event.timestamp)
+            .await
+            .unwrap();
+    }
+    storage
+        .update_token_owner(&t.contract_address, &t.token_id_hex, "0xb")
+        .await
+        .unwrap();
+    assert_eq!(get_token().await.unwrap().unwrap().owner, "0xb");
+
+    storage.clean_block(210, Some(21)).await.unwrap();
+    let restored = get_token().await.unwrap().unwrap();
+    assert_eq!(restored.owner, "0xa");
+    assert_eq!(restored.mint_address.as_deref(), Some("0xa"));
+    assert_eq!(get_balance(storage, &t, "0xb").await, "0");
+
+    storage.clean_block(200, Some(20)).await.unwrap();
+    assert!(get_token().await.unwrap().is_none());
+}
+
+async fn check_events<S: Storage + Sync>(storage: &S) {
+    let t = token("0x2", "1", "0xa");
+    storage.register_token(&t, 10).await.unwrap();
+
+    let mint = transfer(&t, "0x01", 1, EventType::Mint);
+    let first = transfer(&t, "0x02", 2, EventType::Transfer);
+    let sold = sale(&t, "0x03", 2);
+    let second = transfer(&t, "0x04", 3, EventType::Transfer);
+
+    for event in [&mint, &first, &second] {
+        storage
+            .register_transfer_event(event, event.timestamp)
+            .await
+            .unwrap();
+    }
+    storage
+        .register_sale_event(&sold, sold.timestamp)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        storage.register_transfer_event(&mint, mint.timestamp).await,
+        Err(StorageError::AlreadyExists(_))
+    ));
+
+    let page = storage
+        .get_token_history(&t.contract_address, &t.token_id_hex, None, 2)
+        .await
+        .unwrap();
+    assert_eq!(
+        page.events,
+        vec![
+            TokenEvent::Transfer(mint.clone()),
+            TokenEvent::Transfer(first.clone())
+        ]
+    );
+    assert_eq!(
+        page.next,
+        Some(EventCursor {
+            block_number: 2,
+            event_id: "0x02".to_string(),
+        })
+    );
+
+    let page = storage
+        .get_token_history(&t.contract_address, &t.token_id_hex, page.next, 2)
+        .await
+        .unwrap();
+    assert_eq!(
+        page.events,
+        vec![
+            TokenEvent::Sale(sold.clone()),
+            TokenEvent::Transfer(second.clone())
+        ]
+    );
+    assert_eq!(page.next, None);
+
+    assert_eq!(
+        storage.get_events_by_block_range(2, 2).await.unwrap(),
+        vec![TokenEvent::Transfer(first), TokenEvent::Sale(sold)]
+    );
+
+    assert_eq!(
+        storage
+            .get_collection_stats(&t.contract_address)
+            .await
+            .unwrap(),
+        CollectionStats {
+            contract_address: t.contract_address.clone(),
+            token_count: 1,
+            owner_count: 1,
+            transfer_count: 3,
+            sale_count: 1,
+        }
+    );
+}
+
+/// Checks the tokens of an owner across the collections, without
+/// the tokens transferred to other owners.
+async fn check_tokens_by_owner<S: Storage + Sync>(storage: &S) {
+    let first = token("0xa1", "1", "");
+    let second = token("0xa1", "2", "");
+    let other = token("0xa2", "1", "");
+
+    for event in [
+        transfer_of(&other, "0xa11", 30, "0x0", "0xc1", "1"),
+        transfer_of(&second, "0xa12", 30, "0x0", "0xc1", "2"),
+        transfer_of(&first, "0xa13", 30, "0x0", "0xc1", "5"),
+        transfer_of(&second, "0xa14", 31, "0xc1", "0xd1", "2"),
+    ] {
+        storage
+            .register_transfer_event(&event, event.timestamp)
+            .await
+            .unwrap();
+    }
+
+    // Ordered by collection then by token.
+    assert_eq!(
+        storage.get_tokens_by_owner("0xc1").await.unwrap(),
+        vec![balance(&first, "0xc1", "5"), balance(&other, "0xc1", "1")]
+    );
+    assert_eq!(
+        storage.get_tokens_by_owner("0xd1").await.unwrap(),
+        vec![balance(&second, "0xd1", "2")]
+    );
+    assert!(storage
+        .get_tokens_by_owner("0xe1")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+/// Checks the pages of the history of a token, ordered by block then
+/// by event id whatever the order the events were registered in.
+async fn check_token_history<S: Storage + Sync>(storage: &S) {
+    let t = token("0xb1", "1", "0xa");
+    let neighbour = token("0xb1", "2", "0xa");
+    let history = |after: Option<EventCursor>, limit: u64| {
+        storage.get_token_history(&t.contract_address, &t.token_id_hex, after, limit)
+    };
+
+    assert_eq!(history(None, 10).await.unwrap(), EventPage::default());
+
+    let mint = transfer(&t, "0xb2", 32, EventType::Mint);
+    let first = transfer(&t, "0xb1", 33, EventType::Transfer);
+    let second = transfer(&t, "0xb3", 33, EventType::Transfer);
+    let other = transfer(&neighbour, "0xb4", 33, EventType::Mint);
+    for event in [&second, &other, &first, &mint] {
+        storage
+            .register_transfer_event(event, event.timestamp)
+            .await
+            .unwrap();
+    }
+
+    // A page with all the remaining events is the last one.
+    assert_eq!(
+        history(None, 3).await.unwrap(),
+        EventPage {
+            events: vec![
+                TokenEvent::Transfer(mint.clone()),
+                TokenEvent::Transfer(first.clone()),
+                TokenEvent::Transfer(second.clone()),
+            ],
+            next: None,
+        }
+    );
+
+    let page = history(None, 1).await.unwrap();
+    assert_eq!(page.events, vec![TokenEvent::Transfer(mint)]);
+
+    let page = history(page.next, 1).await.unwrap();
+    assert_eq!(
+        page,
+        EventPage {
+            events: vec![TokenEvent::Transfer(first)],
+            next: Some(EventCursor {
+                block_number: 33,
+                event_id: "0xb1".to_string(),
+            }),
+        }
+    );
+
+    let page = history(page.next, 1).await.unwrap();
+    assert_eq!(
+        page,
+        EventPage {
+            events: vec![TokenEvent::Transfer(second)],
+            next: None,
+        }
+    );
+}
+
+/// Checks the statistics of a collection, the burns being counted
+/// as transfers and only the owners with a balance being counted.
+async fn check_collection_stats<S: Storage + Sync>(storage: &S) {
+    let first = token("0xc1", "1", "0xf1");
+    let second = token("0xc1", "2", "0xf1");
+    let stats = || storage.get_collection_stats(&first.contract_address);
+
+    assert_eq!(
+        stats().await.unwrap(),
+        CollectionStats {
+            contract_address: first.contract_address.clone(),
+            ..Default::default()
+        }
+    );
+
+    for t in [&first, &second] {
+        storage.register_token(t, 350).await.unwrap();
+    }
+    let burn = TokenTransferEvent {
+        event_type: EventType::Burn,
+        ..transfer_of(&first, "0xc14", 36, "0xf1", "0x0", "1")
+    };
+    for event in [
+        transfer_of(&first, "0xc11", 35, "0x0", "0xf1", "1"),
+        transfer_of(&second, "0xc12", 35, "0x0", "0xf1", "1"),
+        transfer_of(&second, "0xc13", 36, "0xf1", "0xf2", "1"),
+        burn,
+    ] {
+        storage
+            .register_transfer_event(&event, event.timestamp)
+            .await
+            .unwrap();
+    }
+    let sold = sale(&second, "0xc15", 36);
+    storage
+        .register_sale_event(&sold, sold.timestamp)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        stats().await.unwrap(),
+        CollectionStats {
+            contract_address: first.contract_address.clone(),
+            token_count: 2,
+            owner_count: 1,
+            transfer_count: 4,
+            sale_count: 1,
+        }
+    );
+}
+
+/// Checks the events of a block range across the collections,
+/// ordered by block then by event id, the bounds being included.
+async fn check_block_range<S: Storage + Sync>(storage: &S) {
+    let t = token("0xd1", "1", "0xa");
+    let other = token("0xd2", "1", "0xa");
+
+    let before = transfer(&t, "0xd1", 39, EventType::Mint);
+    let first = transfer(&other, "0xd3", 40, EventType::Mint);
+    let second = transfer(&t, "0xd2", 40, EventType::Transfer);
+    let after = transfer(&t, "0xd5", 42, EventType::Transfer);
+    for event in [&before, &first, &second, &after] {
+        storage
+            .register_transfer_event(event, event.timestamp)
+            .await
+            .unwrap();
+    }
+    let sold = sale(&t, "0xd4", 41);
+    storage
+        .register_sale_event(&sold, sold.timestamp)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        storage.get_events_by_block_range(40, 41).await.unwrap(),
+        vec![
+            TokenEvent::Transfer(second),
+            TokenEvent::Transfer(first),
+            TokenEvent::Sale(sold.clone()),
+        ]
+    );
+    assert_eq!(
+        storage.get_events_by_block_range(41, 41).await.unwrap(),
+        vec![TokenEvent::Sale(sold)]
+    );
+    assert!(storage
+        .get_events_by_block_range(43, 50)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(storage
+        .get_events_by_block_range(41, 40)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+async fn check_contracts<S: Storage + Sync>(storage: &S) {
+    let info = ContractInfo {
+        contract_address: "0x3".to_string(),
+        chain_id: CHAIN_ID.to_string(),
+        contract_type: ContractType::ERC1155.to_string(),
+        total_supply: Some(MAX_TOKEN_ID.to_string()),
+        interfaces: vec!["0x1".to_string(), "0x2".to_string()],
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        storage.get_contract_type("0x3", CHAIN_ID).await,
+        Err(StorageError::NotFound(_))
+    ));
+
+    let unidentified = ContractInfo {
+        contract_type: ContractType::Other.to_string(),
+        ..info.clone()
+    };
+    storage
+        .register_contract_info(&unidentified, 10, CHAIN_ID)
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.get_contract_type("0x3", CHAIN_ID).await.unwrap(),
+        ContractType::Other
+    );
+
+    // Identified again once its cache expired, the contract is updated.
+    storage
+        .register_contract_info(&info, 11, CHAIN_ID)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        storage.get_contract_type("0x3", CHAIN_ID).await.unwrap(),
+        ContractType::ERC1155
+    );
+    assert_eq!(
+        storage.get_contract_types(CHAIN_ID, 10).await.unwrap(),
+        vec![("0x3".to_string(), ContractType::ERC1155)]
+    );
+}
+
+async fn check_blocks<S: Storage + Sync>(storage: &S) {
+    storage
+        .set_block_info(5, 50, block(BlockIndexingStatus::Processing))
+        .await
+        .unwrap();
+
+    let info = storage.get_block_info(5).await.unwrap();
+    assert_eq!(info.status, BlockIndexingStatus::Processing);
+    assert_eq!(info.block_timestamp, 50);
+    assert_eq!(info.block_hash, Some("0x5".to_string()));
+
+    storage
+        .set_block_info(5, 50, block(BlockIndexingStatus::Terminated))
+        .await
+        .unwrap();
+
+    let blocks = storage
+        .get_blocks_by_status("indexer", BlockIndexingStatus::Terminated)
+        .await
+        .unwrap();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].block_number, 5);
+
+    assert_eq!(storage.get_checkpoint("indexer").await.unwrap(), Some(5));
+    storage.set_checkpoint("indexer", 7).await.unwrap();
+    assert_eq!(storage.get_checkpoint("indexer").await.unwrap(), Some(7));
+    assert_eq!(storage.get_checkpoint("unknown").await.unwrap(), None);
+
+    storage.clean_block(50, Some(5)).await.unwrap();
+    assert!(matches!(
+        storage.get_block_info(5).await,
+        Err(StorageError::NotFound(_))
+    ));
+}
+
+async fn check_unit_of_work<S: Storage + Sync>(storage: &S) {
+    let t = token("0x4", "1", "0xa");
+    let mint = transfer_of(&t, "0x81", 8, "0x0", "0xa", "5");
+
+    in_block(8, async {
+        storage.begin_block(8).await.unwrap();
+        storage
+            .register_transfer_event(&mint, mint.timestamp)
+            .await
+            .unwrap();
+        assert_eq!(get_balance(storage, &t, "0xa").await, "5");
+        storage.rollback_block(8).await.unwrap();
+    })
+    .await;
+    assert_eq!(get_balance(storage, &t, "0xa").await, "0");
+
+    assert!(matches!(
+        storage.begin_block(8).await,
+        Err(StorageError::InvalidStatus(_))
+    ));
+
+    in_block(8, async {
+        storage.begin_block(8).await.unwrap();
+        assert!(matches!(
+            storage.begin_block(8).await,
+            Err(StorageError::InvalidStatus(_))
+        ));
+        assert!(matches!(
+            storage.commit_block(9).await,
+            Err(StorageError::InvalidStatus(_))
+        ));
+        storage
+            .register_transfer_event(&mint, mint.timestamp)
+            .await
+            .unwrap();
+        storage.commit_block(8).await.unwrap();
+
+        assert!(matches!(
+            storage.commit_block(8).await,
+            Err(StorageError::InvalidStatus(_))
+        ));
+    })
+    .await;
+    assert_eq!(get_balance(storage, &t, "0xa").await, "5");
+}
+
+/// Checks that blocks written concurrently have their own unit of work.
+async fn check_concurrent_blocks<S: Storage + Sync>(storage: &S) {
+    let t = token("0x6", "1", "0xa");
+    let write_block = |block_number: u64, owner: &'static str, commit: bool| {
+        let t = &t;
+        in_block(block_number, async move {
+            storage.begin_block(block_number).await.unwrap();
+            let mint = transfer_of(
+                t,
+                &format!("0x6{block_number}"),
+                block_number,
+                "0x0",
+                owner,
+                &block_number.to_string(),
+            );
+            storage
+                .register_transfer_event(&mint, mint.timestamp)
+                .await
+                .unwrap();
+            tokio::task::yield_now().await;
+
+            if commit {
+                storage.commit_block(block_number).await.unwrap();
+            } else {
+                storage.rollback_block(block_number).await.unwrap();
+            }
+        })
+    };
+
+    tokio::join!(
+        write_block(12, "0xa", true),
+        write_block(13, "0xb", false),
+        write_block(14, "0xc", true)
+    );
+
+    for (owner, expected) in [("0xa", "12"), ("0xb", "0"), ("0xc", "14")] {
+        assert_eq!(get_balance(storage, &t, owner).await, expected);
+    }
+}
+
+/// Checks the writes buffered until the block is committed,
+/// on a storage created with a flush size of 3 rows.
+async fn check_buffered_writes<S: Storage + Sync>(storage: &S) {
+    let t = token("0x5", "1", "0xa");
+    let mint = transfer(&t, "0x51", 11, EventType::Mint);
+    let first = transfer(&t, "0x52", 11, EventType::Transfer);
+    let history = || storage.get_token_history(&t.contract_address, &t.token_id_hex, None, 10);
+
+    in_block(11, async {
+        storage.begin_block(11).await.unwrap();
+        storage.register_token(&t, 110).await.unwrap();
+        storage
+            .register_transfer_event(&mint, mint.timestamp)
+            .await
+            .unwrap();
+        assert!(history().await.unwrap().events.is_empty());
+
+        // Duplicates are ignored, the third row flushing the buffer.
+        storage
+            .register_transfer_event(&mint, mint.timestamp)
+            .await
+            .unwrap();
+        assert_eq!(
+            history().await.unwrap().events,
+            vec![TokenEvent::Transfer(mint.clone())]
+        );
+
+        storage
+            .register_transfer_event(&first, first.timestamp)
+            .await
+            .unwrap();
+        storage.rollback_block(11).await.unwrap();
+    })
+    .await;
+    assert!(history().await.unwrap().events.is_empty());
+
+    // Duplicated events of a flush are applied once to the balances.
+    in_block(11, async {
+        storage.begin_block(11).await.unwrap();
+        storage.register_token(&t, 110).await.unwrap();
+        for event in [&mint, &mint, &first] {
+            storage
+                .register_transfer_event(event, event.timestamp)
+                .await
+                .unwrap();
+        }
+        storage.commit_block(11).await.unwrap();
+    })
+    .await;
+
+    assert_eq!(
+        history().await.unwrap().events,
+        vec![TokenEvent::Transfer(mint), TokenEvent::Transfer(first)]
+    );
+    assert_eq!(get_balance(storage, &t, "0xb").await, "2");
+    assert_eq!(
+        storage
+            .get_collection_stats(&t.contract_address)
+            .await
+            .unwrap()
+            .token_count,
+        1
+    );
+}
+
+async fn check_storage<S: Storage + Sync>(storage: &S) {
+    check_tokens(storage).await;
+    check_balances(storage).await;
+    check_events(storage).await;
+    check_contracts(storage).await;
+    check_blocks(storage).await;
+    check_unit_of_work(storage).await;
+    check_concurrent_blocks(storage).await;
+    check_tokens_by_owner(storage).await;
+    check_token_history(storage).await;
+    check_collection_stats(storage).await;
+    check_block_range(storage).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_conformance() {
+    let storage = DefaultSqlxStorage::migrate("sqlite::memory:")
+        .await
+        .unwrap();
+    storage.check_schema().await.unwrap();
+
+    check_storage(&storage).await;
+    check_rollback(&storage).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_buffered_conformance() {
+    let storage = DefaultSqlxStorage::migrate("sqlite::memory:")
+        .await
+        .unwrap()
+        .with_flush_size(3);
+
+    check_buffered_writes(&storage).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_outdated_schema() {
+    assert!(matches!(
+        DefaultSqlxStorage::new_any("sqlite::memory:").await,
+        Err(StorageError::OutdatedSchema(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_sqlite_schema_errors_propagated() {
+    let storage = SqliteStorage::migrate("sqlite::memory:").await.unwrap();
+    storage.get_pool_ref().close().await;
+
+    assert!(matches!(
+        storage.check_schema().await,
+        Err(StorageError::DatabaseError(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_sqlite_baseline() {
+    let path = std::env::temp_dir().join(format!("pontos-baseline-{}.db", std::process::id()));
+    let db_url = format!("sqlite://{}?mode=rwc", path.display());
+
+    // Database created before the migrations were versioned.
+    let pool = SqlitePool::connect(&db_url).await.unwrap();
+    pool.execute(&*SQLITE_MIGRATOR.migrations[0].sql)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO token (contract_address, token_id, token_id_hex, owner, block_timestamp) VALUES ('0x1', '1', '0x1', '0xa', 10)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+
+    assert!(matches!(
+        SqliteStorage::new(&db_url).await,
+        Err(StorageError::OutdatedSchema(_))
+    ));
+
+    let storage = SqliteStorage::baseline(&db_url, 0).await.unwrap();
+    storage.check_schema().await.unwrap();
+    let token = storage.get_token_by_id("0x1", "0x1", "1").await.unwrap();
+    assert_eq!(token.map(|t| t.owner), Some("0xa".to_string()));
+    storage.get_pool_ref().close().await;
+
+    // Migrations are not marked again once applied.
+    let storage = SqliteStorage::baseline(&db_url, 0).await.unwrap();
+    storage.check_schema().await.unwrap();
+
+    storage.get_pool_ref().close().await;
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database, see PONTOS_TEST_POSTGRES_URL"]
+async fn test_postgres_conformance() {
+    let db_url = std::env::var("PONTOS_TEST_POSTGRES_URL")
+        .expect("PONTOS_TEST_POSTGRES_URL is the URL of the test database");
+
+    let storage = PostgresStorage::migrate(&db_url).await.unwrap();
+    sqlx::query("TRUNCATE token, token_event, token_balance, contract, block, indexer")
+        .execute(storage.get_pool_ref())
+        .await
+        .unwrap();
+
+    check_storage(&storage).await;
+
+    let storage = DefaultSqlxStorage::new_any(&db_url).await.unwrap();
+    check_rollback(&storage).await;
+
+    let storage = DefaultSqlxStorage::new_any(&db_url)
+        .await
+        .unwrap()
+        .with_flush_size(3);
+    check_buffered_writes(&storage).await;
+}
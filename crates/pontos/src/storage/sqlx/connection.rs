@@ -0,0 +1,68 @@
+//! Connections of the sqlx storages, shared by the backends.
+use sqlx::{pool::PoolConnection, Database, Pool, Transaction};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::storage::current_block;
+use crate::storage::types::StorageError;
+
+/// Transaction of a block being written, see [`Storage::begin_block`].
+/// `None` once the block is committed or rolled back.
+///
+/// [`Storage::begin_block`]: crate::Storage::begin_block
+pub type BlockTransaction<DB> = Arc<Mutex<Option<Transaction<'static, DB>>>>;
+
+/// Transactions of the blocks being written, by block number.
+pub type BlockTransactions<DB> = Mutex<HashMap<u64, BlockTransaction<DB>>>;
+
+/// Connection running the queries: the transaction of the block of
+/// the current scope if begun, a connection of the pool otherwise.
+pub enum Connection<DB: Database> {
+    Block(OwnedMutexGuard<Option<Transaction<'static, DB>>>),
+    Pool(PoolConnection<DB>),
+}
+
+impl<DB: Database> Connection<DB> {
+    /// Returns the connection for the next query. The connection
+    /// must be released before requesting another one.
+    pub async fn acquire(
+        blocks: &BlockTransactions<DB>,
+        pool: &Pool<DB>,
+    ) -> Result<Self, StorageError> {
+        let block_tx = match current_block() {
+            Some(block_number) => blocks.lock().await.get(&block_number).cloned(),
+            None => None,
+        };
+
+        if let Some(block_tx) = block_tx {
+            let tx = block_tx.lock_owned().await;
+            if tx.is_some() {
+                return Ok(Connection::Block(tx));
+            }
+        }
+
+        Ok(Connection::Pool(pool.acquire().await?))
+    }
+}
+
+impl<DB: Database> Deref for Connection<DB> {
+    type Target = DB::Connection;
+
+    fn deref(&self) -> &DB::Connection {
+        match self {
+            Connection::Block(tx) => tx.as_deref().expect("Block transaction is started"),
+            Connection::Pool(conn) => conn,
+        }
+    }
+}
+
+impl<DB: Database> DerefMut for Connection<DB> {
+    fn deref_mut(&mut self) -> &mut DB::Connection {
+        match self {
+            Connection::Block(tx) => tx.as_deref_mut().expect("Block transaction is started"),
+            Connection::Pool(conn) => conn,
+        }
+    }
+}
@@ -1,134 +1,121 @@
-//! Implementation of default storage using sqlx crate.
+//! Storage on the database given by its URL, SQLite or Postgres.
 //!
-//! The implementation in this file is very naive, and mostly
-//! used for testing and as an example of implementation.
-//! No optimization was done for indexing or PK/FK managment.
+//! The implementation in this file only dispatches the calls
+//! to the [`SqlxStorage`] of the database backend.
 use async_trait::async_trait;
+use sqlx::{Postgres, Sqlite};
 
-use log::trace;
-use sqlx::{any::AnyPoolOptions, AnyPool, Error as SqlxError, FromRow};
-use std::str::FromStr;
-
-use super::types::*;
+use super::sqlx_storage::SqlxStorage;
+#[cfg(test)]
+use super::types::TokenData;
 use crate::storage::types::*;
 use crate::Storage;
 
-impl From<SqlxError> for StorageError {
-    fn from(e: SqlxError) -> Self {
-        StorageError::DatabaseError(e.to_string())
-    }
+pub type SqliteStorage = SqlxStorage<Sqlite>;
+pub type PostgresStorage = SqlxStorage<Postgres>;
+
+pub enum DefaultSqlxStorage {
+    Sqlite(SqliteStorage),
+    Postgres(PostgresStorage),
 }
 
-pub struct DefaultSqlxStorage {
-    pool: AnyPool,
+/// Runs `$call` on the storage of the backend, bound to `$s`.
+macro_rules! dispatch {
+    ($storage:expr, $s:ident => $call:expr) => {
+        match $storage {
+            DefaultSqlxStorage::Sqlite($s) => $call,
+            DefaultSqlxStorage::Postgres($s) => $call,
+        }
+    };
 }
 
-impl DefaultSqlxStorage {
-    pub fn get_pool_ref(&self) -> &AnyPool {
-        &self.pool
+/// Backend of a database URL.
+enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+impl Backend {
+    fn from_url(db_url: &str) -> Result<Self, StorageError> {
+        match db_url.split_once(':').map(|(scheme, _)| scheme) {
+            Some("sqlite") => Ok(Backend::Sqlite),
+            Some("postgres" | "postgresql") => Ok(Backend::Postgres),
+            _ => Err(StorageError::DatabaseError(format!(
+                "unsupported database URL {db_url}, SQLite or Postgres expected"
+            ))),
+        }
     }
+}
 
+impl DefaultSqlxStorage {
+    /// Connects to an existing database, failing with
+    /// [`StorageError::OutdatedSchema`] if its schema is older than the
+    /// one expected, see [`DefaultSqlxStorage::migrate`].
     pub async fn new_any(db_url: &str) -> Result<Self, StorageError> {
-        Ok(Self {
-            pool: AnyPoolOptions::new()
-                .max_connections(1)
-                .connect(db_url)
-                .await?,
+        Ok(match Backend::from_url(db_url)? {
+            Backend::Sqlite => DefaultSqlxStorage::Sqlite(SqliteStorage::new(db_url).await?),
+            Backend::Postgres => DefaultSqlxStorage::Postgres(PostgresStorage::new(db_url).await?),
         })
     }
 
-    pub async fn dump_tables(&self) -> Result<(), StorageError> {
-        let q = "SELECT * FROM token";
-        let rows = sqlx::query(q).fetch_all(&self.pool).await?;
+    /// Connects to the database, and applies the migrations not
+    /// applied yet. The tables are created on an empty database.
+    pub async fn migrate(db_url: &str) -> Result<Self, StorageError> {
+        Ok(match Backend::from_url(db_url)? {
+            Backend::Sqlite => DefaultSqlxStorage::Sqlite(SqliteStorage::migrate(db_url).await?),
+            Backend::Postgres => {
+                DefaultSqlxStorage::Postgres(PostgresStorage::migrate(db_url).await?)
+            }
+        })
+    }
 
-        rows.iter().for_each(|r| {
-            println!("{:?}", TokenData::from_row(r).unwrap());
-        });
+    /// See [`SqlxStorage::baseline`].
+    pub async fn baseline(db_url: &str, version: i64) -> Result<Self, StorageError> {
+        Ok(match Backend::from_url(db_url)? {
+            Backend::Sqlite => {
+                DefaultSqlxStorage::Sqlite(SqliteStorage::baseline(db_url, version).await?)
+            }
+            Backend::Postgres => {
+                DefaultSqlxStorage::Postgres(PostgresStorage::baseline(db_url, version).await?)
+            }
+        })
+    }
 
-        Ok(())
+    /// Checks that all the migrations embedded in the crate
+    /// are applied to the database.
+    pub async fn check_schema(&self) -> Result<(), StorageError> {
+        dispatch!(self, s => s.check_schema().await)
     }
 
-    async fn get_token_by_id(
-        &self,
-        contract_address: &str,
-        _token_id_hex: &str,
-        token_id: &str,
-    ) -> Result<Option<TokenData>, StorageError> {
-        let q = "SELECT * FROM token WHERE contract_address = $1 AND token_id = $2";
-
-        match sqlx::query(q)
-            .bind(contract_address)
-            .bind(token_id)
-            .fetch_all(&self.pool)
-            .await
-        {
-            Ok(rows) => {
-                if rows.is_empty() {
-                    Ok(None)
-                } else {
-                    Ok(Some(TokenData::from_row(&rows[0])?))
-                }
+    /// See [`SqlxStorage::with_flush_size`].
+    pub fn with_flush_size(self, flush_size: usize) -> Self {
+        match self {
+            DefaultSqlxStorage::Sqlite(s) => {
+                DefaultSqlxStorage::Sqlite(s.with_flush_size(flush_size))
+            }
+            DefaultSqlxStorage::Postgres(s) => {
+                DefaultSqlxStorage::Postgres(s.with_flush_size(flush_size))
             }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
         }
     }
 
-    async fn get_event_by_id(&self, event_id: &str) -> Result<Option<EventData>, StorageError> {
-        let q = "SELECT * FROM event WHERE event_id = $1";
+    /// Writes all the buffered rows.
+    pub async fn flush(&self) -> Result<(), StorageError> {
+        dispatch!(self, s => s.flush().await)
+    }
 
-        match sqlx::query(q).bind(event_id).fetch_all(&self.pool).await {
-            Ok(rows) => {
-                if rows.is_empty() {
-                    Ok(None)
-                } else {
-                    Ok(Some(EventData::from_row(&rows[0])?))
-                }
-            }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
-        }
+    pub async fn dump_tables(&self) -> Result<(), StorageError> {
+        dispatch!(self, s => s.dump_tables().await)
     }
 
-    async fn get_contract_by_address(
+    #[cfg(test)]
+    pub(crate) async fn get_token_by_id(
         &self,
         contract_address: &str,
-        chain_id: &str,
-    ) -> Result<Option<ContractData>, StorageError> {
-        let q = "SELECT * FROM contract WHERE contract_address = $1 AND chain_id = $2";
-
-        match sqlx::query(q)
-            .bind(contract_address.to_string())
-            .bind(chain_id.to_string())
-            .fetch_all(&self.pool)
-            .await
-        {
-            Ok(rows) => {
-                if rows.is_empty() {
-                    Ok(None)
-                } else {
-                    Ok(Some(ContractData::from_row(&rows[0])?))
-                }
-            }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
-        }
-    }
-
-    async fn get_block_by_timestamp(&self, ts: u64) -> Result<Option<Block>, StorageError> {
-        let q = "SELECT block_number, block_status, block_timestamp, indexer_identifier FROM block WHERE block_timestamp = $1";
-
-        match sqlx::query(q)
-            .bind(ts.to_string())
-            .fetch_all(&self.pool)
-            .await
-        {
-            Ok(rows) => {
-                if rows.is_empty() {
-                    Ok(None)
-                } else {
-                    Ok(Some(Block::from_row(&rows[0])?))
-                }
-            }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
-        }
+        token_id_hex: &str,
+        token_id: &str,
+    ) -> Result<Option<TokenData>, StorageError> {
+        dispatch!(self, s => s.get_token_by_id(contract_address, token_id_hex, token_id).await)
     }
 }
 
@@ -137,28 +124,11 @@ impl Storage for DefaultSqlxStorage {
     async fn register_mint(
         &self,
         contract_address: &str,
-        _token_id_hex: &str,
+        token_id_hex: &str,
         token_id: &str,
         info: &TokenMintInfo,
     ) -> Result<(), StorageError> {
-        trace!(
-            "Registering mint {} {} {:?}",
-            contract_address,
-            token_id,
-            info
-        );
-
-        let q = "UPDATE token SET mint_address = $1, mint_timestamp = $2, mint_transaction_hash = $3 WHERE token_id = $4";
-
-        let _r = sqlx::query(q)
-            .bind(info.address.clone())
-            .bind(info.timestamp.to_string())
-            .bind(info.transaction_hash.clone())
-            .bind(token_id)
-            .execute(&self.pool)
-            .await?;
-
-        Ok(())
+        dispatch!(self, s => s.register_mint(contract_address, token_id_hex, token_id, info).await)
     }
 
     async fn register_token(
@@ -166,75 +136,41 @@ impl Storage for DefaultSqlxStorage {
         token: &TokenInfo,
         block_timestamp: u64,
     ) -> Result<(), StorageError> {
-        trace!("Registering token {:?}", token);
-
-        if (self
-            .get_token_by_id(
-                &token.contract_address,
-                &token.token_id_hex,
-                &token.token_id,
-            )
-            .await?)
-            .is_some()
-        {
-            return Err(StorageError::AlreadyExists(format!(
-                "token id = {}",
-                token.token_id_hex
-            )));
-        }
-
-        let q = "INSERT INTO token (contract_address, token_id, chain_id, owner, block_timestamp) VALUES ($1, $2, $3, $4, $5)";
+        dispatch!(self, s => s.register_token(token, block_timestamp).await)
+    }
 
-        let _r = sqlx::query(q)
-            .bind(token.contract_address.clone())
-            .bind(token.token_id.clone())
-            .bind(token.chain_id.clone())
-            .bind(token.owner.clone())
-            .bind(block_timestamp.to_string())
-            .execute(&self.pool)
-            .await?;
+    async fn update_token_owner(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        owner: &str,
+    ) -> Result<(), StorageError> {
+        dispatch!(self, s => s.update_token_owner(contract_address, token_id_hex, owner).await)
+    }
 
-        Ok(())
+    async fn get_token_balance(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        owner: &str,
+    ) -> Result<String, StorageError> {
+        dispatch!(self, s => s.get_token_balance(contract_address, token_id_hex, owner).await)
     }
 
     async fn register_sale_event(
         &self,
-        _event: &TokenSaleEvent,
-        _block_timestamp: u64,
+        event: &TokenSaleEvent,
+        block_timestamp: u64,
     ) -> Result<(), StorageError> {
-        Ok(())
+        dispatch!(self, s => s.register_sale_event(event, block_timestamp).await)
     }
 
     async fn register_transfer_event(
         &self,
         event: &TokenTransferEvent,
-        _block_timestamp: u64,
+        block_timestamp: u64,
     ) -> Result<(), StorageError> {
-        trace!("Registering event {:?}", event);
-
-        if (self.get_event_by_id(&event.event_id).await?).is_some() {
-            return Err(StorageError::AlreadyExists(format!(
-                "event id = {}",
-                event.event_id
-            )));
-        }
-
-        let q = "INSERT INTO token_event (block_timestamp, contract_address, from_address, to_address, transaction_hash, token_id, contract_type, event_type, event_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
-
-        let _r = sqlx::query(q)
-            .bind(event.timestamp.to_string())
-            .bind(event.from_address.clone())
-            .bind(event.to_address.clone())
-            .bind(event.contract_address.clone())
-            .bind(event.transaction_hash.clone())
-            .bind(event.token_id.clone())
-            .bind(event.contract_type.clone())
-            .bind(event.event_type.to_string())
-            .bind(event.event_id.clone())
-            .execute(&self.pool)
-            .await?;
-
-        Ok(())
+        dispatch!(self, s => s.register_transfer_event(event, block_timestamp).await)
     }
 
     async fn get_contract_type(
@@ -242,18 +178,15 @@ impl Storage for DefaultSqlxStorage {
         contract_address: &str,
         chain_id: &str,
     ) -> Result<ContractType, StorageError> {
-        trace!("Getting contract info for contract {}", contract_address);
-
-        if let Some(c) = self
-            .get_contract_by_address(contract_address, chain_id)
-            .await?
-        {
-            Ok(ContractType::from_str(&c.contract_type).unwrap())
-        } else {
-            Err(StorageError::NotFound(format!(
-                "contract_address: {contract_address}"
-            )))
-        }
+        dispatch!(self, s => s.get_contract_type(contract_address, chain_id).await)
+    }
+
+    async fn get_contract_types(
+        &self,
+        chain_id: &str,
+        limit: u64,
+    ) -> Result<Vec<(String, ContractType)>, StorageError> {
+        dispatch!(self, s => s.get_contract_types(chain_id, limit).await)
     }
 
     async fn register_contract_info(
@@ -262,33 +195,19 @@ impl Storage for DefaultSqlxStorage {
         block_timestamp: u64,
         chain_id: &str,
     ) -> Result<(), StorageError> {
-        trace!(
-            "Registering contract info {:?} for contract {}",
-            info.contract_type,
-            info.contract_address
-        );
-
-        if (self
-            .get_contract_by_address(&info.contract_address, chain_id)
-            .await?)
-            .is_some()
-        {
-            return Err(StorageError::AlreadyExists(format!(
-                "contract addr = {}",
-                info.contract_address
-            )));
-        }
+        dispatch!(self, s => s.register_contract_info(info, block_timestamp, chain_id).await)
+    }
 
-        let q = "INSERT INTO contract (contract_address, contract_type, deployed_timestamp) VALUES ($1, $2, $3)";
+    async fn begin_block(&self, block_number: u64) -> Result<(), StorageError> {
+        dispatch!(self, s => s.begin_block(block_number).await)
+    }
 
-        let _r = sqlx::query(q)
-            .bind(info.contract_address.clone())
-            .bind(info.contract_type.to_string())
-            .bind(block_timestamp.to_string())
-            .execute(&self.pool)
-            .await?;
+    async fn commit_block(&self, block_number: u64) -> Result<(), StorageError> {
+        dispatch!(self, s => s.commit_block(block_number).await)
+    }
 
-        Ok(())
+    async fn rollback_block(&self, block_number: u64) -> Result<(), StorageError> {
+        dispatch!(self, s => s.rollback_block(block_number).await)
     }
 
     async fn set_block_info(
@@ -297,74 +216,31 @@ impl Storage for DefaultSqlxStorage {
         block_timestamp: u64,
         info: BlockInfo,
     ) -> Result<(), StorageError> {
-        trace!("Setting block info {:?} for block #{}", info, block_number);
-
-        let exists = sqlx::query("SELECT 1 FROM indexer WHERE indexer_identifier = $1")
-            .bind(info.indexer_identifier.clone())
-            .fetch_optional(&self.pool)
-            .await?
-            .is_some();
-
-        if !exists {
-            let q = "INSERT INTO indexer (indexer_identifier, indexer_version) VALUES ($1, $2)";
-            sqlx::query(q)
-                .bind(info.indexer_identifier.clone())
-                .bind(info.indexer_version.clone())
-                .execute(&self.pool)
-                .await?;
-        }
-
-        let _r = if (self.get_block_by_timestamp(block_timestamp).await?).is_some() {
-            let q = "UPDATE block SET block_number = $1, block_status = $2, indexer_identifier = $3 WHERE block_timestamp = $4";
-            sqlx::query(q)
-                .bind(block_number.to_string())
-                .bind(info.status.to_string())
-                .bind(info.indexer_identifier.clone())
-                .bind(block_timestamp.to_string())
-                .execute(&self.pool)
-                .await?
-        } else {
-            let q = "INSERT INTO block (block_timestamp, block_number, block_status, indexer_identifier) VALUES ($1, $2, $3, $4) ON CONFLICT (block_number) DO NOTHING";
-
-            sqlx::query(q)
-                .bind(block_timestamp.to_string())
-                .bind(block_number.to_string())
-                .bind(info.status.to_string())
-                .bind(info.indexer_identifier.clone())
-                .execute(&self.pool)
-                .await?
-        };
-
-        Ok(())
+        dispatch!(self, s => s.set_block_info(block_number, block_timestamp, info).await)
     }
 
     async fn get_block_info(&self, block_number: u64) -> Result<BlockInfo, StorageError> {
-        trace!("Getting block info for block #{}", block_number);
-
-        let q = "SELECT * FROM block WHERE block_number = $1";
-
-        match sqlx::query(q)
-            .bind(block_number.to_string())
-            .fetch_all(&self.pool)
-            .await
-        {
-            Ok(rows) => {
-                if rows.is_empty() {
-                    Err(StorageError::NotFound(format!(
-                        "block number {block_number}"
-                    )))
-                } else {
-                    let d = BlockData::from_row(&rows[0])?;
-                    Ok(BlockInfo {
-                        indexer_version: d.indexer_version.clone(),
-                        indexer_identifier: d.indexer_identifier.clone(),
-                        status: BlockIndexingStatus::from_str(&d.status).unwrap(),
-                        block_number,
-                    })
-                }
-            }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
-        }
+        dispatch!(self, s => s.get_block_info(block_number).await)
+    }
+
+    async fn get_blocks_by_status(
+        &self,
+        indexer_identifier: &str,
+        status: BlockIndexingStatus,
+    ) -> Result<Vec<BlockInfo>, StorageError> {
+        dispatch!(self, s => s.get_blocks_by_status(indexer_identifier, status).await)
+    }
+
+    async fn get_checkpoint(&self, indexer_identifier: &str) -> Result<Option<u64>, StorageError> {
+        dispatch!(self, s => s.get_checkpoint(indexer_identifier).await)
+    }
+
+    async fn set_checkpoint(
+        &self,
+        indexer_identifier: &str,
+        block_number: u64,
+    ) -> Result<(), StorageError> {
+        dispatch!(self, s => s.set_checkpoint(indexer_identifier, block_number).await)
     }
 
     async fn clean_block(
@@ -372,23 +248,60 @@ impl Storage for DefaultSqlxStorage {
         block_timestamp: u64,
         block_number: Option<u64>,
     ) -> Result<(), StorageError> {
-        trace!(
-            "Cleaning block #{:?} [ts: {}]",
-            block_number,
-            block_timestamp.to_string()
-        );
-        let q = "DELETE FROM block WHERE block_timestamp = $1::bigint";
-        sqlx::query(q)
-            .bind(block_timestamp.to_string())
-            .fetch_all(&self.pool)
-            .await?;
-
-        let q = "DELETE FROM token_event WHERE block_timestamp = $1::bigint";
-        sqlx::query(q)
-            .bind(block_timestamp.to_string())
-            .fetch_all(&self.pool)
-            .await?;
-
-        Ok(())
+        dispatch!(self, s => s.clean_block(block_timestamp, block_number).await)
+    }
+
+    async fn get_tokens_by_owner(&self, owner: &str) -> Result<Vec<TokenBalance>, StorageError> {
+        dispatch!(self, s => s.get_tokens_by_owner(owner).await)
+    }
+
+    async fn get_token_history(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        after: Option<EventCursor>,
+        limit: u64,
+    ) -> Result<EventPage, StorageError> {
+        dispatch!(self, s => s.get_token_history(contract_address, token_id_hex, after, limit).await)
+    }
+
+    async fn get_collection_stats(
+        &self,
+        contract_address: &str,
+    ) -> Result<CollectionStats, StorageError> {
+        dispatch!(self, s => s.get_collection_stats(contract_address).await)
+    }
+
+    async fn get_events_by_block_range(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<TokenEvent>, StorageError> {
+        dispatch!(self, s => s.get_events_by_block_range(from_block, to_block).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_from_url() {
+        assert!(matches!(
+            Backend::from_url("sqlite::memory:"),
+            Ok(Backend::Sqlite)
+        ));
+        assert!(matches!(
+            Backend::from_url("postgres://localhost/pontos"),
+            Ok(Backend::Postgres)
+        ));
+        assert!(matches!(
+            Backend::from_url("postgresql://localhost/pontos"),
+            Ok(Backend::Postgres)
+        ));
+        assert!(matches!(
+            Backend::from_url("mysql://localhost/pontos"),
+            Err(StorageError::DatabaseError(_))
+        ));
     }
 }
@@ -0,0 +1,104 @@
+//! SQL dialects of the databases supported by [`SqlxStorage`].
+//!
+//! The queries are the same on all the databases, except for the
+//! u256 values: stored as `NUMERIC(78)` on Postgres, they are cast
+//! from and to text, SQLite storing them as text already.
+//!
+//! [`SqlxStorage`]: super::SqlxStorage
+use sqlx::migrate::Migrator;
+use sqlx::{Database, Postgres, Sqlite};
+
+use super::schema::{POSTGRES_MIGRATOR, SQLITE_MIGRATOR};
+
+pub trait Dialect: Database {
+    /// Maximum number of connections of the pool.
+    const MAX_CONNECTIONS: u32;
+
+    /// Cast of the parameters bound to u256 columns.
+    const NUMERIC: &'static str;
+
+    /// True if the database computes on u256 values, the balances
+    /// being updated in place. Otherwise, the storage computes them.
+    const NUMERIC_ARITHMETIC: bool;
+
+    /// Counts the tables named `$1`.
+    const TABLE_COUNT_QUERY: &'static str;
+
+    /// Migrations creating the tables of the storage.
+    fn migrator() -> &'static Migrator;
+
+    /// Selects the u256 `column` as a decimal string.
+    fn numeric_text(column: &str) -> String;
+}
+
+impl Dialect for Sqlite {
+    // In-memory databases only live as long as their connection.
+    const MAX_CONNECTIONS: u32 = 1;
+    const NUMERIC: &'static str = "";
+    // The balances are read then written, the single connection
+    // preventing concurrent updates.
+    const NUMERIC_ARITHMETIC: bool = false;
+    const TABLE_COUNT_QUERY: &'static str =
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = $1";
+
+    fn migrator() -> &'static Migrator {
+        &SQLITE_MIGRATOR
+    }
+
+    fn numeric_text(column: &str) -> String {
+        column.to_string()
+    }
+}
+
+impl Dialect for Postgres {
+    const MAX_CONNECTIONS: u32 = 10;
+    const NUMERIC: &'static str = "::numeric";
+    const NUMERIC_ARITHMETIC: bool = true;
+    const TABLE_COUNT_QUERY: &'static str = "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = $1";
+
+    fn migrator() -> &'static Migrator {
+        &POSTGRES_MIGRATOR
+    }
+
+    fn numeric_text(column: &str) -> String {
+        format!("{column}::text AS {column}")
+    }
+}
+
+/// Columns of `token`.
+pub fn token_columns<DB: Dialect>() -> String {
+    format!(
+        "contract_address, {}, token_id_hex, owner, block_timestamp, mint_address, mint_timestamp, mint_transaction_hash",
+        DB::numeric_text("token_id")
+    )
+}
+
+/// Columns of `token_balance`.
+pub fn token_balance_columns<DB: Dialect>() -> String {
+    format!(
+        "contract_address, {}, token_id_hex, chain_id, owner, {}",
+        DB::numeric_text("token_id"),
+        DB::numeric_text("balance")
+    )
+}
+
+/// Columns of `token_event`.
+pub fn token_event_columns<DB: Dialect>() -> String {
+    format!(
+        "block_timestamp, block_number, chain_id, contract_address, from_address, to_address, transaction_hash, {}, token_id_hex, contract_type, event_type, event_id, {}, marketplace_contract_address, marketplace_name, currency_address, {}, currency_symbol, currency_decimals, price_decimal, reference_price",
+        DB::numeric_text("token_id"),
+        DB::numeric_text("quantity"),
+        DB::numeric_text("price")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numeric_text() {
+        assert_eq!(Sqlite::numeric_text("price"), "price");
+        assert_eq!(Postgres::numeric_text("price"), "price::text AS price");
+    }
+}
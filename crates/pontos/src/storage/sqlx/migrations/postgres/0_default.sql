@@ -17,7 +17,7 @@ CREATE TABLE token (
        PRIMARY KEY (contract_address, token_id_hex)
 );
 
-CREATE TABLE event (
+CREATE TABLE token_event (
        block_timestamp BIGINT NOT NULL,
        from_address TEXT NOT NULL,
        to_address TEXT NOT NULL,
@@ -32,10 +32,17 @@ CREATE TABLE event (
        PRIMARY KEY (event_id)
 );
 
+CREATE TABLE indexer (
+       indexer_identifier TEXT NOT NULL,
+       indexer_version TEXT NOT NULL,
+
+       PRIMARY KEY (indexer_identifier)
+);
+
 CREATE TABLE block (
        block_timestamp BIGINT NOT NULL,
-       block_number BIGINT NOT NULL,
-       status TEXT NOT NULL,
+       block_number BIGINT NOT NULL UNIQUE,
+       block_status TEXT NOT NULL,
        indexer_version TEXT NOT NULL,
        indexer_identifier TEXT NOT NULL,
 
@@ -0,0 +1,6 @@
+-- Indexer checkpoints, to resume the indexation after a restart.
+--
+-- Indexers without checkpoint are resumed from
+-- their last terminated block.
+
+ALTER TABLE indexer ADD COLUMN checkpoint BIGINT;
@@ -0,0 +1,17 @@
+-- Token balances by owner, to support ERC1155 tokens
+-- owned by several addresses at once.
+-- ERC1155 transfers move any u256 quantity, the balances
+-- being the sums of the quantities transferred.
+
+CREATE TABLE token_balance (
+       contract_address TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       owner TEXT NOT NULL,
+       balance NUMERIC(78) NOT NULL,
+
+       PRIMARY KEY (contract_address, token_id_hex, owner)
+);
+
+ALTER TABLE token_event ADD COLUMN quantity NUMERIC(78) NOT NULL DEFAULT 1;
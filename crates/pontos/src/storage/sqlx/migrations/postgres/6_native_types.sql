@@ -0,0 +1,11 @@
+-- Numeric types for the token ids, prices and supplies, stored as
+-- decimal strings before. A u256 has up to 78 decimal digits.
+ALTER TABLE token ALTER COLUMN token_id TYPE NUMERIC(78) USING token_id::NUMERIC(78);
+ALTER TABLE token_event ALTER COLUMN token_id TYPE NUMERIC(78) USING token_id::NUMERIC(78);
+ALTER TABLE token_event ALTER COLUMN price TYPE NUMERIC(78) USING price::NUMERIC(78);
+ALTER TABLE token_balance ALTER COLUMN token_id TYPE NUMERIC(78) USING token_id::NUMERIC(78);
+ALTER TABLE contract ALTER COLUMN total_supply TYPE NUMERIC(78) USING total_supply::NUMERIC(78);
+
+CREATE INDEX token_contract_token_id ON token (contract_address, token_id);
+CREATE INDEX token_owner ON token (owner);
+CREATE INDEX token_balance_contract_token_id ON token_balance (contract_address, token_id);
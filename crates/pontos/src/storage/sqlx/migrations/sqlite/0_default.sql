@@ -0,0 +1,58 @@
+-- SQL default migration with simple tables.
+--
+-- TODO: investigate why sqlx is complaining for
+-- NULL not being compatible with `Option<T>`...
+-- Supposed to be fixed in older versions of sqlx.
+
+CREATE TABLE token (
+       contract_address TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       owner TEXT NOT NULL,
+       mint_address TEXT DEFAULT '',
+       mint_timestamp INTEGER DEFAULT 0,
+       mint_transaction_hash TEXT DEFAULT '',
+       block_timestamp INTEGER NOT NULL,
+
+       PRIMARY KEY (contract_address, token_id_hex)
+);
+
+CREATE TABLE token_event (
+       block_timestamp INTEGER NOT NULL,
+       from_address TEXT NOT NULL,
+       to_address TEXT NOT NULL,
+       contract_address TEXT NOT NULL,
+       transaction_hash TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       contract_type TEXT NOT NULL,
+       event_type TEXT NOT NULL,
+       event_id TEXT NOT NULL,
+
+       PRIMARY KEY (event_id)
+);
+
+CREATE TABLE indexer (
+       indexer_identifier TEXT NOT NULL,
+       indexer_version TEXT NOT NULL,
+
+       PRIMARY KEY (indexer_identifier)
+);
+
+CREATE TABLE block (
+       block_timestamp INTEGER NOT NULL,
+       block_number INTEGER NOT NULL UNIQUE,
+       block_status TEXT NOT NULL,
+       indexer_version TEXT NOT NULL,
+       indexer_identifier TEXT NOT NULL,
+
+       PRIMARY KEY (block_timestamp)
+);
+
+CREATE TABLE contract (
+       contract_address TEXT NOT NULL,
+       contract_type TEXT NOT NULL,
+       block_timestamp INTEGER NOT NULL,
+
+       PRIMARY KEY (contract_address)
+);
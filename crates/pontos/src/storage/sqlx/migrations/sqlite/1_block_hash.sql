@@ -0,0 +1,7 @@
+-- Block hashes, to detect chain reorganizations.
+--
+-- Blocks indexed before this migration have no hash,
+-- and are never considered as orphaned.
+
+ALTER TABLE block ADD COLUMN block_hash TEXT;
+ALTER TABLE block ADD COLUMN parent_hash TEXT;
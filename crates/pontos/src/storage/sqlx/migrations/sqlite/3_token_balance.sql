@@ -0,0 +1,18 @@
+-- Token balances by owner, to support ERC1155 tokens
+-- owned by several addresses at once.
+-- ERC1155 transfers move any u256 quantity, stored as decimal
+-- strings like the token ids: INTEGER columns would convert
+-- the quantities above i64 to lossy reals.
+
+CREATE TABLE token_balance (
+       contract_address TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       owner TEXT NOT NULL,
+       balance TEXT NOT NULL,
+
+       PRIMARY KEY (contract_address, token_id_hex, owner)
+);
+
+ALTER TABLE token_event ADD COLUMN quantity TEXT NOT NULL DEFAULT '1';
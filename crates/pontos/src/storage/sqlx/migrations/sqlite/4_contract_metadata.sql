@@ -0,0 +1,12 @@
+-- Contract metadata collected when the contract is identified.
+--
+-- Interfaces are the supported SRC5 interface IDs, comma separated.
+ALTER TABLE contract ADD COLUMN chain_id TEXT;
+ALTER TABLE contract ADD COLUMN name TEXT;
+ALTER TABLE contract ADD COLUMN symbol TEXT;
+ALTER TABLE contract ADD COLUMN image TEXT;
+ALTER TABLE contract ADD COLUMN contract_uri TEXT;
+ALTER TABLE contract ADD COLUMN total_supply TEXT;
+ALTER TABLE contract ADD COLUMN interfaces TEXT;
+ALTER TABLE contract ADD COLUMN deployer TEXT;
+ALTER TABLE contract ADD COLUMN class_hash TEXT;
@@ -0,0 +1,19 @@
+-- Block numbers and sales of the token events, read by the
+-- token history and block range queries.
+--
+-- Events indexed before this migration have no block number,
+-- and are not returned by these queries.
+ALTER TABLE token_event ADD COLUMN block_number INTEGER;
+ALTER TABLE token_event ADD COLUMN chain_id TEXT;
+ALTER TABLE token_event ADD COLUMN marketplace_contract_address TEXT;
+ALTER TABLE token_event ADD COLUMN marketplace_name TEXT;
+ALTER TABLE token_event ADD COLUMN currency_address TEXT;
+ALTER TABLE token_event ADD COLUMN price TEXT;
+ALTER TABLE token_event ADD COLUMN currency_symbol TEXT;
+ALTER TABLE token_event ADD COLUMN currency_decimals INTEGER;
+ALTER TABLE token_event ADD COLUMN price_decimal TEXT;
+ALTER TABLE token_event ADD COLUMN reference_price TEXT;
+
+CREATE INDEX token_event_block_number ON token_event (block_number);
+CREATE INDEX token_event_token ON token_event (contract_address, token_id_hex, block_number);
+CREATE INDEX token_balance_owner ON token_balance (owner);
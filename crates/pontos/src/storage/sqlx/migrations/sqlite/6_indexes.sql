@@ -0,0 +1,5 @@
+-- Indexes of the token lookups. Token ids stay decimal
+-- strings, SQLite having no type for u256.
+CREATE INDEX token_contract_token_id ON token (contract_address, token_id);
+CREATE INDEX token_owner ON token (owner);
+CREATE INDEX token_balance_contract_token_id ON token_balance (contract_address, token_id);
@@ -1,9 +1,20 @@
-//! Module implementing Sqlx backend for Pontos.
+//! Module implementing Sqlx backends for Pontos.
 //!
-//! The main objective of this module is to add a default
-//! implementation for examples and testing.
-//! No optimization was made at database level.
+//! [`SqlxStorage`] stores the data on SQLite, for local use and tests,
+//! or on Postgres with native types. [`DefaultSqlxStorage`] selects
+//! the backend from the database URL. Both create their tables with
+//! the embedded migrations.
+pub mod balances;
+pub mod bulk;
+pub mod connection;
 pub mod default_storage;
-pub use default_storage::DefaultSqlxStorage;
+pub use default_storage::{DefaultSqlxStorage, PostgresStorage, SqliteStorage};
+pub mod dialect;
+pub mod schema;
+pub mod sqlx_storage;
+pub use sqlx_storage::SqlxStorage;
 
 pub mod types;
+
+#[cfg(test)]
+mod conformance;
@@ -0,0 +1,63 @@
+//! Versioned migrations of the sqlx storages schema.
+//!
+//! The migrations are embedded in the crate, one directory by
+//! database backend. A storage only starts on a database having
+//! all the migrations of its backend applied.
+//!
+//! Databases created before the migrations were versioned have no
+//! migrations table: they are baselined, their first migrations being
+//! marked as applied without running them, see [`SqlxStorage::baseline`].
+//!
+//! [`SqlxStorage::baseline`]: super::SqlxStorage::baseline
+use sqlx::migrate::Migrator;
+
+use crate::storage::types::StorageError;
+
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("src/storage/sqlx/migrations/postgres");
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("src/storage/sqlx/migrations/sqlite");
+
+/// Last migration applied to the database, `NULL` if none. The
+/// migrations table doesn't exist if the database was never migrated.
+pub const APPLIED_VERSION_QUERY: &str = "SELECT MAX(version) FROM _sqlx_migrations WHERE success";
+
+/// Table of the migrations applied to the database.
+pub const MIGRATIONS_TABLE: &str = "_sqlx_migrations";
+
+/// Marks a migration as applied without running it.
+pub const BASELINE_QUERY: &str = "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES ($1, $2, TRUE, $3, 0)";
+
+/// Checks that the `applied` version, the last migration applied
+/// to the database, is at least the last migration of `migrator`.
+pub fn check_version(migrator: &Migrator, applied: Option<i64>) -> Result<(), StorageError> {
+    let expected = migrator.iter().map(|m| m.version).max().unwrap_or_default();
+
+    match applied {
+        Some(version) if version >= expected => Ok(()),
+        Some(version) => Err(StorageError::OutdatedSchema(format!(
+            "database is at version {version}, version {expected} expected"
+        ))),
+        None => Err(StorageError::OutdatedSchema(format!(
+            "no migration applied, version {expected} expected, a database created before the versioned migrations must be baselined"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_version() {
+        let last = SQLITE_MIGRATOR.iter().map(|m| m.version).max().unwrap();
+
+        assert!(check_version(&SQLITE_MIGRATOR, Some(last)).is_ok());
+        assert!(matches!(
+            check_version(&SQLITE_MIGRATOR, Some(last - 1)),
+            Err(StorageError::OutdatedSchema(_))
+        ));
+        assert!(matches!(
+            check_version(&SQLITE_MIGRATOR, None),
+            Err(StorageError::OutdatedSchema(_))
+        ));
+    }
+}
@@ -0,0 +1,1240 @@
+//! Implementation of the storage using sqlx crate.
+//!
+//! The same implementation runs on SQLite, mostly used for testing
+//! and local indexation, and on Postgres for production databases.
+//! The differences between both are in the [`Dialect`] trait.
+use ark_starknet::CairoU256;
+use async_trait::async_trait;
+
+use log::{debug, trace, warn};
+use sqlx::database::HasArguments;
+use sqlx::migrate::Migrate;
+use sqlx::pool::PoolOptions;
+use sqlx::{
+    ColumnIndex, Decode, Encode, Error as SqlxError, Executor, FromRow, IntoArguments, Pool, Row,
+    Transaction, Type,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use super::balances::BalanceChanges;
+use super::bulk::{rows_per_query, values_placeholders, BufferedMint, BufferedOwner, WriteBuffer};
+use super::connection::{BlockTransactions, Connection};
+use super::dialect::{token_balance_columns, token_columns, token_event_columns, Dialect};
+use super::schema::{check_version, APPLIED_VERSION_QUERY, BASELINE_QUERY, MIGRATIONS_TABLE};
+use super::types::*;
+use crate::storage::current_block;
+use crate::storage::types::*;
+use crate::Storage;
+
+impl From<SqlxError> for StorageError {
+    fn from(e: SqlxError) -> Self {
+        StorageError::DatabaseError(e.to_string())
+    }
+}
+
+pub struct SqlxStorage<DB: Dialect> {
+    pool: Pool<DB>,
+    blocks: BlockTransactions<DB>,
+    /// Number of buffered rows triggering a flush, 0 if not buffered.
+    flush_size: usize,
+    /// Rows buffered in the scope of each block, `None` out of any block.
+    buffers: Mutex<HashMap<Option<u64>, WriteBuffer>>,
+}
+
+impl<DB> SqlxStorage<DB>
+where
+    DB: Dialect,
+    DB::Connection: Migrate,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    for<'q> i64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> String: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> Option<i64>: Encode<'q, DB>,
+    for<'q> Option<String>: Encode<'q, DB>,
+    for<'q> Vec<u8>: Encode<'q, DB> + Type<DB>,
+    for<'a> &'a str: ColumnIndex<DB::Row>,
+    usize: ColumnIndex<DB::Row>,
+    for<'r> TokenData: FromRow<'r, DB::Row>,
+    for<'r> EventData: FromRow<'r, DB::Row>,
+    for<'r> ContractData: FromRow<'r, DB::Row>,
+    for<'r> Block: FromRow<'r, DB::Row>,
+    for<'r> BlockData: FromRow<'r, DB::Row>,
+    for<'r> TokenEventData: FromRow<'r, DB::Row>,
+    for<'r> TokenBalanceData: FromRow<'r, DB::Row>,
+    for<'r> CollectionStatsData: FromRow<'r, DB::Row>,
+{
+    pub fn get_pool_ref(&self) -> &Pool<DB> {
+        &self.pool
+    }
+
+    /// Connects to an existing database, failing with
+    /// [`StorageError::OutdatedSchema`] if its schema is older than the
+    /// one expected, see [`SqlxStorage::migrate`].
+    pub async fn new(db_url: &str) -> Result<Self, StorageError> {
+        let storage = Self::connect(db_url).await?;
+        storage.check_schema().await?;
+        Ok(storage)
+    }
+
+    /// Connects to the database, and applies the migrations not
+    /// applied yet. The tables are created on an empty database.
+    pub async fn migrate(db_url: &str) -> Result<Self, StorageError> {
+        let storage = Self::connect(db_url).await?;
+        DB::migrator()
+            .run(&storage.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(storage)
+    }
+
+    /// Connects to a database created before the migrations were
+    /// versioned, marking the migrations up to `version` as applied
+    /// without running them, then applies the following ones.
+    ///
+    /// `version` is the last migration the schema of the database
+    /// already has. Nothing is marked if migrations were applied.
+    pub async fn baseline(db_url: &str, version: i64) -> Result<Self, StorageError> {
+        let storage = Self::connect(db_url).await?;
+
+        {
+            let mut conn = storage.pool.acquire().await?;
+            conn.ensure_migrations_table()
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+            let applied = conn
+                .list_applied_migrations()
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+            if applied.is_empty() {
+                for migration in DB::migrator().iter().filter(|m| m.version <= version) {
+                    debug!("Baselining migration {}", migration.version);
+                    sqlx::query(BASELINE_QUERY)
+                        .bind(migration.version)
+                        .bind(migration.description.to_string())
+                        .bind(migration.checksum.to_vec())
+                        .execute(&mut *conn)
+                        .await?;
+                }
+            }
+        }
+
+        DB::migrator()
+            .run(&storage.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(storage)
+    }
+
+    /// Checks that all the migrations embedded in the crate
+    /// are applied to the database.
+    pub async fn check_schema(&self) -> Result<(), StorageError> {
+        // The migrations table doesn't exist if the
+        // database was never migrated.
+        let tables: i64 = sqlx::query_scalar(DB::TABLE_COUNT_QUERY)
+            .bind(MIGRATIONS_TABLE.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+
+        let applied: Option<i64> = if tables == 0 {
+            None
+        } else {
+            sqlx::query_scalar(APPLIED_VERSION_QUERY)
+                .fetch_one(&self.pool)
+                .await?
+        };
+
+        check_version(DB::migrator(), applied)
+    }
+
+    async fn connect(db_url: &str) -> Result<Self, StorageError> {
+        Ok(Self {
+            pool: PoolOptions::new()
+                .max_connections(DB::MAX_CONNECTIONS)
+                .connect(db_url)
+                .await?,
+            blocks: Mutex::new(HashMap::new()),
+            flush_size: 0,
+            buffers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Buffers the tokens, mints and transfer events, written with
+    /// multi-row inserts once `flush_size` rows are buffered, and when
+    /// a block is committed or terminated. 0 disables the buffering.
+    ///
+    /// Buffered rows are not checked for duplicates, the existing
+    /// rows being kept on conflict. They are not returned by the
+    /// queries until flushed. The rows of each block are buffered
+    /// apart, see [`crate::storage::in_block`].
+    pub fn with_flush_size(mut self, flush_size: usize) -> Self {
+        self.flush_size = flush_size;
+        self
+    }
+
+    /// Writes the rows buffered in the current scope.
+    pub async fn flush(&self) -> Result<(), StorageError> {
+        let rows = self.take_buffer().await;
+        self.write_rows(rows).await
+    }
+
+    /// Empties the buffer of the current scope, returning its rows.
+    async fn take_buffer(&self) -> WriteBuffer {
+        self.buffers
+            .lock()
+            .await
+            .remove(&current_block())
+            .unwrap_or_default()
+    }
+
+    /// Buffers the rows added by `f`, flushing the buffer if full.
+    /// Returns false if the writes are not buffered.
+    async fn buffer(&self, f: impl FnOnce(&mut WriteBuffer)) -> Result<bool, StorageError> {
+        if self.flush_size == 0 {
+            return Ok(false);
+        }
+
+        let mut buffers = self.buffers.lock().await;
+        let buffer = buffers.entry(current_block()).or_default();
+        f(buffer);
+
+        if buffer.len() >= self.flush_size {
+            let rows = buffer.take();
+            drop(buffers);
+            self.write_rows(rows).await?;
+        }
+
+        Ok(true)
+    }
+
+    async fn write_rows(&self, rows: WriteBuffer) -> Result<(), StorageError> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        trace!(
+            "Flushing {} tokens, {} mints, {} owners and {} events",
+            rows.tokens.len(),
+            rows.mints.len(),
+            rows.owners.len(),
+            rows.events.len()
+        );
+
+        self.insert_tokens(&rows.tokens).await?;
+
+        for mint in &rows.mints {
+            self.update_mint(&mint.contract_address, &mint.token_id, &mint.info)
+                .await?;
+        }
+
+        for owner in &rows.owners {
+            self.set_owner(&owner.contract_address, &owner.token_id_hex, &owner.owner)
+                .await?;
+        }
+
+        self.insert_transfer_events(&rows.events).await
+    }
+
+    /// Inserts the tokens with multi-row inserts, keeping the existing ones.
+    async fn insert_tokens(&self, tokens: &[(TokenInfo, u64)]) -> Result<(), StorageError> {
+        let casts = ["", DB::NUMERIC, "", "", ""];
+
+        for chunk in tokens.chunks(rows_per_query(casts.len())) {
+            let q = format!(
+                "INSERT INTO token (contract_address, token_id, token_id_hex, owner, block_timestamp) VALUES {} ON CONFLICT (contract_address, token_id_hex) DO NOTHING",
+                values_placeholders(chunk.len(), &casts)
+            );
+
+            let mut query = sqlx::query(&q);
+            for (token, block_timestamp) in chunk {
+                query = query
+                    .bind(token.contract_address.clone())
+                    .bind(token.token_id.clone())
+                    .bind(token.token_id_hex.clone())
+                    .bind(token.owner.clone())
+                    .bind(*block_timestamp as i64);
+            }
+
+            query.execute(&mut *self.connection().await?).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn set_owner(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        owner: &str,
+    ) -> Result<(), StorageError> {
+        let q = "UPDATE token SET owner = $1 WHERE contract_address = $2 AND token_id_hex = $3";
+
+        sqlx::query(q)
+            .bind(owner.to_string())
+            .bind(contract_address.to_string())
+            .bind(token_id_hex.to_string())
+            .execute(&mut *self.connection().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update_mint(
+        &self,
+        contract_address: &str,
+        token_id: &str,
+        info: &TokenMintInfo,
+    ) -> Result<(), StorageError> {
+        let q = format!("UPDATE token SET mint_address = $1, mint_timestamp = $2, mint_transaction_hash = $3 WHERE contract_address = $4 AND token_id = $5{}", DB::NUMERIC);
+
+        sqlx::query(&q)
+            .bind(info.address.clone())
+            .bind(info.timestamp as i64)
+            .bind(info.transaction_hash.clone())
+            .bind(contract_address.to_string())
+            .bind(token_id.to_string())
+            .execute(&mut *self.connection().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Inserts the events with multi-row inserts, keeping the existing ones,
+    /// and applies the balance changes of the events inserted.
+    async fn insert_transfer_events(
+        &self,
+        events: &[TokenTransferEvent],
+    ) -> Result<(), StorageError> {
+        let casts = [
+            "",
+            "",
+            "",
+            "",
+            "",
+            "",
+            "",
+            DB::NUMERIC,
+            "",
+            "",
+            "",
+            "",
+            DB::NUMERIC,
+        ];
+        let mut inserted = HashSet::new();
+
+        for chunk in events.chunks(rows_per_query(casts.len())) {
+            let q = format!(
+                "INSERT INTO token_event (block_timestamp, block_number, chain_id, contract_address, from_address, to_address, transaction_hash, token_id, token_id_hex, contract_type, event_type, event_id, quantity) VALUES {} ON CONFLICT (event_id) DO NOTHING RETURNING event_id",
+                values_placeholders(chunk.len(), &casts)
+            );
+
+            let mut query = sqlx::query(&q);
+            for event in chunk {
+                query = query
+                    .bind(event.timestamp as i64)
+                    .bind(event.block_number.map(|n| n as i64))
+                    .bind(event.chain_id.clone())
+                    .bind(event.contract_address.clone())
+                    .bind(event.from_address.clone())
+                    .bind(event.to_address.clone())
+                    .bind(event.transaction_hash.clone())
+                    .bind(event.token_id.clone())
+                    .bind(event.token_id_hex.clone())
+                    .bind(event.contract_type.clone())
+                    .bind(event.event_type.to_string())
+                    .bind(event.event_id.clone())
+                    .bind(event.quantity.clone());
+            }
+
+            for row in query.fetch_all(&mut *self.connection().await?).await? {
+                inserted.insert(row.try_get::<String, _>(0)?);
+            }
+        }
+
+        // Duplicated events of a buffer are only inserted once.
+        let mut changes = BalanceChanges::default();
+        for event in events {
+            if inserted.remove(&event.event_id) {
+                changes.add(event, false)?;
+            }
+        }
+
+        self.apply_balance_changes(&changes).await
+    }
+
+    /// Restores the tokens transferred in the block cleaned: the tokens
+    /// without transfers left are removed, the mints of the block are
+    /// cleared, and the ERC721 tokens get back the owner of their balance.
+    async fn restore_tokens(
+        &self,
+        block_timestamp: u64,
+        tokens: &BTreeMap<(String, String), bool>,
+    ) -> Result<(), StorageError> {
+        let q = "UPDATE token SET mint_address = NULL, mint_timestamp = NULL, mint_transaction_hash = NULL WHERE mint_timestamp = $1";
+        sqlx::query(q)
+            .bind(block_timestamp as i64)
+            .execute(&mut *self.connection().await?)
+            .await?;
+
+        for ((contract_address, token_id_hex), is_erc721) in tokens {
+            let q = format!("DELETE FROM token WHERE contract_address = $1 AND token_id_hex = $2 AND NOT EXISTS (SELECT 1 FROM token_event WHERE token_event.contract_address = token.contract_address AND token_event.token_id_hex = token.token_id_hex AND event_type <> '{}')", EventType::Sale);
+            sqlx::query(&q)
+                .bind(contract_address.clone())
+                .bind(token_id_hex.clone())
+                .execute(&mut *self.connection().await?)
+                .await?;
+
+            if *is_erc721 {
+                let q = "UPDATE token SET owner = COALESCE((SELECT owner FROM token_balance WHERE token_balance.contract_address = token.contract_address AND token_balance.token_id_hex = token.token_id_hex AND balance > 0 LIMIT 1), '') WHERE contract_address = $1 AND token_id_hex = $2";
+                sqlx::query(q)
+                    .bind(contract_address.clone())
+                    .bind(token_id_hex.clone())
+                    .execute(&mut *self.connection().await?)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the balance changes, in place if the database computes
+    /// on u256 values, see [`Dialect::NUMERIC_ARITHMETIC`].
+    async fn apply_balance_changes(&self, changes: &BalanceChanges) -> Result<(), StorageError> {
+        for ((contract_address, token_id_hex, owner), change) in changes.iter() {
+            let (q, balance) = if DB::NUMERIC_ARITHMETIC {
+                (format!("INSERT INTO token_balance (contract_address, token_id, token_id_hex, chain_id, owner, balance) VALUES ($1, $2{numeric}, $3, $4, $5, GREATEST($6{numeric} - $7{numeric}, 0)) ON CONFLICT (contract_address, token_id_hex, owner) DO UPDATE SET balance = GREATEST(token_balance.balance + $6{numeric} - $7{numeric}, 0)", numeric = DB::NUMERIC), None)
+            } else {
+                let balance = self
+                    .get_token_balance(contract_address, token_id_hex, owner)
+                    .await?;
+                let balance = CairoU256::from_str(&balance).map_err(|_| {
+                    StorageError::DatabaseError(format!("invalid balance {balance} of {owner}"))
+                })?;
+
+                let (balance, covered) = change.apply(&balance);
+                if !covered {
+                    warn!(
+                        "Balance of {} for token {} {} lower than transferred quantity",
+                        owner, contract_address, change.token_id
+                    );
+                }
+
+                (format!("INSERT INTO token_balance (contract_address, token_id, token_id_hex, chain_id, owner, balance) VALUES ($1, $2{numeric}, $3, $4, $5, $6{numeric}) ON CONFLICT (contract_address, token_id_hex, owner) DO UPDATE SET balance = $6{numeric}", numeric = DB::NUMERIC), Some(balance))
+            };
+
+            let query = sqlx::query(&q)
+                .bind(contract_address.clone())
+                .bind(change.token_id.clone())
+                .bind(token_id_hex.clone())
+                .bind(change.chain_id.clone())
+                .bind(owner.clone());
+            let query = match balance {
+                Some(balance) => query.bind(balance.to_string()),
+                None => query
+                    .bind(change.received.to_string())
+                    .bind(change.sent.to_string()),
+            };
+
+            query.execute(&mut *self.connection().await?).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the connection for the next query. The connection
+    /// must be released before requesting another one.
+    async fn connection(&self) -> Result<Connection<DB>, StorageError> {
+        Connection::acquire(&self.blocks, &self.pool).await
+    }
+
+    /// Removes the transaction of the block, `None` if not begun.
+    async fn take_block_tx(&self, block_number: u64) -> Option<Transaction<'static, DB>> {
+        let block_tx = self.blocks.lock().await.remove(&block_number)?;
+        let mut tx = block_tx.lock().await;
+        tx.take()
+    }
+
+    pub async fn dump_tables(&self) -> Result<(), StorageError> {
+        let q = format!("SELECT {} FROM token", token_columns::<DB>());
+        let rows = sqlx::query(&q)
+            .fetch_all(&mut *self.connection().await?)
+            .await?;
+
+        for row in &rows {
+            debug!("{:?}", TokenData::from_row(row)?);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_token_by_id(
+        &self,
+        contract_address: &str,
+        _token_id_hex: &str,
+        token_id: &str,
+    ) -> Result<Option<TokenData>, StorageError> {
+        let q = format!(
+            "SELECT {} FROM token WHERE contract_address = $1 AND token_id = $2{}",
+            token_columns::<DB>(),
+            DB::NUMERIC
+        );
+
+        match sqlx::query(&q)
+            .bind(contract_address.to_string())
+            .bind(token_id.to_string())
+            .fetch_all(&mut *self.connection().await?)
+            .await
+        {
+            Ok(rows) => {
+                if rows.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(TokenData::from_row(&rows[0])?))
+                }
+            }
+            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+        }
+    }
+
+    async fn get_event_by_id(&self, event_id: &str) -> Result<Option<EventData>, StorageError> {
+        let q = format!(
+            "SELECT block_timestamp, contract_address, from_address, to_address, transaction_hash, {}, token_id_hex, contract_type, event_type, event_id FROM token_event WHERE event_id = $1",
+            DB::numeric_text("token_id")
+        );
+
+        match sqlx::query(&q)
+            .bind(event_id.to_string())
+            .fetch_all(&mut *self.connection().await?)
+            .await
+        {
+            Ok(rows) => {
+                if rows.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(EventData::from_row(&rows[0])?))
+                }
+            }
+            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+        }
+    }
+
+    async fn get_contract_by_address(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<Option<ContractData>, StorageError> {
+        let q = "SELECT * FROM contract WHERE contract_address = $1 AND chain_id = $2";
+
+        match sqlx::query(q)
+            .bind(contract_address.to_string())
+            .bind(chain_id.to_string())
+            .fetch_all(&mut *self.connection().await?)
+            .await
+        {
+            Ok(rows) => {
+                if rows.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(ContractData::from_row(&rows[0])?))
+                }
+            }
+            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+        }
+    }
+
+    async fn get_block_by_timestamp(&self, ts: u64) -> Result<Option<Block>, StorageError> {
+        let q = "SELECT block_number, block_status, block_timestamp, indexer_identifier FROM block WHERE block_timestamp = $1";
+
+        match sqlx::query(q)
+            .bind(ts as i64)
+            .fetch_all(&mut *self.connection().await?)
+            .await
+        {
+            Ok(rows) => {
+                if rows.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(Block::from_row(&rows[0])?))
+                }
+            }
+            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+        }
+    }
+}
+
+#[async_trait]
+impl<DB> Storage for SqlxStorage<DB>
+where
+    DB: Dialect,
+    DB::Connection: Migrate,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    for<'q> i64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> String: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> Option<i64>: Encode<'q, DB>,
+    for<'q> Option<String>: Encode<'q, DB>,
+    for<'q> Vec<u8>: Encode<'q, DB> + Type<DB>,
+    for<'a> &'a str: ColumnIndex<DB::Row>,
+    usize: ColumnIndex<DB::Row>,
+    for<'r> TokenData: FromRow<'r, DB::Row>,
+    for<'r> EventData: FromRow<'r, DB::Row>,
+    for<'r> ContractData: FromRow<'r, DB::Row>,
+    for<'r> Block: FromRow<'r, DB::Row>,
+    for<'r> BlockData: FromRow<'r, DB::Row>,
+    for<'r> TokenEventData: FromRow<'r, DB::Row>,
+    for<'r> TokenBalanceData: FromRow<'r, DB::Row>,
+    for<'r> CollectionStatsData: FromRow<'r, DB::Row>,
+{
+    async fn register_mint(
+        &self,
+        contract_address: &str,
+        _token_id_hex: &str,
+        token_id: &str,
+        info: &TokenMintInfo,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering mint {} {} {:?}",
+            contract_address,
+            token_id,
+            info
+        );
+
+        if self
+            .buffer(|buffer| {
+                buffer.mints.push(BufferedMint {
+                    contract_address: contract_address.to_string(),
+                    token_id: token_id.to_string(),
+                    info: info.clone(),
+                })
+            })
+            .await?
+        {
+            return Ok(());
+        }
+
+        self.update_mint(contract_address, token_id, info).await
+    }
+
+    async fn register_token(
+        &self,
+        token: &TokenInfo,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!("Registering token {:?}", token);
+
+        if self
+            .buffer(|buffer| buffer.tokens.push((token.clone(), block_timestamp)))
+            .await?
+        {
+            return Ok(());
+        }
+
+        if (self
+            .get_token_by_id(
+                &token.contract_address,
+                &token.token_id_hex,
+                &token.token_id,
+            )
+            .await?)
+            .is_some()
+        {
+            return Err(StorageError::AlreadyExists(format!(
+                "token id = {}",
+                token.token_id_hex
+            )));
+        }
+
+        self.insert_tokens(&[(token.clone(), block_timestamp)])
+            .await
+    }
+
+    async fn update_token_owner(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        owner: &str,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Updating owner of token {} {} to {}",
+            contract_address,
+            token_id_hex,
+            owner
+        );
+
+        if self
+            .buffer(|buffer| {
+                buffer.owners.push(BufferedOwner {
+                    contract_address: contract_address.to_string(),
+                    token_id_hex: token_id_hex.to_string(),
+                    owner: owner.to_string(),
+                })
+            })
+            .await?
+        {
+            return Ok(());
+        }
+
+        self.set_owner(contract_address, token_id_hex, owner).await
+    }
+
+    async fn get_token_balance(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        owner: &str,
+    ) -> Result<String, StorageError> {
+        trace!(
+            "Getting balance of {} for token {} {}",
+            owner,
+            contract_address,
+            token_id_hex
+        );
+
+        let q = format!(
+            "SELECT {} FROM token_balance WHERE contract_address = $1 AND token_id_hex = $2 AND owner = $3",
+            DB::numeric_text("balance")
+        );
+
+        match sqlx::query(&q)
+            .bind(contract_address.to_string())
+            .bind(token_id_hex.to_string())
+            .bind(owner.to_string())
+            .fetch_optional(&mut *self.connection().await?)
+            .await?
+        {
+            Some(row) => Ok(row.try_get::<String, _>(0)?),
+            None => Ok("0".to_string()),
+        }
+    }
+
+    async fn register_sale_event(
+        &self,
+        event: &TokenSaleEvent,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!("Registering sale event {:?}", event);
+
+        let q = format!("INSERT INTO token_event (block_timestamp, block_number, contract_address, from_address, to_address, transaction_hash, token_id, token_id_hex, contract_type, event_type, event_id, quantity, marketplace_contract_address, marketplace_name, currency_address, price, currency_symbol, currency_decimals, price_decimal, reference_price) VALUES ($1, $2, $3, $4, $5, $6, $7{numeric}, $8, $9, $10, $11, $12{numeric}, $13, $14, $15, $16{numeric}, $17, $18, $19, $20) ON CONFLICT (event_id) DO NOTHING", numeric = DB::NUMERIC);
+
+        sqlx::query(&q)
+            .bind(block_timestamp as i64)
+            .bind(event.block_number.map(|n| n as i64))
+            .bind(event.nft_contract_address.clone())
+            .bind(event.from_address.clone())
+            .bind(event.to_address.clone())
+            .bind(event.transaction_hash.clone())
+            .bind(event.token_id.clone())
+            .bind(event.token_id_hex.clone())
+            .bind(event.nft_type.clone().unwrap_or_default())
+            .bind(EventType::Sale.to_string())
+            .bind(event.event_id.clone())
+            .bind(event.quantity.to_string())
+            .bind(event.marketplace_contract_address.clone())
+            .bind(event.marketplace_name.clone())
+            .bind(event.currency_address.clone())
+            .bind(event.price.clone())
+            .bind(event.currency_symbol.clone())
+            .bind(event.currency_decimals.map(i64::from))
+            .bind(event.price_decimal.clone())
+            .bind(event.reference_price.clone())
+            .execute(&mut *self.connection().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn register_transfer_event(
+        &self,
+        event: &TokenTransferEvent,
+        _block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!("Registering event {:?}", event);
+
+        if self
+            .buffer(|buffer| buffer.events.push(event.clone()))
+            .await?
+        {
+            return Ok(());
+        }
+
+        if (self.get_event_by_id(&event.event_id).await?).is_some() {
+            return Err(StorageError::AlreadyExists(format!(
+                "event id = {}",
+                event.event_id
+            )));
+        }
+
+        self.insert_transfer_events(std::slice::from_ref(event))
+            .await
+    }
+
+    async fn get_contract_type(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<ContractType, StorageError> {
+        trace!("Getting contract info for contract {}", contract_address);
+
+        if let Some(c) = self
+            .get_contract_by_address(contract_address, chain_id)
+            .await?
+        {
+            Ok(ContractType::from_str(&c.contract_type).unwrap())
+        } else {
+            Err(StorageError::NotFound(format!(
+                "contract_address: {contract_address}"
+            )))
+        }
+    }
+
+    async fn get_contract_types(
+        &self,
+        chain_id: &str,
+        limit: u64,
+    ) -> Result<Vec<(String, ContractType)>, StorageError> {
+        trace!("Getting {} contract types for chain {}", limit, chain_id);
+
+        let q = "SELECT contract_address, contract_type FROM contract WHERE chain_id = $1 ORDER BY block_timestamp DESC LIMIT $2";
+
+        let rows = sqlx::query(q)
+            .bind(chain_id.to_string())
+            .bind(limit as i64)
+            .fetch_all(&mut *self.connection().await?)
+            .await?;
+
+        rows.iter()
+            .map(|row| -> Result<_, StorageError> {
+                let contract_address: String = row.try_get("contract_address")?;
+                let contract_type: String = row.try_get("contract_type")?;
+                Ok((
+                    contract_address,
+                    ContractType::from_str(&contract_type).unwrap(),
+                ))
+            })
+            .collect()
+    }
+
+    async fn register_contract_info(
+        &self,
+        info: &ContractInfo,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering contract info {:?} for contract {}",
+            info.contract_type,
+            info.contract_address
+        );
+
+        let q = format!("INSERT INTO contract (contract_address, chain_id, contract_type, block_timestamp, name, symbol, image, contract_uri, total_supply, interfaces, deployer, class_hash) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9{}, $10, $11, $12) ON CONFLICT (contract_address) DO UPDATE SET contract_type = excluded.contract_type, name = excluded.name, symbol = excluded.symbol, image = COALESCE(excluded.image, contract.image), contract_uri = excluded.contract_uri, total_supply = excluded.total_supply, interfaces = excluded.interfaces, deployer = COALESCE(excluded.deployer, contract.deployer), class_hash = excluded.class_hash", DB::NUMERIC);
+
+        let _r = sqlx::query(&q)
+            .bind(info.contract_address.clone())
+            .bind(chain_id.to_string())
+            .bind(info.contract_type.to_string())
+            .bind(block_timestamp as i64)
+            .bind(info.name.clone())
+            .bind(info.symbol.clone())
+            .bind(info.image.clone())
+            .bind(info.contract_uri.clone())
+            .bind(info.total_supply.clone())
+            .bind(info.interfaces.join(","))
+            .bind(info.deployer.clone())
+            .bind(info.class_hash.clone())
+            .execute(&mut *self.connection().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn begin_block(&self, block_number: u64) -> Result<(), StorageError> {
+        trace!("Beginning block #{}", block_number);
+
+        check_block_scope(block_number, "begun")?;
+        if self.blocks.lock().await.contains_key(&block_number) {
+            return Err(StorageError::InvalidStatus(format!(
+                "block #{block_number} begun while already written"
+            )));
+        }
+
+        // The transactions map is not locked while waiting for a
+        // connection, released by the blocks being committed.
+        let tx = self.pool.begin().await?;
+        self.blocks
+            .lock()
+            .await
+            .insert(block_number, Arc::new(Mutex::new(Some(tx))));
+
+        Ok(())
+    }
+
+    async fn commit_block(&self, block_number: u64) -> Result<(), StorageError> {
+        trace!("Committing block #{}", block_number);
+
+        check_block_scope(block_number, "committed")?;
+        self.flush().await?;
+
+        match self.take_block_tx(block_number).await {
+            Some(tx) => Ok(tx.commit().await?),
+            None => Err(StorageError::InvalidStatus(format!(
+                "block #{block_number} committed without being begun"
+            ))),
+        }
+    }
+
+    async fn rollback_block(&self, block_number: u64) -> Result<(), StorageError> {
+        trace!("Rolling back block #{}", block_number);
+
+        check_block_scope(block_number, "rolled back")?;
+        self.take_buffer().await;
+
+        match self.take_block_tx(block_number).await {
+            Some(tx) => Ok(tx.rollback().await?),
+            None => Err(StorageError::InvalidStatus(format!(
+                "block #{block_number} rolled back without being begun"
+            ))),
+        }
+    }
+
+    async fn set_block_info(
+        &self,
+        block_number: u64,
+        block_timestamp: u64,
+        info: BlockInfo,
+    ) -> Result<(), StorageError> {
+        trace!("Setting block info {:?} for block #{}", info, block_number);
+
+        // Rows of a terminated block are written before its status.
+        if info.status == BlockIndexingStatus::Terminated {
+            self.flush().await?;
+        }
+
+        let exists = sqlx::query("SELECT 1 FROM indexer WHERE indexer_identifier = $1")
+            .bind(info.indexer_identifier.clone())
+            .fetch_optional(&mut *self.connection().await?)
+            .await?
+            .is_some();
+
+        if !exists {
+            let q = "INSERT INTO indexer (indexer_identifier, indexer_version) VALUES ($1, $2)";
+            sqlx::query(q)
+                .bind(info.indexer_identifier.clone())
+                .bind(info.indexer_version.clone())
+                .execute(&mut *self.connection().await?)
+                .await?;
+        }
+
+        let _r = if (self.get_block_by_timestamp(block_timestamp).await?).is_some() {
+            let q = "UPDATE block SET block_number = $1, block_status = $2, indexer_version = $3, indexer_identifier = $4, block_hash = $5, parent_hash = $6 WHERE block_timestamp = $7";
+            sqlx::query(q)
+                .bind(block_number as i64)
+                .bind(info.status.to_string())
+                .bind(info.indexer_version.clone())
+                .bind(info.indexer_identifier.clone())
+                .bind(info.block_hash.clone())
+                .bind(info.parent_hash.clone())
+                .bind(block_timestamp as i64)
+                .execute(&mut *self.connection().await?)
+                .await?
+        } else {
+            let q = "INSERT INTO block (block_timestamp, block_number, block_status, indexer_version, indexer_identifier, block_hash, parent_hash) VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (block_number) DO NOTHING";
+
+            sqlx::query(q)
+                .bind(block_timestamp as i64)
+                .bind(block_number as i64)
+                .bind(info.status.to_string())
+                .bind(info.indexer_version.clone())
+                .bind(info.indexer_identifier.clone())
+                .bind(info.block_hash.clone())
+                .bind(info.parent_hash.clone())
+                .execute(&mut *self.connection().await?)
+                .await?
+        };
+
+        Ok(())
+    }
+
+    async fn get_block_info(&self, block_number: u64) -> Result<BlockInfo, StorageError> {
+        trace!("Getting block info for block #{}", block_number);
+
+        let q = "SELECT * FROM block WHERE block_number = $1";
+
+        match sqlx::query(q)
+            .bind(block_number as i64)
+            .fetch_all(&mut *self.connection().await?)
+            .await
+        {
+            Ok(rows) => {
+                if rows.is_empty() {
+                    Err(StorageError::NotFound(format!(
+                        "block number {block_number}"
+                    )))
+                } else {
+                    Ok(block_info(BlockData::from_row(&rows[0])?))
+                }
+            }
+            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+        }
+    }
+
+    async fn get_blocks_by_status(
+        &self,
+        indexer_identifier: &str,
+        status: BlockIndexingStatus,
+    ) -> Result<Vec<BlockInfo>, StorageError> {
+        trace!(
+            "Getting {} blocks of indexer {}",
+            status.to_string(),
+            indexer_identifier
+        );
+
+        let q = "SELECT * FROM block WHERE indexer_identifier = $1 AND block_status = $2 ORDER BY block_number";
+
+        let rows = sqlx::query(q)
+            .bind(indexer_identifier.to_string())
+            .bind(status.to_string())
+            .fetch_all(&mut *self.connection().await?)
+            .await?;
+
+        rows.iter()
+            .map(|r| Ok(block_info(BlockData::from_row(r)?)))
+            .collect()
+    }
+
+    /// Indexers without checkpoint, created before checkpoints were
+    /// recorded, are resumed from their last terminated block.
+    async fn get_checkpoint(&self, indexer_identifier: &str) -> Result<Option<u64>, StorageError> {
+        trace!("Getting checkpoint of indexer {}", indexer_identifier);
+
+        let q = "SELECT checkpoint FROM indexer WHERE indexer_identifier = $1";
+        let checkpoint: Option<i64> = match sqlx::query(q)
+            .bind(indexer_identifier.to_string())
+            .fetch_optional(&mut *self.connection().await?)
+            .await?
+        {
+            Some(row) => row.try_get(0)?,
+            None => None,
+        };
+
+        if let Some(checkpoint) = checkpoint {
+            return Ok(Some(checkpoint as u64));
+        }
+
+        let q = "SELECT MAX(block_number) FROM block WHERE indexer_identifier = $1 AND block_status = $2";
+        let last_terminated: Option<i64> = sqlx::query(q)
+            .bind(indexer_identifier.to_string())
+            .bind(BlockIndexingStatus::Terminated.to_string())
+            .fetch_one(&mut *self.connection().await?)
+            .await?
+            .try_get(0)?;
+
+        Ok(last_terminated.map(|n| n as u64))
+    }
+
+    /// The indexer is registered when its first block info is set.
+    async fn set_checkpoint(
+        &self,
+        indexer_identifier: &str,
+        block_number: u64,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Setting checkpoint of indexer {} to block #{}",
+            indexer_identifier,
+            block_number
+        );
+
+        let q = "UPDATE indexer SET checkpoint = $1 WHERE indexer_identifier = $2";
+        sqlx::query(q)
+            .bind(block_number as i64)
+            .bind(indexer_identifier.to_string())
+            .execute(&mut *self.connection().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clean_block(
+        &self,
+        block_timestamp: u64,
+        block_number: Option<u64>,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Cleaning block #{:?} [ts: {}]",
+            block_number,
+            block_timestamp.to_string()
+        );
+
+        // Buffered rows of the block are written to be cleaned too.
+        self.flush().await?;
+        let q = "DELETE FROM block WHERE block_timestamp = $1";
+        sqlx::query(q)
+            .bind(block_timestamp as i64)
+            .fetch_all(&mut *self.connection().await?)
+            .await?;
+
+        // The balance changes of the transfers cleaned are reverted.
+        let q = format!(
+            "SELECT {} FROM token_event WHERE block_timestamp = $1",
+            token_event_columns::<DB>()
+        );
+        let rows = sqlx::query(&q)
+            .bind(block_timestamp as i64)
+            .fetch_all(&mut *self.connection().await?)
+            .await?;
+
+        let mut changes = BalanceChanges::default();
+        let mut tokens = BTreeMap::new();
+        for row in &rows {
+            if let TokenEvent::Transfer(event) = token_event(TokenEventData::from_row(row)?) {
+                changes.add(&event, true)?;
+                tokens.insert(
+                    (event.contract_address, event.token_id_hex),
+                    event.contract_type == ContractType::ERC721.to_string(),
+                );
+            }
+        }
+        self.apply_balance_changes(&changes).await?;
+
+        let q = "DELETE FROM token_event WHERE block_timestamp = $1";
+        sqlx::query(q)
+            .bind(block_timestamp as i64)
+            .fetch_all(&mut *self.connection().await?)
+            .await?;
+
+        self.restore_tokens(block_timestamp, &tokens).await
+    }
+
+    async fn get_tokens_by_owner(&self, owner: &str) -> Result<Vec<TokenBalance>, StorageError> {
+        trace!("Getting tokens of owner {}", owner);
+
+        let q = format!("SELECT {} FROM token_balance WHERE owner = $1 AND balance > 0 ORDER BY contract_address, token_id_hex", token_balance_columns::<DB>());
+
+        let rows = sqlx::query(&q)
+            .bind(owner.to_string())
+            .fetch_all(&mut *self.connection().await?)
+            .await?;
+
+        rows.iter()
+            .map(|row| -> Result<_, StorageError> {
+                Ok(token_balance(TokenBalanceData::from_row(row)?))
+            })
+            .collect()
+    }
+
+    async fn get_token_history(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        after: Option<EventCursor>,
+        limit: u64,
+    ) -> Result<EventPage, StorageError> {
+        trace!(
+            "Getting history of token {} {} after {:?}",
+            contract_address,
+            token_id_hex,
+            after
+        );
+
+        // One more event is read to know if there is a next page.
+        let q = format!("SELECT {} FROM token_event WHERE contract_address = $1 AND token_id_hex = $2 AND (block_number > $3 OR (block_number = $3 AND event_id > $4)) ORDER BY block_number, event_id LIMIT $5", token_event_columns::<DB>());
+
+        let (after_block, after_event) = after
+            .map(|c| (c.block_number as i64, c.event_id))
+            .unwrap_or((-1, String::new()));
+
+        let rows = sqlx::query(&q)
+            .bind(contract_address.to_string())
+            .bind(token_id_hex.to_string())
+            .bind(after_block)
+            .bind(after_event)
+            .bind(limit as i64 + 1)
+            .fetch_all(&mut *self.connection().await?)
+            .await?;
+
+        let mut events = rows
+            .iter()
+            .map(TokenEventData::from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let next = if events.len() as u64 > limit {
+            events.truncate(limit as usize);
+            events.last().map(|e| EventCursor {
+                block_number: e.block_number.unwrap_or_default() as u64,
+                event_id: e.event_id.clone(),
+            })
+        } else {
+            None
+        };
+
+        Ok(EventPage {
+            events: events.into_iter().map(token_event).collect(),
+            next,
+        })
+    }
+
+    async fn get_collection_stats(
+        &self,
+        contract_address: &str,
+    ) -> Result<CollectionStats, StorageError> {
+        trace!("Getting stats of collection {}", contract_address);
+
+        let q = "SELECT
+            (SELECT COUNT(*) FROM token WHERE contract_address = $1) AS token_count,
+            (SELECT COUNT(DISTINCT owner) FROM token_balance WHERE contract_address = $1 AND balance > 0) AS owner_count,
+            (SELECT COUNT(*) FROM token_event WHERE contract_address = $1 AND event_type <> $2) AS transfer_count,
+            (SELECT COUNT(*) FROM token_event WHERE contract_address = $1 AND event_type = $2) AS sale_count";
+
+        let row = sqlx::query(q)
+            .bind(contract_address.to_string())
+            .bind(EventType::Sale.to_string())
+            .fetch_one(&mut *self.connection().await?)
+            .await?;
+
+        let d = CollectionStatsData::from_row(&row)?;
+
+        Ok(CollectionStats {
+            contract_address: contract_address.to_string(),
+            token_count: d.token_count as u64,
+            owner_count: d.owner_count as u64,
+            transfer_count: d.transfer_count as u64,
+            sale_count: d.sale_count as u64,
+        })
+    }
+
+    async fn get_events_by_block_range(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<TokenEvent>, StorageError> {
+        trace!("Getting events of blocks #{} to #{}", from_block, to_block);
+
+        let q = format!("SELECT {} FROM token_event WHERE block_number >= $1 AND block_number <= $2 ORDER BY block_number, event_id", token_event_columns::<DB>());
+
+        let rows = sqlx::query(&q)
+            .bind(from_block as i64)
+            .bind(to_block as i64)
+            .fetch_all(&mut *self.connection().await?)
+            .await?;
+
+        rows.iter()
+            .map(|row| -> Result<_, StorageError> {
+                Ok(token_event(TokenEventData::from_row(row)?))
+            })
+            .collect()
+    }
+}
+
+/// Checks that the unit of work of the block is `action`
+/// in the scope of the block, see [`Storage::begin_block`].
+fn check_block_scope(block_number: u64, action: &str) -> Result<(), StorageError> {
+    match current_block() {
+        Some(current) if current == block_number => Ok(()),
+        _ => Err(StorageError::InvalidStatus(format!(
+            "block #{block_number} {action} out of its scope"
+        ))),
+    }
+}
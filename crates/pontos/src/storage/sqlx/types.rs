@@ -1,6 +1,12 @@
 //! Those types are decoupling the actual pontos
 //! storage types and the data annotations required
 //! for sqlx code generation.
+use std::str::FromStr;
+
+use crate::storage::types::{
+    BlockIndexingStatus, BlockInfo, EventType, TokenBalance, TokenEvent, TokenSaleEvent,
+    TokenTransferEvent,
+};
 
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct TokenData {
@@ -49,6 +55,8 @@ pub struct BlockData {
     pub status: String,
     pub indexer_version: String,
     pub indexer_identifier: String,
+    pub block_hash: Option<String>,
+    pub parent_hash: Option<String>,
 }
 
 #[derive(Debug, Clone, sqlx::FromRow)]
@@ -57,3 +65,117 @@ pub struct ContractData {
     pub contract_address: String,
     pub contract_type: String,
 }
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct TokenEventData {
+    pub block_timestamp: i64,
+    pub block_number: Option<i64>,
+    pub chain_id: Option<String>,
+    pub contract_address: String,
+    pub from_address: String,
+    pub to_address: String,
+    pub transaction_hash: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub contract_type: String,
+    pub event_type: String,
+    pub event_id: String,
+    pub quantity: String,
+    pub marketplace_contract_address: Option<String>,
+    pub marketplace_name: Option<String>,
+    pub currency_address: Option<String>,
+    pub price: Option<String>,
+    pub currency_symbol: Option<String>,
+    pub currency_decimals: Option<i64>,
+    pub price_decimal: Option<String>,
+    pub reference_price: Option<String>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct TokenBalanceData {
+    pub contract_address: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub chain_id: String,
+    pub owner: String,
+    pub balance: String,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CollectionStatsData {
+    pub token_count: i64,
+    pub owner_count: i64,
+    pub transfer_count: i64,
+    pub sale_count: i64,
+}
+
+pub fn token_balance(d: TokenBalanceData) -> TokenBalance {
+    TokenBalance {
+        contract_address: d.contract_address,
+        token_id: d.token_id,
+        token_id_hex: d.token_id_hex,
+        chain_id: d.chain_id,
+        owner: d.owner,
+        balance: d.balance,
+    }
+}
+
+pub fn token_event(d: TokenEventData) -> TokenEvent {
+    let event_type = EventType::from_str(&d.event_type).unwrap_or(EventType::Uninitialized);
+    let block_number = d.block_number.map(|n| n as u64);
+
+    if event_type == EventType::Sale {
+        return TokenEvent::Sale(TokenSaleEvent {
+            timestamp: d.block_timestamp as u64,
+            from_address: d.from_address,
+            to_address: d.to_address,
+            nft_contract_address: d.contract_address,
+            nft_type: Some(d.contract_type).filter(|t| !t.is_empty()),
+            marketplace_contract_address: d.marketplace_contract_address.unwrap_or_default(),
+            marketplace_name: d.marketplace_name.unwrap_or_default(),
+            transaction_hash: d.transaction_hash,
+            token_id: d.token_id,
+            token_id_hex: d.token_id_hex,
+            event_type,
+            event_id: d.event_id,
+            block_number,
+            updated_at: None,
+            quantity: d.quantity.parse().unwrap_or(1),
+            currency_address: d.currency_address,
+            price: d.price.unwrap_or_default(),
+            currency_symbol: d.currency_symbol,
+            currency_decimals: d.currency_decimals.and_then(|d| u8::try_from(d).ok()),
+            price_decimal: d.price_decimal,
+            reference_price: d.reference_price,
+        });
+    }
+
+    TokenEvent::Transfer(TokenTransferEvent {
+        timestamp: d.block_timestamp as u64,
+        from_address: d.from_address,
+        to_address: d.to_address,
+        contract_address: d.contract_address,
+        chain_id: d.chain_id.unwrap_or_default(),
+        contract_type: d.contract_type,
+        transaction_hash: d.transaction_hash,
+        token_id: d.token_id,
+        token_id_hex: d.token_id_hex,
+        event_type,
+        event_id: d.event_id,
+        block_number,
+        updated_at: None,
+        quantity: d.quantity,
+    })
+}
+
+pub fn block_info(d: BlockData) -> BlockInfo {
+    BlockInfo {
+        indexer_version: d.indexer_version,
+        indexer_identifier: d.indexer_identifier,
+        status: BlockIndexingStatus::from_str(&d.status).unwrap(),
+        block_number: d.number as u64,
+        block_timestamp: d.timestamp as u64,
+        block_hash: d.block_hash,
+        parent_hash: d.parent_hash,
+    }
+}
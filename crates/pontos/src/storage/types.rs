@@ -11,6 +11,8 @@ pub enum StorageError {
     DuplicateToken(String),
     InvalidMintData(String),
     AlreadyExists(String),
+    /// The database schema is older than the one expected.
+    OutdatedSchema(String),
 }
 
 impl fmt::Display for StorageError {
@@ -23,6 +25,9 @@ impl fmt::Display for StorageError {
             StorageError::DuplicateToken(s) => write!(f, "Token already exists in storage: {s}"),
             StorageError::InvalidMintData(s) => write!(f, "Provided mint data is invalid: {s}"),
             StorageError::AlreadyExists(s) => write!(f, "Item already exists in storage: {s}"),
+            StorageError::OutdatedSchema(s) => {
+                write!(f, "Database schema is outdated, run the migrations: {s}")
+            }
         }
     }
 }
@@ -84,6 +89,7 @@ impl Serialize for TokenEvent {
                 map.insert("contract_type", event.contract_type.clone());
                 map.insert("event_type", "transfer".to_string());
                 map.insert("event_id", event.event_id.clone());
+                map.insert("quantity", event.quantity.clone());
                 map.insert(
                     "block_number",
                     event
@@ -116,6 +122,23 @@ impl Serialize for TokenEvent {
                 }
 
                 map.insert("price", event.price.clone());
+
+                if let Some(currency_symbol) = event.currency_symbol.clone() {
+                    map.insert("currency_symbol", currency_symbol);
+                }
+
+                if let Some(currency_decimals) = event.currency_decimals {
+                    map.insert("currency_decimals", currency_decimals.to_string());
+                }
+
+                if let Some(price_decimal) = event.price_decimal.clone() {
+                    map.insert("price_decimal", price_decimal);
+                }
+
+                if let Some(reference_price) = event.reference_price.clone() {
+                    map.insert("reference_price", reference_price);
+                }
+
                 map.insert(
                     "block_number",
                     event
@@ -152,6 +175,9 @@ pub struct TokenTransferEvent {
     pub event_id: String,
     pub block_number: Option<u64>,
     pub updated_at: Option<u64>,
+    /// Number of tokens transferred, a u256 as a decimal
+    /// string, always 1 for ERC721.
+    pub quantity: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -172,7 +198,14 @@ pub struct TokenSaleEvent {
     pub updated_at: Option<u64>,
     pub quantity: u64,
     pub currency_address: Option<String>,
+    /// Raw price, in the smallest unit of the currency.
     pub price: String,
+    pub currency_symbol: Option<String>,
+    pub currency_decimals: Option<u8>,
+    /// Price normalised with the currency decimals.
+    pub price_decimal: Option<String>,
+    /// Price converted to the reference currency of the price oracle.
+    pub reference_price: Option<String>,
 }
 
 impl Default for TokenTransferEvent {
@@ -191,6 +224,7 @@ impl Default for TokenTransferEvent {
             block_number: None,
             updated_at: None,
             chain_id: "0x534e5f4d41494e".to_string(),
+            quantity: "1".to_string(),
         }
     }
 }
@@ -204,6 +238,47 @@ pub struct TokenInfo {
     pub owner: String,
 }
 
+/// Number of tokens of a given id owned by an address.
+/// ERC721 tokens have a balance of 1 for their owner.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub contract_address: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub chain_id: String,
+    pub owner: String,
+    /// A u256 as a decimal string.
+    pub balance: String,
+}
+
+/// Position of an event in the events ordered by block
+/// then by event id, see [`EventPage`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventCursor {
+    pub block_number: u64,
+    pub event_id: String,
+}
+
+/// Page of token events, ordered by block then by event id.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct EventPage {
+    pub events: Vec<TokenEvent>,
+    /// Cursor of the next page, `None` on the last page.
+    pub next: Option<EventCursor>,
+}
+
+/// Statistics of the tokens and events of a collection.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CollectionStats {
+    pub contract_address: String,
+    pub token_count: u64,
+    /// Addresses owning at least one token of the collection.
+    pub owner_count: u64,
+    /// Transfers, including the mints and the burns.
+    pub transfer_count: u64,
+    pub sale_count: u64,
+}
+
 #[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct TokenMintInfo {
     pub address: String,
@@ -280,9 +355,15 @@ pub struct BlockInfo {
     pub indexer_identifier: String,
     pub status: BlockIndexingStatus,
     pub block_number: u64,
+    pub block_timestamp: u64,
+    /// Hash of the block when it was indexed, used to detect
+    /// chain reorganizations. `None` for blocks indexed before
+    /// hashes were tracked.
+    pub block_hash: Option<String>,
+    pub parent_hash: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum ContractType {
     Other,
@@ -321,6 +402,16 @@ pub struct ContractInfo {
     pub name: Option<String>,
     pub symbol: Option<String>,
     pub image: Option<String>,
+    /// URI of the collection metadata, from `contract_uri` or `contractURI`.
+    pub contract_uri: Option<String>,
+    /// Total supply in decimal, for contracts exposing `total_supply`.
+    pub total_supply: Option<String>,
+    /// SRC5 interface IDs supported by the contract.
+    pub interfaces: Vec<String>,
+    /// Account deploying the contract, known for the contracts
+    /// deployed through the UDC in the indexed blocks.
+    pub deployer: Option<String>,
+    pub class_hash: Option<String>,
 }
 
 #[cfg(test)]
@@ -344,6 +435,7 @@ mod tests {
             block_number: Some(123),
             updated_at: Some(1625101200),
             chain_id: "0x534e5f4d41494e".to_string(),
+            quantity: "1".to_string(),
         });
 
         let serialized = serde_json::to_string(&event).expect("Failed to serialize TokenEvent");
@@ -359,7 +451,8 @@ mod tests {
             "token_id": "123",
             "token_id_hex": "0x123",
             "contract_type": "ERC721",
-            "event_id": "evt123"
+            "event_id": "evt123",
+            "quantity": "1"
         });
 
         let expected = expected_json.to_string();
@@ -0,0 +1,6 @@
+//! Selection of the contracts indexed by Sana, filtering
+//! on the contract types of its storage.
+use crate::storage::types::ContractType;
+
+pub type ContractFilter = ark_starknet::filter::ContractFilter<ContractType>;
+pub type ContractFilterHandle = ark_starknet::filter::ContractFilterHandle<ContractType>;
@@ -1,14 +1,20 @@
 pub mod event_handler;
+pub mod filter;
 pub mod managers;
 pub mod storage;
 
 use crate::storage::types::BlockIndexingStatus;
 use anyhow::Result;
+use ark_starknet::client::retry::RetryPolicy;
+use ark_starknet::client::stream::{events_pages, DEFAULT_CHUNK_SIZE};
 use ark_starknet::client::{StarknetClient, StarknetClientError};
 use ark_starknet::format::to_hex_str;
 use event_handler::EventHandler;
+use filter::{ContractFilter, ContractFilterHandle};
+use futures::TryStreamExt;
 use managers::{BlockManager, ContractManager, EventManager, PendingBlockData, TokenManager};
 use starknet::core::types::*;
+use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
 use storage::types::{ContractType, StorageError};
@@ -18,6 +24,10 @@ use tracing::{debug, error, info, trace, warn};
 
 pub type IndexerResult<T> = Result<T, IndexerError>;
 
+/// Number of block timestamps requested in one batch
+/// while walking a block range.
+const BLOCK_TIMES_BATCH_SIZE: u64 = 100;
+
 const ELEMENT_MARKETPLACE_EVENT_HEX: &str =
     "0x351e5a57ea6ca22e3e3cd212680ef7f3b57404609bda942a5e75ba4724b55e0";
 
@@ -65,6 +75,12 @@ impl std::error::Error for IndexerError {}
 pub struct SanaConfig {
     pub indexer_version: String,
     pub indexer_identifier: String,
+    /// Contracts indexed, see [`Sana::contract_filter`]
+    /// to change them while indexing.
+    pub contract_filter: ContractFilter,
+    /// Backoff between the attempts to index the blocks failing
+    /// in [`Sana::index_new_heads`], giving up after `max_attempts`.
+    pub retry_policy: RetryPolicy,
 }
 
 pub struct Sana<S: Storage, C: StarknetClient, E: EventHandler> {
@@ -76,12 +92,13 @@ pub struct Sana<S: Storage, C: StarknetClient, E: EventHandler> {
     token_manager: Arc<TokenManager<S, C>>,
     contract_manager: Arc<AsyncRwLock<ContractManager<S, C>>>,
     pending_cache: Arc<AsyncRwLock<PendingBlockData>>,
+    contract_filter: ContractFilterHandle,
 }
 
 impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E> {
-    ///
     pub fn new(client: Arc<C>, storage: Arc<S>, event_handler: Arc<E>, config: SanaConfig) -> Self {
         Sana {
+            contract_filter: ContractFilterHandle::new(config.contract_filter.clone()),
             config,
             client: Arc::clone(&client),
             event_handler: Arc::clone(&event_handler),
@@ -99,6 +116,11 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         }
     }
 
+    /// Returns a handle to change the contracts indexed while indexing.
+    pub fn contract_filter(&self) -> ContractFilterHandle {
+        self.contract_filter.clone()
+    }
+
     /// Starts a loop to only index the pending block.
     pub async fn index_pending(&self) -> IndexerResult<()> {
         loop {
@@ -159,6 +181,208 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         }
     }
 
+    /// Fetches the timestamps of the given blocks in a single batch.
+    /// Blocks with unavailable timestamp are missing from the returned map.
+    async fn fetch_block_times(
+        &self,
+        block_numbers: impl IntoIterator<Item = u64>,
+    ) -> HashMap<u64, u64> {
+        let block_numbers: Vec<u64> = block_numbers.into_iter().collect();
+        let block_ids: Vec<BlockId> = block_numbers.iter().map(|n| BlockId::Number(*n)).collect();
+
+        match self.client.block_times(&block_ids).await {
+            Ok(times) => block_numbers
+                .into_iter()
+                .zip(times)
+                .filter_map(|(block_number, ts)| match ts {
+                    Ok(ts) => Some((block_number, ts)),
+                    Err(e) => {
+                        error!("Couldn't get timestamp for block {}: {:?}", block_number, e);
+                        None
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                error!("Error while fetching block timestamps: {:?}", e);
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Indexes the blocks as soon as they are pushed by the client
+    /// new heads subscription, instead of polling like [`Self::index_pending`].
+    ///
+    /// Blocks missed between two heads (while the node was reconnecting
+    /// for instance) are indexed first. Blocks failing with a Starknet error
+    /// are retried with the [`SanaConfig::retry_policy`]. Returns once the
+    /// subscription is closed, or an error if the client doesn't support
+    /// subscriptions, letting the caller subscribe again or fall back to polling.
+    pub async fn index_new_heads(&self, chain_id: &str) -> IndexerResult<()> {
+        let mut heads = self.client.subscribe_new_heads().await?;
+        let mut last_indexed: Option<u64> = None;
+
+        while let Some(head) = heads.try_next().await? {
+            debug!("New head #{}", head.block_number);
+            self.event_handler
+                .on_new_latest_block(head.block_number)
+                .await;
+
+            if let Some(last) = last_indexed {
+                if head.block_number > last + 1 {
+                    self.index_block_range(
+                        BlockId::Number(last + 1),
+                        BlockId::Number(head.block_number - 1),
+                        false,
+                        chain_id,
+                    )
+                    .await?;
+                }
+            }
+
+            let mut attempt = 0;
+            loop {
+                match self
+                    .index_block(head.block_number, head.timestamp, false, None, chain_id)
+                    .await
+                {
+                    Ok(_) => break,
+                    Err(IndexerError::Starknet(e)) => {
+                        attempt += 1;
+                        if attempt >= self.config.retry_policy.max_attempts {
+                            return Err(IndexerError::Starknet(e));
+                        }
+
+                        let delay = self.config.retry_policy.backoff(attempt);
+                        error!(
+                            "Error while indexing block {}, retrying in {:?}: {:?}",
+                            head.block_number, delay, e
+                        );
+                        tokio::time::sleep(delay).await;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            self.event_handler
+                .on_block_processed(
+                    head.block_number,
+                    100.0,
+                    false,
+                    head.block_number,
+                    head.block_number,
+                )
+                .await;
+
+            last_indexed = Some(head.block_number);
+        }
+
+        info!("New heads subscription closed");
+        Ok(())
+    }
+
+    /// Indexes the blocks from the events pushed by the client events
+    /// subscription, the events of each block being pushed once instead
+    /// of being requested like [`Self::index_new_heads`] does.
+    ///
+    /// The events of a block are complete once an event of a following
+    /// block is pushed: the block is then indexed, with the blocks in
+    /// between which have no event indexed. Returns once the subscription
+    /// is closed, the block of the last events pushed being left to
+    /// [`Self::index_block_range`], or an error if the client doesn't
+    /// support subscriptions.
+    pub async fn index_new_events(&self, chain_id: &str) -> IndexerResult<()> {
+        // Like for the events requested, the events are only filtered on their keys.
+        let mut pushed = self
+            .client
+            .subscribe_events(None, self.event_manager.keys_selector())
+            .await?;
+
+        // Block whose events are being pushed, with its events so far.
+        let mut current: Option<(u64, Vec<EmittedEvent>)> = None;
+
+        while let Some(event) = pushed.try_next().await? {
+            let block_number = match event.block_number {
+                Some(block_number) => block_number,
+                None => {
+                    trace!("Skipping event of the pending block");
+                    continue;
+                }
+            };
+
+            match current.as_mut() {
+                Some((n, events)) if *n == block_number => {
+                    events.push(event);
+                    continue;
+                }
+                Some((n, _)) if *n > block_number => {
+                    // Blocks are pushed again after a reorganization,
+                    // the block being pushed was orphaned.
+                    warn!(
+                        "Events of block {} pushed after block {}, dropping block {}",
+                        block_number, n, n
+                    );
+                }
+                _ => {}
+            }
+
+            if let Some((n, events)) = current.take().filter(|(n, _)| *n < block_number) {
+                self.event_handler.on_new_latest_block(block_number).await;
+                self.index_pushed_blocks(n, events, block_number - 1, chain_id)
+                    .await?;
+            }
+
+            current = Some((block_number, vec![event]));
+        }
+
+        info!("Events subscription closed");
+        Ok(())
+    }
+
+    /// Indexes the block `block_number` with the `events` pushed by the
+    /// subscription, then the following blocks up to `to_block`, which
+    /// have no event indexed.
+    async fn index_pushed_blocks(
+        &self,
+        block_number: u64,
+        events: Vec<EmittedEvent>,
+        to_block: u64,
+        chain_id: &str,
+    ) -> IndexerResult<()> {
+        let mut events = Some(events);
+
+        for window_start in (block_number..=to_block).step_by(BLOCK_TIMES_BATCH_SIZE as usize) {
+            let window = window_start..=to_block.min(window_start + BLOCK_TIMES_BATCH_SIZE - 1);
+            let block_times = self.fetch_block_times(window.clone()).await;
+
+            for n in window {
+                let block_ts = match block_times.get(&n) {
+                    Some(ts) => *ts,
+                    None => {
+                        warn!("Skipping block {} as timestamp is not available", n);
+                        continue;
+                    }
+                };
+
+                let block_events = if n == block_number {
+                    events.take().unwrap_or_default()
+                } else {
+                    vec![]
+                };
+
+                if self
+                    .index_block(n, block_ts, false, Some(block_events), chain_id)
+                    .await?
+                {
+                    self.event_handler
+                        .on_block_processed(n, 100.0, false, n, n)
+                        .await;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// If "Latest" is used for the `to_block`,
     /// this function will only index the latest block
     /// that is not pending.
@@ -176,15 +400,8 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         let to_u64 = self.client.block_id_to_u64(&to_block).await?;
         let from_u64 = current_u64;
 
-        // Some contracts are causing too much recursion for the Cairo VM.
-        // This is restarting the full node (Juno) as it is OOM and is shutdown by the OS.
-        // To mitigate this problem before scaling the full node up,
-        // we setup a `max_attempt` to reach the full node before skipping
-        // the entire block.
-        // Currently, we observed that the node almost always reponds after the
-        // second attempt.
-        let max_attempt = 5;
-        let mut attempt = 0;
+        // Timestamps are prefetched in batches of blocks.
+        let mut block_times: HashMap<u64, u64> = HashMap::new();
 
         loop {
             trace!("Indexing block range: {} {}", current_u64, to_u64);
@@ -194,96 +411,42 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
                 break;
             }
 
-            let block_ts = match self.client.block_time(BlockId::Number(current_u64)).await {
-                Ok(ts) => ts,
-                Err(e) => {
-                    error!(
-                        "Attempt #{} - Couldn't get timestamp for block {}: {:?}",
-                        attempt + 1,
-                        current_u64,
-                        e
-                    );
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                    attempt += 1;
-
-                    if attempt > max_attempt {
-                        warn!(
-                            "Skipping block {} as timestamp is not available",
-                            current_u64
-                        );
-                        current_u64 += 1;
-                    }
+            if !block_times.contains_key(&current_u64) {
+                let window_end = to_u64.min(current_u64 + BLOCK_TIMES_BATCH_SIZE - 1);
+                block_times = self.fetch_block_times(current_u64..=window_end).await;
+            }
 
+            // Transient errors (full node restarting, rate limit...) are already
+            // retried by the client with its retry policy. If the timestamp is still
+            // not available, the entire block is skipped.
+            let block_ts = match block_times.get(&current_u64) {
+                Some(ts) => *ts,
+                None => {
+                    warn!(
+                        "Skipping block {} as timestamp is not available",
+                        current_u64
+                    );
+                    current_u64 += 1;
                     continue;
                 }
             };
 
-            if self
-                .block_manager
-                .should_skip_indexing(
-                    current_u64,
-                    block_ts,
-                    self.config.indexer_version.clone(),
-                    force_mode,
-                )
-                .await?
-            {
-                info!("Skipping block {}", current_u64);
-                current_u64 += 1;
-                continue;
-            }
-
-            self.event_handler
-                .on_block_processing(block_ts, Some(current_u64))
-                .await;
-
-            // Set block as processing.
-            self.block_manager
-                .set_block_info(
-                    current_u64,
-                    block_ts,
-                    self.config.indexer_version.clone(),
-                    self.config.indexer_identifier.clone(),
-                    BlockIndexingStatus::Processing,
-                )
-                .await?;
-
-            let blocks_events = match self
-                .client
-                .fetch_all_block_events(
-                    BlockId::Number(current_u64),
-                    self.event_manager.keys_selector(),
-                )
+            match self
+                .index_block(current_u64, block_ts, force_mode, None, chain_id)
                 .await
             {
-                Ok(events) => events,
-                Err(e) => {
-                    error!("Error while fetching events: {:?}", e);
+                Ok(true) => {}
+                Ok(false) => {
+                    current_u64 += 1;
+                    continue;
+                }
+                Err(IndexerError::Starknet(_)) => {
                     tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
                     continue;
                 }
-            };
-
-            let total_events_count: usize = blocks_events.values().map(|events| events.len()).sum();
-            info!(
-                "✨ Processing block {}. Total Events Count: {}.",
-                current_u64, total_events_count
-            );
-
-            for (_, events) in blocks_events {
-                self.process_events(events, block_ts, chain_id).await?;
+                Err(e) => return Err(e),
             }
 
-            self.block_manager
-                .set_block_info(
-                    current_u64,
-                    block_ts,
-                    self.config.indexer_version.clone(),
-                    self.config.indexer_identifier.clone(),
-                    BlockIndexingStatus::Terminated,
-                )
-                .await?;
-
             let progress = if to_u64 == from_u64 {
                 if current_u64 == to_u64 {
                     100.0
@@ -306,27 +469,145 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         Ok(())
     }
 
-    pub async fn index_pending_block(&self, timestamp: u64, chain_id: &str) -> IndexerResult<()> {
-        let blocks_events = match self
-            .client
-            .fetch_all_block_events_for_pending_block(timestamp, self.event_manager.keys_selector())
-            .await
+    /// Indexes one block, returning false if the block is skipped
+    /// as already indexed. The events of the block are fetched, unless
+    /// `events` pushed by a subscription are given.
+    ///
+    /// On Starknet error, the events of the block that may already be
+    /// processed are cleaned before returning the error, for the block
+    /// to be fully indexed again on the next attempt.
+    async fn index_block(
+        &self,
+        block_number: u64,
+        block_ts: u64,
+        do_force: bool,
+        events: Option<Vec<EmittedEvent>>,
+        chain_id: &str,
+    ) -> IndexerResult<bool> {
+        if self
+            .block_manager
+            .should_skip_indexing(
+                block_number,
+                block_ts,
+                self.config.indexer_version.clone(),
+                do_force,
+            )
+            .await?
         {
-            Ok(events) => events,
-            Err(e) => {
+            info!("Skipping block {}", block_number);
+            return Ok(false);
+        }
+
+        self.event_handler
+            .on_block_processing(block_ts, Some(block_number))
+            .await;
+
+        // Set block as processing.
+        self.block_manager
+            .set_block_info(
+                block_number,
+                block_ts,
+                self.config.indexer_version.clone(),
+                self.config.indexer_identifier.clone(),
+                BlockIndexingStatus::Processing,
+            )
+            .await?;
+
+        info!("✨ Processing block {}.", block_number);
+
+        let processed = match events {
+            Some(events) => {
+                let total_events_count = events.len();
+                self.process_events(events, block_ts, chain_id)
+                    .await
+                    .map(|_| total_events_count)
+            }
+            None => {
+                self.process_block_events(BlockId::Number(block_number), block_ts, chain_id)
+                    .await
+            }
+        };
+
+        match processed {
+            Ok(total_events_count) => {
+                info!(
+                    "Block {} processed. Total Events Count: {}.",
+                    block_number, total_events_count
+                );
+            }
+            Err(IndexerError::Starknet(e)) => {
+                // Some pages may already be processed, the block is cleaned
+                // to be fully indexed again on the next attempt.
                 error!("Error while fetching events: {:?}", e);
-                return Err(e.into());
+                self.block_manager
+                    .clean_block(block_ts, Some(block_number))
+                    .await?;
+                return Err(IndexerError::Starknet(e));
             }
+            Err(e) => return Err(e),
         };
 
-        let total_events_count: usize = blocks_events.values().map(|events| events.len()).sum();
+        self.block_manager
+            .set_block_info(
+                block_number,
+                block_ts,
+                self.config.indexer_version.clone(),
+                self.config.indexer_identifier.clone(),
+                BlockIndexingStatus::Terminated,
+            )
+            .await?;
+
+        Ok(true)
+    }
+
+    pub async fn index_pending_block(&self, timestamp: u64, chain_id: &str) -> IndexerResult<()> {
+        let total_events_count = self
+            .process_block_events(BlockId::Tag(BlockTag::Pending), timestamp, chain_id)
+            .await
+            .map_err(|e| {
+                error!("Error while fetching events: {:?}", e);
+                e
+            })?;
+
         trace!("Number of events: {:?}", total_events_count);
 
-        for (_, events) in blocks_events {
-            self.process_events(events, timestamp, chain_id).await?;
+        Ok(())
+    }
+
+    /// Streams the events of the given block page by page,
+    /// processing each page as soon as it is received.
+    /// Returns the number of events processed.
+    async fn process_block_events(
+        &self,
+        block_id: BlockId,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> IndexerResult<usize> {
+        // Marketplace events are always required, the RPC filter on one
+        // address can't be used while several marketplaces are indexed:
+        // the events are only filtered on their keys.
+        let filter = EventFilter {
+            from_block: Some(block_id),
+            to_block: Some(block_id),
+            address: None,
+            keys: self.event_manager.keys_selector(),
+        };
+
+        let mut pages = std::pin::pin!(events_pages(
+            self.client.as_ref(),
+            filter,
+            DEFAULT_CHUNK_SIZE
+        ));
+
+        let mut total_events_count = 0;
+
+        while let Some(page) = pages.try_next().await? {
+            total_events_count += page.events.len();
+            self.process_events(page.events, block_timestamp, chain_id)
+                .await?;
         }
 
-        Ok(())
+        Ok(total_events_count)
     }
 
     async fn process_element_sale(
@@ -349,6 +630,14 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
             e
         })?;
 
+        if !self.contract_filter.is_address_allowed(&contract_addr) {
+            trace!(
+                "Contract filtered out: {}",
+                token_sale_event.nft_contract_address
+            );
+            return Ok(());
+        }
+
         let contract_type = match self
             .contract_manager
             .write()
@@ -374,6 +663,14 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
             return Ok(());
         }
 
+        if !self.contract_filter.is_type_allowed(&contract_type) {
+            trace!(
+                "Contract type filtered out: {}",
+                token_sale_event.nft_contract_address
+            );
+            return Ok(());
+        }
+
         token_sale_event.nft_type = Some(contract_type.to_string());
         self.event_manager
             .register_sale_event(&token_sale_event, block_timestamp)
@@ -403,6 +700,14 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
             e
         })?;
 
+        if !self.contract_filter.is_address_allowed(&contract_addr) {
+            trace!(
+                "Contract filtered out: {}",
+                token_sale_event.nft_contract_address
+            );
+            return Ok(());
+        }
+
         let contract_type = match self
             .contract_manager
             .write()
@@ -428,6 +733,14 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
             return Ok(());
         }
 
+        if !self.contract_filter.is_type_allowed(&contract_type) {
+            trace!(
+                "Contract type filtered out: {}",
+                token_sale_event.nft_contract_address
+            );
+            return Ok(());
+        }
+
         token_sale_event.nft_type = Some(contract_type.to_string());
         self.event_manager
             .register_sale_event(&token_sale_event, block_timestamp)
@@ -474,6 +787,12 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         chain_id: &str,
     ) -> Result<()> {
         let contract_address_hex = to_hex_str(&contract_address);
+
+        if !self.contract_filter.is_address_allowed(&contract_address) {
+            trace!("Contract filtered out: {}", contract_address_hex);
+            return Ok(());
+        }
+
         let contract_type = self
             .contract_manager
             .write()
@@ -493,6 +812,11 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
             return Ok(());
         }
 
+        if !self.contract_filter.is_type_allowed(&contract_type) {
+            trace!("Contract type filtered out: {}", contract_address_hex);
+            return Ok(());
+        }
+
         info!(
             "Processing event... Block Id: {:?}, Tx Hash: 0x{:064x}, contract_type: {:?}",
             event.block_number, event.transaction_hash, contract_type
@@ -533,16 +857,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         block_timestamp: u64,
         chain_id: &str,
     ) -> IndexerResult<()> {
-        let marketplace_contracts = [
-            FieldElement::from_hex_be(
-                "0x04d8bb956e6bd7a50fcb8b49d8e9fd8269cfadbeb73f457fd6d3fc1dff4b879e", // Element Marketplace
-            )
-            .unwrap(),
-            FieldElement::from_hex_be(
-                "0x008755a98ccf7d25e69aa90ef3b73b07c470ba4ec6391b0b0c7c598f992c3fee", // Ventory Marketplace
-            )
-            .unwrap(),
-        ];
+        let marketplace_contracts = marketplace_contracts();
 
         for e in events {
             let contract_address = e.from_address;
@@ -568,3 +883,125 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         Ok(())
     }
 }
+
+fn marketplace_contracts() -> [FieldElement; 2] {
+    [
+        FieldElement::from_hex_be(
+            "0x04d8bb956e6bd7a50fcb8b49d8e9fd8269cfadbeb73f457fd6d3fc1dff4b879e", // Element Marketplace
+        )
+        .unwrap(),
+        FieldElement::from_hex_be(
+            "0x008755a98ccf7d25e69aa90ef3b73b07c470ba4ec6391b0b0c7c598f992c3fee", // Ventory Marketplace
+        )
+        .unwrap(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MockStorage;
+    use ark_starknet::client::{RecordingStarknetClient, ReplayStarknetClient, StarknetClientHttp};
+
+    /// Fixture shared with the Pontos and metadata tests: a block with the
+    /// mint of the token 42 of an ERC721 contract, and an ERC20 transfer.
+    /// See the `recording` module of `ark-starknet` to regenerate it.
+    const RECORDED_FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../ark-starknet/fixtures/erc721_mint_block.jsonl"
+    );
+    const RECORDED_BLOCK: u64 = 640_512;
+    const RECORDED_NFT: &str = "0x0727a63f78ee3f1bd18f78009067411ab369c31dece1ae22e16f567906409905";
+    const RECORDED_OWNER: &str =
+        "0x05ea7b0e9e4a8b3a7f1e7cbd1ab79be0c5e0a2b8d6c2f3a1e4b9d8c7a6f5e4d3";
+
+    struct NoopEventHandler;
+
+    impl EventHandler for NoopEventHandler {}
+
+    /// Storage of a block never indexed, expecting the mint of the recorded block.
+    fn recorded_block_storage() -> MockStorage {
+        let mut storage = MockStorage::default();
+        storage.expect_get_contract_type().returning(|address, _| {
+            Box::pin(futures::future::ready(Err(StorageError::NotFound(
+                address.to_string(),
+            ))))
+        });
+        storage
+            .expect_register_contract_info()
+            .times(2)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_set_block_info()
+            .withf(|_, info| info.block_number == RECORDED_BLOCK)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_register_token()
+            .withf(|token, _| {
+                token.contract_address == RECORDED_NFT
+                    && token.token_id == "42"
+                    && token.owner == RECORDED_OWNER
+            })
+            .times(1)
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(()))));
+        storage
+            .expect_register_transfer_event()
+            .withf(|event| {
+                event.contract_address == RECORDED_NFT
+                    && event.to_address == RECORDED_OWNER
+                    && event.block_number == Some(RECORDED_BLOCK)
+            })
+            .times(1)
+            .returning(|_| Box::pin(futures::future::ready(Ok(()))));
+        storage
+    }
+
+    /// Indexes the recorded block.
+    async fn index_recorded_block<C>(client: C, storage: MockStorage)
+    where
+        C: StarknetClient + Send + Sync + 'static,
+    {
+        let sana = Sana::new(
+            Arc::new(client),
+            Arc::new(storage),
+            Arc::new(NoopEventHandler),
+            SanaConfig {
+                indexer_version: String::from("v0.0.1"),
+                indexer_identifier: String::from("TASK#123"),
+                contract_filter: ContractFilter::default(),
+                retry_policy: RetryPolicy::default(),
+            },
+        );
+
+        sana.index_block_range(
+            BlockId::Number(RECORDED_BLOCK),
+            BlockId::Number(RECORDED_BLOCK),
+            false,
+            "0x534e5f4d41494e",
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Records the requests of the pipeline on a mainnet node.
+    #[tokio::test]
+    #[ignore = "requires a mainnet RPC node, see ARK_STARKNET_RECORD_RPC_URL"]
+    async fn test_record_block() {
+        let rpc_url = std::env::var("ARK_STARKNET_RECORD_RPC_URL")
+            .expect("ARK_STARKNET_RECORD_RPC_URL is the URL of a mainnet RPC node");
+        let client = RecordingStarknetClient::wrap(
+            StarknetClientHttp::new(&rpc_url).unwrap(),
+            std::env::temp_dir().join("erc721_mint_block.sana.jsonl"),
+        )
+        .unwrap();
+
+        index_recorded_block(client, recorded_block_storage()).await;
+    }
+
+    #[tokio::test]
+    async fn test_index_recorded_block() {
+        let client = ReplayStarknetClient::from_path(RECORDED_FIXTURE).unwrap();
+
+        index_recorded_block(client, recorded_block_storage()).await;
+    }
+}
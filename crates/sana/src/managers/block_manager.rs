@@ -32,7 +32,7 @@ impl<S: Storage> BlockManager<S> {
         &self,
         block_number: u64,
         block_timestamp: u64,
-        indexer_version: String,
+        _indexer_version: String,
         do_force: bool,
     ) -> Result<bool, StorageError> {
         if do_force {
@@ -181,7 +181,7 @@ mod tests {
             .await
             .unwrap();
 
-        assert!(result == false);
+        assert!(!result);
     }
 
     #[tokio::test]
@@ -223,13 +223,13 @@ mod tests {
             .should_skip_indexing(1, 0, "v0.0.2".to_string(), false)
             .await
             .unwrap();
-        assert!(result == false);
+        assert!(!result);
 
         // Force but same version, should return true for indexing.
         let result = manager
             .should_skip_indexing(2, 0, "v0.0.1".to_string(), true)
             .await
             .unwrap();
-        assert!(result == false);
+        assert!(!result);
     }
 }
@@ -2,14 +2,14 @@ use crate::storage::{
     types::{ContractInfo, ContractType, StorageError},
     Storage,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ark_starknet::{
     cairo_string_parser::parse_cairo_string,
     client::{StarknetClient, StarknetClientError},
     format::to_hex_str,
 };
 use starknet::core::{
-    types::{BlockId, BlockTag, FieldElement},
+    types::{BlockId, BlockTag, FieldElement, FunctionCall},
     utils::get_selector_from_name,
 };
 use std::collections::HashMap;
@@ -80,29 +80,33 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
                 }
 
                 // If the contract info is not cached, identify and cache it.
-                let contract_type = self.get_contract_type(address).await?;
-
-                self.cache.insert(address, contract_type.clone());
-
-                let name = self
-                    .get_contract_property_string(
+                // Type probes, name and symbol are requested in a single batch.
+                let [owner_of_camel, owner_of, balance_of_camel, balance_of, name, symbol] = self
+                    .call_entrypoints(
                         address,
-                        "name",
-                        vec![],
-                        BlockId::Tag(BlockTag::Pending),
+                        [
+                            ("ownerOf", erc721_probe_calldata()),
+                            ("owner_of", erc721_probe_calldata()),
+                            ("balanceOf", erc1155_probe_calldata()),
+                            ("balance_of", erc1155_probe_calldata()),
+                            ("name", vec![]),
+                            ("symbol", vec![]),
+                        ],
                     )
-                    .await
-                    .ok();
+                    .await?;
 
-                let symbol = self
-                    .get_contract_property_string(
-                        address,
-                        "symbol",
-                        vec![],
-                        BlockId::Tag(BlockTag::Pending),
-                    )
-                    .await
-                    .ok();
+                let contract_type = if is_erc721_from_probes(&owner_of_camel, &owner_of) {
+                    ContractType::ERC721
+                } else if is_erc1155_from_probes(&balance_of_camel, &balance_of) {
+                    ContractType::ERC1155
+                } else {
+                    ContractType::Other
+                };
+
+                self.cache.insert(address, contract_type.clone());
+
+                let name = name.and_then(parse_property_string).ok();
+                let symbol = symbol.and_then(parse_property_string).ok();
 
                 info!(
                     "Contract [0x{:064x}] details - Type: {}, Name: {:?}, Symbol: {:?}",
@@ -140,12 +144,24 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
     /// Verifies if the contract is an ERC721, ERC1155 or an other type.
     /// `owner_of` is specific to ERC721.
     /// `balance_of` is specific to ERC1155 and different from ERC20 as 2 arguments are expected.
+    ///
+    /// All the entrypoints are probed with a single batch of calls.
     pub async fn get_contract_type(&self, contract_address: FieldElement) -> Result<ContractType> {
-        let _block = BlockId::Tag(BlockTag::Pending);
+        let [owner_of_camel, owner_of, balance_of_camel, balance_of] = self
+            .call_entrypoints(
+                contract_address,
+                [
+                    ("ownerOf", erc721_probe_calldata()),
+                    ("owner_of", erc721_probe_calldata()),
+                    ("balanceOf", erc1155_probe_calldata()),
+                    ("balance_of", erc1155_probe_calldata()),
+                ],
+            )
+            .await?;
 
-        if self.is_erc721(contract_address).await? {
+        if is_erc721_from_probes(&owner_of_camel, &owner_of) {
             Ok(ContractType::ERC721)
-        } else if self.is_erc1155(contract_address).await? {
+        } else if is_erc1155_from_probes(&balance_of_camel, &balance_of) {
             Ok(ContractType::ERC1155)
         } else {
             Ok(ContractType::Other)
@@ -154,82 +170,67 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
 
     /// Returns true if the contract is ERC721, false otherwise.
     pub async fn is_erc721(&self, contract_address: FieldElement) -> Result<bool> {
-        let block = BlockId::Tag(BlockTag::Pending);
-        let token_id = vec![FieldElement::ONE, FieldElement::ZERO]; // u256.
-
-        match self
-            .get_contract_response(contract_address, "ownerOf", token_id.clone(), block)
-            .await
-        {
-            Ok(_) => return Ok(true),
-            Err(e) => match e {
-                StarknetClientError::Contract(s) => {
-                    // Token ID may not exist, but the entrypoint was hit.
-                    if s.contains("not found in contract") {
-                        // do nothing and go to the next selector.
-                    } else {
-                        return Ok(true);
-                    }
-                }
-                StarknetClientError::EntrypointNotFound(_) => (),
-                _ => return Ok(false),
-            },
-        };
+        let [owner_of_camel, owner_of] = self
+            .call_entrypoints(
+                contract_address,
+                [
+                    ("ownerOf", erc721_probe_calldata()),
+                    ("owner_of", erc721_probe_calldata()),
+                ],
+            )
+            .await?;
 
-        match self
-            .get_contract_response(contract_address, "owner_of", token_id, block)
-            .await
-        {
-            Ok(_) => Ok(true),
-            Err(e) => match e {
-                StarknetClientError::Contract(s) => {
-                    // Token ID may not exist, but the entrypoint was hit.
-                    if s.contains("not found in contract") {
-                        Ok(false)
-                    } else {
-                        Ok(true)
-                    }
-                }
-                StarknetClientError::EntrypointNotFound(_) => Ok(false),
-                _ => Ok(false),
-            },
-        }
+        Ok(is_erc721_from_probes(&owner_of_camel, &owner_of))
     }
 
     /// Returns true if the contract is ERC1155, false otherwise.
     pub async fn is_erc1155(&self, contract_address: FieldElement) -> Result<bool> {
-        let block = BlockId::Tag(BlockTag::Pending);
-        // felt and u256 expected.
-        let address_and_token_id = vec![FieldElement::ZERO, FieldElement::ONE, FieldElement::ZERO];
+        let [balance_of_camel, balance_of] = self
+            .call_entrypoints(
+                contract_address,
+                [
+                    ("balanceOf", erc1155_probe_calldata()),
+                    ("balance_of", erc1155_probe_calldata()),
+                ],
+            )
+            .await?;
+
+        Ok(is_erc1155_from_probes(&balance_of_camel, &balance_of))
+    }
 
-        match self
-            .get_contract_response(
+    /// Calls the given entrypoints of a contract on the pending block,
+    /// in a single batch, returning the result of each call in the same order.
+    async fn call_entrypoints<const N: usize>(
+        &self,
+        contract_address: FieldElement,
+        entrypoints: [(&str, Vec<FieldElement>); N],
+    ) -> Result<[Result<Vec<FieldElement>, StarknetClientError>; N]> {
+        let calls = entrypoints
+            .into_iter()
+            .map(|(selector_name, calldata)| {
+                Ok(FunctionCall {
+                    contract_address,
+                    entry_point_selector: get_selector_from_name(selector_name).map_err(|_| {
+                        StarknetClientError::Other(format!("Invalid selector: {}", selector_name))
+                    })?,
+                    calldata,
+                })
+            })
+            .collect::<Result<Vec<_>, StarknetClientError>>()?;
+
+        let responses = self
+            .client
+            .call_contracts(calls, BlockId::Tag(BlockTag::Pending))
+            .await?;
+
+        responses.try_into().map_err(|r: Vec<_>| {
+            anyhow!(
+                "Expected {} responses for contract 0x{:064x}, got {}",
+                N,
                 contract_address,
-                "balanceOf",
-                address_and_token_id.clone(),
-                block,
+                r.len()
             )
-            .await
-        {
-            Ok(_) => return Ok(true),
-            Err(e) => match e {
-                StarknetClientError::EntrypointNotFound(_) => (),
-                StarknetClientError::InputTooLong => return Ok(false), // ERC20.
-                _ => return Ok(false),
-            },
-        };
-
-        match self
-            .get_contract_response(contract_address, "balance_of", address_and_token_id, block)
-            .await
-        {
-            Ok(_) => Ok(true),
-            Err(e) => match e {
-                StarknetClientError::EntrypointNotFound(_) => Ok(false),
-                StarknetClientError::InputTooLong => Ok(false), // ERC20.
-                _ => Ok(false),
-            },
-        }
+        })
     }
 
     pub async fn get_contract_response(
@@ -270,8 +271,104 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
             )
             .await?;
 
-        parse_cairo_string(response).map_err(|e| {
-            StarknetClientError::Other(format!("Impossible to decode response string: {:?}", e))
-        })
+        parse_property_string(response)
+    }
+}
+
+/// Calldata of the ERC721 probes: a u256 token id.
+fn erc721_probe_calldata() -> Vec<FieldElement> {
+    vec![FieldElement::ONE, FieldElement::ZERO]
+}
+
+/// Calldata of the ERC1155 probes: a felt address and a u256 token id.
+fn erc1155_probe_calldata() -> Vec<FieldElement> {
+    vec![FieldElement::ZERO, FieldElement::ONE, FieldElement::ZERO]
+}
+
+/// Returns true if the responses of `ownerOf` and `owner_of`
+/// identify an ERC721 contract.
+fn is_erc721_from_probes(
+    owner_of_camel: &Result<Vec<FieldElement>, StarknetClientError>,
+    owner_of: &Result<Vec<FieldElement>, StarknetClientError>,
+) -> bool {
+    match owner_of_camel {
+        Ok(_) => return true,
+        // Token ID may not exist, but the entrypoint was hit.
+        Err(StarknetClientError::Contract(s)) if !s.contains("not found in contract") => {
+            return true
+        }
+        // Go to the next selector.
+        Err(StarknetClientError::Contract(_) | StarknetClientError::EntrypointNotFound(_)) => {}
+        Err(_) => return false,
+    };
+
+    match owner_of {
+        Ok(_) => true,
+        // Token ID may not exist, but the entrypoint was hit.
+        Err(StarknetClientError::Contract(s)) => !s.contains("not found in contract"),
+        Err(_) => false,
+    }
+}
+
+/// Returns true if the responses of `balanceOf` and `balance_of`
+/// identify an ERC1155 contract.
+/// ERC20 contracts fail with `InputTooLong`, as only one argument is expected.
+fn is_erc1155_from_probes(
+    balance_of_camel: &Result<Vec<FieldElement>, StarknetClientError>,
+    balance_of: &Result<Vec<FieldElement>, StarknetClientError>,
+) -> bool {
+    match balance_of_camel {
+        Ok(_) => true,
+        Err(StarknetClientError::EntrypointNotFound(_)) => balance_of.is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn parse_property_string(response: Vec<FieldElement>) -> Result<String, StarknetClientError> {
+    parse_cairo_string(response).map_err(|e| {
+        StarknetClientError::Other(format!("Impossible to decode response string: {}", e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contract_error(s: &str) -> Result<Vec<FieldElement>, StarknetClientError> {
+        Err(StarknetClientError::Contract(s.to_string()))
+    }
+
+    fn not_found() -> Result<Vec<FieldElement>, StarknetClientError> {
+        Err(StarknetClientError::EntrypointNotFound(
+            "Entry point not found in contract".to_string(),
+        ))
+    }
+
+    #[test]
+    fn test_is_erc721_from_probes() {
+        assert!(is_erc721_from_probes(&Ok(vec![]), &not_found()));
+        // Token ID doesn't exist, but the entrypoint was hit.
+        assert!(is_erc721_from_probes(
+            &contract_error("ERC721: invalid token ID"),
+            &not_found()
+        ));
+        assert!(is_erc721_from_probes(&not_found(), &Ok(vec![])));
+        assert!(!is_erc721_from_probes(&not_found(), &not_found()));
+        assert!(!is_erc721_from_probes(
+            &Err(StarknetClientError::InputTooLong),
+            &Ok(vec![])
+        ));
+    }
+
+    #[test]
+    fn test_is_erc1155_from_probes() {
+        assert!(is_erc1155_from_probes(&Ok(vec![]), &not_found()));
+        assert!(is_erc1155_from_probes(&not_found(), &Ok(vec![])));
+        assert!(!is_erc1155_from_probes(&not_found(), &not_found()));
+        // ERC20.
+        assert!(!is_erc1155_from_probes(
+            &Err(StarknetClientError::InputTooLong),
+            &Ok(vec![])
+        ));
     }
 }
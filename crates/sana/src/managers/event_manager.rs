@@ -2,7 +2,11 @@ use crate::storage::types::{EventType, TokenSaleEvent, TokenTransferEvent};
 use crate::storage::Storage;
 use crate::{ContractType, VENTORY_MARKETPLACE_EVENT_HEX};
 use anyhow::{anyhow, Result};
-use ark_starknet::{format::to_hex_str, CairoU256};
+use ark_starknet::{
+    cairo_serde::{CairoDeserialize, FeltReader},
+    format::to_hex_str,
+    CairoU256,
+};
 use starknet::core::types::{EmittedEvent, FieldElement};
 use starknet::core::utils::starknet_keccak;
 use starknet::macros::selector;
@@ -14,6 +18,30 @@ const TRANSFER_SELECTOR: FieldElement = selector!("Transfer");
 const ELEMENT_NFT_MARKETPLACE_HEX: &str =
     "0x351e5a57ea6ca22e3e3cd212680ef7f3b57404609bda942a5e75ba4724b55e0";
 
+/// Data of Ventory sale events.
+#[derive(Debug, CairoDeserialize)]
+struct VentorySaleData {
+    _listing_counter: FieldElement,
+    token_id: u128,
+    price: FieldElement,
+    asset_contract: FieldElement,
+    seller: FieldElement,
+    buyer: FieldElement,
+    _status: FieldElement,
+}
+
+/// Data of Element sale events, the maker address is in the keys.
+#[derive(Debug, CairoDeserialize)]
+struct ElementSaleData {
+    taker: FieldElement,
+    currency: FieldElement,
+    price: FieldElement,
+    _fees: Vec<(FieldElement, FieldElement)>,
+    nft_contract: FieldElement,
+    token_id: CairoU256,
+    quantity: u64,
+}
+
 #[derive(Debug)]
 pub struct EventManager<S: Storage> {
     storage: Arc<S>,
@@ -58,41 +86,16 @@ impl<S: Storage> EventManager<S> {
         event: &EmittedEvent,
         block_timestamp: u64,
     ) -> Result<TokenSaleEvent> {
-        let _listing_counter = event
-            .data
-            .first()
-            .ok_or_else(|| anyhow!("Listing counter not found"))?;
-        let token_id = event
-            .data
-            .get(1)
-            .ok_or_else(|| anyhow!("Token id not found"))?;
-        let price = event
-            .data
-            .get(2)
-            .ok_or_else(|| anyhow!("Price not found"))?;
-        let asset_contract = event
-            .data
-            .get(3)
-            .ok_or_else(|| anyhow!("Asset contract not found"))?;
-        let seller = event
-            .data
-            .get(4)
-            .ok_or_else(|| anyhow!("Seller not found"))?;
-        let buyer = event
-            .data
-            .get(5)
-            .ok_or_else(|| anyhow!("Buyer not found"))?;
-        let _status = event
-            .data
-            .get(6)
-            .ok_or_else(|| anyhow!("Status not found"))?;
+        let data: VentorySaleData = FeltReader::new(&event.data)
+            .read()
+            .map_err(|e| anyhow!("Invalid Ventory sale event: {}", e))?;
 
         let token_id = CairoU256 {
-            low: (*token_id)
-                .try_into()
-                .map_err(|_| anyhow!("Failed to parse token id"))?,
+            low: data.token_id,
             high: 0,
         };
+        let (seller, buyer, asset_contract, price) =
+            (&data.seller, &data.buyer, &data.asset_contract, &data.price);
 
         let event_id = Self::get_event_id(&token_id, seller, buyer, block_timestamp, event);
 
@@ -133,71 +136,13 @@ impl<S: Storage> EventManager<S> {
             .get(3)
             .ok_or_else(|| anyhow!("Maker address not found"))?;
 
-        let taker_address = event
-            .data
-            .first()
-            .ok_or_else(|| anyhow!("Taker address not found"))?;
-        let currency_address = event
-            .data
-            .get(1)
-            .ok_or_else(|| anyhow!("Currency address not found"))?;
-        let price = event
-            .data
-            .get(2)
-            .ok_or_else(|| anyhow!("Price not found"))?;
-
-        let number_of_fee_recipients = event
-            .data
-            .get(3)
-            .ok_or_else(|| anyhow!("Number of fee recipients not found"))?;
-
-        let number_of_fee_recipients_u64: u32 = (*number_of_fee_recipients)
-            .try_into()
-            .map_err(|_| anyhow!("Failed to parse number of fee recipients"))?;
-
-        let mut index = 4;
-        // for loop with number_of_fee_recipients_u64 iterations
-        for _ in 0..number_of_fee_recipients_u64 {
-            let _fee0_recipient = event.data.get(index);
-            index += 1;
-            let _fee0_value = event.data.get(index);
-            index += 1;
-        }
-
-        let nft_contract_address = event
-            .data
-            .get(index)
-            .ok_or_else(|| anyhow!("NFT contract address not found"))?;
-
-        index += 1;
-
-        let token_id_low = event
-            .data
-            .get(index)
-            .ok_or_else(|| anyhow!("Token id low not found"))?;
-
-        index += 1;
+        let data: ElementSaleData = FeltReader::new(&event.data)
+            .read()
+            .map_err(|e| anyhow!("Invalid Element sale event: {}", e))?;
 
-        let token_id_high = event
-            .data
-            .get(index)
-            .ok_or_else(|| anyhow!("Token id high not found"))?;
-
-        index += 1;
-
-        let quantity = event
-            .data
-            .get(index)
-            .ok_or_else(|| anyhow!("Quantity not found"))?;
-
-        let token_id = CairoU256 {
-            low: (*token_id_low)
-                .try_into()
-                .map_err(|_| anyhow!("Failed to parse token id low"))?,
-            high: (*token_id_high)
-                .try_into()
-                .map_err(|_| anyhow!("Failed to parse token id high"))?,
-        };
+        let token_id = data.token_id;
+        let (taker_address, currency_address, nft_contract_address, price) =
+            (&data.taker, &data.currency, &data.nft_contract, &data.price);
 
         let event_id = Self::get_event_id(
             &token_id,
@@ -220,9 +165,7 @@ impl<S: Storage> EventManager<S> {
             token_id_hex: token_id.to_hex(),
             token_id: token_id.to_decimal(false),
             updated_at: Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
-            quantity: (*quantity)
-                .try_into()
-                .map_err(|_| anyhow!("Failed to parse quantity"))?,
+            quantity: data.quantity,
             currency_address: Some(to_hex_str(currency_address)),
             marketplace_contract_address: to_hex_str(&event.from_address),
             marketplace_name: "Element".to_string(),
@@ -327,30 +270,19 @@ impl<S: Storage> EventManager<S> {
     /// Returns the event info from vector of felts.
     /// Event info are (from, to, token_id).
     ///
-    /// This methods considers that the info of the
-    /// event is starting at index 0 of the input vector.
+    /// The info of the event is starting at index 0 of the input
+    /// vector, or at index 1 if 5 felts are given.
+    /// Returns `None` if the felts don't match this layout.
     fn get_event_info_from_felts(
         felts: &[FieldElement],
     ) -> Option<(FieldElement, FieldElement, CairoU256)> {
-        match felts.len() {
-            4 | 5 => {
-                let from_index = if felts.len() == 4 { 0 } else { 1 };
-                let to_index = from_index + 1;
-                let low_index = to_index + 1;
-                let high_index = low_index + 1;
-
-                let from = felts[from_index];
-                let to = felts[to_index];
-
-                let low = felts[low_index].try_into().ok()?;
-                let high = felts[high_index].try_into().ok()?;
-
-                let token_id = CairoU256 { low, high };
+        let offset = match felts.len() {
+            4 => 0,
+            5 => 1,
+            _ => return None,
+        };
 
-                Some((from, to, token_id))
-            }
-            _ => None,
-        }
+        <(FieldElement, FieldElement, CairoU256)>::from_felts(&felts[offset..]).ok()
     }
 }
 
@@ -374,7 +306,7 @@ mod tests {
         EmittedEvent {
             from_address: FieldElement::from_hex_be("0x0").unwrap(),
             block_hash: Some(block_hash),
-            transaction_hash: transaction_hash,
+            transaction_hash,
             block_number: Some(111),
             keys: vec![
                 TRANSFER_SELECTOR,
@@ -512,7 +444,7 @@ mod tests {
         let result = EventManager::<MockStorage>::get_event_info_from_felts(&sample_data);
 
         // Assert the output
-        assert_eq!(result.is_some(), true);
+        assert!(result.is_some());
         let (from, to, token_id) = result.unwrap();
         assert_eq!(from, from_value);
         assert_eq!(to, to_value);
@@ -534,6 +466,6 @@ mod tests {
         let result = EventManager::<MockStorage>::get_event_info_from_felts(&sample_data);
 
         // Assert the output
-        assert_eq!(result.is_none(), true);
+        assert!(result.is_none());
     }
 }
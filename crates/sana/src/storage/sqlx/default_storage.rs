@@ -1,8 +1,12 @@
+use super::schema::{
+    check_version, APPLIED_VERSION_QUERY, BASELINE_QUERY, MIGRATIONS_TABLE, MIGRATOR,
+    TABLE_COUNT_QUERY,
+};
 use super::types::*;
 use crate::storage::types::*;
 use crate::Storage;
 use async_trait::async_trait;
-use sqlx::{postgres::PgPoolOptions, Error as SqlxError, FromRow, PgPool};
+use sqlx::{migrate::Migrate, postgres::PgPoolOptions, Error as SqlxError, FromRow, PgPool};
 use std::str::FromStr;
 use tracing::{error, info, trace};
 
@@ -21,7 +25,88 @@ impl PostgresStorage {
         &self.pool
     }
 
+    /// Connects to an existing database, failing with
+    /// [`StorageError::OutdatedSchema`] if its schema is older than the
+    /// one expected, see [`PostgresStorage::migrate`].
     pub async fn new(db_url: &str) -> Result<Self, StorageError> {
+        let storage = Self::connect(db_url).await?;
+        storage.check_schema().await?;
+        Ok(storage)
+    }
+
+    /// Connects to the database, and applies the migrations not
+    /// applied yet. The tables are created on an empty database.
+    pub async fn migrate(db_url: &str) -> Result<Self, StorageError> {
+        let storage = Self::connect(db_url).await?;
+        MIGRATOR
+            .run(&storage.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(storage)
+    }
+
+    /// Connects to a database created before the migrations were
+    /// versioned, marking the migrations up to `version` as applied
+    /// without running them, then applies the following ones.
+    ///
+    /// `version` is the last migration the schema of the database
+    /// already has. Nothing is marked if migrations were applied.
+    pub async fn baseline(db_url: &str, version: i64) -> Result<Self, StorageError> {
+        let storage = Self::connect(db_url).await?;
+
+        {
+            let mut conn = storage.pool.acquire().await?;
+            conn.ensure_migrations_table()
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+            let applied = conn
+                .list_applied_migrations()
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+            if applied.is_empty() {
+                for migration in MIGRATOR.iter().filter(|m| m.version <= version) {
+                    trace!("Baselining migration {}", migration.version);
+                    sqlx::query(BASELINE_QUERY)
+                        .bind(migration.version)
+                        .bind(migration.description.to_string())
+                        .bind(migration.checksum.to_vec())
+                        .execute(&mut *conn)
+                        .await?;
+                }
+            }
+        }
+
+        MIGRATOR
+            .run(&storage.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(storage)
+    }
+
+    /// Checks that all the migrations embedded in the crate
+    /// are applied to the database.
+    pub async fn check_schema(&self) -> Result<(), StorageError> {
+        // The migrations table doesn't exist if the
+        // database was never migrated.
+        let tables: i64 = sqlx::query_scalar(TABLE_COUNT_QUERY)
+            .bind(MIGRATIONS_TABLE)
+            .fetch_one(&self.pool)
+            .await?;
+
+        let applied: Option<i64> = if tables == 0 {
+            None
+        } else {
+            sqlx::query_scalar(APPLIED_VERSION_QUERY)
+                .fetch_one(&self.pool)
+                .await?
+        };
+
+        check_version(&MIGRATOR, applied)
+    }
+
+    async fn connect(db_url: &str) -> Result<Self, StorageError> {
         sqlx::any::install_default_drivers();
 
         Ok(Self {
@@ -142,6 +227,7 @@ impl PostgresStorage {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn update_indexer_progression(
         &self,
         indexer_identifier: &str,
@@ -216,7 +302,7 @@ impl Storage for PostgresStorage {
 
         let _r = sqlx::query(q)
             .bind(info.address.clone())
-            .bind(info.block_timestamp.to_string())
+            .bind(info.block_timestamp as i64)
             .bind(info.transaction_hash.clone())
             .bind(token_id)
             .execute(&self.pool)
@@ -315,10 +401,10 @@ impl Storage for PostgresStorage {
             let insert_query = "INSERT INTO token_event (token_event_id, contract_address, chain_id, token_id, token_id_hex, event_type, block_timestamp, transaction_hash, to_address, from_address)
                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) ON CONFLICT (token_event_id) DO NOTHING";
 
-            let event_type = event.event_type.as_ref().map(|e| {
-                let res = self.to_title_case(&e.to_string().to_lowercase());
-                res
-            });
+            let event_type = event
+                .event_type
+                .as_ref()
+                .map(|e| self.to_title_case(&e.to_string().to_lowercase()));
 
             info!("Inserting transfer event... {:?}", event_type);
 
@@ -0,0 +1,74 @@
+-- Initial schema of the Sana tables.
+--
+-- Amounts and token ids are stored as decimal strings,
+-- as they may not fit in a BIGINT.
+
+CREATE TABLE token (
+       contract_address TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       current_owner TEXT NOT NULL,
+       block_timestamp BIGINT NOT NULL,
+       mint_address TEXT,
+       mint_timestamp BIGINT,
+       mint_transaction_hash TEXT,
+
+       PRIMARY KEY (contract_address, chain_id, token_id)
+);
+
+CREATE TABLE token_event (
+       token_event_id TEXT NOT NULL,
+       contract_address TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       broker_id TEXT,
+       order_hash TEXT,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       event_type TEXT,
+       block_timestamp BIGINT NOT NULL,
+       transaction_hash TEXT NOT NULL,
+       to_address TEXT,
+       from_address TEXT,
+       amount TEXT,
+       canceled_reason TEXT,
+
+       PRIMARY KEY (token_event_id)
+);
+
+CREATE TABLE contract (
+       contract_address TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       contract_type TEXT NOT NULL,
+       updated_timestamp BIGINT NOT NULL,
+       contract_symbol TEXT,
+       contract_image TEXT,
+       contract_name TEXT,
+       metadata_ok BOOLEAN NOT NULL DEFAULT FALSE,
+       deployed_timestamp BIGINT,
+
+       PRIMARY KEY (contract_address, chain_id)
+);
+
+CREATE TABLE indexer (
+       indexer_identifier TEXT NOT NULL,
+       indexer_version TEXT NOT NULL,
+       indexer_status TEXT,
+       last_updated_timestamp BIGINT,
+       indexation_progress_percentage DOUBLE PRECISION,
+       current_block_number BIGINT,
+       is_force_mode_enabled BOOLEAN,
+       start_block_number BIGINT,
+       end_block_number BIGINT,
+
+       PRIMARY KEY (indexer_identifier)
+);
+
+CREATE TABLE block (
+       block_timestamp BIGINT NOT NULL,
+       block_number BIGINT NOT NULL UNIQUE,
+       block_status TEXT NOT NULL,
+       indexer_identifier TEXT NOT NULL,
+
+       PRIMARY KEY (block_timestamp)
+);
@@ -6,4 +6,6 @@
 pub mod default_storage;
 pub use default_storage::PostgresStorage;
 
+pub mod schema;
+
 pub mod types;
@@ -0,0 +1,64 @@
+//! Versioned migrations of the Postgres storage schema.
+//!
+//! The migrations are embedded in the crate. The storage only starts
+//! on a database having all the migrations applied.
+//!
+//! Databases created before the migrations were versioned have no
+//! migrations table: they are baselined, their first migrations being
+//! marked as applied without running them, see [`PostgresStorage::baseline`].
+//!
+//! [`PostgresStorage::baseline`]: super::PostgresStorage::baseline
+use sqlx::migrate::Migrator;
+
+use crate::storage::types::StorageError;
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("src/storage/sqlx/migrations");
+
+/// Tables of the current schema named `$1`, to check
+/// that the migrations table exists.
+pub const TABLE_COUNT_QUERY: &str = "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = $1";
+
+/// Table of the migrations applied to the database.
+pub const MIGRATIONS_TABLE: &str = "_sqlx_migrations";
+
+/// Last migration applied to the database, `NULL` if none.
+pub const APPLIED_VERSION_QUERY: &str = "SELECT MAX(version) FROM _sqlx_migrations WHERE success";
+
+/// Marks a migration as applied without running it.
+pub const BASELINE_QUERY: &str = "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES ($1, $2, TRUE, $3, 0)";
+
+/// Checks that the `applied` version, the last migration applied
+/// to the database, is at least the last migration of `migrator`.
+pub fn check_version(migrator: &Migrator, applied: Option<i64>) -> Result<(), StorageError> {
+    let expected = migrator.iter().map(|m| m.version).max().unwrap_or_default();
+
+    match applied {
+        Some(version) if version >= expected => Ok(()),
+        Some(version) => Err(StorageError::OutdatedSchema(format!(
+            "database is at version {version}, version {expected} expected"
+        ))),
+        None => Err(StorageError::OutdatedSchema(format!(
+            "no migration applied, version {expected} expected, a database created before the versioned migrations must be baselined"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_version() {
+        let last = MIGRATOR.iter().map(|m| m.version).max().unwrap();
+
+        assert!(check_version(&MIGRATOR, Some(last)).is_ok());
+        assert!(matches!(
+            check_version(&MIGRATOR, Some(last - 1)),
+            Err(StorageError::OutdatedSchema(_))
+        ));
+        assert!(matches!(
+            check_version(&MIGRATOR, None),
+            Err(StorageError::OutdatedSchema(_))
+        ));
+    }
+}
@@ -11,6 +11,8 @@ pub enum StorageError {
     DuplicateToken(String),
     InvalidMintData(String),
     AlreadyExists(String),
+    /// The database schema is older than the one expected.
+    OutdatedSchema(String),
 }
 
 impl fmt::Display for StorageError {
@@ -23,6 +25,9 @@ impl fmt::Display for StorageError {
             StorageError::DuplicateToken(s) => write!(f, "Token already exists in storage: {s}"),
             StorageError::InvalidMintData(s) => write!(f, "Provided mint data is invalid: {s}"),
             StorageError::AlreadyExists(s) => write!(f, "Item already exists in storage: {s}"),
+            StorageError::OutdatedSchema(s) => {
+                write!(f, "Database schema is outdated, run the migrations: {s}")
+            }
         }
     }
 }
@@ -240,6 +245,7 @@ pub enum BlockIndexingStatus {
     Terminated,
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for BlockIndexingStatus {
     fn to_string(&self) -> String {
         match self {
@@ -301,7 +307,7 @@ pub struct BlockInfo {
     pub block_number: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum ContractType {
     Other,
@@ -309,6 +315,7 @@ pub enum ContractType {
     ERC1155,
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for ContractType {
     fn to_string(&self) -> String {
         match self {
@@ -42,11 +42,7 @@ async fn main() -> Result<()> {
         indexer_identifier: "task_1234".to_string(),
     };
 
-    let storage = Arc::new(DefaultSqlxStorage::new_any("sqlite::memory:").await?);
-
-    sqlx::migrate!("./crates/pontos/src/storage/sqlx/migrations")
-        .run(storage.get_pool_ref())
-        .await?;
+    let storage = Arc::new(DefaultSqlxStorage::migrate("sqlite::memory:").await?);
 
     let pontos = Arc::new(Pontos::new(
         Arc::clone(&client),